@@ -0,0 +1,153 @@
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use crate::stable_mem_storage::ROLES;
+
+/// Roles ordered by privilege; a role satisfies every requirement at or below its rank
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Controller,
+    Admin,
+    Operator,
+    User,
+}
+
+impl Role {
+    fn rank(&self) -> u8 {
+        match self {
+            Role::Controller => 3,
+            Role::Admin => 2,
+            Role::Operator => 1,
+            Role::User => 0,
+        }
+    }
+
+    pub fn satisfies(&self, required: Role) -> bool {
+        self.rank() >= required.rank()
+    }
+}
+
+impl Storable for Role {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 64, is_fixed_size: false };
+}
+
+/// Reject the current call unless the caller holds at least `role`.
+/// Use `require_role!(role, trap)` in endpoints that do not return a `Result`.
+#[macro_export]
+macro_rules! require_role {
+    ($role:expr) => {
+        $crate::access_control::check_role(&ic_cdk::api::caller(), $role)?;
+    };
+    ($role:expr, trap) => {
+        if let Err(e) = $crate::access_control::check_role(&ic_cdk::api::caller(), $role) {
+            ic_cdk::trap(&e);
+        }
+    };
+}
+
+#[cfg(not(test))]
+fn is_controller(principal: &Principal) -> bool {
+    ic_cdk::api::is_controller(principal)
+}
+
+// Native tests have no canister controllers; treat the management canister as one
+#[cfg(test)]
+fn is_controller(principal: &Principal) -> bool {
+    *principal == Principal::management_canister()
+}
+
+/// Effective role of a principal: canister controllers are always `Controller`,
+/// everyone without a stored grant is a plain `User`
+pub fn get_role(principal: &Principal) -> Role {
+    if is_controller(principal) {
+        return Role::Controller;
+    }
+    ROLES.with(|roles| roles.borrow().get(principal)).unwrap_or(Role::User)
+}
+
+pub fn check_role(principal: &Principal, required: Role) -> Result<(), String> {
+    if get_role(principal).satisfies(required) {
+        Ok(())
+    } else {
+        Err(format!("No permission: requires {:?} role", required))
+    }
+}
+
+// Admins manage operators; only controllers may hand out or take away admin rights
+fn check_can_manage(caller: &Principal, role: Role) -> Result<(), String> {
+    let required = if role.satisfies(Role::Admin) { Role::Controller } else { Role::Admin };
+    check_role(caller, required)
+}
+
+pub fn grant_role(caller: &Principal, principal: Principal, role: Role) -> Result<(), String> {
+    check_can_manage(caller, role)?;
+    if role == Role::User {
+        ROLES.with(|roles| roles.borrow_mut().remove(&principal));
+    } else {
+        ROLES.with(|roles| roles.borrow_mut().insert(principal, role));
+    }
+    Ok(())
+}
+
+pub fn revoke_role(caller: &Principal, principal: Principal) -> Result<(), String> {
+    let current = ROLES.with(|roles| roles.borrow().get(&principal))
+        .ok_or_else(|| format!("Principal {} has no granted role", principal))?;
+    check_can_manage(caller, current)?;
+    ROLES.with(|roles| roles.borrow_mut().remove(&principal));
+    Ok(())
+}
+
+/// Grants stored in the role map (controllers are implicit and not listed)
+pub fn list_roles() -> Vec<(Principal, Role)> {
+    ROLES.with(|roles| roles.borrow().iter().collect())
+}
+
+// The role map lives in MemoryManager-backed stable memory, so it survives upgrades
+// without serialization; the hooks only report what is carried across.
+pub fn pre_upgrade() {
+    let count = ROLES.with(|roles| roles.borrow().len());
+    ic_cdk::println!("[access_control] pre_upgrade: {} role grants in stable memory", count);
+}
+
+pub fn post_upgrade() {
+    let count = ROLES.with(|roles| roles.borrow().len());
+    ic_cdk::println!("[access_control] post_upgrade: restored {} role grants", count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_grants_follow_hierarchy() {
+        let controller = Principal::management_canister();
+        let admin = Principal::from_slice(&[1]);
+        let operator = Principal::from_slice(&[2]);
+
+        assert_eq!(get_role(&admin), Role::User);
+        assert!(grant_role(&admin, admin, Role::Admin).is_err());
+
+        grant_role(&controller, admin, Role::Admin).unwrap();
+        grant_role(&admin, operator, Role::Operator).unwrap();
+        assert!(grant_role(&admin, operator, Role::Admin).is_err());
+
+        assert!(check_role(&operator, Role::Operator).is_ok());
+        assert!(check_role(&operator, Role::Admin).is_err());
+        assert!(check_role(&controller, Role::Controller).is_ok());
+
+        assert!(revoke_role(&operator, admin).is_err());
+        revoke_role(&admin, operator).unwrap();
+        assert_eq!(get_role(&operator), Role::User);
+        assert_eq!(list_roles(), vec![(admin, Role::Admin)]);
+    }
+}
@@ -7,6 +7,8 @@ use std::borrow::Cow;
 use std::cell::RefCell;
 use crate::token_economy_types::{AccountInfo};
 use crate::stable_mem_storage::ACCOUNTS;
+use crate::types::{InstructionGuard, PagedResult, clamp_page_limit, cursor_key_range};
+use crate::trace_storage::{IOValue, IOValueType};
 use std::collections::HashMap;
 use candid::Principal;
 use std::sync::LazyLock;
@@ -62,6 +64,10 @@ impl ic_stable_structures::Storable for AccountInfo {
                 created_at,
                 updated_at,
                 metadata,
+                last_claim_time: None,
+                last_claim_amount: None,
+                last_claim_timestamp: None,
+                last_emission_time: None,
             };
         }
         
@@ -80,6 +86,10 @@ impl ic_stable_structures::Storable for AccountInfo {
                 created_at,
                 updated_at,
                 metadata,
+                last_claim_time: None,
+                last_claim_amount: None,
+                last_claim_timestamp: None,
+                last_emission_time: None,
             };
         }
         
@@ -98,6 +108,10 @@ impl ic_stable_structures::Storable for AccountInfo {
                 created_at,
                 updated_at: None,
                 metadata: None,
+                last_claim_time: None,
+                last_claim_amount: None,
+                last_claim_timestamp: None,
+                last_emission_time: None,
             };
         }
         
@@ -116,6 +130,10 @@ impl ic_stable_structures::Storable for AccountInfo {
                 created_at: created_at.0.to_u64().unwrap_or(ic_cdk::api::time()),
                 updated_at: updated_at.map(|t| t.0.to_u64().unwrap_or(0)),
                 metadata,
+                last_claim_time: None,
+                last_claim_amount: None,
+                last_claim_timestamp: None,
+                last_emission_time: None,
             };
         }
         
@@ -158,8 +176,54 @@ pub fn get_all_accounts() -> Vec<AccountInfo> {
     })
 }
 
+/// All accounts in principal order, read only while the instruction budget lasts
+pub fn get_all_accounts_guarded(guard: &InstructionGuard) -> PagedResult<AccountInfo> {
+    let start_after = guard.start_after().map(|principal_id| AccountKey { principal_id });
+    ACCOUNTS.with(|accounts| {
+        guard.collect(accounts.borrow().range(cursor_key_range(start_after)).map(|(key, account)| (key.principal_id, account)))
+    })
+}
+
 /// Delete an account
-pub fn delete_account(principal_id: String) -> Result<(), String> {
+/// Accounts holding any token, credit or staked balance are only removed when `force` is set,
+/// in which case the discarded balances are recorded as a trace.
+pub fn delete_account(principal_id: String, force: bool) -> Result<(), String> {
+    let account = get_account(principal_id.clone()).ok_or_else(|| "Account not found".to_string())?;
+    let info = &account.token_info;
+    let has_balance = info.token_balance > 0 || info.credit_balance > 0 || info.staked_credits > 0;
+
+    if has_balance {
+        if !force {
+            return Err(format!(
+                "Account has nonzero balances (token={}, credit={}, staked={}); pass force to delete",
+                info.token_balance, info.credit_balance, info.staked_credits
+            ));
+        }
+
+        let discarded = serde_json::json!({
+            "token_balance": info.token_balance,
+            "credit_balance": info.credit_balance,
+            "staked_credits": info.staked_credits,
+        });
+        // A principal can be deleted, re-created and deleted again; number the traces so each one stays separate
+        let trace_id = (0u64..)
+            .map(|n| format!("delete_account_{}_{}", principal_id, n))
+            .find(|id| crate::trace_storage::get_trace_by_id(id.clone()).is_none())
+            .unwrap();
+        crate::trace_storage::record_trace_call(
+            trace_id,
+            principal_id.clone(),
+            "account".to_string(),
+            principal_id.clone(),
+            "admin".to_string(),
+            "delete_account".to_string(),
+            IOValue { data_type: "object".to_string(), value: IOValueType::Object(discarded.to_string()) },
+            IOValue { data_type: "null".to_string(), value: IOValueType::Null },
+            "ok".to_string(),
+            None,
+        )?;
+    }
+
     ACCOUNTS.with(|accounts| {
         let mut accounts = accounts.borrow_mut();
         let key = AccountKey { principal_id };
@@ -171,6 +235,76 @@ pub fn delete_account(principal_id: String) -> Result<(), String> {
     })
 }
 
+fn record_account_audit(caller: &str, method: &str, input: serde_json::Value, output: serde_json::Value) -> Result<(), String> {
+    crate::trace_storage::record_trace_call(
+        String::new(),
+        format!("{}_{}", method, caller),
+        "account".to_string(),
+        caller.to_string(),
+        "admin".to_string(),
+        method.to_string(),
+        IOValue { data_type: "object".to_string(), value: IOValueType::Object(input.to_string()) },
+        IOValue { data_type: "object".to_string(), value: IOValueType::Object(output.to_string()) },
+        "ok".to_string(),
+        None,
+    ).map(|_| ())
+}
+
+/// One page of an account export; `json` is an array that `import_accounts_from_json`
+/// accepts as is. Pass `next_cursor` back to export the next page.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct AccountsJsonPage {
+    pub json: String,
+    pub next_cursor: Option<String>,
+}
+
+/// Serialize one page of accounts, in principal order after `cursor`, to a JSON array,
+/// for backups and migrations
+pub fn export_all_accounts_json(caller: String, cursor: Option<String>, limit: usize) -> Result<AccountsJsonPage, String> {
+    let limit = clamp_page_limit(limit);
+    let mut accounts: Vec<AccountInfo> = ACCOUNTS.with(|accounts| {
+        accounts.borrow()
+            .range(cursor_key_range(cursor.clone().map(|principal_id| AccountKey { principal_id })))
+            .take(limit + 1)
+            .map(|(_, account)| account)
+            .collect()
+    });
+    let has_more = accounts.len() > limit;
+    accounts.truncate(limit);
+    let next_cursor = if has_more { accounts.last().map(|account| account.principal_id.clone()) } else { None };
+
+    let json = serde_json::to_string(&accounts).map_err(|e| format!("Failed to serialize accounts: {}", e))?;
+    record_account_audit(
+        &caller,
+        "export_all_accounts_json",
+        serde_json::json!({ "cursor": cursor, "limit": limit }),
+        serde_json::json!({ "exported": accounts.len() }),
+    )?;
+    Ok(AccountsJsonPage { json, next_cursor })
+}
+
+/// Upsert accounts from a JSON array produced by `export_all_accounts_json`.
+/// Existing accounts are left untouched unless `force` is set. Returns the number imported.
+pub fn import_accounts_from_json(caller: String, json: String, force: bool) -> Result<u64, String> {
+    let accounts: Vec<AccountInfo> = serde_json::from_str(&json).map_err(|e| format!("Invalid accounts JSON: {}", e))?;
+    let total = accounts.len();
+    let mut imported = 0u64;
+    for account in accounts {
+        if !force && get_account(account.principal_id.clone()).is_some() {
+            continue;
+        }
+        upsert_account(account)?;
+        imported += 1;
+    }
+    record_account_audit(
+        &caller,
+        "import_accounts_from_json",
+        serde_json::json!({ "records": total, "force": force }),
+        serde_json::json!({ "imported": imported }),
+    )?;
+    Ok(imported)
+}
+
 /// Get accounts with pagination
 pub fn get_accounts_paginated(offset: u64, limit: usize) -> Vec<AccountInfo> {
     ACCOUNTS.with(|accounts| {
@@ -182,4 +316,105 @@ pub fn get_accounts_paginated(offset: u64, limit: usize) -> Vec<AccountInfo> {
             .map(|(_, account)| account)
             .collect()
     })
-} 
\ No newline at end of file
+} 
+
+/// Get accounts that currently have staked credits, as (principal_id, staked_credits)
+pub fn get_accounts_with_stake(offset: u64, limit: usize) -> Vec<(String, u64)> {
+    ACCOUNTS.with(|accounts| {
+        let accounts = accounts.borrow();
+        accounts
+            .iter()
+            .filter(|(_, account)| account.token_info.staked_credits > 0)
+            .skip(offset as usize)
+            .take(limit)
+            .map(|(key, account)| (key.principal_id, account.token_info.staked_credits))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token_economy_types::TokenInfo;
+
+    fn test_account(principal_id: &str, credit_balance: u64) -> AccountInfo {
+        AccountInfo {
+            principal_id: principal_id.to_string(),
+            token_info: TokenInfo {
+                token_balance: 0,
+                credit_balance,
+                staked_credits: 0,
+                kappa_multiplier: 1.0,
+            },
+            created_at: 0,
+            updated_at: None,
+            metadata: None,
+            last_claim_time: None,
+            last_claim_amount: None,
+            last_claim_timestamp: None,
+            last_emission_time: None,
+        }
+    }
+
+    #[test]
+    fn test_delete_account_checks_balances() {
+        upsert_account(test_account("empty", 0)).unwrap();
+        upsert_account(test_account("funded", 50)).unwrap();
+
+        assert!(delete_account("empty".to_string(), false).is_ok());
+        assert!(get_account("empty".to_string()).is_none());
+
+        assert!(delete_account("funded".to_string(), false).is_err());
+        assert!(get_account("funded".to_string()).is_some());
+
+        assert!(delete_account("missing".to_string(), false).is_err());
+
+        // Each forced delete of the same principal keeps its own trace
+        delete_account("funded".to_string(), true).unwrap();
+        upsert_account(test_account("funded", 20)).unwrap();
+        delete_account("funded".to_string(), true).unwrap();
+        assert!(crate::trace_storage::get_trace_by_id("delete_account_funded_0".to_string()).is_some());
+        assert!(crate::trace_storage::get_trace_by_id("delete_account_funded_1".to_string()).is_some());
+    }
+
+    #[test]
+    fn test_get_accounts_with_stake() {
+        let mut staked_a = test_account("staker_a", 0);
+        staked_a.token_info.staked_credits = 100;
+        let mut staked_b = test_account("staker_b", 0);
+        staked_b.token_info.staked_credits = 250;
+        upsert_account(staked_a).unwrap();
+        upsert_account(test_account("no_stake", 500)).unwrap();
+        upsert_account(staked_b).unwrap();
+
+        let stakers = get_accounts_with_stake(0, 10);
+        assert_eq!(stakers, vec![("staker_a".to_string(), 100), ("staker_b".to_string(), 250)]);
+        assert_eq!(get_accounts_with_stake(1, 10), vec![("staker_b".to_string(), 250)]);
+    }
+
+    #[test]
+    fn test_import_accounts_skips_existing_unless_forced() {
+        upsert_account(test_account("import_existing", 10)).unwrap();
+        let json = serde_json::to_string(&vec![
+            test_account("import_existing", 99),
+            test_account("import_new", 5),
+        ]).unwrap();
+
+        assert!(import_accounts_from_json("admin".to_string(), "not json".to_string(), false).is_err());
+        assert_eq!(import_accounts_from_json("admin".to_string(), json.clone(), false), Ok(1));
+        assert_eq!(get_account("import_existing".to_string()).unwrap().token_info.credit_balance, 10);
+        assert_eq!(get_account("import_new".to_string()).unwrap().token_info.credit_balance, 5);
+
+        assert_eq!(import_accounts_from_json("admin".to_string(), json, true), Ok(2));
+        assert_eq!(get_account("import_existing".to_string()).unwrap().token_info.credit_balance, 99);
+
+        let first = export_all_accounts_json("admin".to_string(), None, 1).unwrap();
+        let exported: Vec<AccountInfo> = serde_json::from_str(&first.json).unwrap();
+        assert_eq!(exported.len(), 1);
+        let rest = export_all_accounts_json("admin".to_string(), first.next_cursor, 100).unwrap();
+        let exported: Vec<AccountInfo> = exported.into_iter().chain(serde_json::from_str::<Vec<AccountInfo>>(&rest.json).unwrap()).collect();
+        assert!(rest.next_cursor.is_none());
+        assert!(exported.iter().any(|a| a.principal_id == "import_new"));
+        assert!(exported.iter().any(|a| a.principal_id == "import_existing"));
+    }
+}
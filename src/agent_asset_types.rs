@@ -1,14 +1,23 @@
 use candid::{CandidType, Decode, Encode};
+use ic_cdk::api::management_canister::http_request::{
+    http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod, TransformContext,
+};
 use ic_stable_structures::storable::Bound;
 use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableVec};
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::cell::RefCell;
-use crate::stable_mem_storage::{AGENT_ITEMS, USER_AGENT_INDEX};
+use std::collections::{BTreeSet, HashMap};
+use crate::stable_mem_storage::{AGENT_ITEMS, USER_AGENT_INDEX, AGENT_TRIGRAM_INDEX, AGENT_MCP_INDEX};
+use crate::aio_protocal_types::StringVec;
+use crate::types::{CursorPage, InstructionGuard, PagedResult, clamp_page_limit, cursor_start_index, time};
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
+const AGENT_RESPONSE_MAX_BYTES: u64 = 64 * 1024;
+const MAX_AGENT_METHOD_LEN: usize = 128;
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum Platform {
     Windows,
@@ -30,7 +39,50 @@ pub struct AgentItem {
     pub output_example: Option<String>,
     pub image_url: Option<String>,
     pub exec_file_url: Option<String>,
-    pub version: String
+    pub version: String,
+    pub deleted_at: Option<u64>, // Set when soft-deleted; hidden from regular reads
+    pub mcp_dependencies: Vec<String>, // Names of MCPs this agent relies on
+}
+
+// AgentItem layout before mcp_dependencies was added, used to decode older records
+#[derive(CandidType, Deserialize)]
+struct LegacyAgentItem {
+    id: u64,
+    name: String,
+    description: String,
+    author: String,
+    owner: String,
+    platform: Option<Platform>,
+    git_repo: String,
+    homepage: Option<String>,
+    input_params: Option<String>,
+    output_example: Option<String>,
+    image_url: Option<String>,
+    exec_file_url: Option<String>,
+    version: String,
+    deleted_at: Option<u64>,
+}
+
+impl From<LegacyAgentItem> for AgentItem {
+    fn from(item: LegacyAgentItem) -> Self {
+        Self {
+            id: item.id,
+            name: item.name,
+            description: item.description,
+            author: item.author,
+            owner: item.owner,
+            platform: item.platform,
+            git_repo: item.git_repo,
+            homepage: item.homepage,
+            input_params: item.input_params,
+            output_example: item.output_example,
+            image_url: item.image_url,
+            exec_file_url: item.exec_file_url,
+            version: item.version,
+            deleted_at: item.deleted_at,
+            mcp_dependencies: Vec::new(),
+        }
+    }
 }
 
 // Define the key for user data association
@@ -53,13 +105,36 @@ impl ic_stable_structures::Storable for UserAgentKey {
     const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false };
 }
 
+// Full-text index entry: one per (trigram, agent) pair, so an agent's trigrams are added and
+// removed without rewriting the id lists of popular trigrams
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AgentTrigramKey {
+    pub trigram: String,
+    pub agent_id: u64,
+}
+
+impl ic_stable_structures::Storable for AgentTrigramKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.trigram, &self.agent_id).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let (trigram, agent_id) = Decode!(bytes.as_ref(), String, u64).unwrap();
+        Self { trigram, agent_id }
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 128, is_fixed_size: false };
+}
+
 impl ic_stable_structures::Storable for AgentItem {
     fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
 
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
+        Decode!(bytes.as_ref(), Self)
+            .or_else(|_| Decode!(bytes.as_ref(), LegacyAgentItem).map(AgentItem::from))
+            .unwrap()
     }
 
     // Define a concrete bound instead of Unbounded
@@ -68,6 +143,7 @@ impl ic_stable_structures::Storable for AgentItem {
 
 /// Add a new agent item to the storage
 pub fn add_agent_item(mut agent: AgentItem) -> Result<u64, String> {
+    agent.mcp_dependencies = validate_mcp_dependencies(&agent.mcp_dependencies)?;
     AGENT_ITEMS.with(|items| {
         let items = items.borrow_mut(); // Removed mut from items
         let total_items = items.len();
@@ -83,7 +159,10 @@ pub fn add_agent_item(mut agent: AgentItem) -> Result<u64, String> {
         // If name is unique, add the new agent
         let index = items.len();
         agent.id = index;
+        agent.deleted_at = None;
         items.push(&agent).unwrap();
+        index_agent_trigrams(index, &agent);
+        index_agent_mcps(index, &agent.mcp_dependencies);
         
         // Create owner index entry
         USER_AGENT_INDEX.with(|user_index| {
@@ -99,12 +178,69 @@ pub fn add_agent_item(mut agent: AgentItem) -> Result<u64, String> {
     })
 }
 
-/// Get an agent item by index
+/// Maximum number of agents accepted by `batch_add_agent_items`
+pub const MAX_AGENT_BATCH: usize = 50;
+
+/// Add several agents for one owner, all or nothing. Items are validated up front;
+/// if an insert still fails, the agents added by this batch are removed again.
+pub fn batch_add_agent_items(agents: Vec<AgentItem>, principalid: String) -> Result<Vec<u64>, String> {
+    if agents.is_empty() {
+        return Err("Batch is empty".to_string());
+    }
+    if agents.len() > MAX_AGENT_BATCH {
+        return Err(format!("Batch cannot contain more than {} agents", MAX_AGENT_BATCH));
+    }
+
+    let existing: Vec<String> = get_all_agent_items_admin(true).into_iter().map(|a| a.name).collect();
+    let mut seen: Vec<&str> = Vec::new();
+    for agent in &agents {
+        if agent.name.trim().is_empty() {
+            return Err("Agent name cannot be empty".to_string());
+        }
+        if existing.contains(&agent.name) || seen.contains(&agent.name.as_str()) {
+            return Err(format!("Agent with name '{}' already exists", agent.name));
+        }
+        seen.push(&agent.name);
+    }
+
+    let mut added = Vec::new();
+    for agent in agents {
+        let mut agent = agent;
+        agent.owner = principalid.clone();
+        match add_agent_item(agent) {
+            Ok(index) => added.push(index),
+            Err(e) => {
+                rollback_agent_items(&added);
+                return Err(e);
+            }
+        }
+    }
+    Ok(added)
+}
+
+// Undo agents appended by a failed batch; they are always the tail of the vector
+fn rollback_agent_items(indices: &[u64]) {
+    for &index in indices.iter().rev() {
+        let item = AGENT_ITEMS.with(|items| {
+            let items = items.borrow_mut();
+            if index + 1 == items.len() { items.pop() } else { None }
+        });
+        if let Some(item) = item {
+            remove_agent_trigrams(index, &item);
+            remove_agent_mcps(index, &item.mcp_dependencies);
+            USER_AGENT_INDEX.with(|user_index| {
+                user_index.borrow_mut().remove(&UserAgentKey { owner: item.owner.clone(), item_id: index });
+            });
+        }
+    }
+}
+
+/// Get an agent item by index (soft-deleted items are hidden)
 pub fn get_agent_item(index: u64) -> Option<AgentItem> {
     AGENT_ITEMS.with(|items| {
         let items = items.borrow();
         if index < items.len() {
-            Some(items.get(index).unwrap())
+            Some(items.get(index).unwrap()).filter(|item| item.deleted_at.is_none())
         } else {
             None
         }
@@ -113,16 +249,111 @@ pub fn get_agent_item(index: u64) -> Option<AgentItem> {
 
 /// Get all agent items
 pub fn get_all_agent_items() -> Vec<AgentItem> {
+    get_all_agent_items_admin(false)
+}
+
+/// Get all agent items, optionally including soft-deleted ones
+pub fn get_all_agent_items_admin(include_deleted: bool) -> Vec<AgentItem> {
     AGENT_ITEMS.with(|items| {
         let items = items.borrow();
         let mut result = Vec::new();
         for i in 0..items.len() {
-            result.push(items.get(i).unwrap());
+            let item = items.get(i).unwrap();
+            if include_deleted || item.deleted_at.is_none() {
+                result.push(item);
+            }
         }
         result
     })
 }
 
+/// Agent items as in `get_all_agent_items_admin`, read only while the instruction budget lasts.
+/// Cursors are the index of the last agent read; an unparsable one reads nothing
+pub fn get_all_agent_items_guarded(include_deleted: bool, guard: &InstructionGuard) -> PagedResult<AgentItem> {
+    AGENT_ITEMS.with(|items| {
+        let items = items.borrow();
+        let start = cursor_start_index(&guard.start_after()).unwrap_or(u64::MAX);
+        let entries = (start..items.len()).filter_map(|index| items.get(index).map(|item| (index.to_string(), item)));
+        guard.collect_matching(entries, |item| include_deleted || item.deleted_at.is_none())
+    })
+}
+
+/// Owner of an agent item, including soft-deleted ones
+pub fn get_agent_owner(index: u64) -> Option<String> {
+    AGENT_ITEMS.with(|items| items.borrow().get(index).map(|item| item.owner))
+}
+
+/// Soft-delete an agent item by setting its deleted_at timestamp; linked agent contacts are marked deleted
+pub fn delete_soft(index: u64) -> Result<(), String> {
+    AGENT_ITEMS.with(|items| {
+        let items = items.borrow_mut();
+        let mut item = items.get(index).ok_or_else(|| "Index out of bounds".to_string())?;
+        if item.deleted_at.is_some() {
+            return Err("Agent item is already deleted".to_string());
+        }
+        item.deleted_at = Some(time());
+        items.set(index, &item);
+        Ok(())
+    })?;
+    crate::society_profile_types::sync_agent_contacts(index, None);
+    Ok(())
+}
+
+/// Restore a soft-deleted agent item
+pub fn restore_agent_item(index: u64) -> Result<(), String> {
+    let item = AGENT_ITEMS.with(|items| {
+        let items = items.borrow_mut();
+        let mut item = items.get(index).ok_or_else(|| "Index out of bounds".to_string())?;
+        if item.deleted_at.is_none() {
+            return Err("Agent item is not deleted".to_string());
+        }
+        if item.name.is_empty() {
+            return Err("Agent item has been purged".to_string());
+        }
+        item.deleted_at = None;
+        items.set(index, &item);
+        Ok(item)
+    })?;
+    crate::society_profile_types::sync_agent_contacts(index, Some(&item));
+    Ok(())
+}
+
+/// Scrub agent tombstones deleted before the cutoff. Slots stay in place because the
+/// vector index is the agent id; the record is emptied and dropped from all indices.
+pub fn purge_deleted_older_than(cutoff_ns: u64) -> u64 {
+    let expired: Vec<AgentItem> = get_all_agent_items_admin(true)
+        .into_iter()
+        .filter(|item| !item.name.is_empty() && item.deleted_at.is_some_and(|t| t < cutoff_ns))
+        .collect();
+
+    for item in &expired {
+        remove_agent_trigrams(item.id, item);
+        remove_agent_mcps(item.id, &item.mcp_dependencies);
+        USER_AGENT_INDEX.with(|user_index| {
+            user_index.borrow_mut().remove(&UserAgentKey { owner: item.owner.clone(), item_id: item.id });
+        });
+        let tombstone = AgentItem {
+            id: item.id,
+            name: String::new(),
+            description: String::new(),
+            author: String::new(),
+            owner: String::new(),
+            platform: None,
+            git_repo: String::new(),
+            homepage: None,
+            input_params: None,
+            output_example: None,
+            image_url: None,
+            exec_file_url: None,
+            version: String::new(),
+            deleted_at: item.deleted_at,
+            mcp_dependencies: Vec::new(),
+        };
+        AGENT_ITEMS.with(|items| items.borrow_mut().set(item.id, &tombstone));
+    }
+    expired.len() as u64
+}
+
 /// Get all agent items owned by a specific user
 pub fn get_user_agent_items(owner: String) -> Vec<AgentItem> {
     let mut result = Vec::new();
@@ -145,12 +376,16 @@ pub fn get_user_agent_items(owner: String) -> Vec<AgentItem> {
     result
 }
 
-/// Update an existing agent item
+/// Update an existing agent item and refresh the contacts linked to it
 pub fn update_agent_item(index: u64, mut agent: AgentItem) -> Result<(), String> {
+    agent.mcp_dependencies = validate_mcp_dependencies(&agent.mcp_dependencies)?;
     AGENT_ITEMS.with(|items| {
         let items = items.borrow_mut(); // Removed mut from items
         if index < items.len() {
             let existing = items.get(index).unwrap();
+            if existing.deleted_at.is_some() {
+                return Err("Agent item has been deleted".to_string());
+            }
             
             // Check if the caller is the owner
             if existing.owner != agent.owner {
@@ -159,36 +394,194 @@ pub fn update_agent_item(index: u64, mut agent: AgentItem) -> Result<(), String>
             
             // Keep the ID and owner
             agent.id = index;
+            agent.deleted_at = None;
             
             items.set(index, &agent);
+            remove_agent_trigrams(index, &existing);
+            index_agent_trigrams(index, &agent);
+            remove_agent_mcps(index, &existing.mcp_dependencies);
+            index_agent_mcps(index, &agent.mcp_dependencies);
             Ok(())
         } else {
             Err("Index out of bounds".to_string())
         }
-    })
+    })?;
+    crate::society_profile_types::sync_agent_contacts(index, Some(&agent));
+    Ok(())
+}
+
+// Trim and dedup declared MCP names, rejecting any that are not stored
+fn validate_mcp_dependencies(names: &[String]) -> Result<Vec<String>, String> {
+    let mut deps: Vec<String> = Vec::new();
+    for name in names {
+        let name = name.trim().to_string();
+        if name.is_empty() || deps.contains(&name) {
+            continue;
+        }
+        if crate::mcp_asset_types::get_mcp_item(name.clone()).is_none() {
+            return Err(format!("MCP dependency '{}' not found", name));
+        }
+        deps.push(name);
+    }
+    Ok(deps)
+}
+
+fn index_agent_mcps(index: u64, mcp_names: &[String]) {
+    let id = index.to_string();
+    AGENT_MCP_INDEX.with(|mcp_index| {
+        let mut mcp_index = mcp_index.borrow_mut();
+        for name in mcp_names {
+            let mut ids = mcp_index.get(name).unwrap_or(StringVec(Vec::new()));
+            if !ids.0.contains(&id) {
+                ids.0.push(id.clone());
+                mcp_index.insert(name.clone(), ids);
+            }
+        }
+    });
+}
+
+fn remove_agent_mcps(index: u64, mcp_names: &[String]) {
+    let id = index.to_string();
+    AGENT_MCP_INDEX.with(|mcp_index| {
+        let mut mcp_index = mcp_index.borrow_mut();
+        for name in mcp_names {
+            if let Some(mut ids) = mcp_index.get(name) {
+                ids.0.retain(|existing| existing != &id);
+                if ids.0.is_empty() {
+                    mcp_index.remove(name);
+                } else {
+                    mcp_index.insert(name.clone(), ids);
+                }
+            }
+        }
+    });
+}
+
+/// MCP items an agent declares as dependencies (missing or deleted MCPs are skipped)
+pub fn get_mcps_for_agent(agent_index: u64) -> Vec<crate::mcp_asset_types::McpItem> {
+    get_agent_item(agent_index)
+        .map(|agent| {
+            agent.mcp_dependencies.into_iter()
+                .filter_map(crate::mcp_asset_types::get_mcp_item)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Live agents that declare the given MCP as a dependency
+pub fn get_agents_for_mcp(mcp_name: String) -> Vec<AgentItem> {
+    let ids = AGENT_MCP_INDEX.with(|mcp_index| mcp_index.borrow().get(&mcp_name).map(|ids| ids.0).unwrap_or_default());
+    ids.iter()
+        .filter_map(|id| id.parse::<u64>().ok())
+        .filter_map(get_agent_item)
+        .collect()
+}
+
+/// Split text into lowercase character trigrams; each word is padded with spaces
+/// so that prefixes, suffixes and two-letter words still produce trigrams
+fn trigrams(text: &str) -> BTreeSet<String> {
+    let mut result = BTreeSet::new();
+    for word in text.to_lowercase().split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        let chars: Vec<char> = format!(" {} ", word).chars().collect();
+        for window in chars.windows(3) {
+            result.insert(window.iter().collect());
+        }
+    }
+    result
+}
+
+// Searchable text of an agent (AgentItem has no tag field; author is indexed instead)
+fn agent_trigrams(agent: &AgentItem) -> BTreeSet<String> {
+    trigrams(&format!("{} {} {}", agent.name, agent.description, agent.author))
+}
+
+fn index_agent_trigrams(index: u64, agent: &AgentItem) {
+    AGENT_TRIGRAM_INDEX.with(|trigram_index| {
+        let mut trigram_index = trigram_index.borrow_mut();
+        for trigram in agent_trigrams(agent) {
+            trigram_index.insert(AgentTrigramKey { trigram, agent_id: index }, ());
+        }
+    });
+}
+
+fn remove_agent_trigrams(index: u64, agent: &AgentItem) {
+    AGENT_TRIGRAM_INDEX.with(|trigram_index| {
+        let mut trigram_index = trigram_index.borrow_mut();
+        for trigram in agent_trigrams(agent) {
+            trigram_index.remove(&AgentTrigramKey { trigram, agent_id: index });
+        }
+    });
+}
+
+/// Rebuild the trigram index from all stored agents (e.g. for agents added before the index existed)
+pub fn rebuild_agent_trigram_index() -> u64 {
+    AGENT_TRIGRAM_INDEX.with(|trigram_index| {
+        let mut trigram_index = trigram_index.borrow_mut();
+        let keys: Vec<AgentTrigramKey> = trigram_index.iter().map(|(k, _)| k).collect();
+        for key in keys {
+            trigram_index.remove(&key);
+        }
+    });
+    let agents = get_all_agent_items();
+    for agent in &agents {
+        index_agent_trigrams(agent.id, agent);
+    }
+    agents.len() as u64
+}
+
+/// Build the trigram index on upgrade when it is still empty (it moved to per-pair entries)
+pub fn seed_agent_trigram_index() {
+    if AGENT_TRIGRAM_INDEX.with(|trigram_index| trigram_index.borrow().is_empty()) {
+        rebuild_agent_trigram_index();
+    }
+}
+
+/// Search agents by name, description and author, ranked by the number of shared trigrams
+pub fn search_agents_full_text(query: String, limit: usize) -> Vec<AgentItem> {
+    let mut scores: HashMap<u64, usize> = HashMap::new();
+    AGENT_TRIGRAM_INDEX.with(|trigram_index| {
+        let trigram_index = trigram_index.borrow();
+        for trigram in trigrams(&query) {
+            let start = AgentTrigramKey { trigram: trigram.clone(), agent_id: 0 };
+            for (key, _) in trigram_index.range(start..).take_while(|(key, _)| key.trigram == trigram) {
+                *scores.entry(key.agent_id).or_insert(0) += 1;
+            }
+        }
+    });
+
+    let mut ranked: Vec<(u64, usize)> = scores.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranked.into_iter()
+        .take(limit)
+        .filter_map(|(id, _)| get_agent_item(id))
+        .collect()
 }
 
 /// Get agent items with pagination
 pub fn get_agent_items_paginated(offset: u64, limit: usize) -> Vec<AgentItem> {
     AGENT_ITEMS.with(|items| {
         let items = items.borrow();
-        let total_items = items.len();
-        
-        // If offset is beyond the end, return empty vec
-        if offset >= total_items {
-            return Vec::new();
-        }
-        
-        // Calculate the end index
-        let end = std::cmp::min(offset + limit as u64, total_items);
-        
-        // Collect the items in the range
-        let mut result = Vec::new();
-        for i in offset..end {
-            result.push(items.get(i).unwrap());
-        }
-        
-        result
+        (0..items.len())
+            .filter_map(|i| items.get(i))
+            .filter(|item| item.deleted_at.is_none())
+            .skip(offset as usize)
+            .take(limit)
+            .collect()
+    })
+}
+
+/// Get agent items after the given cursor (the index of the last item already seen)
+pub fn get_agent_items_paginated_cursor(cursor: Option<String>, limit: usize) -> Result<CursorPage<AgentItem>, String> {
+    let start = cursor_start_index(&cursor)?;
+    let limit = clamp_page_limit(limit);
+    AGENT_ITEMS.with(|items| {
+        let items = items.borrow();
+        let entries = (start..items.len())
+            .filter_map(|i| items.get(i).map(|item| (i.to_string(), item)))
+            .filter(|(_, item)| item.deleted_at.is_none())
+            .take(limit + 1)
+            .collect();
+        Ok(CursorPage::from_entries(entries, limit))
     })
 }
 
@@ -199,10 +592,284 @@ pub fn get_agent_item_by_name(name: String) -> Option<AgentItem> {
         let items = items.borrow();
         for i in 0..items.len() {
             let item = items.get(i).unwrap();
-            if item.name == name {
+            if item.name == name && item.deleted_at.is_none() {
                 return Some(item);
             }
         }
         None
     })
 }
+
+/// Credit service name an invocation of `agent_name` is charged under
+fn agent_invoke_service(agent_name: &str) -> String {
+    format!("agent_invoke/{}", agent_name)
+}
+
+/// Resolve the URL to call and the fee to charge for an invocation, checking that the
+/// agent has an HTTPS endpoint and the caller's account can pay
+pub fn prepare_agent_invocation(caller: &str, agent_name: &str, method: &str) -> Result<(String, u64), String> {
+    let method = method.trim_matches('/');
+    if method.is_empty() || method.len() > MAX_AGENT_METHOD_LEN
+        || !method.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '/')) {
+        return Err("Method must be 1-128 characters of letters, digits, '_', '-' or '/'".to_string());
+    }
+    let agent = get_agent_item_by_name(agent_name.to_string()).ok_or("Agent not found")?;
+    let endpoint = agent.exec_file_url
+        .map(|url| url.trim().trim_end_matches('/').to_string())
+        .filter(|url| url.starts_with("https://"))
+        .ok_or("Agent has no HTTPS endpoint")?;
+
+    let account = crate::account_storage::get_account(caller.to_string()).ok_or("Account not found")?;
+    let fee = crate::token_economy::compute_credit_fee(
+        &agent_invoke_service(agent_name),
+        crate::settings::get_u64(crate::settings::AGENT_INVOKE_FEE),
+    );
+    if account.get_credit_balance() < fee {
+        return Err(format!("Invoking an agent costs {} credits", fee));
+    }
+    Ok((format!("{}/{}", endpoint, method), fee))
+}
+
+/// POST `payload` to `{endpoint}/{method}` of a registered agent and record the exchange as a trace.
+/// The fee is debited before the outcall, so concurrent calls cannot spend the same credits, and
+/// refunded when no response arrives. The trace id is sent as the `Idempotency-Key` header.
+pub async fn invoke_agent(caller: String, agent_name: String, method: String, payload: String) -> Result<String, String> {
+    let (url, fee) = prepare_agent_invocation(&caller, &agent_name, &method)?;
+    let service = agent_invoke_service(&agent_name);
+    crate::token_economy::use_credits(
+        caller.clone(),
+        crate::settings::get_u64(crate::settings::AGENT_INVOKE_FEE),
+        service.clone(),
+        Some(format!("invoke_agent {}", agent_name)),
+    )?;
+    let trace_id = crate::trace_storage::generate_trace_id(&caller);
+
+    let arg = CanisterHttpRequestArgument {
+        url,
+        method: HttpMethod::POST,
+        headers: vec![
+            HttpHeader { name: "Content-Type".into(), value: "application/json".into() },
+            HttpHeader { name: "Idempotency-Key".into(), value: trace_id.clone() },
+        ],
+        body: Some(payload.clone().into_bytes()),
+        max_response_bytes: Some(AGENT_RESPONSE_MAX_BYTES),
+        transform: Some(TransformContext::from_name("transform".to_string(), vec![])),
+    };
+    let cycles = crate::http_outcall::outcall_cycles(&arg);
+    let result = match http_request(arg, cycles).await {
+        Ok((resp,)) => {
+            let body = String::from_utf8_lossy(&resp.body).to_string();
+            if resp.status >= 200u16 && resp.status < 300u16 {
+                Ok(body)
+            } else {
+                Err((format!("Agent responded with status {}", resp.status), Some(body)))
+            }
+        }
+        Err((code, msg)) => Err((format!("Agent call failed: {:?} {}", code, msg), None)),
+    };
+
+    let (output, status, error_message) = match &result {
+        Ok(body) => (crate::trace_storage::IOValueType::Text(body.clone()), "ok", None),
+        Err((error, Some(body))) => (crate::trace_storage::IOValueType::Text(body.clone()), "error", Some(error.clone())),
+        Err((error, None)) => (crate::trace_storage::IOValueType::Null, "error", Some(error.clone())),
+    };
+    if let Err(e) = crate::trace_storage::record_trace_call(
+        trace_id.clone(),
+        caller.clone(),
+        "http".to_string(),
+        agent_name.clone(),
+        "invoke_agent".to_string(),
+        method,
+        crate::trace_storage::IOValue { data_type: "text".to_string(), value: crate::trace_storage::IOValueType::Text(payload) },
+        crate::trace_storage::IOValue { data_type: "text".to_string(), value: output },
+        status.to_string(),
+        error_message,
+    ) {
+        ic_cdk::println!("Failed to record trace for agent {}: {}", agent_name, e);
+    }
+
+    if matches!(result, Err((_, None))) {
+        crate::token_economy::refund_credits(caller, fee, service, Some(format!("invoke_agent {} transport failure, trace {}", agent_name, trace_id)))?;
+    }
+    result.map_err(|(error, _)| error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_agent(name: &str, description: &str) -> AgentItem {
+        AgentItem {
+            id: 0,
+            name: name.to_string(),
+            description: description.to_string(),
+            author: "tester".to_string(),
+            owner: "owner".to_string(),
+            platform: None,
+            git_repo: "https://github.com/example/agent".to_string(),
+            homepage: None,
+            input_params: None,
+            output_example: None,
+            image_url: None,
+            exec_file_url: None,
+            version: "1.0.0".to_string(),
+            deleted_at: None,
+            mcp_dependencies: Vec::new(),
+        }
+    }
+
+    fn add_test_mcp(name: &str) {
+        crate::mcp_asset_types::add_mcp_item(crate::mcp_asset_types::McpItem {
+            name: name.to_string(),
+            description: "Dependency".to_string(),
+            author: "tester".to_string(),
+            git_repo: "https://github.com/example/mcp".to_string(),
+            mcp_type: "stdio".to_string(),
+            ..Default::default()
+        }, "owner".to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_prepare_agent_invocation_checks_endpoint_and_credits() {
+        add_agent_item(test_agent("offline_agent", "No endpoint")).unwrap();
+        let mut agent = test_agent("remote_agent", "Served over HTTPS");
+        agent.exec_file_url = Some("https://agent.example.com/api/".to_string());
+        add_agent_item(agent).unwrap();
+
+        assert_eq!(prepare_agent_invocation("payer", "remote_agent", "run").unwrap_err(), "Account not found");
+        let mut account = crate::token_economy::create_account("payer".to_string()).unwrap();
+        account.token_info.credit_balance = 5;
+        crate::account_storage::upsert_account(account.clone()).unwrap();
+        assert!(prepare_agent_invocation("payer", "remote_agent", "run").unwrap_err().contains("costs 10 credits"));
+
+        account.token_info.credit_balance = 10;
+        crate::account_storage::upsert_account(account).unwrap();
+        assert_eq!(prepare_agent_invocation("payer", "remote_agent", "/tasks/run").unwrap(),
+                   ("https://agent.example.com/api/tasks/run".to_string(), 10));
+        assert!(prepare_agent_invocation("payer", "remote_agent", "../admin?x=1").is_err());
+        assert!(prepare_agent_invocation("payer", "offline_agent", "run").is_err());
+        assert!(prepare_agent_invocation("payer", "missing_agent", "run").is_err());
+    }
+
+    #[test]
+    fn test_agent_mcp_dependencies() {
+        add_test_mcp("dep_weather");
+        add_test_mcp("dep_maps");
+
+        let mut agent = test_agent("planner", "Plans trips");
+        agent.mcp_dependencies = vec!["dep_missing".to_string()];
+        assert!(add_agent_item(agent.clone()).is_err());
+
+        agent.mcp_dependencies = vec!["dep_weather".to_string(), " dep_maps ".to_string(), "dep_weather".to_string()];
+        let index = add_agent_item(agent.clone()).unwrap();
+        let mcps: Vec<String> = get_mcps_for_agent(index).into_iter().map(|m| m.name).collect();
+        assert_eq!(mcps, vec!["dep_weather", "dep_maps"]);
+        assert_eq!(get_agents_for_mcp("dep_maps".to_string())[0].name, "planner");
+
+        agent.mcp_dependencies = vec!["dep_weather".to_string()];
+        update_agent_item(index, agent).unwrap();
+        assert!(get_agents_for_mcp("dep_maps".to_string()).is_empty());
+        assert_eq!(get_agents_for_mcp("dep_weather".to_string()).len(), 1);
+
+        delete_soft(index).unwrap();
+        assert!(get_agents_for_mcp("dep_weather".to_string()).is_empty());
+    }
+
+    #[test]
+    fn test_search_agents_full_text_ranks_by_overlap() {
+        add_agent_item(test_agent("weather_forecast", "Daily weather forecasts")).unwrap();
+        let index = add_agent_item(test_agent("image_tools", "Resize and crop images")).unwrap();
+        add_agent_item(test_agent("weatherman", "Chats about the news")).unwrap();
+
+        let results = search_agents_full_text("weather forecast".to_string(), 10);
+        assert_eq!(results[0].name, "weather_forecast");
+        assert_eq!(results[1].name, "weatherman");
+        assert_eq!(results.last().unwrap().name, "image_tools");
+
+        // Updating an agent replaces its trigrams
+        update_agent_item(index, test_agent("image_tools", "Weather radar imagery")).unwrap();
+        let results = search_agents_full_text("radar".to_string(), 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "image_tools");
+        assert!(search_agents_full_text("crop".to_string(), 10).is_empty());
+    }
+
+    #[test]
+    fn test_soft_delete_restore_and_purge_agent() {
+        let index = add_agent_item(test_agent("translator", "Translates text")).unwrap();
+
+        delete_soft(index).unwrap();
+        assert!(get_agent_item(index).is_none());
+        assert!(get_all_agent_items().is_empty());
+        assert!(search_agents_full_text("translator".to_string(), 10).is_empty());
+        assert_eq!(get_all_agent_items_admin(true).len(), 1);
+
+        restore_agent_item(index).unwrap();
+        assert_eq!(get_agent_item(index).unwrap().name, "translator");
+
+        delete_soft(index).unwrap();
+        assert_eq!(purge_deleted_older_than(1), 1);
+        assert!(get_all_agent_items_admin(true)[0].name.is_empty());
+        assert!(get_user_agent_items("owner".to_string()).is_empty());
+        assert!(restore_agent_item(index).is_err());
+    }
+
+    #[test]
+    fn test_agent_contacts_follow_agent_changes() {
+        use crate::society_profile_types::{create_agent_contact, get_agent_contacts_by_owner, upsert_contact, Contact, ContactStatus, ContactType};
+
+        let index = add_agent_item(test_agent("concierge", "Books tables")).unwrap();
+        assert!(create_agent_contact("alice".to_string(), index + 1, None).is_err());
+        create_agent_contact("alice".to_string(), index, Some("butler".to_string())).unwrap();
+
+        let contacts = get_agent_contacts_by_owner("alice".to_string());
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].contact_type, ContactType::Agent);
+        assert_eq!(contacts[0].agent_index, Some(index));
+        assert_eq!(contacts[0].name, "concierge");
+        assert!(get_agent_contacts_by_owner("bob".to_string()).is_empty());
+
+        let mut renamed = test_agent("concierge_pro", "Books tables");
+        renamed.image_url = Some("https://example.com/concierge.png".to_string());
+        update_agent_item(index, renamed).unwrap();
+        let contact = get_agent_contacts_by_owner("alice".to_string())[0].clone();
+        assert_eq!(contact.name, "concierge_pro");
+        assert_eq!(contact.avatar.as_deref(), Some("https://example.com/concierge.png"));
+        assert_eq!(contact.nickname.as_deref(), Some("butler"));
+
+        // Re-adding the contact without an agent link keeps the stored one
+        upsert_contact(Contact { agent_index: None, ..contact.clone() }).unwrap();
+        assert_eq!(get_agent_contacts_by_owner("alice".to_string())[0].agent_index, Some(index));
+
+        delete_soft(index).unwrap();
+        assert_eq!(get_agent_contacts_by_owner("alice".to_string())[0].status, ContactStatus::Deleted);
+
+        restore_agent_item(index).unwrap();
+        assert_eq!(get_agent_contacts_by_owner("alice".to_string())[0].status, ContactStatus::Active);
+    }
+
+    #[test]
+    fn test_batch_add_agent_items_is_all_or_nothing() {
+        let indices = batch_add_agent_items(
+            vec![test_agent("batch_a", "First"), test_agent("batch_b", "Second")],
+            "batch_owner".to_string(),
+        ).unwrap();
+        assert_eq!(indices, vec![0, 1]);
+        assert_eq!(get_user_agent_items("batch_owner".to_string()).len(), 2);
+
+        // A duplicate anywhere in the batch rejects the whole batch
+        let result = batch_add_agent_items(
+            vec![test_agent("batch_c", "Third"), test_agent("batch_a", "Duplicate")],
+            "batch_owner".to_string(),
+        );
+        assert!(result.is_err());
+        assert!(get_agent_item_by_name("batch_c".to_string()).is_none());
+
+        let too_many = (0..=MAX_AGENT_BATCH).map(|i| test_agent(&format!("bulk_{}", i), "Bulk")).collect();
+        assert!(batch_add_agent_items(too_many, "batch_owner".to_string()).is_err());
+
+        rollback_agent_items(&indices);
+        assert!(get_all_agent_items().is_empty());
+        assert!(search_agents_full_text("batch".to_string(), 10).is_empty());
+    }
+}
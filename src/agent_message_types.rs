@@ -0,0 +1,200 @@
+use candid::{CandidType, Decode, Encode};
+use ic_stable_structures::storable::Bound;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use crate::stable_mem_storage::{AGENT_MESSAGES, AGENT_MESSAGE_COUNTER};
+
+use crate::types::{clamp_page_size, time};
+
+const MAX_AGENT_MESSAGE_PAYLOAD: usize = 16 * 1024;
+
+/// Credit service name sending a message is charged under
+const AGENT_MESSAGE_SERVICE: &str = "agent_message";
+
+/// A message from one agent to another. Dequeuing marks it consumed and removes it from
+/// the queue, so queued messages are never consumed.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AgentMessage {
+    pub id: u64,
+    pub sender_agent: String,
+    pub receiver_agent: String,
+    pub payload: String,
+    pub priority: u8,               // 255 is delivered first
+    pub created_at: u64,
+    pub consumed: bool,
+}
+
+/// Queue key: each receiver's messages form a contiguous range; `rank` is 255 - priority, so
+/// higher priorities sort first and ids keep FIFO order within one
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AgentMessageKey {
+    pub receiver_agent: String,
+    pub rank: u8,
+    pub id: u64,
+}
+
+impl ic_stable_structures::Storable for AgentMessage {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode AgentMessage"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode AgentMessage")
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 20 * 1024, is_fixed_size: false };
+}
+
+impl ic_stable_structures::Storable for AgentMessageKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode AgentMessageKey"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode AgentMessageKey")
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false };
+}
+
+// The caller must own the named agent to send or read its messages
+fn require_agent_owner(caller: &str, agent_name: &str) -> Result<(), String> {
+    let agent = crate::agent_asset_types::get_agent_item_by_name(agent_name.to_string())
+        .ok_or_else(|| format!("Agent '{}' not found", agent_name))?;
+    if agent.owner != caller {
+        return Err(format!("Only the owner of agent '{}' can do this", agent_name));
+    }
+    Ok(())
+}
+
+/// Queue a message from `sender` (owned by the caller) to `receiver`. The caller pays the
+/// per-message fee before the message is stored. Returns the message id.
+pub fn enqueue_agent_message(caller: &str, sender: String, receiver: String, payload: String, priority: u8) -> Result<u64, String> {
+    if payload.is_empty() || payload.len() > MAX_AGENT_MESSAGE_PAYLOAD {
+        return Err(format!("Payload must be between 1 and {} bytes", MAX_AGENT_MESSAGE_PAYLOAD));
+    }
+    require_agent_owner(caller, &sender)?;
+    if crate::agent_asset_types::get_agent_item_by_name(receiver.clone()).is_none() {
+        return Err(format!("Agent '{}' not found", receiver));
+    }
+
+    let fee = crate::settings::get_u64(crate::settings::AGENT_MESSAGE_FEE);
+    crate::token_economy::use_credits(
+        caller.to_string(),
+        fee,
+        AGENT_MESSAGE_SERVICE.to_string(),
+        Some(format!("message {} -> {}", sender, receiver)),
+    )?;
+
+    let id = AGENT_MESSAGE_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        let id = *counter.get();
+        counter.set(id + 1).expect("Failed to update agent message counter");
+        id
+    });
+    AGENT_MESSAGES.with(|messages| {
+        messages.borrow_mut().insert(AgentMessageKey { receiver_agent: receiver.clone(), rank: 255 - priority, id }, AgentMessage {
+            id,
+            sender_agent: sender,
+            receiver_agent: receiver,
+            payload,
+            priority,
+            created_at: time(),
+            consumed: false,
+        });
+    });
+    Ok(id)
+}
+
+// Queued messages for a receiver in delivery order
+fn pending_messages(receiver_agent: &str, limit: u64) -> Vec<(AgentMessageKey, AgentMessage)> {
+    let start = AgentMessageKey { receiver_agent: receiver_agent.to_string(), rank: 0, id: 0 };
+    AGENT_MESSAGES.with(|messages| {
+        messages.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.receiver_agent == receiver_agent)
+            .take(limit as usize)
+            .collect()
+    })
+}
+
+/// Consume the highest-priority, oldest message queued for an agent owned by the caller;
+/// it is removed from the queue and returned marked consumed
+pub fn dequeue_agent_message(caller: &str, receiver_agent: String) -> Result<Option<AgentMessage>, String> {
+    require_agent_owner(caller, &receiver_agent)?;
+    let Some((key, mut message)) = pending_messages(&receiver_agent, 1).pop() else {
+        return Ok(None);
+    };
+    AGENT_MESSAGES.with(|messages| messages.borrow_mut().remove(&key));
+    message.consumed = true;
+    Ok(Some(message))
+}
+
+/// Up to `limit` messages queued for an agent owned by the caller, in delivery order, without removing them
+pub fn peek_agent_queue(caller: &str, receiver_agent: String, limit: u64) -> Result<Vec<AgentMessage>, String> {
+    require_agent_owner(caller, &receiver_agent)?;
+    Ok(pending_messages(&receiver_agent, clamp_page_size(limit))
+        .into_iter()
+        .map(|(_, message)| message)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent_asset_types::{add_agent_item, AgentItem};
+
+    fn add_agent(name: &str, owner: &str) {
+        add_agent_item(AgentItem {
+            id: 0,
+            name: name.to_string(),
+            description: "Messaging agent".to_string(),
+            author: "tester".to_string(),
+            owner: owner.to_string(),
+            platform: None,
+            git_repo: "https://github.com/example/agent".to_string(),
+            homepage: None,
+            input_params: None,
+            output_example: None,
+            image_url: None,
+            exec_file_url: None,
+            version: "1.0.0".to_string(),
+            deleted_at: None,
+            mcp_dependencies: Vec::new(),
+        }).unwrap();
+    }
+
+    #[test]
+    fn test_agent_messages_are_delivered_by_priority_and_charged() {
+        add_agent("planner", "alice");
+        add_agent("worker", "bob");
+        let mut account = crate::token_economy::create_account("alice".to_string()).unwrap();
+        account.token_info.credit_balance = 3;
+        crate::account_storage::upsert_account(account).unwrap();
+
+        assert!(enqueue_agent_message("bob", "planner".to_string(), "worker".to_string(), "hi".to_string(), 1).is_err());
+        assert!(enqueue_agent_message("alice", "planner".to_string(), "missing".to_string(), "hi".to_string(), 1).is_err());
+        assert!(enqueue_agent_message("alice", "planner".to_string(), "worker".to_string(), String::new(), 1).is_err());
+
+        let low = enqueue_agent_message("alice", "planner".to_string(), "worker".to_string(), "low".to_string(), 1).unwrap();
+        let urgent = enqueue_agent_message("alice", "planner".to_string(), "worker".to_string(), "urgent".to_string(), 200).unwrap();
+        let low_later = enqueue_agent_message("alice", "planner".to_string(), "worker".to_string(), "low later".to_string(), 1).unwrap();
+        // Each message costs one credit and the balance is now spent
+        assert_eq!(crate::account_storage::get_account("alice".to_string()).unwrap().get_credit_balance(), 0);
+        assert!(enqueue_agent_message("alice", "planner".to_string(), "worker".to_string(), "broke".to_string(), 1).is_err());
+
+        assert!(peek_agent_queue("alice", "worker".to_string(), 10).is_err());
+        let peeked = peek_agent_queue("bob", "worker".to_string(), 10).unwrap();
+        assert_eq!(peeked.iter().map(|m| m.id).collect::<Vec<_>>(), vec![urgent, low, low_later]);
+
+        assert_eq!(dequeue_agent_message("bob", "worker".to_string()).unwrap().unwrap().payload, "urgent");
+        let next = dequeue_agent_message("bob", "worker".to_string()).unwrap().unwrap();
+        assert_eq!(next.id, low);
+        assert!(next.consumed);
+        assert!(peek_agent_queue("bob", "worker".to_string(), 10).unwrap().iter().all(|m| !m.consumed));
+        assert_eq!(peek_agent_queue("bob", "worker".to_string(), 10).unwrap().len(), 1);
+        assert_eq!(AGENT_MESSAGES.with(|messages| messages.borrow().len()), 1);
+        dequeue_agent_message("bob", "worker".to_string()).unwrap();
+        assert!(dequeue_agent_message("bob", "worker".to_string()).unwrap().is_none());
+    }
+}
@@ -5,6 +5,7 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::cell::RefCell;
 use crate::stable_mem_storage::INVERTED_INDEX_STORE;
+use crate::types::InstructionGuard;
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
@@ -15,9 +16,10 @@ pub fn store_inverted_index(json_str: String) -> Result<(), String> {
     })
 }
 
-pub fn get_all_inverted_index_items() -> String {
+/// JSON of the inverted index items read while the instruction budget lasts, as a PagedResult
+pub fn get_all_inverted_index_items(guard: &InstructionGuard) -> String {
     INVERTED_INDEX_STORE.with(|store| {
-        store.borrow().get_all_items()
+        store.borrow().get_all_items_guarded(guard)
     })
 }
 
@@ -39,6 +41,12 @@ pub fn find_inverted_index_by_mcp(mcp_name: String) -> String {
     })
 }
 
+pub fn find_inverted_index_by_mcp_paginated(mcp_name: String, offset: usize, limit: usize) -> String {
+    INVERTED_INDEX_STORE.with(|store| {
+        store.borrow().find_by_mcp_name_paginated(&mcp_name, offset, limit)
+    })
+}
+
 pub fn find_inverted_index_by_confidence(min_confidence: f32) -> String {
     INVERTED_INDEX_STORE.with(|store| {
         store.borrow().find_by_confidence(min_confidence)
@@ -51,18 +59,111 @@ pub fn find_inverted_index_by_keywords(keywords: Vec<String>, min_confidence: f3
     })
 }
 
+pub fn find_inverted_index_by_keywords_and(keywords: Vec<String>, min_confidence: f32) -> String {
+    INVERTED_INDEX_STORE.with(|store| {
+        store.borrow().find_by_keywords_and(&keywords, min_confidence)
+    })
+}
+
+pub fn find_inverted_index_ranked(keywords: Vec<String>, min_confidence: f32, top_k: usize) -> String {
+    INVERTED_INDEX_STORE.with(|store| {
+        store.borrow().find_ranked(&keywords, min_confidence, top_k)
+    })
+}
+
 pub fn delete_inverted_index_by_mcp(mcp_name: String) -> Result<(), String> {
     INVERTED_INDEX_STORE.with(|store| {
         store.borrow_mut().delete_by_mcp_name(&mcp_name)
     })
 }
 
+/// Remove the entries derived from an MCP's name and description, keeping curated ones;
+/// returns how many were removed
+pub fn delete_derived_inverted_index_by_mcp(mcp_name: String) -> Result<u64, String> {
+    INVERTED_INDEX_STORE.with(|store| {
+        Ok(store.borrow_mut().delete_where(|item| item.mcp_name == mcp_name && item.standard_match == DERIVED_STANDARD_MATCH))
+    })
+}
+
+/// Remove every index entry for `keyword`, returning how many were removed
+pub fn delete_inverted_index_by_keyword(keyword: String) -> Result<u64, String> {
+    let keyword = keyword.trim();
+    if keyword.is_empty() {
+        return Err("Keyword cannot be empty".to_string());
+    }
+    INVERTED_INDEX_STORE.with(|store| {
+        Ok(store.borrow_mut().delete_where(|item| item.keyword == keyword))
+    })
+}
+
+/// Remove index entries whose MCP no longer exists, returning how many were removed.
+/// Soft-deleted MCPs keep their entries so a restore finds them intact.
+pub fn prune_inverted_index_orphans() -> Result<u64, String> {
+    let stored: std::collections::BTreeSet<String> = crate::mcp_asset_types::get_all_mcp_items_admin(true)
+        .into_iter()
+        .map(|item| item.name)
+        .collect();
+    INVERTED_INDEX_STORE.with(|store| {
+        Ok(store.borrow_mut().delete_where(|item| !stored.contains(&item.mcp_name)))
+    })
+}
+
 pub fn get_all_keywords() -> String {
     INVERTED_INDEX_STORE.with(|store| {
         store.borrow().get_all_keywords()
     })
 }
 
+// standard_match of entries built by `derive_index_entries_from_mcp`
+const DERIVED_STANDARD_MATCH: &str = "derived";
+
+// Words too common to be useful as derived keywords
+const DERIVED_STOP_WORDS: &[&str] = &[
+    "the", "and", "for", "with", "that", "this", "from", "into", "your", "are", "was", "can", "use", "using", "via", "its",
+];
+
+/// Build an inverted index JSON payload from an MCP item's name and description.
+/// Derived entries carry no method and stay below the 0.7 confidence that
+/// `find_by_keywords_strategy` requires, so they aid search without routing calls.
+pub fn derive_index_entries_from_mcp(item: &crate::mcp_asset_types::McpItem) -> String {
+    let mut seen = std::collections::BTreeSet::new();
+    let mut entries = Vec::new();
+    for (source_field, text, confidence) in [("name", &item.name, 0.6f32), ("description", &item.description, 0.5f32)] {
+        for word in text.split(|c: char| !c.is_alphanumeric()) {
+            let keyword = word.to_lowercase();
+            if keyword.chars().count() < 3 || DERIVED_STOP_WORDS.contains(&keyword.as_str()) || !seen.insert(keyword.clone()) {
+                continue;
+            }
+            entries.push(InvertedIndexItem {
+                keyword,
+                keyword_group: source_field.to_string(),
+                mcp_name: item.name.clone(),
+                method_name: String::new(),
+                source_field: source_field.to_string(),
+                confidence,
+                standard_match: DERIVED_STANDARD_MATCH.to_string(),
+            });
+        }
+    }
+    serde_json::to_string(&entries).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// Compare MCP names referenced by the inverted index with stored MCP items.
+/// Entries are `index_only:<name>` for index entries whose MCP no longer exists and
+/// `mcp_only:<name>` for MCPs that have no index entries. Soft-deleted MCPs count as
+/// existing because their index entries are only dropped when the tombstone is purged.
+pub fn audit_inverted_index() -> Vec<String> {
+    let indexed = INVERTED_INDEX_STORE.with(|store| store.borrow().get_all_mcp_names());
+    let stored: std::collections::BTreeSet<String> = crate::mcp_asset_types::get_all_mcp_items_admin(true)
+        .into_iter()
+        .map(|item| item.name)
+        .collect();
+
+    let mut report: Vec<String> = indexed.difference(&stored).map(|name| format!("index_only:{}", name)).collect();
+    report.extend(stored.difference(&indexed).map(|name| format!("mcp_only:{}", name)));
+    report
+}
+
 // add validate json str
 pub fn validate_json_str(json_str: &str) -> Result<(), String> {
     let items: Vec<InvertedIndexItem> = serde_json::from_str(json_str)
@@ -134,6 +235,16 @@ impl Default for InvertedIndexItem {
     }
 }
 
+/// An MCP matched by a ranked keyword search.
+/// `score` is the number of matched keywords plus the sum of their confidences.
+#[derive(CandidType, Clone, Debug, Serialize, SerdeDeserialize)]
+pub struct RankedIndexMatch {
+    pub mcp_name: String,
+    pub score: f32,
+    pub matched_keywords: Vec<String>,
+    pub items: Vec<InvertedIndexItem>,
+}
+
 pub struct InvertedIndexStore {
     items: StableBTreeMap<Vec<u8>, InvertedIndexItem, Memory>,
     keyword_to_docs: HashMap<String, Vec<String>>,
@@ -170,6 +281,12 @@ impl InvertedIndexStore {
             if item.standard_match.is_empty() {
                 return Err("standard_match field cannot be empty".to_string());
             }
+            // Deprecated and archived MCPs no longer accept new index entries
+            if let Some(mcp) = crate::mcp_asset_types::get_mcp_item(item.mcp_name.clone()) {
+                if !mcp.status().is_indexable() {
+                    return Err(format!("MCP '{}' is {:?} and cannot be indexed", item.mcp_name, mcp.status()));
+                }
+            }
             ic_cdk::println!("Processing item - keyword: {}, standard_match: {}", 
                 item.keyword, item.standard_match);
         }
@@ -202,6 +319,17 @@ impl InvertedIndexStore {
             "[]".to_string()
         })
     }
+
+    pub fn get_all_items_guarded(&self, guard: &InstructionGuard) -> String {
+        // Cursors are the hex encoding of the last item's key; an undecodable one restarts the scan
+        let start_after = guard.start_after().and_then(|cursor| hex::decode(cursor).ok());
+        let result = guard.collect(self.items.range(crate::types::cursor_key_range(start_after)).map(|(k, v)| (hex::encode(k), v)));
+        ic_cdk::println!("Retrieved {} items from storage, truncated={}", result.items.len(), result.truncated);
+        serde_json::to_string(&result).unwrap_or_else(|e| {
+            ic_cdk::println!("Error serializing items: {}", e);
+            "{}".to_string()
+        })
+    }
     
 
     // Find index items by keyword
@@ -217,6 +345,9 @@ impl InvertedIndexStore {
                 if v.method_name != "help" && (v.keyword.to_lowercase().contains("help") || v.keyword_group.to_lowercase().contains("help")) {
                     return None;
                 }
+                if crate::mcp_asset_types::is_mcp_hidden(&v.mcp_name) {
+                    return None;
+                }
                 
                 let key_str = String::from_utf8_lossy(&k).to_lowercase();
                 // Count how many parts of the keyword match in this item
@@ -253,7 +384,7 @@ impl InvertedIndexStore {
                 if v.method_name != "help" && (v.keyword.to_lowercase().contains("help") || v.keyword_group.to_lowercase().contains("help")) {
                     return false;
                 }
-                v.keyword_group == group
+                v.keyword_group == group && !crate::mcp_asset_types::is_mcp_hidden(&v.mcp_name)
             })
             .map(|(_, v)| v.clone())
             .collect::<Vec<_>>();
@@ -278,6 +409,22 @@ impl InvertedIndexStore {
         })
     }
 
+    // Find index items by MCP name, one page at a time
+    pub fn find_by_mcp_name_paginated(&self, mcp_name: &str, offset: usize, limit: usize) -> String {
+        let items = self.items
+            .iter()
+            .filter(|(k, _)| String::from_utf8_lossy(k).contains(&format!(":{}:", mcp_name)))
+            .skip(offset)
+            .take(limit)
+            .map(|(_, v)| v.clone())
+            .collect::<Vec<_>>();
+        ic_cdk::println!("Found {} items for MCP: {} (offset={}, limit={})", items.len(), mcp_name, offset, limit);
+        serde_json::to_string(&items).unwrap_or_else(|e| {
+            ic_cdk::println!("Error serializing items: {}", e);
+            "[]".to_string()
+        })
+    }
+
     // Find index items by confidence threshold
     pub fn find_by_confidence(&self, min_confidence: f32) -> String {
         let items = self.items
@@ -332,28 +479,125 @@ impl InvertedIndexStore {
         })
     }
 
+    // Items stored under exactly `keyword`, read from the `{keyword}:` key range
+    fn items_with_keyword<'a>(&'a self, keyword: &'a str) -> impl Iterator<Item = InvertedIndexItem> + 'a {
+        let prefix = format!("{}:", keyword).into_bytes();
+        self.items.range(prefix.clone()..)
+            .take_while(move |(k, _)| k.starts_with(&prefix))
+            .map(|(_, item)| item)
+            .filter(move |item| item.keyword == keyword)
+    }
+
+    // For each MCP, the most confident item per matched query keyword.
+    // Each keyword is looked up as given and lowercased, so stored keywords must match one of those spellings.
+    fn keyword_matches_by_mcp(&self, keywords: &[String], min_confidence: f32) -> (usize, std::collections::BTreeMap<String, std::collections::BTreeMap<String, InvertedIndexItem>>) {
+        let spellings: std::collections::BTreeMap<String, std::collections::BTreeSet<&str>> = keywords.iter()
+            .map(|k| k.trim())
+            .filter(|k| !k.is_empty())
+            .fold(std::collections::BTreeMap::new(), |mut acc, k| {
+                acc.entry(k.to_lowercase()).or_insert_with(std::collections::BTreeSet::new).insert(k);
+                acc
+            });
+
+        let mut matches: std::collections::BTreeMap<String, std::collections::BTreeMap<String, InvertedIndexItem>> = std::collections::BTreeMap::new();
+        let candidates = spellings.iter().flat_map(|(keyword, given)| {
+            let mut variants: std::collections::BTreeSet<&str> = given.clone();
+            variants.insert(keyword.as_str());
+            variants.into_iter().flat_map(move |variant| self.items_with_keyword(variant).map(move |item| (keyword.clone(), item)))
+        });
+        for (keyword, item) in candidates {
+            if item.confidence < min_confidence || crate::mcp_asset_types::is_mcp_hidden(&item.mcp_name) {
+                continue;
+            }
+            let per_keyword = matches.entry(item.mcp_name.clone()).or_default();
+            match per_keyword.get(&keyword) {
+                Some(best) if best.confidence >= item.confidence => {}
+                _ => {
+                    per_keyword.insert(keyword, item);
+                }
+            }
+        }
+        (spellings.len(), matches)
+    }
+
+    // Find index items of MCPs that match every keyword (AND semantics)
+    pub fn find_by_keywords_and(&self, keywords: &[String], min_confidence: f32) -> String {
+        let (wanted, matches) = self.keyword_matches_by_mcp(keywords, min_confidence);
+        let items: Vec<InvertedIndexItem> = if wanted == 0 {
+            Vec::new()
+        } else {
+            matches.into_values()
+                .filter(|per_keyword| per_keyword.len() == wanted)
+                .flat_map(|per_keyword| per_keyword.into_values())
+                .collect()
+        };
+        ic_cdk::println!("Found {} items matching all keywords with confidence >= {}", items.len(), min_confidence);
+        serde_json::to_string(&items).unwrap_or_else(|e| {
+            ic_cdk::println!("Error serializing items: {}", e);
+            "[]".to_string()
+        })
+    }
+
+    // Rank MCPs matching any keyword by matched keyword count plus summed confidence, best first
+    pub fn find_ranked(&self, keywords: &[String], min_confidence: f32, top_k: usize) -> String {
+        let (_, matches) = self.keyword_matches_by_mcp(keywords, min_confidence);
+        let mut ranked: Vec<RankedIndexMatch> = matches.into_iter()
+            .map(|(mcp_name, per_keyword)| {
+                let confidence_sum: f32 = per_keyword.values().map(|item| item.confidence).sum();
+                RankedIndexMatch {
+                    mcp_name,
+                    score: per_keyword.len() as f32 + confidence_sum,
+                    matched_keywords: per_keyword.keys().cloned().collect(),
+                    items: per_keyword.into_values().collect(),
+                }
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        ranked.truncate(top_k);
+
+        ic_cdk::println!("Ranked {} MCPs for keywords {:?}", ranked.len(), keywords);
+        serde_json::to_string(&ranked).unwrap_or_else(|e| {
+            ic_cdk::println!("Error serializing ranked matches: {}", e);
+            "[]".to_string()
+        })
+    }
+
+    // Distinct MCP names referenced by index items
+    pub fn get_all_mcp_names(&self) -> std::collections::BTreeSet<String> {
+        self.items.iter().map(|(_, v)| v.mcp_name).collect()
+    }
+
     // Delete all index items for a specific MCP
     pub fn delete_by_mcp_name(&mut self, mcp_name: &str) -> Result<(), String> {
+        self.delete_where(|item| item.mcp_name == mcp_name);
+        Ok(())
+    }
+
+    // Delete the index items matching `predicate`, returning how many were removed
+    pub fn delete_where(&mut self, predicate: impl Fn(&InvertedIndexItem) -> bool) -> u64 {
         let items_to_delete: Vec<Vec<u8>> = self
             .items
             .iter()
-            .filter(|(k, _)| String::from_utf8_lossy(k).ends_with(&format!(":{}", mcp_name)))
-            .map(|(k, _)| k.clone())
+            .filter(|(_, item)| predicate(item))
+            .map(|(k, _)| k)
             .collect();
 
+        let mut deleted = 0;
         for key in items_to_delete {
             if let Some(item) = self.items.remove(&key) {
                 // Update keyword to document mapping
                 if let Some(docs) = self.keyword_to_docs.get_mut(&item.keyword) {
-                    docs.retain(|doc| doc != mcp_name);
+                    if let Some(pos) = docs.iter().position(|doc| *doc == item.mcp_name) {
+                        docs.remove(pos);
+                    }
                     if docs.is_empty() {
                         self.keyword_to_docs.remove(&item.keyword);
                     }
                 }
+                deleted += 1;
             }
         }
-
-        Ok(())
+        deleted
     }
 
     // Find the most suitable index item by keywords with strategy
@@ -500,6 +744,7 @@ mod tests {
             keyword: "test".to_string(),
             keyword_group: "group1".to_string(),
             mcp_name: "mcp1".to_string(),
+            method_name: "method1".to_string(),
             source_field: "field1".to_string(),
             confidence: 0.95,
             standard_match: "exact".to_string(),
@@ -526,6 +771,7 @@ mod tests {
                 keyword: "test".to_string(),
                 keyword_group: "group1".to_string(),
                 mcp_name: "mcp1".to_string(),
+                method_name: "method1".to_string(),
                 source_field: "field1".to_string(),
                 confidence: 0.95,
                 standard_match: "exact".to_string(),
@@ -534,6 +780,7 @@ mod tests {
                 keyword: "test".to_string(),
                 keyword_group: "group1".to_string(),
                 mcp_name: "mcp2".to_string(),
+                method_name: "method1".to_string(),
                 source_field: "field1".to_string(),
                 confidence: 0.85,
                 standard_match: "partial".to_string(),
@@ -552,6 +799,42 @@ mod tests {
         assert!(items.iter().any(|item| item.standard_match == "partial"));
     }
 
+    #[test]
+    fn test_keyword_search_hides_soft_deleted_mcps() {
+        let mut store = setup_test_store();
+        let item = |mcp_name: &str| InvertedIndexItem {
+            keyword: "weather".to_string(),
+            keyword_group: "forecast".to_string(),
+            mcp_name: mcp_name.to_string(),
+            method_name: "get_weather".to_string(),
+            source_field: "description".to_string(),
+            confidence: 0.9,
+            standard_match: "exact".to_string(),
+        };
+        store.store_from_json(&serde_json::to_string(&vec![item("live_mcp"), item("deleted_mcp")]).unwrap()).unwrap();
+        for name in ["live_mcp", "deleted_mcp"] {
+            let mcp = crate::mcp_asset_types::McpItem {
+                name: name.to_string(),
+                description: "Weather MCP".to_string(),
+                author: "tester".to_string(),
+                git_repo: "https://github.com/example/weather".to_string(),
+                mcp_type: "http".to_string(),
+                ..Default::default()
+            };
+            crate::mcp_asset_types::add_mcp_item(mcp, "owner".to_string()).unwrap();
+        }
+        crate::mcp_asset_types::delete_soft("deleted_mcp".to_string()).unwrap();
+
+        let names = |json: String| serde_json::from_str::<Vec<InvertedIndexItem>>(&json).unwrap().into_iter().map(|i| i.mcp_name).collect::<Vec<_>>();
+        assert_eq!(names(store.find_by_keyword("weather")), vec!["live_mcp"]);
+        assert_eq!(names(store.find_by_keyword_group("forecast")), vec!["live_mcp"]);
+        assert_eq!(names(store.find_by_keywords_and(&["weather".to_string()], 0.5)), vec!["live_mcp"]);
+        assert_eq!(store.find_by_keywords_strategy(&["weather".to_string()]).map(|i| i.mcp_name), Some("live_mcp".to_string()));
+
+        crate::mcp_asset_types::restore_mcp_item("deleted_mcp".to_string()).unwrap();
+        assert_eq!(names(store.find_by_keyword("weather")).len(), 2);
+    }
+
     #[test]
     fn test_find_by_mcp_name_with_standard_match() {
         let mut store = setup_test_store();
@@ -561,6 +844,7 @@ mod tests {
             keyword: "test".to_string(),
             keyword_group: "group1".to_string(),
             mcp_name: "mcp1".to_string(),
+            method_name: "method1".to_string(),
             source_field: "field1".to_string(),
             confidence: 0.95,
             standard_match: "exact".to_string(),
@@ -577,6 +861,125 @@ mod tests {
         assert_eq!(items[0].standard_match, "exact");
     }
 
+    #[test]
+    fn test_find_by_mcp_name_paginated() {
+        let mut store = setup_test_store();
+
+        let test_items: Vec<InvertedIndexItem> = ["alpha", "beta", "gamma", "delta", "epsilon"]
+            .iter()
+            .map(|keyword| InvertedIndexItem {
+                keyword: keyword.to_string(),
+                keyword_group: "group1".to_string(),
+                mcp_name: "mcp1".to_string(),
+                method_name: "method1".to_string(),
+                source_field: "field1".to_string(),
+                confidence: 0.9,
+                standard_match: "exact".to_string(),
+            })
+            .collect();
+        let json_str = serde_json::to_string(&test_items).unwrap();
+        store.store_from_json(&json_str).unwrap();
+
+        let full: Vec<InvertedIndexItem> = serde_json::from_str(&store.find_by_mcp_name("mcp1")).unwrap();
+        let mut paged: Vec<InvertedIndexItem> = Vec::new();
+        let mut offset = 0;
+        loop {
+            let page: Vec<InvertedIndexItem> =
+                serde_json::from_str(&store.find_by_mcp_name_paginated("mcp1", offset, 2)).unwrap();
+            if page.is_empty() {
+                break;
+            }
+            offset += page.len();
+            paged.extend(page);
+        }
+
+        assert_eq!(full.len(), 5);
+        let full_keywords: Vec<String> = full.into_iter().map(|item| item.keyword).collect();
+        let paged_keywords: Vec<String> = paged.into_iter().map(|item| item.keyword).collect();
+        assert_eq!(paged_keywords, full_keywords);
+    }
+
+    #[test]
+    fn test_find_by_keywords_and_and_ranked() {
+        let mut store = setup_test_store();
+        let item = |keyword: &str, mcp_name: &str, confidence: f32| InvertedIndexItem {
+            keyword: keyword.to_string(),
+            keyword_group: "group1".to_string(),
+            mcp_name: mcp_name.to_string(),
+            method_name: "method1".to_string(),
+            source_field: "field1".to_string(),
+            confidence,
+            standard_match: "exact".to_string(),
+        };
+        let test_items = vec![
+            item("weather", "mcp_full", 0.9),
+            item("forecast", "mcp_full", 0.8),
+            item("weather", "mcp_half", 0.95),
+            item("weather", "mcp_weak", 0.9),
+            item("forecast", "mcp_weak", 0.3),
+        ];
+        store.store_from_json(&serde_json::to_string(&test_items).unwrap()).unwrap();
+        let keywords = vec!["Weather".to_string(), "forecast".to_string()];
+
+        let and_items: Vec<InvertedIndexItem> = serde_json::from_str(&store.find_by_keywords_and(&keywords, 0.5)).unwrap();
+        assert_eq!(and_items.len(), 2);
+        assert!(and_items.iter().all(|i| i.mcp_name == "mcp_full"));
+        let and_low: Vec<InvertedIndexItem> = serde_json::from_str(&store.find_by_keywords_and(&keywords, 0.0)).unwrap();
+        assert_eq!(and_low.len(), 4);
+        assert_eq!(store.find_by_keywords_and(&[], 0.0), "[]");
+
+        let ranked: Vec<RankedIndexMatch> = serde_json::from_str(&store.find_ranked(&keywords, 0.5, 2)).unwrap();
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].mcp_name, "mcp_full");
+        assert!((ranked[0].score - 3.7).abs() < 1e-4);
+        assert_eq!(ranked[0].matched_keywords, vec!["forecast".to_string(), "weather".to_string()]);
+        assert_eq!(ranked[1].mcp_name, "mcp_half");
+        assert!((ranked[1].score - 1.95).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_derive_index_entries_and_delete_by_mcp() {
+        let mut store = setup_test_store();
+        let mcp = crate::mcp_asset_types::McpItem {
+            name: "weather_mcp".to_string(),
+            description: "Forecast the weather for any city".to_string(),
+            ..Default::default()
+        };
+        let derived: Vec<InvertedIndexItem> = serde_json::from_str(&derive_index_entries_from_mcp(&mcp)).unwrap();
+        let keywords: Vec<&str> = derived.iter().map(|i| i.keyword.as_str()).collect();
+        assert_eq!(keywords, vec!["weather", "mcp", "forecast", "any", "city"]);
+        assert!(derived.iter().all(|i| i.mcp_name == "weather_mcp" && i.confidence < 0.7));
+
+        store.store_from_json(&derive_index_entries_from_mcp(&mcp)).unwrap();
+        store.delete_by_mcp_name("weather_mcp").unwrap();
+        assert_eq!(store.find_by_mcp_name("weather_mcp"), "[]");
+    }
+
+    #[test]
+    fn test_delete_derived_entries_keeps_curated_ones() {
+        let mcp = crate::mcp_asset_types::McpItem {
+            name: "curated_mcp".to_string(),
+            description: "Translate documents".to_string(),
+            ..Default::default()
+        };
+        let curated = InvertedIndexItem {
+            keyword: "translate".to_string(),
+            keyword_group: "language".to_string(),
+            mcp_name: "curated_mcp".to_string(),
+            method_name: "translate".to_string(),
+            source_field: "methods".to_string(),
+            confidence: 0.95,
+            standard_match: "exact".to_string(),
+        };
+        store_inverted_index(serde_json::to_string(&vec![curated]).unwrap()).unwrap();
+        store_inverted_index(derive_index_entries_from_mcp(&mcp)).unwrap();
+
+        assert_eq!(delete_derived_inverted_index_by_mcp("curated_mcp".to_string()), Ok(4));
+        let remaining: Vec<InvertedIndexItem> = serde_json::from_str(&find_inverted_index_by_mcp("curated_mcp".to_string())).unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].standard_match, "exact");
+    }
+
     #[test]
     fn test_validate_json_str() {
         // Test valid JSON
@@ -611,4 +1014,64 @@ mod tests {
         }]"#;
         assert!(validate_json_str(empty_standard_match_json).is_err());
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_audit_inverted_index_reports_orphaned_mcp() {
+        let item = InvertedIndexItem {
+            keyword: "weather".to_string(),
+            keyword_group: "forecast".to_string(),
+            mcp_name: "audit_mcp".to_string(),
+            method_name: "get_forecast".to_string(),
+            source_field: "description".to_string(),
+            confidence: 0.9,
+            standard_match: "exact".to_string(),
+        };
+        store_inverted_index(serde_json::to_string(&vec![item]).unwrap()).unwrap();
+        crate::mcp_asset_types::add_mcp_item(crate::mcp_asset_types::McpItem {
+            name: "audit_mcp".to_string(),
+            description: "Weather tools".to_string(),
+            author: "tester".to_string(),
+            git_repo: "https://github.com/example/weather".to_string(),
+            mcp_type: "stdio".to_string(),
+            ..Default::default()
+        }, "owner".to_string()).unwrap();
+        assert!(audit_inverted_index().is_empty());
+
+        // Hard delete without touching the index leaves an orphan behind
+        crate::mcp_asset_types::delete_mcp_item("audit_mcp".to_string()).unwrap();
+        assert_eq!(audit_inverted_index(), vec!["index_only:audit_mcp".to_string()]);
+    }
+
+    #[test]
+    fn test_delete_by_keyword_and_prune_orphans() {
+        crate::mcp_asset_types::add_mcp_item(crate::mcp_asset_types::McpItem {
+            name: "live_mcp".to_string(),
+            description: "Still registered".to_string(),
+            author: "tester".to_string(),
+            git_repo: "https://github.com/example/mcp".to_string(),
+            mcp_type: "stdio".to_string(),
+            ..Default::default()
+        }, "owner".to_string()).unwrap();
+        let entry = |keyword: &str, mcp_name: &str| InvertedIndexItem {
+            keyword: keyword.to_string(),
+            mcp_name: mcp_name.to_string(),
+            standard_match: "exact".to_string(),
+            ..Default::default()
+        };
+        let entries = vec![entry("stale", "live_mcp"), entry("stale", "gone_mcp"), entry("weather", "live_mcp"), entry("weather", "gone_mcp")];
+        store_inverted_index(serde_json::to_string(&entries).unwrap()).unwrap();
+
+        assert!(delete_inverted_index_by_keyword(" ".to_string()).is_err());
+        assert_eq!(delete_inverted_index_by_keyword("stale".to_string()), Ok(2));
+        assert_eq!(delete_inverted_index_by_keyword("stale".to_string()), Ok(0));
+        assert!(!get_all_keywords().contains("stale"));
+
+        assert_eq!(prune_inverted_index_orphans(), Ok(1));
+        let remaining: crate::types::PagedResult<InvertedIndexItem> =
+            serde_json::from_str(&get_all_inverted_index_items(&InstructionGuard::new(None))).unwrap();
+        assert_eq!(remaining.items.len(), 1);
+        assert_eq!(remaining.items[0].mcp_name, "live_mcp");
+        assert!(!remaining.truncated);
+        assert_eq!(prune_inverted_index_orphans(), Ok(0));
+    }
+}
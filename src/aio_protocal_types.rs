@@ -8,10 +8,81 @@ use std::borrow::Cow;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use serde_json::Value;
-use crate::stable_mem_storage::{AIO_INDICES, KEYWORD_INDEX};
+use sha2::{Sha256, Digest};
+use crate::stable_mem_storage::{AIO_INDICES, KEYWORD_INDEX, AIO_INDEX_HASHES, TRANSPORT_INDEX, METHOD_INDEX, AIO_INDEX_VERSIONS};
+use crate::types::{CursorPage, InstructionGuard, PagedResult, clamp_page_limit, cursor_key_range, MAX_PAGE_SIZE};
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
+/// A thread-local string -> ids secondary index over AIO_INDICES
+type StringIndex = std::thread::LocalKey<RefCell<StableBTreeMap<String, StringVec, Memory>>>;
+
+/// Maximum number of prior revisions kept per index, besides the current one
+const MAX_INDEX_HISTORY: usize = 10;
+
+/// Number of recently read indices kept in the heap cache
+const INDEX_CACHE_CAPACITY: usize = 64;
+
+/// Small LRU cache of recently read indices. It lives on the heap, so it is empty after
+/// an upgrade and changes made during query calls are discarded with the call.
+struct IndexCache {
+    entries: HashMap<String, AioIndex>,
+    order: std::collections::VecDeque<String>,
+}
+
+impl IndexCache {
+    fn new() -> Self {
+        Self { entries: HashMap::new(), order: std::collections::VecDeque::new() }
+    }
+
+    fn touch(&mut self, id: &str) {
+        if let Some(pos) = self.order.iter().position(|k| k == id) {
+            self.order.remove(pos);
+        }
+        self.order.push_back(id.to_string());
+    }
+
+    fn get(&mut self, id: &str) -> Option<AioIndex> {
+        let index = self.entries.get(id).cloned()?;
+        self.touch(id);
+        Some(index)
+    }
+
+    fn put(&mut self, index: AioIndex) {
+        self.touch(&index.id);
+        self.entries.insert(index.id.clone(), index);
+        while self.order.len() > INDEX_CACHE_CAPACITY {
+            if let Some(evicted) = self.order.pop_front() {
+                self.entries.remove(&evicted);
+            }
+        }
+    }
+
+    fn invalidate(&mut self, id: &str) {
+        if self.entries.remove(id).is_some() {
+            self.order.retain(|k| k != id);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+thread_local! {
+    static INDEX_CACHE: RefCell<IndexCache> = RefCell::new(IndexCache::new());
+}
+
+/// Drop every cached index; reads go back to stable memory
+pub fn clear_index_cache() {
+    INDEX_CACHE.with(|cache| cache.borrow_mut().clear());
+}
+
+fn invalidate_cached_index(id: &str) {
+    INDEX_CACHE.with(|cache| cache.borrow_mut().invalidate(id));
+}
+
 /// A wrapper around Vec<String> that implements Storable
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct StringVec(pub Vec<String>);
@@ -119,6 +190,56 @@ impl ic_stable_structures::Storable for AioIndex {
     const BOUND: Bound = Bound::Bounded { max_size: 1024 * 128, is_fixed_size: false };
 }
 
+// Parse a schema literal (enum value or default) into the property's JSON type,
+// falling back to a string when it does not parse
+fn schema_literal(property_type: &str, literal: &str) -> serde_json::Value {
+    match property_type {
+        "string" => serde_json::Value::String(literal.to_string()),
+        _ => serde_json::from_str(literal).unwrap_or_else(|_| serde_json::Value::String(literal.to_string())),
+    }
+}
+
+// Example value for one schema property, recursing into arrays and objects
+fn example_value(property: &SchemaProperty) -> serde_json::Value {
+    if let Some(first) = property.enum_values.as_ref().and_then(|values| values.first()) {
+        return schema_literal(&property.property_type, first);
+    }
+    if let Some(default) = &property.default {
+        return schema_literal(&property.property_type, default);
+    }
+    match property.property_type.as_str() {
+        "string" => serde_json::Value::String(String::new()),
+        "integer" | "number" => serde_json::json!(0),
+        "boolean" => serde_json::Value::Bool(false),
+        "array" => serde_json::Value::Array(property.items.iter().map(|item| example_value(item)).collect()),
+        "object" => serde_json::Value::Object(property.properties.iter()
+            .flatten()
+            .map(|(name, child)| (name.clone(), example_value(child)))
+            .collect()),
+        _ => serde_json::Value::Null,
+    }
+}
+
+/// (index id, version); the retained revisions of an index, numbered from 1
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AioIndexVersionKey {
+    pub id: String,
+    pub version: u32,
+}
+
+impl ic_stable_structures::Storable for AioIndexVersionKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.id, &self.version).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let (id, version) = Decode!(bytes.as_ref(), String, u32).unwrap();
+        Self { id, version }
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 512, is_fixed_size: false };
+}
+
 /// Manager for AioIndex storage and operations
 pub struct AioIndexManager;
 
@@ -142,11 +263,15 @@ impl AioIndexManager {
             ic_cdk::println!("Creating new AioIndex: id={}, description={}, keywords={:?}", 
                 id, index.description, index.keywords);
             indices.insert(id.clone(), index.clone());
+            self.store_content_hash(&id, &index);
+            self.remove_versions(&id);
+            self.store_version(&id, 1, &index);
             
             // Add to keyword indices
             for keyword in &index.keywords {
                 self.add_to_keyword_index(keyword, &id);
             }
+            self.add_to_secondary_indices(&index);
             
             Ok(())
         })
@@ -169,11 +294,66 @@ impl AioIndexManager {
             }
         });
     }
+
+    /// Helper function to add an id under `key` in a string -> ids secondary index
+    fn add_to_string_index(&self, index: &'static StringIndex, key: String, id: &str) {
+        index.with(|index| {
+            let mut index = index.borrow_mut();
+            let mut ids = index.get(&key).map(|v| v.0).unwrap_or_default();
+            if !ids.contains(&id.to_string()) {
+                ids.push(id.to_string());
+                index.insert(key, StringVec(ids));
+            }
+        });
+    }
+
+    /// Helper function to remove an id under `key` from a string -> ids secondary index
+    fn remove_from_string_index(&self, index: &'static StringIndex, key: String, id: &str) {
+        index.with(|index| {
+            let mut index = index.borrow_mut();
+            if let Some(ids) = index.get(&key) {
+                let mut new_ids = ids.0;
+                new_ids.retain(|item_id| item_id != id);
+                if new_ids.is_empty() {
+                    index.remove(&key);
+                } else {
+                    index.insert(key, StringVec(new_ids));
+                }
+            }
+        });
+    }
+
+    /// Helper function to add an index to the transport and method indices
+    fn add_to_secondary_indices(&self, index: &AioIndex) {
+        for transport in &index.transport {
+            self.add_to_string_index(&TRANSPORT_INDEX, transport.to_lowercase(), &index.id);
+        }
+        for method in &index.methods {
+            self.add_to_string_index(&METHOD_INDEX, method.name.clone(), &index.id);
+        }
+    }
+
+    /// Helper function to remove an index from the transport and method indices
+    fn remove_from_secondary_indices(&self, index: &AioIndex) {
+        for transport in &index.transport {
+            self.remove_from_string_index(&TRANSPORT_INDEX, transport.to_lowercase(), &index.id);
+        }
+        for method in &index.methods {
+            self.remove_from_string_index(&METHOD_INDEX, method.name.clone(), &index.id);
+        }
+    }
+
     pub fn read(&self, id: &str) -> Option<AioIndex> {
-        AIO_INDICES.with(|indices| {
+        if let Some(index) = INDEX_CACHE.with(|cache| cache.borrow_mut().get(id)) {
+            return Some(index);
+        }
+
+        let index = AIO_INDICES.with(|indices| {
             let indices = indices.borrow();
             indices.get(&id.to_string())
-        })
+        })?;
+        INDEX_CACHE.with(|cache| cache.borrow_mut().put(index.clone()));
+        Some(index)
     }
 
     /// Update an existing AioIndex
@@ -191,19 +371,135 @@ impl AioIndexManager {
                 for keyword in &old_index.keywords {
                     self.remove_from_keyword_index(keyword, id);
                 }
+                self.remove_from_secondary_indices(&old_index);
+
+                // Keep the prior version in history
+                if self.latest_version(id).is_none() {
+                    // Indices created before versioning get their current revision recorded as version 1
+                    self.store_version(id, 1, &old_index);
+                }
             }
+            let next_version = self.latest_version(id).unwrap_or(0) + 1;
+            self.store_version(id, next_version, &updated_index);
             
             // Add to new keywords
             for keyword in &updated_index.keywords {
                 self.add_to_keyword_index(keyword, id);
             }
+            self.add_to_secondary_indices(&updated_index);
             
             // Update the index
+            self.store_content_hash(id, &updated_index);
             indices.insert(id.to_string(), updated_index);
+            invalidate_cached_index(id);
             Ok(())
         })
     }
 
+    /// Compute the content hash of an index over its description, methods and keywords
+    pub fn content_hash(index: &AioIndex) -> String {
+        // Go through serde_json::Value so that schema property maps serialize in sorted key order
+        let content = serde_json::json!({
+            "description": index.description,
+            "methods": serde_json::to_value(&index.methods).unwrap_or(Value::Null),
+            "keywords": index.keywords,
+        });
+
+        let mut hasher = Sha256::new();
+        hasher.update(content.to_string().as_bytes());
+        hex::encode(hasher.finalize())
+    }
+
+    /// Helper function to store the content hash of an index
+    fn store_content_hash(&self, id: &str, index: &AioIndex) {
+        AIO_INDEX_HASHES.with(|hashes| {
+            hashes.borrow_mut().insert(id.to_string(), Self::content_hash(index));
+        });
+    }
+
+    /// Find pairs of index IDs whose content hashes are identical
+    pub fn find_duplicate_indices(&self) -> Vec<(String, String)> {
+        let mut ids_by_hash: std::collections::BTreeMap<String, Vec<String>> = std::collections::BTreeMap::new();
+
+        AIO_INDICES.with(|indices| {
+            for (id, index) in indices.borrow().iter() {
+                // Indices stored before hashing was introduced have no stored hash
+                let hash = AIO_INDEX_HASHES.with(|hashes| hashes.borrow().get(&id))
+                    .unwrap_or_else(|| Self::content_hash(&index));
+                ids_by_hash.entry(hash).or_default().push(id);
+            }
+        });
+
+        let mut duplicates = Vec::new();
+        for ids in ids_by_hash.values() {
+            for (i, first) in ids.iter().enumerate() {
+                for second in &ids[i + 1..] {
+                    duplicates.push((first.clone(), second.clone()));
+                }
+            }
+        }
+        duplicates
+    }
+
+    /// Get prior versions of an index, most recent first
+    pub fn get_history(&self, id: &str, limit: usize) -> Vec<AioIndex> {
+        AIO_INDEX_VERSIONS.with(|versions| {
+            versions.borrow()
+                .range(Self::version_range(id))
+                .rev()
+                .skip(1)
+                .take(limit)
+                .map(|(_, index)| index)
+                .collect()
+        })
+    }
+
+    fn version_range(id: &str) -> std::ops::RangeInclusive<AioIndexVersionKey> {
+        AioIndexVersionKey { id: id.to_string(), version: 0 }..=AioIndexVersionKey { id: id.to_string(), version: u32::MAX }
+    }
+
+    /// Store a revision, dropping the oldest ones beyond MAX_INDEX_HISTORY prior revisions
+    fn store_version(&self, id: &str, version: u32, index: &AioIndex) {
+        AIO_INDEX_VERSIONS.with(|versions| {
+            let mut versions = versions.borrow_mut();
+            versions.insert(AioIndexVersionKey { id: id.to_string(), version }, index.clone());
+            let keys: Vec<AioIndexVersionKey> = versions.range(Self::version_range(id)).map(|(key, _)| key).collect();
+            let excess = keys.len().saturating_sub(MAX_INDEX_HISTORY + 1);
+            for key in keys.into_iter().take(excess) {
+                versions.remove(&key);
+            }
+        });
+    }
+
+    fn remove_versions(&self, id: &str) {
+        for version in self.list_versions(id) {
+            AIO_INDEX_VERSIONS.with(|versions| {
+                versions.borrow_mut().remove(&AioIndexVersionKey { id: id.to_string(), version });
+            });
+        }
+    }
+
+    fn latest_version(&self, id: &str) -> Option<u32> {
+        self.list_versions(id).last().copied()
+    }
+
+    /// Version numbers retained for an index, ascending; the last one is the current revision
+    pub fn list_versions(&self, id: &str) -> Vec<u32> {
+        AIO_INDEX_VERSIONS.with(|versions| {
+            versions.borrow()
+                .range(Self::version_range(id))
+                .map(|(key, _)| key.version)
+                .collect()
+        })
+    }
+
+    /// Get one stored revision of an index
+    pub fn get_version(&self, id: &str, version: u32) -> Option<AioIndex> {
+        AIO_INDEX_VERSIONS.with(|versions| {
+            versions.borrow().get(&AioIndexVersionKey { id: id.to_string(), version })
+        })
+    }
+
     /// Delete an AioIndex by ID
     pub fn delete(&self, id: &str) -> Result<(), String> {
         AIO_INDICES.with(|indices| {
@@ -219,14 +515,25 @@ impl AioIndexManager {
                 for keyword in &index.keywords {
                     self.remove_from_keyword_index(keyword, id);
                 }
+                self.remove_from_secondary_indices(&index);
             }
             
             // Remove the index
             indices.remove(&id.to_string());
+            self.remove_versions(id);
+            invalidate_cached_index(id);
+            AIO_INDEX_HASHES.with(|hashes| {
+                hashes.borrow_mut().remove(&id.to_string());
+            });
             Ok(())
         })
     }
 
+    /// Delete several indices, returning one result per id in the given order
+    pub fn delete_many(&self, ids: &[String]) -> Vec<Result<(), String>> {
+        ids.iter().map(|id| self.delete(id)).collect()
+    }
+
     /// Helper function to remove an index from the keyword index
     fn remove_from_keyword_index(&self, keyword: &str, id: &str) {
         KEYWORD_INDEX.with(|keyword_index| {
@@ -254,6 +561,11 @@ impl AioIndexManager {
         })
     }
 
+    /// All indices in id order, read only while the instruction budget lasts
+    pub fn list_all_guarded(&self, guard: &InstructionGuard) -> PagedResult<AioIndex> {
+        AIO_INDICES.with(|indices| guard.collect(indices.borrow().range(cursor_key_range(guard.start_after()))))
+    }
+
     /// Get indices with pagination
     pub fn get_indices_paginated(&self, offset: usize, limit: usize) -> Vec<AioIndex> {
         AIO_INDICES.with(|indices| {
@@ -266,6 +578,87 @@ impl AioIndexManager {
         })
     }
 
+    /// Get indices supporting a transport (case-insensitive), with pagination
+    pub fn get_indices_by_transport(&self, transport: &str, offset: usize, limit: usize) -> Vec<AioIndex> {
+        let ids = TRANSPORT_INDEX.with(|transport_index| {
+            transport_index.borrow()
+                .get(&transport.to_lowercase())
+                .map(|v| v.0)
+                .unwrap_or_default()
+        });
+        // Hidden indices are dropped before paging so every page is full while matches remain
+        ids.iter()
+            .filter_map(|id| self.read_live(id))
+            .skip(offset)
+            .take(limit.min(MAX_PAGE_SIZE))
+            .collect()
+    }
+
+    /// All transport values with at least one index, in sorted order
+    pub fn list_transports(&self) -> Vec<String> {
+        TRANSPORT_INDEX.with(|transport_index| {
+            transport_index.borrow().iter().map(|(transport, _)| transport).collect()
+        })
+    }
+
+    /// Get indices that define a method with exactly this name
+    pub fn search_by_method(&self, method_name: &str) -> Vec<AioIndex> {
+        METHOD_INDEX.with(|method_index| method_index.borrow().get(&method_name.to_string()))
+            .map(|ids| ids.0.iter().filter_map(|id| self.read_live(id)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Get indices that define every one of the given methods; an empty list matches nothing
+    pub fn search_by_methods_all(&self, method_names: &[String]) -> Vec<AioIndex> {
+        let mut matching: Option<Vec<String>> = None;
+        METHOD_INDEX.with(|method_index| {
+            let method_index = method_index.borrow();
+            for method_name in method_names {
+                let ids = method_index.get(method_name).map(|v| v.0).unwrap_or_default();
+                matching = Some(match matching.take() {
+                    None => ids,
+                    Some(mut current) => {
+                        current.retain(|id| ids.contains(id));
+                        current
+                    }
+                });
+            }
+        });
+        matching.unwrap_or_default().iter().filter_map(|id| self.read_live(id)).collect()
+    }
+
+    /// Rebuild the transport and method indices from all stored indices
+    /// (e.g. for indices created before they existed)
+    pub fn rebuild_secondary_indices(&self) -> u64 {
+        for index in [&TRANSPORT_INDEX, &METHOD_INDEX] {
+            index.with(|index| {
+                let mut index = index.borrow_mut();
+                let keys: Vec<String> = index.iter().map(|(k, _)| k).collect();
+                for key in keys {
+                    index.remove(&key);
+                }
+            });
+        }
+        let indices = self.list_all();
+        for index in &indices {
+            self.add_to_secondary_indices(index);
+        }
+        indices.len() as u64
+    }
+
+    /// Get indices ordered by id, starting after the cursor id
+    pub fn get_indices_paginated_cursor(&self, cursor: Option<String>, limit: usize) -> CursorPage<AioIndex> {
+        let limit = clamp_page_limit(limit);
+        AIO_INDICES.with(|indices| {
+            let entries = indices
+                .borrow()
+                .range(cursor_key_range(cursor))
+                .take(limit + 1)
+                .collect();
+            CursorPage::from_entries(entries, limit)
+        })
+    }
+
     /// Helper function to recursively parse a SchemaProperty from JSON
     fn parse_schema_property(value: &Value) -> Option<Box<SchemaProperty>> {
         let obj = value.as_object()?;
@@ -323,6 +716,33 @@ impl AioIndexManager {
     }
 
     pub fn create_from_json(&self, name: &str, json_str: &str) -> Result<(), String> {
+        let aio_index = Self::parse_index_json(name, json_str)?;
+        self.validate_index(&aio_index)?;
+        self.create(aio_index)
+    }
+
+    /// Dry run of create_from_json: parse and validate without storing anything
+    pub fn validate_json(&self, name: &str, json_str: &str) -> Result<(), String> {
+        let aio_index = Self::parse_index_json(name, json_str)?;
+        self.validate_index(&aio_index)
+    }
+
+    /// Check that an index is complete enough to be stored
+    pub fn validate_index(&self, index: &AioIndex) -> Result<(), String> {
+        if index.id.trim().is_empty() {
+            return Err("Index name cannot be empty".to_string());
+        }
+        if let Some(pos) = index.methods.iter().position(|m| m.name.trim().is_empty()) {
+            return Err(format!("Method at position {} is missing a name", pos));
+        }
+        if AIO_INDICES.with(|indices| indices.borrow().contains_key(&index.id)) {
+            return Err(format!("Index with ID {} already exists", index.id));
+        }
+        Ok(())
+    }
+
+    /// Build an AioIndex from its JSON description
+    fn parse_index_json(name: &str, json_str: &str) -> Result<AioIndex, String> {
         let parsed: Value = serde_json::from_str(json_str)
             .map_err(|e| format!("JSON parsing error: {}", e))?;
         
@@ -455,8 +875,7 @@ impl AioIndexManager {
             })
             .unwrap_or_else(Vec::new);
         
-        // Create and store the index
-        let aio_index = AioIndex {
+        Ok(AioIndex {
             id: mcp_id.to_string(),
             description: description.to_string(),
             transport,
@@ -464,11 +883,14 @@ impl AioIndexManager {
             source,
             keywords,
             scenarios,
-        };
-        
-        self.create(aio_index)
+        })
     }
     
+    /// Read an index for search results; indices of soft-deleted or unpublished MCPs are hidden
+    fn read_live(&self, id: &str) -> Option<AioIndex> {
+        self.read(id).filter(|_| !crate::mcp_asset_types::is_mcp_hidden(id))
+    }
+
     /// Search for indices by keyword
     pub fn search_by_keyword(&self, keyword: &str) -> Vec<AioIndex> {
         let keyword_lower = keyword.to_lowercase();
@@ -480,7 +902,7 @@ impl AioIndexManager {
             // Check for exact keyword match
             if let Some(ids) = keyword_index.get(&keyword_lower) {
                 for id in &ids.0 {
-                    if let Some(index) = self.read(id) {
+                    if let Some(index) = self.read_live(id) {
                         result.push(index);
                     }
                 }
@@ -490,7 +912,7 @@ impl AioIndexManager {
             for (key, index_ids) in keyword_index.iter() {
                 if key.contains(&keyword_lower) && *key != keyword_lower {
                     for id in &index_ids.0 {
-                        let index = self.read(id);
+                        let index = self.read_live(id);
                         if let Some(index_item) = index {
                             if !result.iter().any(|i| i.id == index_item.id) {
                                 result.push(index_item);
@@ -509,6 +931,24 @@ impl AioIndexManager {
         let index = self.read(id).ok_or_else(|| format!("Index with ID {} not found", id))?;
         serde_json::to_string(&index).map_err(|e| format!("Failed to convert index to JSON: {}", e))
     }
+
+    /// Build a sample JSON request body for one of an index's methods from its input schema.
+    /// Each field takes its first enum value, else its default, else a placeholder for its type.
+    pub fn generate_example_input(&self, id: &str, method: &str) -> Result<String, String> {
+        let index = self.read(id).ok_or_else(|| format!("Index with ID {} not found", id))?;
+        let method = index.methods.iter()
+            .find(|m| m.name == method)
+            .ok_or_else(|| format!("Method {} not found in index {}", method, id))?;
+
+        let mut example = serde_json::Map::new();
+        if let Some(schema) = &method.input_schema {
+            for (name, property) in &schema.properties {
+                example.insert(name.clone(), example_value(property));
+            }
+        }
+        serde_json::to_string(&serde_json::Value::Object(example))
+            .map_err(|e| format!("Failed to serialize example input: {}", e))
+    }
     
     pub fn search_full_text(&self, query: &str) -> Vec<AioIndex> {
         if query.is_empty() {
@@ -523,6 +963,10 @@ impl AioIndexManager {
             let indices = indices.borrow();
             
             for (_, index) in indices.iter() {
+                if crate::mcp_asset_types::is_mcp_hidden(&index.id) {
+                    continue;
+                }
+
                 // Check ID
                 let id_match = index.id.to_lowercase().contains(&query_lower);
                 
@@ -641,9 +1085,9 @@ mod tests {
         let index = manager.read("test_id").unwrap();
         assert_eq!(index.id, "test_id");
         assert_eq!(index.description, "Test Service");
-        assert_eq!(index.author, "Test Author");
-        assert_eq!(index.version, "1.0.0");
-        assert_eq!(index.github, "https://github.com/test");
+        assert_eq!(index.source.author, "Source Author");
+        assert_eq!(index.source.version, "1.0.0");
+        assert_eq!(index.source.github, "https://github.com/source");
         assert_eq!(index.transport, vec!["http", "https"]);
         assert_eq!(index.keywords, vec!["test", "keyword"]);
         assert_eq!(index.scenarios, vec!["test scenario"]);
@@ -675,4 +1119,273 @@ mod tests {
         let name_prop = items.properties.as_ref().unwrap().get("name").unwrap();
         assert_eq!(name_prop.property_type, "string");
     }
+
+    #[test]
+    fn test_generate_example_input() {
+        let manager = AioIndexManager::new();
+        let mut properties = HashMap::new();
+        properties.insert("city".to_string(), Box::new(SchemaProperty {
+            property_type: "string".to_string(),
+            description: Some("City name".to_string()),
+            default: Some("Paris".to_string()),
+            enum_values: None,
+            items: None,
+            properties: None,
+            required: None,
+        }));
+        properties.insert("unit".to_string(), Box::new(SchemaProperty {
+            property_type: "string".to_string(),
+            description: None,
+            default: None,
+            enum_values: Some(vec!["celsius".to_string(), "fahrenheit".to_string()]),
+            items: None,
+            properties: None,
+            required: None,
+        }));
+        manager.create(AioIndex {
+            id: "example_id".to_string(),
+            methods: vec![Method {
+                name: "get_weather".to_string(),
+                description: "Current weather".to_string(),
+                required_params: Some(vec!["city".to_string()]),
+                input_schema: Some(InputSchema {
+                    schema_type: "object".to_string(),
+                    properties,
+                    required: Some(vec!["city".to_string()]),
+                }),
+            }],
+            ..Default::default()
+        }).unwrap();
+
+        let example: serde_json::Value = serde_json::from_str(
+            &manager.generate_example_input("example_id", "get_weather").unwrap()
+        ).unwrap();
+        assert_eq!(example, serde_json::json!({ "city": "Paris", "unit": "celsius" }));
+        assert!(manager.generate_example_input("example_id", "missing").is_err());
+        assert!(manager.generate_example_input("missing_id", "get_weather").is_err());
+    }
+
+    #[test]
+    fn test_update_bumps_version() {
+        let manager = AioIndexManager::new();
+        let mut index = AioIndex {
+            id: "versioned_id".to_string(),
+            description: "v1".to_string(),
+            ..AioIndex::default()
+        };
+        manager.create(index.clone()).unwrap();
+        assert_eq!(manager.list_versions("versioned_id"), vec![1]);
+
+        index.description = "v2".to_string();
+        manager.update("versioned_id", index).unwrap();
+        assert_eq!(manager.list_versions("versioned_id"), vec![1, 2]);
+        assert_eq!(manager.get_version("versioned_id", 1).unwrap().description, "v1");
+        assert_eq!(manager.get_version("versioned_id", 2).unwrap().description, "v2");
+        assert!(manager.get_version("versioned_id", 3).is_none());
+
+        manager.delete("versioned_id").unwrap();
+        assert!(manager.list_versions("versioned_id").is_empty());
+    }
+
+    #[test]
+    fn test_update_keeps_history() {
+        let manager = AioIndexManager::new();
+        let mut index = AioIndex {
+            id: "history_id".to_string(),
+            description: "v1".to_string(),
+            ..Default::default()
+        };
+        manager.create(index.clone()).unwrap();
+
+        index.description = "v2".to_string();
+        manager.update("history_id", index.clone()).unwrap();
+        index.description = "v3".to_string();
+        manager.update("history_id", index).unwrap();
+
+        let history = manager.get_history("history_id", 10);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].description, "v2");
+        assert_eq!(history[1].description, "v1");
+        assert_eq!(manager.get_history("history_id", 1).len(), 1);
+        assert_eq!(manager.read("history_id").unwrap().description, "v3");
+
+        // Only the current revision and MAX_INDEX_HISTORY prior ones are retained
+        for revision in 4..=15 {
+            let mut index = manager.read("history_id").unwrap();
+            index.description = format!("v{}", revision);
+            manager.update("history_id", index).unwrap();
+        }
+        assert_eq!(manager.list_versions("history_id"), (5..=15).collect::<Vec<u32>>());
+        let history = manager.get_history("history_id", 100);
+        assert_eq!(history.len(), MAX_INDEX_HISTORY);
+        assert_eq!(history[0].description, "v14");
+    }
+
+    #[test]
+    fn test_update_invalidates_cached_index() {
+        let manager = AioIndexManager::new();
+        let mut index = AioIndex {
+            id: "cached_id".to_string(),
+            description: "before".to_string(),
+            keywords: vec!["cache".to_string()],
+            ..Default::default()
+        };
+        manager.create(index.clone()).unwrap();
+        assert_eq!(manager.read("cached_id").unwrap().description, "before");
+        assert!(INDEX_CACHE.with(|c| c.borrow().entries.contains_key("cached_id")));
+
+        index.description = "after".to_string();
+        manager.update("cached_id", index).unwrap();
+        assert_eq!(manager.read("cached_id").unwrap().description, "after");
+        assert_eq!(manager.search_by_keyword("cache")[0].description, "after");
+
+        manager.delete("cached_id").unwrap();
+        assert!(manager.read("cached_id").is_none());
+
+        clear_index_cache();
+        assert!(INDEX_CACHE.with(|c| c.borrow().entries.is_empty()));
+    }
+
+    #[test]
+    fn test_validate_json_dry_run() {
+        let manager = AioIndexManager::new();
+        let json_str = r#"{"description": "Dry run", "methods": [{"name": "ping"}], "functional_keywords": ["dry"]}"#;
+
+        assert_eq!(manager.validate_json("dry_run", json_str), Ok(()));
+        assert!(manager.read("dry_run").is_none());
+        assert!(manager.search_by_keyword("dry").is_empty());
+
+        let err = manager.validate_json("dry_run", r#"{"description": "#).unwrap_err();
+        assert!(err.starts_with("JSON parsing error"));
+        assert_eq!(manager.validate_json("dry_run", "[1, 2]"), Err("Invalid JSON: expected object".to_string()));
+        assert_eq!(
+            manager.validate_json("dry_run", r#"{"methods": [{"description": "no name"}]}"#),
+            Err("Method at position 0 is missing a name".to_string())
+        );
+    }
+
+    #[test]
+    fn test_delete_many_reports_per_id_results() {
+        let manager = AioIndexManager::new();
+        for id in ["bulk_a", "bulk_b", "bulk_keep"] {
+            manager.create(AioIndex {
+                id: id.to_string(),
+                keywords: vec!["bulk".to_string(), id.to_string()],
+                ..Default::default()
+            }).unwrap();
+        }
+
+        let results = manager.delete_many(&["bulk_a".to_string(), "missing".to_string(), "bulk_b".to_string()]);
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert_eq!(results[1], Err("Index with ID missing does not exist".to_string()));
+        assert!(results[2].is_ok());
+
+        let remaining: Vec<String> = manager.search_by_keyword("bulk").into_iter().map(|i| i.id).collect();
+        assert_eq!(remaining, vec!["bulk_keep".to_string()]);
+        assert!(KEYWORD_INDEX.with(|k| k.borrow().get(&"bulk_a".to_string()).is_none()));
+    }
+
+    #[test]
+    fn test_find_duplicate_indices() {
+        let manager = AioIndexManager::new();
+        let content = AioIndex {
+            description: "Same content".to_string(),
+            keywords: vec!["dup".to_string()],
+            ..Default::default()
+        };
+        manager.create(AioIndex { id: "dup_a".to_string(), ..content.clone() }).unwrap();
+        manager.create(AioIndex { id: "dup_b".to_string(), ..content.clone() }).unwrap();
+        manager.create(AioIndex {
+            id: "unique".to_string(),
+            description: "Other content".to_string(),
+            ..content
+        }).unwrap();
+
+        let duplicates = manager.find_duplicate_indices();
+        assert_eq!(duplicates, vec![("dup_a".to_string(), "dup_b".to_string())]);
+    }
+
+    #[test]
+    fn test_transport_index_tracks_create_update_delete() {
+        let manager = AioIndexManager::new();
+        manager.create(AioIndex {
+            id: "tr_a".to_string(),
+            transport: vec!["stdio".to_string(), "SSE".to_string()],
+            ..Default::default()
+        }).unwrap();
+        manager.create(AioIndex {
+            id: "tr_b".to_string(),
+            transport: vec!["sse".to_string()],
+            ..Default::default()
+        }).unwrap();
+
+        let ids = |transport: &str, offset, limit| -> Vec<String> {
+            manager.get_indices_by_transport(transport, offset, limit).into_iter().map(|i| i.id).collect()
+        };
+        assert_eq!(ids("sse", 0, 10), vec!["tr_a", "tr_b"]);
+        assert_eq!(ids("SSE", 1, 10), vec!["tr_b"]);
+        assert_eq!(manager.list_transports(), vec!["sse", "stdio"]);
+
+        // Indices of a soft-deleted MCP do not take up a slot of the page
+        crate::mcp_asset_types::add_mcp_item(crate::mcp_asset_types::McpItem {
+            name: "tr_a".to_string(),
+            description: "Transport tools".to_string(),
+            author: "tester".to_string(),
+            git_repo: "https://github.com/example/transport".to_string(),
+            mcp_type: "stdio".to_string(),
+            ..Default::default()
+        }, "owner".to_string()).unwrap();
+        crate::mcp_asset_types::delete_soft("tr_a".to_string()).unwrap();
+        assert_eq!(ids("sse", 0, 1), vec!["tr_b"]);
+        crate::mcp_asset_types::restore_mcp_item("tr_a".to_string()).unwrap();
+        assert_eq!(ids("sse", 0, 1), vec!["tr_a"]);
+
+        manager.update("tr_a", AioIndex {
+            id: "tr_a".to_string(),
+            transport: vec!["http".to_string()],
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(ids("sse", 0, 10), vec!["tr_b"]);
+        assert_eq!(ids("http", 0, 10), vec!["tr_a"]);
+
+        manager.delete("tr_b").unwrap();
+        assert_eq!(manager.list_transports(), vec!["http"]);
+    }
+
+    #[test]
+    fn test_method_index_search() {
+        let manager = AioIndexManager::new();
+        let method = |name: &str| Method {
+            name: name.to_string(),
+            description: String::new(),
+            required_params: None,
+            input_schema: None,
+        };
+        manager.create(AioIndex {
+            id: "m_weather".to_string(),
+            methods: vec![method("get_forecast"), method("geocode")],
+            ..Default::default()
+        }).unwrap();
+        manager.create(AioIndex {
+            id: "m_maps".to_string(),
+            methods: vec![method("geocode")],
+            ..Default::default()
+        }).unwrap();
+
+        let ids = |indices: Vec<AioIndex>| -> Vec<String> { indices.into_iter().map(|i| i.id).collect() };
+        assert_eq!(ids(manager.search_by_method("geocode")), vec!["m_weather", "m_maps"]);
+        assert_eq!(ids(manager.search_by_methods_all(&["geocode".to_string(), "get_forecast".to_string()])), vec!["m_weather"]);
+        assert!(manager.search_by_methods_all(&[]).is_empty());
+
+        manager.update("m_weather", AioIndex {
+            id: "m_weather".to_string(),
+            methods: vec![method("get_forecast")],
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(ids(manager.search_by_method("geocode")), vec!["m_maps"]);
+
+        manager.delete("m_maps").unwrap();
+        assert!(manager.search_by_method("geocode").is_empty());
+    }
 }
@@ -156,7 +156,7 @@ pub fn get_user_traces(owner: String) -> Vec<TraceItem> {
         };
         let end_key = UserTraceKey { 
             owner: owner.clone(), 
-            trace_id: String::from_utf8(vec![0xFF; 100]).unwrap_or_default()
+            trace_id: char::MAX.to_string().repeat(100)
         };
         
         // Get all traces in range
@@ -0,0 +1,368 @@
+use candid::{CandidType, Decode, Encode};
+use ic_cdk::api::management_canister::http_request::{
+    http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod, TransformContext,
+};
+use ic_stable_structures::storable::Bound;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use crate::device_types::DeviceService;
+use crate::pixel_creation_types::{export_for_device, get_project, ProjectId, VersionId};
+use crate::stable_mem_storage::{DEVICE_PROJECT_SUBSCRIPTIONS, DEVICE_DELIVERY_QUEUE, DELIVERY_LOG};
+use crate::task_queue;
+
+use crate::types::time;
+
+/// `DeviceInfo.metadata` key holding the HTTPS URL pixel art is pushed to
+pub const DEVICE_ENDPOINT_METADATA_KEY: &str = "endpoint_url";
+
+/// Deliveries POSTed per timer tick; the rest wait for the next tick
+pub const DEVICE_DELIVERY_BATCH_SIZE: usize = 20;
+/// Pending pushes kept at most; versions saved while the queue is full are not pushed
+pub const MAX_DEVICE_DELIVERY_QUEUE_LEN: u64 = 10_000;
+/// Delivery attempts kept per device; older ones are dropped as new ones are recorded
+pub const MAX_DELIVERY_LOG_PER_DEVICE: u64 = 100;
+
+/// A device that receives every new version saved to a pixel project
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DeviceProjectSubscription {
+    pub device_id: String,
+    pub project_id: ProjectId,
+    pub subscriber: String,
+    pub created_at: u64,
+}
+
+// Keyed by project first so one project's subscribed devices form a contiguous range
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DeviceProjectKey {
+    pub project_id: ProjectId,
+    pub device_id: String,
+}
+
+/// One pending push of a project version to a device
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DeviceDeliveryTask {
+    pub device_id: String,
+    pub project_id: ProjectId,
+    pub version_id: VersionId,
+}
+
+// Keyed by device first so one device's attempts form a contiguous range, oldest first
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DeliveryLogKey {
+    pub device_id: String,
+    pub seq: u64,
+}
+
+/// Outcome of one push attempt; `detail` holds the HTTP status or the error
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DeliveryLogEntry {
+    pub device_id: String,
+    pub project_id: ProjectId,
+    pub version_id: VersionId,
+    pub success: bool,
+    pub detail: String,
+    pub attempted_at: u64,
+}
+
+impl ic_stable_structures::Storable for DeviceProjectKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.project_id, &self.device_id).expect("Failed to encode DeviceProjectKey"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let (project_id, device_id) = Decode!(bytes.as_ref(), String, String)
+            .expect("Failed to decode DeviceProjectKey");
+        Self { project_id, device_id }
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 512, is_fixed_size: false };
+}
+
+impl ic_stable_structures::Storable for DeliveryLogKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.device_id, &self.seq).expect("Failed to encode DeliveryLogKey"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let (device_id, seq) = Decode!(bytes.as_ref(), String, u64)
+            .expect("Failed to decode DeliveryLogKey");
+        Self { device_id, seq }
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 512, is_fixed_size: false };
+}
+
+impl ic_stable_structures::Storable for DeviceProjectSubscription {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode DeviceProjectSubscription"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode DeviceProjectSubscription")
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false };
+}
+
+impl ic_stable_structures::Storable for DeviceDeliveryTask {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode DeviceDeliveryTask"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode DeviceDeliveryTask")
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 512, is_fixed_size: false };
+}
+
+impl ic_stable_structures::Storable for DeliveryLogEntry {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode DeliveryLogEntry"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode DeliveryLogEntry")
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false };
+}
+
+fn device_endpoint(device_id: &str) -> Result<String, String> {
+    let device = DeviceService::get_device_by_id(device_id)
+        .filter(|device| !device.deleted)
+        .ok_or_else(|| format!("Device '{}' not found", device_id))?;
+    device.metadata.get(DEVICE_ENDPOINT_METADATA_KEY)
+        .filter(|url| url.starts_with("https://"))
+        .cloned()
+        .ok_or_else(|| format!("Device '{}' has no HTTPS '{}' in its metadata", device_id, DEVICE_ENDPOINT_METADATA_KEY))
+}
+
+/// Push every new version of the project to the device. The principal must own both,
+/// and the device must have registered an HTTPS endpoint.
+pub fn subscribe_device_to_project(device_id: String, project_id: ProjectId, principal_id: String) -> Result<(), String> {
+    let device = DeviceService::get_device_by_id(&device_id)
+        .filter(|device| !device.deleted)
+        .ok_or_else(|| format!("Device '{}' not found", device_id))?;
+    if device.owner.to_text() != principal_id {
+        return Err("Only the device owner can subscribe it to a project".to_string());
+    }
+    let project = get_project(project_id.clone()).ok_or("Project not found")?;
+    if project.owner.to_text() != principal_id {
+        return Err("Only the project owner can subscribe devices to it".to_string());
+    }
+    device_endpoint(&device_id)?;
+
+    DEVICE_PROJECT_SUBSCRIPTIONS.with(|subscriptions| {
+        let mut subscriptions = subscriptions.borrow_mut();
+        let key = DeviceProjectKey { project_id: project_id.clone(), device_id: device_id.clone() };
+        if subscriptions.contains_key(&key) {
+            return Err(format!("Device '{}' is already subscribed to project {}", device_id, project_id));
+        }
+        subscriptions.insert(key, DeviceProjectSubscription {
+            device_id,
+            project_id,
+            subscriber: principal_id,
+            created_at: time(),
+        });
+        Ok(())
+    })
+}
+
+/// Stop pushing a project to a device (subscriber only)
+pub fn unsubscribe_device_from_project(device_id: String, project_id: ProjectId, principal_id: String) -> Result<(), String> {
+    DEVICE_PROJECT_SUBSCRIPTIONS.with(|subscriptions| {
+        let mut subscriptions = subscriptions.borrow_mut();
+        let key = DeviceProjectKey { project_id, device_id };
+        let subscription = subscriptions.get(&key).ok_or("Device subscription not found")?;
+        if subscription.subscriber != principal_id {
+            return Err("Only the subscriber can remove this device subscription".to_string());
+        }
+        subscriptions.remove(&key);
+        Ok(())
+    })
+}
+
+/// Devices subscribed to one project, ordered by device id
+pub fn get_project_device_subscriptions(project_id: ProjectId) -> Vec<DeviceProjectSubscription> {
+    let start = DeviceProjectKey { project_id: project_id.clone(), device_id: String::new() };
+    DEVICE_PROJECT_SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.project_id == project_id)
+            .map(|(_, subscription)| subscription)
+            .collect()
+    })
+}
+
+/// Queue a push of the saved version to every subscribed device; returns the number queued,
+/// which is less than the number of subscribed devices once the queue is full
+pub fn enqueue_project_deliveries(project_id: &ProjectId, version_id: &VersionId) -> u64 {
+    let subscriptions = get_project_device_subscriptions(project_id.clone());
+    DEVICE_DELIVERY_QUEUE.with(|queue| {
+        let mut queue = queue.borrow_mut();
+        let mut queued = 0;
+        for subscription in subscriptions {
+            let task = DeviceDeliveryTask {
+                device_id: subscription.device_id.clone(),
+                project_id: project_id.clone(),
+                version_id: version_id.clone(),
+            };
+            if task_queue::push_task(&mut queue, task, MAX_DEVICE_DELIVERY_QUEUE_LEN) {
+                queued += 1;
+            } else {
+                ic_cdk::println!("Device delivery queue is full, dropping push to {}", subscription.device_id);
+            }
+        }
+        queued
+    })
+}
+
+pub fn get_device_delivery_queue_length() -> u64 {
+    DEVICE_DELIVERY_QUEUE.with(|queue| queue.borrow().len())
+}
+
+/// Remove up to `max` tasks from the front of the queue, oldest first
+pub fn take_device_delivery_batch(max: usize) -> Vec<DeviceDeliveryTask> {
+    DEVICE_DELIVERY_QUEUE.with(|queue| task_queue::take_batch(&mut queue.borrow_mut(), max))
+}
+
+fn record_delivery(task: &DeviceDeliveryTask, success: bool, detail: String) {
+    let entry = DeliveryLogEntry {
+        device_id: task.device_id.clone(),
+        project_id: task.project_id.clone(),
+        version_id: task.version_id.clone(),
+        success,
+        detail,
+        attempted_at: time(),
+    };
+    DELIVERY_LOG.with(|log| {
+        let mut log = log.borrow_mut();
+        let keys: Vec<DeliveryLogKey> = log.range(device_log_range(&entry.device_id)).map(|(key, _)| key).collect();
+        let seq = keys.last().map_or(0, |key| key.seq + 1);
+        let excess = (keys.len() as u64 + 1).saturating_sub(MAX_DELIVERY_LOG_PER_DEVICE);
+        for key in keys.into_iter().take(excess as usize) {
+            log.remove(&key);
+        }
+        log.insert(DeliveryLogKey { device_id: entry.device_id.clone(), seq }, entry);
+    });
+}
+
+fn device_log_range(device_id: &str) -> std::ops::RangeInclusive<DeliveryLogKey> {
+    DeliveryLogKey { device_id: device_id.to_string(), seq: 0 }..=DeliveryLogKey { device_id: device_id.to_string(), seq: u64::MAX }
+}
+
+/// Delivery attempts for one device, newest first
+pub fn get_device_delivery_log(device_id: String, offset: u64, limit: u64) -> Vec<DeliveryLogEntry> {
+    DELIVERY_LOG.with(|log| {
+        log.borrow()
+            .range(device_log_range(&device_id))
+            .rev()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(|(_, entry)| entry)
+            .collect()
+    })
+}
+
+/// POST one batch of queued versions to their devices, logging every attempt. Failures are not retried.
+pub async fn deliver_pending_device_pushes() {
+    for task in take_device_delivery_batch(DEVICE_DELIVERY_BATCH_SIZE) {
+        let prepared = device_endpoint(&task.device_id).and_then(|url| {
+            export_for_device(task.project_id.clone(), Some(task.version_id.clone())).map(|payload| (url, payload))
+        });
+        let (url, payload) = match prepared {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                record_delivery(&task, false, e);
+                continue;
+            }
+        };
+
+        let arg = CanisterHttpRequestArgument {
+            url,
+            method: HttpMethod::POST,
+            headers: vec![
+                HttpHeader { name: "Content-Type".into(), value: "application/json".into() },
+            ],
+            body: Some(payload.into_bytes()),
+            max_response_bytes: Some(2_048),
+            transform: Some(TransformContext::from_name("transform".to_string(), vec![])),
+        };
+        let cycles = crate::http_outcall::outcall_cycles(&arg);
+        match http_request(arg, cycles).await {
+            Ok((resp,)) => {
+                let success = resp.status >= 200u32 && resp.status < 300u32;
+                record_delivery(&task, success, format!("HTTP {}", resp.status));
+            }
+            Err((code, msg)) => record_delivery(&task, false, format!("{:?}: {}", code, msg)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+    use std::collections::BTreeMap;
+    use crate::device_types::{DeviceCapability, DeviceInfo, DeviceStatus, DeviceType};
+    use crate::pixel_creation_types::test_fixtures::{fixture_version, insert_fixture_project};
+
+    #[test]
+    fn test_subscribed_devices_get_queued_deliveries() {
+        let owner = Principal::anonymous();
+        let mut metadata = BTreeMap::new();
+        metadata.insert(DEVICE_ENDPOINT_METADATA_KEY.to_string(), "https://frame.example.com/push".to_string());
+        let device = |id: &str, metadata: BTreeMap<String, String>| DeviceInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            device_name: None,
+            product_id: None,
+            device_type: DeviceType::IoT,
+            owner,
+            status: DeviceStatus::Online,
+            capabilities: vec![DeviceCapability::Custom("display".to_string())],
+            metadata,
+            created_at: 0,
+            updated_at: 0,
+            last_seen: 0,
+            deleted: false,
+            firmware_version: None,
+            latest_firmware_version: None,
+            ota_update_url: None,
+            ota_update_available: false,
+        };
+        DeviceService::add_device(device("push_frame", metadata)).unwrap();
+        DeviceService::add_device(device("pull_frame", BTreeMap::new())).unwrap();
+
+        insert_fixture_project("push_project", owner, vec![fixture_version("v1", owner)]);
+
+        let project = "push_project".to_string();
+        assert!(subscribe_device_to_project("push_frame".to_string(), project.clone(), "aaaaa-aa".to_string()).is_err());
+        assert!(subscribe_device_to_project("pull_frame".to_string(), project.clone(), owner.to_text()).is_err());
+        subscribe_device_to_project("push_frame".to_string(), project.clone(), owner.to_text()).unwrap();
+        assert!(subscribe_device_to_project("push_frame".to_string(), project.clone(), owner.to_text()).is_err());
+
+        assert_eq!(enqueue_project_deliveries(&project, &"v1".to_string()), 1);
+        assert_eq!(enqueue_project_deliveries(&"other_project".to_string(), &"v1".to_string()), 0);
+        let batch = take_device_delivery_batch(10);
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].device_id, "push_frame");
+        assert_eq!(get_device_delivery_queue_length(), 0);
+
+        record_delivery(&batch[0], true, "HTTP 200".to_string());
+        assert!(get_device_delivery_log("push_frame".to_string(), 0, 10)[0].success);
+
+        // Only the newest attempts per device are kept
+        for attempt in 0..MAX_DELIVERY_LOG_PER_DEVICE {
+            record_delivery(&batch[0], false, format!("HTTP {}", 500 + attempt));
+        }
+        let log = get_device_delivery_log("push_frame".to_string(), 0, u64::MAX);
+        assert_eq!(log.len() as u64, MAX_DELIVERY_LOG_PER_DEVICE);
+        assert!(log.iter().all(|entry| !entry.success));
+        assert_eq!(log[0].detail, format!("HTTP {}", 500 + MAX_DELIVERY_LOG_PER_DEVICE - 1));
+
+        unsubscribe_device_from_project("push_frame".to_string(), project.clone(), owner.to_text()).unwrap();
+        assert!(get_project_device_subscriptions(project).is_empty());
+    }
+}
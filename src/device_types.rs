@@ -4,6 +4,7 @@ use serde::Serialize;
 use std::borrow::Cow;
 use std::collections::BTreeMap;
 use candid::Principal;
+use crate::types::{InstructionGuard, PagedResult, time};
 
 /// Device information structure
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -21,10 +22,73 @@ pub struct DeviceInfo {
     pub updated_at: u64,               // Update timestamp
     pub last_seen: u64,                // Last seen timestamp
     pub deleted: bool,                 // Soft delete flag
+    pub firmware_version: Option<String>, // Last firmware version reported by the device
+    pub latest_firmware_version: Option<String>, // Newest release registered for the device type
+    pub ota_update_url: Option<String>, // Download URL of that release
+    pub ota_update_available: bool,    // Whether the latest release is newer than firmware_version
+}
+
+// DeviceInfo layout before firmware_version was added; bincode needs the exact field list
+#[derive(Deserialize)]
+struct LegacyDeviceInfo {
+    id: String,
+    name: String,
+    device_name: Option<String>,
+    product_id: Option<String>,
+    device_type: DeviceType,
+    owner: Principal,
+    status: DeviceStatus,
+    capabilities: Vec<DeviceCapability>,
+    metadata: BTreeMap<String, String>,
+    created_at: u64,
+    updated_at: u64,
+    last_seen: u64,
+    deleted: bool,
+}
+
+impl From<LegacyDeviceInfo> for DeviceInfo {
+    fn from(device: LegacyDeviceInfo) -> Self {
+        Self {
+            id: device.id,
+            name: device.name,
+            device_name: device.device_name,
+            product_id: device.product_id,
+            device_type: device.device_type,
+            owner: device.owner,
+            status: device.status,
+            capabilities: device.capabilities,
+            metadata: device.metadata,
+            created_at: device.created_at,
+            updated_at: device.updated_at,
+            last_seen: device.last_seen,
+            deleted: device.deleted,
+            firmware_version: None,
+            latest_firmware_version: None,
+            ota_update_url: None,
+            ota_update_available: false,
+        }
+    }
+}
+
+// DeviceInfo layout before the OTA fields were added; bincode writes struct fields
+// back to back, so nesting the older layout decodes the same bytes as listing its fields
+#[derive(Deserialize)]
+struct LegacyFirmwareDeviceInfo {
+    device: LegacyDeviceInfo,
+    firmware_version: Option<String>,
+}
+
+impl From<LegacyFirmwareDeviceInfo> for DeviceInfo {
+    fn from(legacy: LegacyFirmwareDeviceInfo) -> Self {
+        Self {
+            firmware_version: legacy.firmware_version,
+            ..DeviceInfo::from(legacy.device)
+        }
+    }
 }
 
 /// Device type enumeration
-#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum DeviceType {
     Mobile,        // Mobile device
     Desktop,       // Desktop device
@@ -68,6 +132,42 @@ pub struct DeviceIdKey {
     pub device_id: String,
 }
 
+/// One metric reading reported by a device
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DeviceTelemetry {
+    pub device_id: String,
+    pub timestamp: u64,
+    pub metric_key: String,
+    pub metric_value: f64,
+    pub unit: Option<String>,
+}
+
+/// Telemetry key ordered by device then time; the metric key keeps several metrics
+/// recorded at the same timestamp from overwriting each other
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DeviceTelemetryKey {
+    pub device_id: String,
+    pub timestamp: u64,
+    pub metric_key: String,
+}
+
+/// A firmware build published for one device type
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FirmwareRelease {
+    pub device_type: DeviceType,
+    pub version: String,
+    pub download_url: String,
+    pub release_notes: String,
+    pub released_at: u64,
+}
+
+/// Firmware release key ordered by device type then version string
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FirmwareReleaseKey {
+    pub device_type: DeviceType,
+    pub version: String,
+}
+
 /// Device query filter
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct DeviceFilter {
@@ -96,7 +196,10 @@ impl Storable for DeviceInfo {
     }
 
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        bincode::deserialize(&bytes).expect("Failed to deserialize DeviceInfo")
+        bincode::deserialize(&bytes)
+            .or_else(|_| bincode::deserialize::<LegacyFirmwareDeviceInfo>(&bytes).map(DeviceInfo::from))
+            .or_else(|_| bincode::deserialize::<LegacyDeviceInfo>(&bytes).map(DeviceInfo::from))
+            .expect("Failed to deserialize DeviceInfo")
     }
 }
 
@@ -128,6 +231,85 @@ impl Storable for DeviceIdKey {
     }
 }
 
+// Implement Storable trait for DeviceTelemetry
+impl Storable for DeviceTelemetry {
+    const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false }; // 1KB per reading
+
+    fn to_bytes(&self) -> Cow<[u8]> {
+        let bytes = bincode::serialize(self).expect("Failed to serialize DeviceTelemetry");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        bincode::deserialize(&bytes).expect("Failed to deserialize DeviceTelemetry")
+    }
+}
+
+// Implement Storable trait for DeviceTelemetryKey
+impl Storable for DeviceTelemetryKey {
+    const BOUND: Bound = Bound::Bounded { max_size: 768, is_fixed_size: false };
+
+    fn to_bytes(&self) -> Cow<[u8]> {
+        let bytes = bincode::serialize(self).expect("Failed to serialize DeviceTelemetryKey");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        bincode::deserialize(&bytes).expect("Failed to deserialize DeviceTelemetryKey")
+    }
+}
+
+// Implement Storable trait for FirmwareRelease
+impl Storable for FirmwareRelease {
+    const BOUND: Bound = Bound::Bounded { max_size: 8192, is_fixed_size: false }; // release notes dominate
+
+    fn to_bytes(&self) -> Cow<[u8]> {
+        let bytes = bincode::serialize(self).expect("Failed to serialize FirmwareRelease");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        bincode::deserialize(&bytes).expect("Failed to deserialize FirmwareRelease")
+    }
+}
+
+// Implement Storable trait for FirmwareReleaseKey
+impl Storable for FirmwareReleaseKey {
+    const BOUND: Bound = Bound::Bounded { max_size: 512, is_fixed_size: false };
+
+    fn to_bytes(&self) -> Cow<[u8]> {
+        let bytes = bincode::serialize(self).expect("Failed to serialize FirmwareReleaseKey");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        bincode::deserialize(&bytes).expect("Failed to deserialize FirmwareReleaseKey")
+    }
+}
+
+const MAX_FIRMWARE_VERSION_LEN: usize = 64;
+const MAX_FIRMWARE_URL_LEN: usize = 512;
+const MAX_RELEASE_NOTES_LEN: usize = 4096;
+
+/// Compare dotted version strings numerically ("1.10.0" > "1.9.2"); a part that is not a
+/// number compares as a string, and missing trailing parts count as zero
+pub fn compare_firmware_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    let parts_a: Vec<&str> = a.trim().trim_start_matches('v').split('.').collect();
+    let parts_b: Vec<&str> = b.trim().trim_start_matches('v').split('.').collect();
+    for i in 0..parts_a.len().max(parts_b.len()) {
+        let part_a = parts_a.get(i).copied().unwrap_or("0");
+        let part_b = parts_b.get(i).copied().unwrap_or("0");
+        let ordering = match (part_a.parse::<u64>(), part_b.parse::<u64>()) {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            _ => part_a.cmp(part_b),
+        };
+        if ordering != std::cmp::Ordering::Equal {
+            return ordering;
+        }
+    }
+    std::cmp::Ordering::Equal
+}
+
 /// Device management service
 pub struct DeviceService;
 
@@ -140,6 +322,8 @@ impl DeviceService {
         
         // Ensure new device is not marked as deleted
         device_info.deleted = false;
+        // OTA fields come from the registered releases, never from the caller
+        Self::apply_latest_release(&mut device_info);
         
         // Check if device ID already exists
         let device_id_key = DeviceIdKey {
@@ -243,8 +427,8 @@ impl DeviceService {
         devices
     }
 
-    /// Update device information
-    pub fn update_device(device_id: &str, updated_device: DeviceInfo) -> Result<(), String> {
+    /// Update device information; the OTA fields are re-derived from the registered releases
+    pub fn update_device(device_id: &str, mut updated_device: DeviceInfo) -> Result<(), String> {
         use crate::stable_mem_storage::{DEVICES, DEVICE_ID_INDEX, DEVICE_OWNER_INDEX};
         
         let device_id_key = DeviceIdKey {
@@ -259,6 +443,7 @@ impl DeviceService {
         if updated_device.id != device_id {
             return Err("Device ID mismatch".to_string());
         }
+        Self::apply_latest_release(&mut updated_device);
 
         // Update device information
         DEVICES.with(|devices| {
@@ -334,7 +519,7 @@ impl DeviceService {
         // Mark device as deleted by updating it in storage
         let mut updated_device = device.clone();
         updated_device.deleted = true;
-        updated_device.updated_at = ic_cdk::api::time();
+        updated_device.updated_at = time();
 
         DEVICES.with(|devices| {
             devices.borrow_mut().set(device_index, &updated_device)
@@ -449,12 +634,20 @@ impl DeviceService {
         devices
     }
 
-    /// Update device status
+    /// Update device status; the owner is notified when the status actually changes
     pub fn update_device_status(device_id: &str, status: DeviceStatus) -> Result<(), String> {
         if let Some(mut device) = Self::get_device_by_id(device_id) {
+            let changed = device.status != status;
+            let owner = device.owner.to_text();
             device.status = status;
-            device.updated_at = ic_cdk::api::time();
-            Self::update_device(device_id, device)
+            device.updated_at = time();
+            Self::update_device(device_id, device)?;
+            if changed {
+                crate::society_profile_types::push_notification(
+                    format!("device:{}", device_id), owner, 0, crate::society_profile_types::NotificationCategory::Device,
+                )?;
+            }
+            Ok(())
         } else {
             Err("Device not found".to_string())
         }
@@ -463,11 +656,374 @@ impl DeviceService {
     /// Update device last seen timestamp
     pub fn update_last_seen(device_id: &str) -> Result<(), String> {
         if let Some(mut device) = Self::get_device_by_id(device_id) {
-            device.last_seen = ic_cdk::api::time();
+            device.last_seen = time();
             device.status = DeviceStatus::Online;
             Self::update_device(device_id, device)
         } else {
             Err("Device not found".to_string())
         }
     }
+
+    /// Record the firmware version a device reports; also counts as the device being seen
+    pub fn report_firmware_version(device_id: &str, version: String) -> Result<(), String> {
+        let version = version.trim().to_string();
+        if version.is_empty() {
+            return Err("Firmware version cannot be empty".to_string());
+        }
+        if let Some(mut device) = Self::get_device_by_id(device_id) {
+            let now = time();
+            device.firmware_version = Some(version);
+            device.last_seen = now;
+            device.updated_at = now;
+            device.status = DeviceStatus::Online;
+            Self::update_device(device_id, device)
+        } else {
+            Err("Device not found".to_string())
+        }
+    }
+
+    /// Publish a firmware build for a device type; devices of that type get their OTA fields refreshed
+    pub fn register_firmware_release(device_type: DeviceType, version: String, download_url: String, release_notes: String) -> Result<(), String> {
+        use crate::stable_mem_storage::{DEVICES, FIRMWARE_RELEASES};
+
+        let version = version.trim().to_string();
+        if version.is_empty() || version.len() > MAX_FIRMWARE_VERSION_LEN {
+            return Err(format!("Firmware version must be between 1 and {} bytes", MAX_FIRMWARE_VERSION_LEN));
+        }
+        let download_url = download_url.trim().to_string();
+        if !download_url.starts_with("https://") || download_url.len() > MAX_FIRMWARE_URL_LEN {
+            return Err(format!("Download URL must be an HTTPS URL of at most {} bytes", MAX_FIRMWARE_URL_LEN));
+        }
+        if release_notes.len() > MAX_RELEASE_NOTES_LEN {
+            return Err(format!("Release notes cannot exceed {} bytes", MAX_RELEASE_NOTES_LEN));
+        }
+
+        let key = FirmwareReleaseKey { device_type: device_type.clone(), version: version.clone() };
+        FIRMWARE_RELEASES.with(|releases| {
+            let mut releases = releases.borrow_mut();
+            if releases.contains_key(&key) {
+                return Err("Firmware release already registered".to_string());
+            }
+            releases.insert(key, FirmwareRelease {
+                device_type: device_type.clone(),
+                version,
+                download_url,
+                release_notes,
+                released_at: time(),
+            });
+            Ok(())
+        })?;
+
+        let affected: Vec<String> = DEVICES.with(|devices| {
+            devices.borrow().iter()
+                .filter(|device| !device.deleted && device.device_type == device_type)
+                .map(|device| device.id)
+                .collect()
+        });
+        for device_id in affected {
+            if let Some(device) = Self::get_device_by_id(&device_id) {
+                Self::update_device(&device_id, device)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Highest registered release for a device type
+    pub fn get_latest_firmware_release(device_type: &DeviceType) -> Option<FirmwareRelease> {
+        use crate::stable_mem_storage::FIRMWARE_RELEASES;
+
+        let start = FirmwareReleaseKey { device_type: device_type.clone(), version: String::new() };
+        FIRMWARE_RELEASES.with(|releases| {
+            releases.borrow()
+                .range(start..)
+                .take_while(|(key, _)| &key.device_type == device_type)
+                .map(|(_, release)| release)
+                .max_by(|a, b| compare_firmware_versions(&a.version, &b.version))
+        })
+    }
+
+    /// Latest release for the device's type if it is newer than the firmware the device reported;
+    /// a device that never reported a version is always offered the latest release
+    pub fn check_firmware_updates(device_id: &str) -> Option<FirmwareRelease> {
+        let device = Self::get_device_by_id(device_id)?;
+        let latest = Self::get_latest_firmware_release(&device.device_type)?;
+        match device.firmware_version.as_deref() {
+            Some(current) if compare_firmware_versions(&latest.version, current) != std::cmp::Ordering::Greater => None,
+            _ => Some(latest),
+        }
+    }
+
+    // Fill the OTA fields from the latest release for the device's type
+    fn apply_latest_release(device: &mut DeviceInfo) {
+        let latest = Self::get_latest_firmware_release(&device.device_type);
+        device.ota_update_available = match (&latest, device.firmware_version.as_deref()) {
+            (Some(release), Some(current)) => compare_firmware_versions(&release.version, current) == std::cmp::Ordering::Greater,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        device.latest_firmware_version = latest.as_ref().map(|release| release.version.clone());
+        device.ota_update_url = latest.map(|release| release.download_url);
+    }
+
+    /// Store a metric reading for an existing device at the current time
+    pub fn record_telemetry(device_id: &str, metric_key: String, metric_value: f64, unit: Option<String>) -> Result<(), String> {
+        use crate::stable_mem_storage::DEVICE_TELEMETRY;
+
+        let metric_key = metric_key.trim().to_string();
+        if metric_key.is_empty() {
+            return Err("Metric key cannot be empty".to_string());
+        }
+        if !metric_value.is_finite() {
+            return Err("Metric value must be a finite number".to_string());
+        }
+        if Self::get_device_by_id(device_id).is_none() {
+            return Err("Device not found".to_string());
+        }
+
+        let timestamp = time();
+        let key = DeviceTelemetryKey {
+            device_id: device_id.to_string(),
+            timestamp,
+            metric_key: metric_key.clone(),
+        };
+        DEVICE_TELEMETRY.with(|telemetry| {
+            telemetry.borrow_mut().insert(key, DeviceTelemetry {
+                device_id: device_id.to_string(),
+                timestamp,
+                metric_key,
+                metric_value,
+                unit,
+            });
+        });
+        Ok(())
+    }
+
+    /// Readings of a device with start_ns <= timestamp <= end_ns, oldest first,
+    /// read only while the instruction budget lasts. Cursors are "timestamp/metric_key"
+    pub fn get_telemetry(device_id: &str, start_ns: u64, end_ns: u64, guard: &InstructionGuard) -> PagedResult<DeviceTelemetry> {
+        use crate::stable_mem_storage::DEVICE_TELEMETRY;
+        use std::ops::Bound;
+
+        let first = DeviceTelemetryKey {
+            device_id: device_id.to_string(),
+            timestamp: start_ns,
+            metric_key: String::new(),
+        };
+        let resume = guard.start_after().and_then(|cursor| {
+            let (timestamp, metric_key) = cursor.split_once('/')?;
+            Some(DeviceTelemetryKey { device_id: device_id.to_string(), timestamp: timestamp.parse().ok()?, metric_key: metric_key.to_string() })
+        });
+        let start = match resume {
+            Some(key) if key >= first => Bound::Excluded(key),
+            _ => Bound::Included(first),
+        };
+        DEVICE_TELEMETRY.with(|telemetry| {
+            guard.collect(telemetry.borrow()
+                .range((start, Bound::Unbounded))
+                .take_while(|(key, _)| key.device_id == device_id && key.timestamp <= end_ns)
+                .map(|(key, reading)| (format!("{}/{}", key.timestamp, key.metric_key), reading)))
+        })
+    }
+
+    /// Most recent reading of one metric for a device
+    pub fn get_latest_telemetry(device_id: &str, metric_key: &str) -> Option<DeviceTelemetry> {
+        use crate::stable_mem_storage::DEVICE_TELEMETRY;
+
+        let start = DeviceTelemetryKey {
+            device_id: device_id.to_string(),
+            timestamp: 0,
+            metric_key: String::new(),
+        };
+        // Smallest key past this device's readings: device ids compare first, and no id sorts
+        // between `device_id` and `device_id` followed by NUL
+        let upper = DeviceTelemetryKey {
+            device_id: format!("{}\0", device_id),
+            timestamp: 0,
+            metric_key: String::new(),
+        };
+        DEVICE_TELEMETRY.with(|telemetry| {
+            telemetry.borrow()
+                .range(start..upper)
+                .rev()
+                .find(|(key, _)| key.metric_key == metric_key)
+                .map(|(_, reading)| reading)
+        })
+    }
+
+    /// List devices currently reporting the given firmware version
+    pub fn list_devices_by_firmware(version: &str) -> Vec<DeviceInfo> {
+        use crate::stable_mem_storage::DEVICES;
+
+        let version = version.trim();
+        DEVICES.with(|devices| {
+            devices.borrow().iter()
+                .filter(|device| !device.deleted && device.firmware_version.as_deref() == Some(version))
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_device(id: &str) -> DeviceInfo {
+        DeviceInfo {
+            id: id.to_string(),
+            name: format!("Device {}", id),
+            device_name: Some(id.to_string()),
+            product_id: Some("product".to_string()),
+            device_type: DeviceType::IoT,
+            owner: Principal::anonymous(),
+            status: DeviceStatus::Offline,
+            capabilities: vec![DeviceCapability::Audio],
+            metadata: BTreeMap::new(),
+            created_at: 0,
+            updated_at: 0,
+            last_seen: 0,
+            deleted: false,
+            firmware_version: None,
+            latest_firmware_version: None,
+            ota_update_url: None,
+            ota_update_available: false,
+        }
+    }
+
+    #[test]
+    fn test_report_firmware_version_and_filter() {
+        DeviceService::add_device(test_device("fw_a")).unwrap();
+        DeviceService::add_device(test_device("fw_b")).unwrap();
+
+        DeviceService::report_firmware_version("fw_a", " 1.2.0 ".to_string()).unwrap();
+        DeviceService::report_firmware_version("fw_b", "1.1.0".to_string()).unwrap();
+        assert!(DeviceService::report_firmware_version("fw_a", "  ".to_string()).is_err());
+        assert!(DeviceService::report_firmware_version("fw_missing", "1.2.0".to_string()).is_err());
+
+        let device = DeviceService::get_device_by_id("fw_a").unwrap();
+        assert_eq!(device.firmware_version.as_deref(), Some("1.2.0"));
+        assert_eq!(device.status, DeviceStatus::Online);
+
+        let on_new = DeviceService::list_devices_by_firmware("1.2.0");
+        assert_eq!(on_new.len(), 1);
+        assert_eq!(on_new[0].id, "fw_a");
+        assert!(DeviceService::list_devices_by_firmware("9.9.9").is_empty());
+    }
+
+    #[test]
+    fn test_device_status_change_notifies_owner() {
+        let device = test_device("status_a");
+        let owner = device.owner.to_text();
+        DeviceService::add_device(device).unwrap();
+        let status_notifications = || crate::society_profile_types::get_notifications_for_receiver(owner.clone())
+            .into_iter()
+            .filter(|n| n.social_pair_key == "device:status_a")
+            .count();
+
+        DeviceService::update_device_status("status_a", DeviceStatus::Offline).unwrap();
+        assert_eq!(status_notifications(), 0);
+        DeviceService::update_device_status("status_a", DeviceStatus::Maintenance).unwrap();
+        assert_eq!(status_notifications(), 1);
+    }
+
+    #[test]
+    fn test_firmware_releases_and_update_check() {
+        assert_eq!(compare_firmware_versions("1.10.0", "1.9.2"), std::cmp::Ordering::Greater);
+        assert_eq!(compare_firmware_versions("v2.0", "2.0.0"), std::cmp::Ordering::Equal);
+
+        DeviceService::add_device(test_device("ota_a")).unwrap();
+        DeviceService::report_firmware_version("ota_a", "1.9.0".to_string()).unwrap();
+        assert!(DeviceService::check_firmware_updates("ota_a").is_none());
+
+        assert!(DeviceService::register_firmware_release(DeviceType::IoT, "2.0.0".to_string(), "http://fw.example.com/2.0.0".to_string(), String::new()).is_err());
+        DeviceService::register_firmware_release(DeviceType::IoT, "1.10.0".to_string(), "https://fw.example.com/1.10.0".to_string(), "fixes".to_string()).unwrap();
+        DeviceService::register_firmware_release(DeviceType::IoT, "1.8.0".to_string(), "https://fw.example.com/1.8.0".to_string(), String::new()).unwrap();
+        DeviceService::register_firmware_release(DeviceType::Mobile, "9.0.0".to_string(), "https://fw.example.com/m9".to_string(), String::new()).unwrap();
+        assert!(DeviceService::register_firmware_release(DeviceType::IoT, "1.10.0".to_string(), "https://fw.example.com/dup".to_string(), String::new()).is_err());
+
+        let update = DeviceService::check_firmware_updates("ota_a").unwrap();
+        assert_eq!(update.version, "1.10.0");
+        let device = DeviceService::get_device_by_id("ota_a").unwrap();
+        assert!(device.ota_update_available);
+        assert_eq!(device.latest_firmware_version.as_deref(), Some("1.10.0"));
+        assert_eq!(device.ota_update_url.as_deref(), Some("https://fw.example.com/1.10.0"));
+
+        DeviceService::report_firmware_version("ota_a", "1.10.0".to_string()).unwrap();
+        assert!(DeviceService::check_firmware_updates("ota_a").is_none());
+        assert!(!DeviceService::get_device_by_id("ota_a").unwrap().ota_update_available);
+        assert!(DeviceService::check_firmware_updates("ota_missing").is_none());
+
+        // Client-supplied OTA fields are ignored on update
+        let mut tampered = DeviceService::get_device_by_id("ota_a").unwrap();
+        tampered.ota_update_url = Some("https://evil.example.com/fw".to_string());
+        tampered.ota_update_available = true;
+        DeviceService::update_device("ota_a", tampered).unwrap();
+        let device = DeviceService::get_device_by_id("ota_a").unwrap();
+        assert_eq!(device.ota_update_url.as_deref(), Some("https://fw.example.com/1.10.0"));
+        assert!(!device.ota_update_available);
+
+        // A device added after a release gets its OTA fields straight away
+        let mut fresh = test_device("ota_b");
+        fresh.ota_update_url = Some("https://evil.example.com/fw".to_string());
+        DeviceService::add_device(fresh).unwrap();
+        let device = DeviceService::get_device_by_id("ota_b").unwrap();
+        assert_eq!(device.ota_update_url.as_deref(), Some("https://fw.example.com/1.10.0"));
+        assert!(device.ota_update_available);
+    }
+
+    #[test]
+    fn test_legacy_device_layouts_decode() {
+        let device = test_device("legacy");
+        // Serialize the pre-OTA layout: every field up to and including firmware_version
+        let bytes = bincode::serialize(&(
+            &device.id, &device.name, &device.device_name, &device.product_id, &device.device_type,
+            &device.owner, &device.status, &device.capabilities, &device.metadata, device.created_at,
+            device.updated_at, device.last_seen, device.deleted, Some("1.0.0".to_string()),
+        )).unwrap();
+        let decoded = DeviceInfo::from_bytes(Cow::Owned(bytes));
+        assert_eq!(decoded.firmware_version.as_deref(), Some("1.0.0"));
+        assert!(!decoded.ota_update_available);
+    }
+
+    #[test]
+    fn test_device_telemetry_range_and_latest() {
+        use crate::stable_mem_storage::DEVICE_TELEMETRY;
+
+        DeviceService::add_device(test_device("tele_a")).unwrap();
+        DeviceService::record_telemetry("tele_a", "temp".to_string(), 21.5, Some("C".to_string())).unwrap();
+        DeviceService::record_telemetry("tele_a", "humidity".to_string(), 40.0, None).unwrap();
+        assert!(DeviceService::record_telemetry("tele_missing", "temp".to_string(), 1.0, None).is_err());
+        assert!(DeviceService::record_telemetry("tele_a", " ".to_string(), 1.0, None).is_err());
+        assert!(DeviceService::record_telemetry("tele_a", "temp".to_string(), f64::NAN, None).is_err());
+
+        // The test clock is fixed at 0, so add later readings directly
+        DEVICE_TELEMETRY.with(|t| {
+            let mut t = t.borrow_mut();
+            // tele_ab sorts right after tele_a and must not leak into its readings
+            for (device_id, timestamp, value) in [("tele_a", 10, 22.0), ("tele_a", 20, 23.0), ("tele_ab", 30, 99.0)] {
+                let key = DeviceTelemetryKey { device_id: device_id.to_string(), timestamp, metric_key: "temp".to_string() };
+                t.insert(key, DeviceTelemetry {
+                    device_id: device_id.to_string(),
+                    timestamp,
+                    metric_key: "temp".to_string(),
+                    metric_value: value,
+                    unit: Some("C".to_string()),
+                });
+            }
+        });
+
+        let guard = InstructionGuard::new(None);
+        assert_eq!(DeviceService::get_telemetry("tele_a", 0, u64::MAX, &guard).items.len(), 4);
+        let page = DeviceService::get_telemetry("tele_a", 0, u64::MAX, &InstructionGuard::new(None).with_max_items(3));
+        assert_eq!((page.items.len(), page.truncated), (3, true));
+        assert_eq!(page.next_start.as_deref(), Some(format!("{}/temp", page.items[2].timestamp).as_str()));
+        let rest = DeviceService::get_telemetry("tele_a", 0, u64::MAX, &InstructionGuard::new(page.next_start).with_max_items(3));
+        assert_eq!((rest.items.len(), rest.truncated, rest.next_start), (1, false, None));
+        let window = DeviceService::get_telemetry("tele_a", 5, 15, &guard).items;
+        assert_eq!(window.len(), 1);
+        assert_eq!(window[0].metric_value, 22.0);
+
+        assert_eq!(DeviceService::get_latest_telemetry("tele_a", "temp").unwrap().metric_value, 23.0);
+        assert_eq!(DeviceService::get_latest_telemetry("tele_a", "humidity").unwrap().timestamp, 0);
+        assert!(DeviceService::get_latest_telemetry("tele_a", "pressure").is_none());
+    }
 }
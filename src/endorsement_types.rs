@@ -0,0 +1,153 @@
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_stable_structures::storable::Bound;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use crate::stable_mem_storage::ENDORSEMENTS;
+use crate::agent_asset_types::get_agent_item_by_name;
+use crate::mcp_asset_types::get_mcp_item;
+
+use crate::types::time;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EndorsementTarget {
+    Agent,
+    Mcp,
+}
+
+/// A principal vouching for an agent or MCP it does not own
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Endorsement {
+    pub endorser: String,
+    pub target_name: String,
+    pub target_type: EndorsementTarget,
+    pub reason: Option<String>,
+    pub timestamp: u64,
+}
+
+// Keyed by target first so one item's endorsements form a contiguous range
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EndorsementKey {
+    pub target_type: EndorsementTarget,
+    pub target_name: String,
+    pub endorser: String,
+}
+
+impl ic_stable_structures::Storable for EndorsementKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.target_type, &self.target_name, &self.endorser).expect("Failed to encode EndorsementKey"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let (target_type, target_name, endorser) = Decode!(bytes.as_ref(), EndorsementTarget, String, String)
+            .expect("Failed to decode EndorsementKey");
+        Self { target_type, target_name, endorser }
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 512, is_fixed_size: false };
+}
+
+impl ic_stable_structures::Storable for Endorsement {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self).expect("Failed to encode Endorsement"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode Endorsement")
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 1024 * 2, is_fixed_size: false };
+}
+
+/// Endorse an agent or MCP. Anonymous callers, owners of the item and repeat endorsements are rejected
+pub fn endorse(endorser: String, target_name: String, target_type: EndorsementTarget, reason: Option<String>) -> Result<(), String> {
+    if endorser == Principal::anonymous().to_text() {
+        return Err("Anonymous principals cannot endorse".to_string());
+    }
+    let reason = reason
+        .map(|r| crate::sanitize::sanitize_and_bound(&r, crate::sanitize::MAX_REVIEW_COMMENT_LEN))
+        .transpose()?;
+
+    let owner = match target_type {
+        EndorsementTarget::Agent => get_agent_item_by_name(target_name.clone())
+            .map(|agent| agent.owner)
+            .ok_or_else(|| format!("Agent with name '{}' not found", target_name))?,
+        EndorsementTarget::Mcp => get_mcp_item(target_name.clone())
+            .map(|mcp| mcp.owner)
+            .ok_or_else(|| format!("MCP with name '{}' not found", target_name))?,
+    };
+    if owner == endorser {
+        return Err("Owners cannot endorse their own items".to_string());
+    }
+
+    ENDORSEMENTS.with(|endorsements| {
+        let mut endorsements = endorsements.borrow_mut();
+        let key = EndorsementKey { target_type, target_name: target_name.clone(), endorser: endorser.clone() };
+        if endorsements.contains_key(&key) {
+            return Err(format!("'{}' has already endorsed '{}'", endorser, target_name));
+        }
+        endorsements.insert(key, Endorsement {
+            endorser,
+            target_name,
+            target_type,
+            reason,
+            timestamp: time(),
+        });
+        Ok(())
+    })
+}
+
+/// All endorsements of one item, ordered by endorser
+pub fn get_endorsements(target_name: String, target_type: EndorsementTarget) -> Vec<Endorsement> {
+    let start = EndorsementKey { target_type, target_name: target_name.clone(), endorser: String::new() };
+    ENDORSEMENTS.with(|endorsements| {
+        endorsements.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.target_type == target_type && key.target_name == target_name)
+            .map(|(_, endorsement)| endorsement)
+            .collect()
+    })
+}
+
+pub fn get_endorsement_count(target_name: String, target_type: EndorsementTarget) -> u64 {
+    let start = EndorsementKey { target_type, target_name: target_name.clone(), endorser: String::new() };
+    ENDORSEMENTS.with(|endorsements| {
+        endorsements.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.target_type == target_type && key.target_name == target_name)
+            .count() as u64
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp_asset_types::{add_mcp_item, McpItem};
+
+    #[test]
+    fn test_endorse_mcp_rejects_self_and_repeat_endorsements() {
+        let mcp = McpItem {
+            name: "endorsed_mcp".to_string(),
+            description: "Endorsed tools".to_string(),
+            author: "tester".to_string(),
+            git_repo: "https://github.com/example/endorsed".to_string(),
+            mcp_type: "stdio".to_string(),
+            ..McpItem::default()
+        };
+        add_mcp_item(mcp, "owner".to_string()).unwrap();
+        let name = "endorsed_mcp".to_string();
+
+        assert!(endorse("owner".to_string(), name.clone(), EndorsementTarget::Mcp, None).is_err());
+        assert!(endorse(Principal::anonymous().to_text(), name.clone(), EndorsementTarget::Mcp, None).is_err());
+        assert!(endorse("alice".to_string(), "missing".to_string(), EndorsementTarget::Mcp, None).is_err());
+
+        endorse("alice".to_string(), name.clone(), EndorsementTarget::Mcp, Some(" Reliable ".to_string())).unwrap();
+        endorse("bob".to_string(), name.clone(), EndorsementTarget::Mcp, None).unwrap();
+        assert!(endorse("bob".to_string(), name.clone(), EndorsementTarget::Mcp, None).is_err());
+
+        let endorsements = get_endorsements(name.clone(), EndorsementTarget::Mcp);
+        assert_eq!(endorsements.len(), 2);
+        assert_eq!(endorsements[0].reason, Some("Reliable".to_string()));
+        assert_eq!(get_endorsement_count(name.clone(), EndorsementTarget::Mcp), 2);
+        assert_eq!(get_endorsement_count(name, EndorsementTarget::Agent), 0);
+    }
+}
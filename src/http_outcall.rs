@@ -0,0 +1,51 @@
+use ic_cdk::api::management_canister::http_request::CanisterHttpRequestArgument;
+
+// HTTPS outcall pricing from the IC docs, for the 13-node application subnets this canister runs on
+const SUBNET_SIZE: u128 = 13;
+const BASE_FEE_PER_NODE: u128 = 3_000_000 + 60_000 * SUBNET_SIZE;
+const REQUEST_BYTE_FEE_PER_NODE: u128 = 400;
+const RESPONSE_BYTE_FEE_PER_NODE: u128 = 800;
+// Outcalls without a response cap are charged for the 2MB maximum
+const DEFAULT_MAX_RESPONSE_BYTES: u64 = 2_000_000;
+
+/// Cycles to attach to an outcall: the published fee for its request size and response cap,
+/// plus a fifth as margin. Whatever the call does not use is refunded.
+pub fn outcall_cycles(arg: &CanisterHttpRequestArgument) -> u128 {
+    let header_bytes: usize = arg.headers.iter().map(|header| header.name.len() + header.value.len()).sum();
+    let transform_bytes = arg.transform.as_ref()
+        .map_or(0, |transform| transform.function.0.method.len() + transform.context.len());
+    let request_bytes = arg.url.len() + header_bytes + arg.body.as_ref().map_or(0, Vec::len) + transform_bytes;
+    let response_bytes = arg.max_response_bytes.unwrap_or(DEFAULT_MAX_RESPONSE_BYTES);
+
+    let fee = BASE_FEE_PER_NODE * SUBNET_SIZE
+        + REQUEST_BYTE_FEE_PER_NODE * SUBNET_SIZE * request_bytes as u128
+        + RESPONSE_BYTE_FEE_PER_NODE * SUBNET_SIZE * response_bytes as u128;
+    fee + fee / 5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_cdk::api::management_canister::http_request::HttpMethod;
+
+    fn request(max_response_bytes: u64) -> CanisterHttpRequestArgument {
+        CanisterHttpRequestArgument {
+            url: "https://example.com/hook".to_string(),
+            method: HttpMethod::POST,
+            headers: vec![],
+            body: Some(vec![0; 100]),
+            max_response_bytes: Some(max_response_bytes),
+            transform: None,
+        }
+    }
+
+    #[test]
+    fn test_outcall_cycles_cover_published_fee() {
+        // 2KB responses cost about 70M cycles and 64KB ones about 0.73B before the margin
+        let small = outcall_cycles(&request(2_048));
+        assert!(small > 70_000_000 && small < 100_000_000);
+        let large = outcall_cycles(&request(64 * 1024));
+        assert!(large > 730_000_000 && large < 1_000_000_000);
+        assert!(outcall_cycles(&CanisterHttpRequestArgument { max_response_bytes: None, ..request(0) }) > 20_000_000_000);
+    }
+}
@@ -0,0 +1,192 @@
+use candid::{CandidType, Deserialize, Principal};
+use sha2::{Digest, Sha224};
+use crate::token_economy_types::RechargePrincipalAccount;
+
+pub const ICP_LEDGER_CANISTER_ID: &str = "ryjl3-tyaaa-aaaaa-aaaba-cai";
+
+// Subset of the ICP ledger interface needed to look up a single block.
+// Record fields not listed here are ignored when decoding.
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetBlocksArgs {
+    pub start: u64,
+    pub length: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Tokens {
+    pub e8s: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum Operation {
+    Mint { to: Vec<u8>, amount: Tokens },
+    Burn { from: Vec<u8>, amount: Tokens },
+    Transfer { from: Vec<u8>, to: Vec<u8>, amount: Tokens, fee: Tokens },
+    Approve { from: Vec<u8> },
+    TransferFrom { from: Vec<u8>, to: Vec<u8>, amount: Tokens, fee: Tokens },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Transaction {
+    pub memo: u64,
+    pub operation: Option<Operation>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Block {
+    pub transaction: Transaction,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ArchivedBlocksRange {
+    pub start: u64,
+    pub length: u64,
+    pub callback: candid::Func,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct QueryBlocksResponse {
+    pub chain_length: u64,
+    pub blocks: Vec<Block>,
+    pub first_block_index: u64,
+    pub archived_blocks: Vec<ArchivedBlocksRange>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BlockRange {
+    pub blocks: Vec<Block>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum GetBlocksError {
+    BadFirstBlockIndex { requested_index: u64, first_valid_index: u64 },
+    Other { error_code: u64, error_message: String },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum ArchiveResult {
+    Ok(BlockRange),
+    Err(GetBlocksError),
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// ICP ledger account identifier: CRC32 checksum followed by SHA-224 of the principal and subaccount
+pub fn account_identifier(principal: &Principal, subaccount: Option<[u8; 32]>) -> Vec<u8> {
+    let mut hasher = Sha224::new();
+    hasher.update(b"\x0Aaccount-id");
+    hasher.update(principal.as_slice());
+    hasher.update(subaccount.unwrap_or([0u8; 32]));
+    let hash = hasher.finalize();
+
+    let mut result = crc32(&hash).to_be_bytes().to_vec();
+    result.extend_from_slice(&hash);
+    result
+}
+
+/// Account identifier of the configured recharge target; `subaccount_id` is hex encoded
+pub fn recharge_account_identifier(target: &RechargePrincipalAccount) -> Result<Vec<u8>, String> {
+    let principal = Principal::from_text(&target.principal_id)
+        .map_err(|e| format!("Invalid recharge principal: {}", e))?;
+    let subaccount = match &target.subaccount_id {
+        Some(hex_str) if !hex_str.is_empty() => {
+            let bytes = hex::decode(hex_str).map_err(|e| format!("Invalid recharge subaccount: {}", e))?;
+            let bytes: [u8; 32] = bytes.try_into().map_err(|_| "Recharge subaccount must be 32 bytes".to_string())?;
+            Some(bytes)
+        }
+        _ => None,
+    };
+    Ok(account_identifier(&principal, subaccount))
+}
+
+/// Check that the operation is a transfer from `from` to `to` and return the amount in e8s
+pub fn verify_transfer(operation: &Operation, from: &[u8], to: &[u8]) -> Result<u64, String> {
+    match operation {
+        Operation::Transfer { from: tx_from, to: tx_to, amount, .. } => {
+            if tx_from.as_slice() != from {
+                return Err("Transfer was not sent from the caller's account".to_string());
+            }
+            if tx_to.as_slice() != to {
+                return Err("Transfer was not sent to the recharge account".to_string());
+            }
+            if amount.e8s == 0 {
+                return Err("Transfer amount is zero".to_string());
+            }
+            Ok(amount.e8s)
+        }
+        _ => Err("Block does not contain an ICP transfer".to_string()),
+    }
+}
+
+/// Fetch the operation recorded at `block_index`, following the archive callback for old blocks
+pub async fn query_block_operation(block_index: u64) -> Result<Operation, String> {
+    let ledger = Principal::from_text(ICP_LEDGER_CANISTER_ID)
+        .map_err(|e| format!("Invalid ICP ledger canister ID: {}", e))?;
+    let args = GetBlocksArgs { start: block_index, length: 1 };
+
+    let (response,): (QueryBlocksResponse,) = ic_cdk::call(ledger, "query_blocks", (args.clone(),))
+        .await
+        .map_err(|(code, msg)| format!("query_blocks failed: {:?} {}", code, msg))?;
+
+    let block = if block_index >= response.first_block_index {
+        response.blocks.into_iter().next()
+    } else {
+        let range = response.archived_blocks.into_iter()
+            .find(|r| block_index >= r.start && block_index < r.start + r.length)
+            .ok_or_else(|| format!("Block {} not found in ledger archives", block_index))?;
+        let (result,): (ArchiveResult,) = ic_cdk::call(range.callback.principal, &range.callback.method, (args,))
+            .await
+            .map_err(|(code, msg)| format!("Archive get_blocks failed: {:?} {}", code, msg))?;
+        match result {
+            ArchiveResult::Ok(range) => range.blocks.into_iter().next(),
+            ArchiveResult::Err(e) => return Err(format!("Archive get_blocks error: {:?}", e)),
+        }
+    };
+
+    block
+        .ok_or_else(|| format!("Block {} does not exist", block_index))?
+        .transaction
+        .operation
+        .ok_or_else(|| format!("Block {} has no operation", block_index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_verify_transfer_matches_accounts() {
+        let caller = account_identifier(&Principal::from_slice(&[1]), None);
+        let target = account_identifier(&Principal::from_slice(&[2]), Some([7u8; 32]));
+        assert_eq!(caller.len(), 32);
+
+        let transfer = Operation::Transfer {
+            from: caller.clone(),
+            to: target.clone(),
+            amount: Tokens { e8s: 150_000_000 },
+            fee: Tokens { e8s: 10_000 },
+        };
+        assert_eq!(verify_transfer(&transfer, &caller, &target), Ok(150_000_000));
+        assert!(verify_transfer(&transfer, &target, &target).is_err());
+        assert!(verify_transfer(&transfer, &caller, &caller).is_err());
+
+        let mint = Operation::Mint { to: target.clone(), amount: Tokens { e8s: 1 } };
+        assert!(verify_transfer(&mint, &caller, &target).is_err());
+    }
+}
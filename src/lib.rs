@@ -16,16 +16,29 @@ mod order_types;
 mod types;
 mod bitpay;
 mod hmac;
+mod user_data_export;
+mod access_control;
+mod sanitize;
+mod settings;
+mod icp_ledger;
+mod endorsement_types;
+mod webhook_types;
+mod device_delivery_types;
+mod agent_message_types;
+mod task_queue;
+mod http_outcall;
 
 use candid::candid_method;
 use candid::{CandidType, Deserialize};
 use std::collections::BTreeMap;
 use ic_cdk::{query, update};
-use types::{Order, OrderStatus, CreateOrderArgs, InvoiceResp};
+use access_control::Role;
+use types::{Order, OrderStatus, CreateOrderArgs, InvoiceResp, CursorPage, InstructionGuard, PagedResult, clamp_page_size};
 use agent_asset_types::AgentItem;
 use mcp_asset_types::{McpItem, McpStackRecord};
+use endorsement_types::{Endorsement, EndorsementTarget};
 use trace_storage::{TraceLog, IOValue};
-use society_profile_types::UserProfile;
+use society_profile_types::{UserProfile, PublicUserProfile};
 use pixel_creation_types::{Project, Version, PixelArtSource, ProjectId, VersionId};
 use ic_cdk::caller;
 use aio_protocal_types::AioIndexManager;
@@ -33,14 +46,14 @@ use serde_json;
 use icrc_ledger_types::{icrc1::account::Account, icrc1::transfer::TransferArg};
 use num_traits::ToPrimitive;
 use token_economy_types::{
-    EmissionPolicy, TokenGrant, TokenInfo,
+    EmissionPolicy, SubscriptionPlan, TokenGrant, TokenInfo,
     TokenActivity, TokenActivityType,
     CreditActivity, CreditActivityType,
     TransferStatus as TokenTransferStatus,
     AccountInfo, TokenGrantStatus, GrantPolicy,
-    NewMcpGrant, RechargePrincipalAccount
+    NewMcpGrant, RechargePrincipalAccount, CreditDispute
 };
-use token_economy::{record_token_activity, record_credit_activity, get_credits_per_icp, update_icp_usd_price, simulate_credit_from_icp, recharge_and_convert_credits, get_user_credit_balance, get_recharge_history};
+use token_economy::{record_token_activity, record_credit_activity, get_credits_per_icp, update_icp_usd_price, simulate_credit_from_icp, get_user_credit_balance, get_recharge_history};
 use crate::stable_mem_storage::INVERTED_INDEX_STORE;
 use ic_cdk_timers::TimerId;
 use std::time::Duration;
@@ -56,10 +69,44 @@ pub use mining_reword::*;
 // add timer id storage
 thread_local! {
     static MINING_TIMER_ID: RefCell<Option<TimerId>> = RefCell::new(None);
+    static CHAT_CLEANUP_TIMER_ID: RefCell<Option<TimerId>> = RefCell::new(None);
+    static WEBHOOK_TIMER_ID: RefCell<Option<TimerId>> = RefCell::new(None);
+    static DEVICE_DELIVERY_TIMER_ID: RefCell<Option<TimerId>> = RefCell::new(None);
+    static MCP_HEALTH_TIMER_ID: RefCell<Option<TimerId>> = RefCell::new(None);
+}
+
+// Register the 5-minute mining interval and remember its id
+fn start_mining_timer() {
+    let timer_id = ic_cdk_timers::set_timer_interval(Duration::from_secs(5 * 60), || {
+        ic_cdk::println!("Executing daily mining rewards calculation...");
+        match mining_reword::perdic_mining() {
+            Ok(_) => ic_cdk::println!("Mining rewards calculation completed"),
+            Err(e) => ic_cdk::println!("Mining rewards calculation failed: {}", e),
+        }
+        match token_economy::record_emission_snapshots(ic_cdk::api::time()) {
+            Ok(count) => ic_cdk::println!("Recorded {} emission snapshots", count),
+            Err(e) => ic_cdk::println!("Emission snapshot failed: {}", e),
+        }
+        if mcp_asset_types::record_weekly_leaderboard_snapshot(ic_cdk::api::time()) {
+            ic_cdk::println!("Recorded weekly staking leaderboard snapshot");
+        }
+    });
+    MINING_TIMER_ID.with(|id| {
+        *id.borrow_mut() = Some(timer_id);
+    });
+
+    // MCP health checks run alongside mining and deprecate MCPs whose endpoints keep failing
+    let health_timer_id = ic_cdk_timers::set_timer_interval(Duration::from_secs(30 * 60), || {
+        ic_cdk::spawn(mcp_asset_types::run_mcp_health_checks());
+    });
+    MCP_HEALTH_TIMER_ID.with(|id| {
+        *id.borrow_mut() = Some(health_timer_id);
+    });
 }
 
 // add dispatch_mining_rewards function
 #[ic_cdk::update]
+#[candid_method(update)]
 fn dispatch_mining_rewards() -> Result<(), String> {
     ic_cdk::println!("Starting mining rewards dispatch...");
     
@@ -72,19 +119,7 @@ fn dispatch_mining_rewards() -> Result<(), String> {
         return Err("Mining rewards dispatch is already running".to_string());
     }
     
-    // set timer, run once per day
-    let timer_id = ic_cdk_timers::set_timer_interval(Duration::from_secs(5 * 60), || {
-        ic_cdk::println!("Executing daily mining rewards calculation...");
-        match mining_reword::perdic_mining() {
-            Ok(_) => ic_cdk::println!("Mining rewards calculation completed"),
-            Err(e) => ic_cdk::println!("Mining rewards calculation failed: {}", e),
-        }
-    });
-    
-    // store timer id
-    MINING_TIMER_ID.with(|id| {
-        *id.borrow_mut() = Some(timer_id);
-    });
+    start_mining_timer();
     
     ic_cdk::println!("Mining rewards dispatch has been started");
     Ok(())
@@ -92,9 +127,13 @@ fn dispatch_mining_rewards() -> Result<(), String> {
 
 // add stop mining rewards function
 #[ic_cdk::update]
+#[candid_method(update)]
 fn stop_mining_rewards() -> Result<(), String> {
     ic_cdk::println!("Stopping mining rewards dispatch...");
     
+    if let Some(id) = MCP_HEALTH_TIMER_ID.with(|timer_id| timer_id.borrow_mut().take()) {
+        ic_cdk_timers::clear_timer(id);
+    }
     MINING_TIMER_ID.with(|timer_id| {
         if let Some(id) = timer_id.borrow_mut().take() {
             ic_cdk_timers::clear_timer(id);
@@ -106,8 +145,159 @@ fn stop_mining_rewards() -> Result<(), String> {
     })
 }
 
+// Register the hourly sweep that blanks chat messages past their TTL, a batch of histories per tick
+fn start_chat_cleanup_timer() {
+    let timer_id = ic_cdk_timers::set_timer_interval(Duration::from_secs(60 * 60), || {
+        let removed = society_profile_types::cleanup_expired_messages(ic_cdk::api::time(), society_profile_types::CHAT_CLEANUP_BATCH);
+        ic_cdk::println!("Chat cleanup blanked {} expired messages", removed);
+    });
+    CHAT_CLEANUP_TIMER_ID.with(|id| {
+        *id.borrow_mut() = Some(timer_id);
+    });
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn dispatch_chat_cleanup() -> Result<(), String> {
+    require_role!(Role::Admin);
+    let timer_exists = CHAT_CLEANUP_TIMER_ID.with(|timer_id| timer_id.borrow().is_some());
+    if timer_exists {
+        return Err("Chat cleanup is already running".to_string());
+    }
+    start_chat_cleanup_timer();
+    ic_cdk::println!("Chat cleanup has been started");
+    Ok(())
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn stop_chat_cleanup() -> Result<(), String> {
+    require_role!(Role::Admin);
+    CHAT_CLEANUP_TIMER_ID.with(|timer_id| {
+        if let Some(id) = timer_id.borrow_mut().take() {
+            ic_cdk_timers::clear_timer(id);
+            ic_cdk::println!("Chat cleanup has been stopped");
+            Ok(())
+        } else {
+            Err("No chat cleanup is currently running".to_string())
+        }
+    })
+}
+
+type TimerSlot = std::thread::LocalKey<RefCell<Option<TimerId>>>;
+
+// Register a once-a-minute timer in `slot` that spawns `drain` whenever `pending` reports queued tasks
+fn start_queue_timer<D, F>(slot: &'static TimerSlot, pending: fn() -> u64, drain: D)
+where
+    D: Fn() -> F + 'static,
+    F: std::future::Future<Output = ()> + 'static,
+{
+    let timer_id = ic_cdk_timers::set_timer_interval(Duration::from_secs(60), move || {
+        if pending() > 0 {
+            ic_cdk::spawn(drain());
+        }
+    });
+    slot.with(|id| {
+        *id.borrow_mut() = Some(timer_id);
+    });
+}
+
+// Start a queue timer unless `slot` already holds one; `name` is used in log and error messages
+fn dispatch_queue_timer(slot: &'static TimerSlot, name: &str, start: fn()) -> Result<(), String> {
+    let timer_exists = slot.with(|timer_id| timer_id.borrow().is_some());
+    if timer_exists {
+        return Err(format!("{} is already running", name));
+    }
+    start();
+    ic_cdk::println!("{} has been started", name);
+    Ok(())
+}
+
+fn stop_queue_timer(slot: &'static TimerSlot, name: &str) -> Result<(), String> {
+    slot.with(|timer_id| {
+        if let Some(id) = timer_id.borrow_mut().take() {
+            ic_cdk_timers::clear_timer(id);
+            ic_cdk::println!("{} has been stopped", name);
+            Ok(())
+        } else {
+            Err(format!("No {} is currently running", name.to_lowercase()))
+        }
+    })
+}
+
+// Register the once-a-minute delivery of queued MCP webhooks
+fn start_webhook_timer() {
+    start_queue_timer(&WEBHOOK_TIMER_ID, webhook_types::get_webhook_queue_length, webhook_types::deliver_pending_webhooks);
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn dispatch_webhook_delivery() -> Result<(), String> {
+    require_role!(Role::Admin);
+    dispatch_queue_timer(&WEBHOOK_TIMER_ID, "Webhook delivery", start_webhook_timer)
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn stop_webhook_delivery() -> Result<(), String> {
+    require_role!(Role::Admin);
+    stop_queue_timer(&WEBHOOK_TIMER_ID, "Webhook delivery")
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn subscribe_webhook(mcp_name: Option<String>, events: Vec<webhook_types::WebhookEventType>, url: String, secret: String) -> Result<String, String> {
+    let subscriber = caller().to_string();
+    ic_cdk::println!("CALL[subscribe_webhook] Input: subscriber={}, mcp_name={:?}, events={:?}, url={}", subscriber, mcp_name, events, url);
+    let result = webhook_types::subscribe_webhook(subscriber, mcp_name, events, url, secret);
+    ic_cdk::println!("CALL[subscribe_webhook] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn unsubscribe_webhook(id: String) -> Result<(), String> {
+    let caller_principal = caller().to_string();
+    ic_cdk::println!("CALL[unsubscribe_webhook] Input: id={}, caller={}", id, caller_principal);
+    let result = webhook_types::unsubscribe_webhook(id, caller_principal);
+    ic_cdk::println!("CALL[unsubscribe_webhook] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_my_webhook_subscriptions() -> Vec<webhook_types::WebhookSubscription> {
+    webhook_types::get_webhook_subscriptions(caller().to_string())
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_webhook_queue_length() -> u64 {
+    webhook_types::get_webhook_queue_length()
+}
+
+// Register the once-a-minute push of saved pixel versions to subscribed devices
+fn start_device_delivery_timer() {
+    start_queue_timer(&DEVICE_DELIVERY_TIMER_ID, device_delivery_types::get_device_delivery_queue_length, device_delivery_types::deliver_pending_device_pushes);
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn dispatch_device_delivery() -> Result<(), String> {
+    require_role!(Role::Admin);
+    dispatch_queue_timer(&DEVICE_DELIVERY_TIMER_ID, "Device delivery", start_device_delivery_timer)
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn stop_device_delivery() -> Result<(), String> {
+    require_role!(Role::Admin);
+    stop_queue_timer(&DEVICE_DELIVERY_TIMER_ID, "Device delivery")
+}
+
 // Store inverted index
 #[ic_cdk::update]
+#[candid_method(update)]
 fn store_inverted_index(mcp_name: String, json_str: String) -> Result<(), String> {
     ic_cdk::println!("CALL[store_inverted_index] Input: {}", json_str);
     ic_cdk::println!("MCP Name: {}", mcp_name);
@@ -141,15 +331,28 @@ fn store_inverted_index(mcp_name: String, json_str: String) -> Result<(), String
 
 // Get all inverted index items
 #[ic_cdk::query]
-fn get_all_inverted_index_items() -> String {
-    ic_cdk::println!("CALL[get_all_inverted_index_items] Input: none");
-    let result = aio_invert_index_types::get_all_inverted_index_items();
+#[candid_method(query)]
+fn get_all_inverted_index_items(start: Option<String>) -> String {
+    ic_cdk::println!("CALL[get_all_inverted_index_items] Input: start={:?}", start);
+    let result = aio_invert_index_types::get_all_inverted_index_items(&InstructionGuard::new(start));
     ic_cdk::println!("CALL[get_all_inverted_index_items] Output: {} items", result.len());
     result
 }
 
+// Report MCP names that are only in the inverted index or only in MCP storage
+#[ic_cdk::query]
+#[candid_method(query)]
+fn audit_inverted_index() -> Vec<String> {
+    ic_cdk::println!("CALL[audit_inverted_index] Input: none");
+    require_role!(Role::Operator, trap);
+    let result = aio_invert_index_types::audit_inverted_index();
+    ic_cdk::println!("CALL[audit_inverted_index] Output: {:?}", result);
+    result
+}
+
 // Get all keywords
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_all_keywords() -> String {
     ic_cdk::println!("CALL[get_all_keywords] Input: none");
     let result = aio_invert_index_types::get_all_keywords();
@@ -159,6 +362,7 @@ fn get_all_keywords() -> String {
 
 // Find index items by keyword
 #[ic_cdk::query]
+#[candid_method(query)]
 fn find_inverted_index_by_keyword(keyword: String) -> String {
     ic_cdk::println!("CALL[find_inverted_index_by_keyword] Input: keyword={}", keyword);
     let result = aio_invert_index_types::find_inverted_index_by_keyword(keyword);
@@ -168,6 +372,7 @@ fn find_inverted_index_by_keyword(keyword: String) -> String {
 
 // Find index items by keyword group
 #[ic_cdk::query]
+#[candid_method(query)]
 fn find_inverted_index_by_group(group: String) -> String {
     ic_cdk::println!("CALL[find_inverted_index_by_group] Input: group={}", group);
     let result = aio_invert_index_types::find_inverted_index_by_group(group);
@@ -177,6 +382,7 @@ fn find_inverted_index_by_group(group: String) -> String {
 
 // Find index items by MCP name
 #[ic_cdk::query]
+#[candid_method(query)]
 fn find_inverted_index_by_mcp(mcp_name: String) -> String {
     ic_cdk::println!("CALL[find_inverted_index_by_mcp] Input: mcp_name={}", mcp_name);
     let result = aio_invert_index_types::find_inverted_index_by_mcp(mcp_name);
@@ -184,8 +390,19 @@ fn find_inverted_index_by_mcp(mcp_name: String) -> String {
     result
 }
 
+// Find index items by MCP name with pagination
+#[ic_cdk::query]
+#[candid_method(query)]
+fn find_inverted_index_by_mcp_paginated(mcp_name: String, offset: usize, limit: usize) -> String {
+    ic_cdk::println!("CALL[find_inverted_index_by_mcp_paginated] Input: mcp_name={}, offset={}, limit={}", mcp_name, offset, limit);
+    let result = aio_invert_index_types::find_inverted_index_by_mcp_paginated(mcp_name, offset, limit);
+    ic_cdk::println!("CALL[find_inverted_index_by_mcp_paginated] Output: {} items", result.len());
+    result
+}
+
 // Find index items by confidence threshold
 #[ic_cdk::query]
+#[candid_method(query)]
 fn find_inverted_index_by_confidence(min_confidence: f32) -> String {
     ic_cdk::println!("CALL[find_inverted_index_by_confidence] Input: min_confidence={}", min_confidence);
     let result = aio_invert_index_types::find_inverted_index_by_confidence(min_confidence);
@@ -195,6 +412,7 @@ fn find_inverted_index_by_confidence(min_confidence: f32) -> String {
 
 // Find index items by multiple keywords with confidence threshold
 #[ic_cdk::query]
+#[candid_method(query)]
 fn find_inverted_index_by_keywords(keywords: Vec<String>, min_confidence: f32) -> String {
     ic_cdk::println!("CALL[find_inverted_index_by_keywords] Input: keywords={:?}, min_confidence={}", keywords, min_confidence);
     let result = aio_invert_index_types::find_inverted_index_by_keywords(keywords, min_confidence);
@@ -202,16 +420,64 @@ fn find_inverted_index_by_keywords(keywords: Vec<String>, min_confidence: f32) -
     result
 }
 
+// Find index items of MCPs matching every keyword
+#[ic_cdk::query]
+#[candid_method(query)]
+fn find_inverted_index_by_keywords_and(keywords: Vec<String>, min_confidence: f32) -> String {
+    ic_cdk::println!("CALL[find_inverted_index_by_keywords_and] Input: keywords={:?}, min_confidence={}", keywords, min_confidence);
+    let result = aio_invert_index_types::find_inverted_index_by_keywords_and(keywords, min_confidence);
+    ic_cdk::println!("CALL[find_inverted_index_by_keywords_and] Output: {} bytes", result.len());
+    result
+}
+
+// Top-k MCPs ranked by matched keyword count plus summed confidence
+#[ic_cdk::query]
+#[candid_method(query)]
+fn find_inverted_index_ranked(keywords: Vec<String>, min_confidence: f32, top_k: usize) -> String {
+    let top_k = clamp_page_size(top_k as u64) as usize;
+    ic_cdk::println!("CALL[find_inverted_index_ranked] Input: keywords={:?}, min_confidence={}, top_k={}", keywords, min_confidence, top_k);
+    let result = aio_invert_index_types::find_inverted_index_ranked(keywords, min_confidence, top_k);
+    ic_cdk::println!("CALL[find_inverted_index_ranked] Output: {} bytes", result.len());
+    result
+}
+
 // Delete all index items for a specific MCP
 #[ic_cdk::update]
+#[candid_method(update)]
 fn delete_inverted_index_by_mcp(mcp_name: String) -> Result<(), String> {
     aio_invert_index_types::delete_inverted_index_by_mcp(mcp_name)
 }
 
+// Delete all index items for a keyword, returning the number removed
+#[ic_cdk::update]
+#[candid_method(update)]
+fn delete_inverted_index_by_keyword(keyword: String) -> Result<u64, String> {
+    ic_cdk::println!("CALL[delete_inverted_index_by_keyword] Input: keyword={}", keyword);
+    require_role!(Role::Operator);
+    let result = aio_invert_index_types::delete_inverted_index_by_keyword(keyword);
+    ic_cdk::println!("CALL[delete_inverted_index_by_keyword] Output: {:?}", result);
+    result
+}
+
+// Delete index items whose MCP no longer exists, returning the number removed
+#[ic_cdk::update]
+#[candid_method(update)]
+fn prune_inverted_index_orphans() -> Result<u64, String> {
+    ic_cdk::println!("CALL[prune_inverted_index_orphans] Input: none");
+    require_role!(Role::Operator);
+    let result = aio_invert_index_types::prune_inverted_index_orphans();
+    ic_cdk::println!("CALL[prune_inverted_index_orphans] Output: {:?}", result);
+    result
+}
+
 #[ic_cdk::query]
+#[candid_method(query)]
 fn greet(name: String) -> String {
     ic_cdk::println!("CALL[greet] Input: {}", name);
-    let result = format!("Hello, {}!", name);
+    let result = match sanitize::sanitize_and_bound(&name, sanitize::MAX_NAME_LEN) {
+        Ok(name) => format!("Hello, {}!", name),
+        Err(e) => e,
+    };
     ic_cdk::println!("CALL[greet] Output: {}", result);
     result
 }
@@ -219,6 +485,7 @@ fn greet(name: String) -> String {
 // ==== Agent Asset API ====
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_agent_item(index: u64) -> Option<AgentItem> {
     ic_cdk::println!("CALL[get_agent_item] Input: index={}", index);
     let result = agent_asset_types::get_agent_item(index);
@@ -227,14 +494,16 @@ fn get_agent_item(index: u64) -> Option<AgentItem> {
 }
 
 #[ic_cdk::query]
-fn get_all_agent_items() -> Vec<AgentItem> {
-    ic_cdk::println!("CALL[get_all_agent_items] Input: none");
-    let result = agent_asset_types::get_all_agent_items();
-    ic_cdk::println!("CALL[get_all_agent_items] Output: count={}", result.len());
+#[candid_method(query)]
+fn get_all_agent_items(start: Option<String>) -> PagedResult<AgentItem> {
+    ic_cdk::println!("CALL[get_all_agent_items] Input: start={:?}", start);
+    let result = agent_asset_types::get_all_agent_items_guarded(false, &InstructionGuard::new(start));
+    ic_cdk::println!("CALL[get_all_agent_items] Output: count={}, truncated={}", result.items.len(), result.truncated);
     result
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_user_agent_items() -> Vec<AgentItem> {
     let caller_id = caller().to_string();
     ic_cdk::println!("CALL[get_user_agent_items] Input: caller_id={}", caller_id);
@@ -243,8 +512,59 @@ fn get_user_agent_items() -> Vec<AgentItem> {
     result
 }
 
+#[ic_cdk::update]
+#[candid_method(update)]
+fn delete_agent_item(index: u64) -> Result<(), String> {
+    ic_cdk::println!("CALL[delete_agent_item] Input: index={}", index);
+    require_owner_or_operator(agent_asset_types::get_agent_owner(index))?;
+    let result = agent_asset_types::delete_soft(index);
+    ic_cdk::println!("CALL[delete_agent_item] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn restore_agent_item(index: u64) -> Result<(), String> {
+    ic_cdk::println!("CALL[restore_agent_item] Input: index={}", index);
+    require_owner_or_operator(agent_asset_types::get_agent_owner(index))?;
+    let result = agent_asset_types::restore_agent_item(index);
+    ic_cdk::println!("CALL[restore_agent_item] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_all_agent_items_admin(include_deleted: bool, start: Option<String>) -> Result<PagedResult<AgentItem>, String> {
+    ic_cdk::println!("CALL[get_all_agent_items_admin] Input: include_deleted={}, start={:?}", include_deleted, start);
+    require_role!(Role::Admin);
+    let result = agent_asset_types::get_all_agent_items_guarded(include_deleted, &InstructionGuard::new(start));
+    ic_cdk::println!("CALL[get_all_agent_items_admin] Output: count={}, truncated={}", result.items.len(), result.truncated);
+    Ok(result)
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn search_agents_full_text(query: String, limit: usize) -> Vec<AgentItem> {
+    ic_cdk::println!("CALL[search_agents_full_text] Input: query={}, limit={}", query, limit);
+    let result = agent_asset_types::search_agents_full_text(query, limit);
+    ic_cdk::println!("CALL[search_agents_full_text] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn rebuild_agent_trigram_index() -> Result<u64, String> {
+    ic_cdk::println!("CALL[rebuild_agent_trigram_index] Input: none");
+    require_role!(Role::Admin);
+    let result = agent_asset_types::rebuild_agent_trigram_index();
+    ic_cdk::println!("CALL[rebuild_agent_trigram_index] Output: {}", result);
+    Ok(result)
+}
+
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_agent_items_paginated(offset: u64, limit: usize) -> Vec<AgentItem> {
+    let limit = clamp_page_size(limit as u64) as usize;
     ic_cdk::println!("CALL[get_agent_items_paginated] Input: offset={}, limit={}", offset, limit);
     let result = agent_asset_types::get_agent_items_paginated(offset, limit);
     ic_cdk::println!("CALL[get_agent_items_paginated] Output: count={}", result.len());
@@ -252,6 +572,34 @@ fn get_agent_items_paginated(offset: u64, limit: usize) -> Vec<AgentItem> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
+fn get_agent_items_paginated_cursor(cursor: Option<String>, limit: usize) -> Result<CursorPage<AgentItem>, String> {
+    ic_cdk::println!("CALL[get_agent_items_paginated_cursor] Input: cursor={:?}, limit={}", cursor, limit);
+    let result = agent_asset_types::get_agent_items_paginated_cursor(cursor, limit);
+    ic_cdk::println!("CALL[get_agent_items_paginated_cursor] Output: {:?}", result.as_ref().map(|p| (p.items.len(), p.next_cursor.clone())));
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_mcps_for_agent(agent_index: u64) -> Vec<McpItem> {
+    ic_cdk::println!("CALL[get_mcps_for_agent] Input: agent_index={}", agent_index);
+    let result = agent_asset_types::get_mcps_for_agent(agent_index);
+    ic_cdk::println!("CALL[get_mcps_for_agent] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_agents_for_mcp(mcp_name: String) -> Vec<AgentItem> {
+    ic_cdk::println!("CALL[get_agents_for_mcp] Input: mcp_name={}", mcp_name);
+    let result = agent_asset_types::get_agents_for_mcp(mcp_name);
+    ic_cdk::println!("CALL[get_agents_for_mcp] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
 fn get_agent_item_by_name(name: String) -> Option<AgentItem> {
     ic_cdk::println!("CALL[get_agent_item_by_name] Input: name={}", name);
     let result = agent_asset_types::get_agent_item_by_name(name);
@@ -266,6 +614,7 @@ fn get_agent_item_by_name(name: String) -> Option<AgentItem> {
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn add_agent_item(agent: AgentItem, principalid: String) -> Result<u64, String> {
     ic_cdk::println!("CALL[add_agent_item] Input: caller_id={}, agent={:?}", principalid, agent);
     let mut agent_item = agent.clone();
@@ -276,6 +625,16 @@ fn add_agent_item(agent: AgentItem, principalid: String) -> Result<u64, String>
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
+fn batch_add_agent_items(agents: Vec<AgentItem>, principalid: String) -> Result<Vec<u64>, String> {
+    ic_cdk::println!("CALL[batch_add_agent_items] Input: caller_id={}, count={}", principalid, agents.len());
+    let result = agent_asset_types::batch_add_agent_items(agents, principalid);
+    ic_cdk::println!("CALL[batch_add_agent_items] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
 fn update_agent_item(index: u64, mut agent: AgentItem) -> Result<(), String> {
     let caller_id = caller().to_string();
     ic_cdk::println!("CALL[update_agent_item] Input: caller_id={}, index={}, agent={:?}", caller_id, index, agent);
@@ -288,6 +647,7 @@ fn update_agent_item(index: u64, mut agent: AgentItem) -> Result<(), String> {
 // ==== MCP Asset API ====
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_mcp_item(name: String) -> Option<McpItem> {
     ic_cdk::println!("CALL[get_mcp_item] Input: name={}", name);
     let result = mcp_asset_types::get_mcp_item(name);
@@ -296,14 +656,26 @@ fn get_mcp_item(name: String) -> Option<McpItem> {
 }
 
 #[ic_cdk::query]
-fn get_all_mcp_items() -> Vec<McpItem> {
-    ic_cdk::println!("CALL[get_all_mcp_items] Input: none");
-    let result = mcp_asset_types::get_all_mcp_items();
-    ic_cdk::println!("CALL[get_all_mcp_items] Output: count={}", result.len());
+#[candid_method(query)]
+fn get_all_mcp_items(start: Option<String>) -> PagedResult<McpItem> {
+    ic_cdk::println!("CALL[get_all_mcp_items] Input: start={:?}", start);
+    let result = mcp_asset_types::get_all_mcp_items_guarded(false, Some(vec![mcp_asset_types::McpStatus::Published]), &InstructionGuard::new(start));
+    ic_cdk::println!("CALL[get_all_mcp_items] Output: count={}, truncated={}", result.items.len(), result.truncated);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_all_mcp_items_by_status(include_statuses: Option<Vec<mcp_asset_types::McpStatus>>, start: Option<String>) -> PagedResult<McpItem> {
+    ic_cdk::println!("CALL[get_all_mcp_items_by_status] Input: include_statuses={:?}, start={:?}", include_statuses, start);
+    let statuses = include_statuses.unwrap_or_else(|| vec![mcp_asset_types::McpStatus::Published]);
+    let result = mcp_asset_types::get_all_mcp_items_guarded(false, Some(statuses), &InstructionGuard::new(start));
+    ic_cdk::println!("CALL[get_all_mcp_items_by_status] Output: count={}, truncated={}", result.items.len(), result.truncated);
     result
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_user_mcp_items() -> Vec<McpItem> {
     let caller_id = caller().to_string();
     ic_cdk::println!("CALL[get_user_mcp_items] Input: caller_id={}", caller_id);
@@ -313,7 +685,9 @@ fn get_user_mcp_items() -> Vec<McpItem> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_mcp_items_paginated(offset: u64, limit: u64) -> Vec<McpItem> {
+    let limit = clamp_page_size(limit);
     ic_cdk::println!("CALL[get_mcp_items_paginated] Input: offset={}, limit={}", offset, limit);
     let result = mcp_asset_types::get_mcp_items_paginated(offset, limit);
     ic_cdk::println!("CALL[get_mcp_items_paginated] Output: count={}", result.len());
@@ -321,7 +695,18 @@ fn get_mcp_items_paginated(offset: u64, limit: u64) -> Vec<McpItem> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
+fn get_mcp_items_paginated_cursor(cursor: Option<String>, limit: usize) -> CursorPage<McpItem> {
+    ic_cdk::println!("CALL[get_mcp_items_paginated_cursor] Input: cursor={:?}, limit={}", cursor, limit);
+    let result = mcp_asset_types::get_mcp_items_paginated_cursor(cursor, limit);
+    ic_cdk::println!("CALL[get_mcp_items_paginated_cursor] Output: count={}, next_cursor={:?}", result.items.len(), result.next_cursor);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
 fn get_user_mcp_items_paginated(offset: u64, limit: usize) -> Vec<McpItem> {
+    let limit = clamp_page_size(limit as u64) as usize;
     let caller_id = caller().to_string();
     ic_cdk::println!("CALL[get_user_mcp_items_paginated] Input: caller_id={}, offset={}, limit={}", caller_id, offset, limit);
     let result = mcp_asset_types::get_user_mcp_items_paginated(caller_id, offset, limit);
@@ -330,6 +715,7 @@ fn get_user_mcp_items_paginated(offset: u64, limit: usize) -> Vec<McpItem> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_mcp_item_by_name(name: String) -> Option<McpItem> {
     ic_cdk::println!("CALL[get_mcp_item_by_name] Input: name={}", name);
     let result = mcp_asset_types::get_mcp_item(name);
@@ -338,6 +724,7 @@ fn get_mcp_item_by_name(name: String) -> Option<McpItem> {
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn add_mcp_item(mcp: McpItem, principalid: String) -> Result<String, String> {
     let caller_id = principalid;
     ic_cdk::println!("CALL[add_mcp_item] Input: caller_id={}, mcp={:?}", caller_id, mcp);
@@ -347,48 +734,237 @@ fn add_mcp_item(mcp: McpItem, principalid: String) -> Result<String, String> {
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
+fn add_mcp_item_with_result(mcp: McpItem, principalid: String) -> Result<mcp_asset_types::McpCreateResult, String> {
+    let caller_id = principalid;
+    ic_cdk::println!("CALL[add_mcp_item_with_result] Input: caller_id={}, mcp={:?}", caller_id, mcp);
+    let result = mcp_asset_types::add_mcp_item_with_result(mcp, caller_id);
+    ic_cdk::println!("CALL[add_mcp_item_with_result] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
 fn update_mcp_item(name: String, mut mcp: McpItem) -> Result<(), String> {
     let caller_id = caller().to_string();
     ic_cdk::println!("CALL[update_mcp_item] Input: caller_id={}, name={}, mcp={:?}", caller_id, name, mcp);
     mcp.owner = caller_id;
-    let result = mcp_asset_types::update_mcp_item(name, mcp);
+    let previous = mcp_asset_types::get_mcp_item(name.clone());
+    let result = mcp_asset_types::update_mcp_item(name.clone(), mcp);
+    if result.is_ok() {
+        sync_inverted_index_after_update(previous, name);
+    }
     ic_cdk::println!("CALL[update_mcp_item] Output: {:?}", result);
     result
 }
 
+// Regenerate an MCP's derived inverted index entries when its name or description changed;
+// curated entries are left untouched
+fn sync_inverted_index_after_update(previous: Option<McpItem>, name: String) {
+    let (Some(previous), Some(updated)) = (previous, mcp_asset_types::get_mcp_item(name)) else {
+        return;
+    };
+    if previous.name == updated.name && previous.description == updated.description {
+        return;
+    }
+    if aio_invert_index_types::delete_derived_inverted_index_by_mcp(previous.name.clone()).is_err() {
+        ic_cdk::println!("Warning: Failed to delete derived inverted index for MCP: {}", previous.name);
+    }
+    let entries = aio_invert_index_types::derive_index_entries_from_mcp(&updated);
+    if let Err(e) = aio_invert_index_types::store_inverted_index(entries) {
+        ic_cdk::println!("Warning: Failed to store derived inverted index for MCP {}: {}", updated.name, e);
+    }
+}
+
 #[ic_cdk::update]
+#[candid_method(update)]
 fn delete_mcp_item(name: String) -> Result<(), String> {
     ic_cdk::println!("CALL[delete_mcp_item] Input: name={}", name);
-    
-    // First delete the MCP item
-    let delete_result = mcp_asset_types::delete_mcp_item(name.clone());
-    
-    if delete_result.is_ok() {
-        // Delete the inverted index
-        ic_cdk::println!("CALL[delete_mcp_item] Deleting inverted index for MCP: {}", name);
-        let index_result = aio_invert_index_types::delete_inverted_index_by_mcp(name.clone());
-        if index_result.is_err() {
+    require_owner_or_operator(mcp_asset_types::get_mcp_owner(name.clone()))?;
+    // Soft delete; index entries are dropped when the tombstone is purged
+    let result = mcp_asset_types::delete_soft(name);
+    ic_cdk::println!("CALL[delete_mcp_item] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn restore_mcp_item(name: String) -> Result<(), String> {
+    ic_cdk::println!("CALL[restore_mcp_item] Input: name={}", name);
+    require_owner_or_operator(mcp_asset_types::get_mcp_owner(name.clone()))?;
+    let result = mcp_asset_types::restore_mcp_item(name);
+    ic_cdk::println!("CALL[restore_mcp_item] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn transition_mcp_status(name: String, new_status: mcp_asset_types::McpStatus) -> Result<(), String> {
+    ic_cdk::println!("CALL[transition_mcp_status] Input: name={}, new_status={:?}", name, new_status);
+    require_owner_or_operator(mcp_asset_types::get_mcp_owner(name.clone()))?;
+    let result = mcp_asset_types::transition_mcp_status(name, new_status);
+    ic_cdk::println!("CALL[transition_mcp_status] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_mcp_health(mcp_name: String) -> Option<mcp_asset_types::McpHealth> {
+    ic_cdk::println!("CALL[get_mcp_health] Input: mcp_name={}", mcp_name);
+    let result = mcp_asset_types::get_mcp_health(mcp_name);
+    ic_cdk::println!("CALL[get_mcp_health] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_unhealthy_mcps(offset: u64, limit: u64) -> Vec<McpItem> {
+    let limit = clamp_page_size(limit);
+    ic_cdk::println!("CALL[get_unhealthy_mcps] Input: offset={}, limit={}", offset, limit);
+    let result = mcp_asset_types::get_unhealthy_mcps(offset, limit as usize);
+    ic_cdk::println!("CALL[get_unhealthy_mcps] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_mcp_items_by_tags(tags: Vec<String>, mode: mcp_asset_types::TagMatchMode) -> Vec<McpItem> {
+    ic_cdk::println!("CALL[get_mcp_items_by_tags] Input: tags={:?}, mode={:?}", tags, mode);
+    let result = mcp_asset_types::get_mcp_items_by_tags(tags, mode);
+    ic_cdk::println!("CALL[get_mcp_items_by_tags] Output: {} items", result.len());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_all_mcp_tags() -> Vec<(String, u64)> {
+    ic_cdk::println!("CALL[get_all_mcp_tags] Input: none");
+    let result = mcp_asset_types::get_all_mcp_tags();
+    ic_cdk::println!("CALL[get_all_mcp_tags] Output: {} tags", result.len());
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn add_mcp_rating(mcp_name: String, score: u8, comment: String) -> Result<(), String> {
+    let caller_id = caller().to_string();
+    ic_cdk::println!("CALL[add_mcp_rating] Input: caller_id={}, mcp_name={}, score={}", caller_id, mcp_name, score);
+    let result = mcp_asset_types::add_mcp_rating(caller_id, mcp_name, score, comment);
+    ic_cdk::println!("CALL[add_mcp_rating] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_mcp_ratings(mcp_name: String, offset: u64, limit: usize) -> Vec<mcp_asset_types::RatingEntry> {
+    let limit = clamp_page_size(limit as u64) as usize;
+    ic_cdk::println!("CALL[get_mcp_ratings] Input: mcp_name={}, offset={}, limit={}", mcp_name, offset, limit);
+    let result = mcp_asset_types::get_mcp_ratings(mcp_name, offset, limit);
+    ic_cdk::println!("CALL[get_mcp_ratings] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn endorse_agent(agent_name: String, reason: Option<String>) -> Result<(), String> {
+    let caller_id = caller().to_string();
+    ic_cdk::println!("CALL[endorse_agent] Input: caller_id={}, agent_name={}", caller_id, agent_name);
+    let result = endorsement_types::endorse(caller_id, agent_name, EndorsementTarget::Agent, reason);
+    ic_cdk::println!("CALL[endorse_agent] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn endorse_mcp(mcp_name: String, reason: Option<String>) -> Result<(), String> {
+    let caller_id = caller().to_string();
+    ic_cdk::println!("CALL[endorse_mcp] Input: caller_id={}, mcp_name={}", caller_id, mcp_name);
+    let result = endorsement_types::endorse(caller_id, mcp_name, EndorsementTarget::Mcp, reason);
+    ic_cdk::println!("CALL[endorse_mcp] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_endorsements_for_agent(name: String) -> Vec<Endorsement> {
+    ic_cdk::println!("CALL[get_endorsements_for_agent] Input: name={}", name);
+    let result = endorsement_types::get_endorsements(name, EndorsementTarget::Agent);
+    ic_cdk::println!("CALL[get_endorsements_for_agent] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_endorsements_for_mcp(name: String) -> Vec<Endorsement> {
+    ic_cdk::println!("CALL[get_endorsements_for_mcp] Input: name={}", name);
+    let result = endorsement_types::get_endorsements(name, EndorsementTarget::Mcp);
+    ic_cdk::println!("CALL[get_endorsements_for_mcp] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_endorsement_count(name: String, target_type: EndorsementTarget) -> u64 {
+    endorsement_types::get_endorsement_count(name, target_type)
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_mcp_average_rating(mcp_name: String) -> Option<f32> {
+    ic_cdk::println!("CALL[get_mcp_average_rating] Input: mcp_name={}", mcp_name);
+    let result = mcp_asset_types::get_mcp_average_rating(mcp_name);
+    ic_cdk::println!("CALL[get_mcp_average_rating] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_all_mcp_items_admin(include_deleted: bool, start: Option<String>) -> Result<PagedResult<McpItem>, String> {
+    ic_cdk::println!("CALL[get_all_mcp_items_admin] Input: include_deleted={}, start={:?}", include_deleted, start);
+    require_role!(Role::Admin);
+    let result = mcp_asset_types::get_all_mcp_items_guarded(include_deleted, None, &InstructionGuard::new(start));
+    ic_cdk::println!("CALL[get_all_mcp_items_admin] Output: count={}, truncated={}", result.items.len(), result.truncated);
+    Ok(result)
+}
+
+/// Physically remove agent, MCP and profile tombstones deleted before the cutoff
+#[ic_cdk::update]
+#[candid_method(update)]
+fn purge_deleted_older_than(cutoff_ns: u64) -> Result<u64, String> {
+    ic_cdk::println!("CALL[purge_deleted_older_than] Input: cutoff_ns={}", cutoff_ns);
+    require_role!(Role::Admin);
+
+    let agents = agent_asset_types::purge_deleted_older_than(cutoff_ns);
+    let mcp_names = mcp_asset_types::purge_deleted_older_than(cutoff_ns);
+    let manager = AioIndexManager::new();
+    for name in &mcp_names {
+        if aio_invert_index_types::delete_inverted_index_by_mcp(name.clone()).is_err() {
             ic_cdk::println!("Warning: Failed to delete inverted index for MCP: {}", name);
-            // We don't return error here as the MCP was successfully deleted
         }
-
-        // Delete the index info from aio_protocal_types
-        let manager = AioIndexManager::new();
-        let protocol_result = manager.delete(&name);
-        ic_cdk::println!("CALL[delete_mcp_item] Deleting index info from aio_protocal_types for MCP: {}", name);
-        if protocol_result.is_err() {
+        if manager.delete(name).is_err() {
             ic_cdk::println!("Warning: Failed to delete index info from aio_protocal_types for MCP: {}", name);
-            // We don't return error here as the MCP was successfully deleted
         }
     }
-    
-    ic_cdk::println!("CALL[delete_mcp_item] Output: {:?}", delete_result);
-    delete_result
+    let profiles = society_profile_types::purge_deleted_profiles_older_than(cutoff_ns);
+
+    let result = agents + mcp_names.len() as u64 + profiles;
+    ic_cdk::println!("CALL[purge_deleted_older_than] Output: agents={}, mcps={}, profiles={}", agents, mcp_names.len(), profiles);
+    Ok(result)
+}
+
+// Owners manage their own items; anyone else needs at least the operator role
+fn require_owner_or_operator(owner: Option<String>) -> Result<(), String> {
+    if owner.as_deref() == Some(caller().to_string().as_str()) {
+        return Ok(());
+    }
+    require_role!(Role::Operator);
+    Ok(())
 }
 
 // ==== Work Ledger API - Trace System ====
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_trace(trace_id: String) -> Option<TraceLog> {
     ic_cdk::println!("CALL[get_trace] Input: trace_id={}", trace_id);
     let result = trace_storage::get_trace_by_id(trace_id);
@@ -397,6 +973,16 @@ fn get_trace(trace_id: String) -> Option<TraceLog> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
+fn get_all_traces_for_principal(principal_id: String, start: Option<String>) -> PagedResult<UnifiedTrace> {
+    ic_cdk::println!("CALL[get_all_traces_for_principal] Input: principal_id={}, start={:?}", principal_id, start);
+    let result = trace_storage::get_all_traces_for_principal(principal_id, &InstructionGuard::new(start));
+    ic_cdk::println!("CALL[get_all_traces_for_principal] Output: count={}, truncated={}", result.items.len(), result.truncated);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
 fn get_trace_by_context(context_id: String) -> Option<TraceLog> {
     ic_cdk::println!("CALL[get_trace_by_context] Input: context_id={}", context_id);
     let result = trace_storage::get_trace_by_context_id(context_id);
@@ -405,15 +991,18 @@ fn get_trace_by_context(context_id: String) -> Option<TraceLog> {
 }
 
 #[ic_cdk::query]
-fn get_all_traces() -> Vec<TraceLog> {
-    ic_cdk::println!("CALL[get_all_traces] Input: none");
-    let result = trace_storage::get_all_trace_logs();
-    ic_cdk::println!("CALL[get_all_traces] Output: count={}", result.len());
+#[candid_method(query)]
+fn get_all_traces(start: Option<String>) -> PagedResult<TraceLog> {
+    ic_cdk::println!("CALL[get_all_traces] Input: start={:?}", start);
+    let result = trace_storage::get_all_trace_logs_guarded(&InstructionGuard::new(start));
+    ic_cdk::println!("CALL[get_all_traces] Output: count={}, truncated={}", result.items_processed, result.truncated);
     result
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_traces_paginated(offset: u64, limit: usize) -> Vec<TraceLog> {
+    let limit = clamp_page_size(limit as u64) as usize;
     ic_cdk::println!("CALL[get_traces_paginated] Input: offset={}, limit={}", offset, limit);
     let result = trace_storage::get_traces_paginated(offset, limit as u64);
     ic_cdk::println!("CALL[get_traces_paginated] Output: count={}", result.len());
@@ -421,6 +1010,57 @@ fn get_traces_paginated(offset: u64, limit: usize) -> Vec<TraceLog> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
+fn get_traces_by_agent_name(agent_name: String, offset: u64, limit: u64) -> Vec<TraceLog> {
+    let limit = clamp_page_size(limit);
+    ic_cdk::println!("CALL[get_traces_by_agent_name] Input: agent_name={}, offset={}, limit={}", agent_name, offset, limit);
+    let result = trace_storage::get_traces_by_agent_name(agent_name, offset, limit);
+    ic_cdk::println!("CALL[get_traces_by_agent_name] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_agent_execution_stats(agent_name: String) -> trace_storage::AgentExecutionStats {
+    ic_cdk::println!("CALL[get_agent_execution_stats] Input: agent_name={}", agent_name);
+    let result = trace_storage::get_agent_execution_stats(agent_name);
+    ic_cdk::println!("CALL[get_agent_execution_stats] Output: {:?}", result);
+    result
+}
+
+/// Export a page of traces as CSV; `anonymize` hashes principal ids and redacts payloads
+#[ic_cdk::query]
+#[candid_method(query)]
+fn export_traces_csv(offset: u64, limit: u64, anonymize: bool) -> Result<String, String> {
+    ic_cdk::println!("CALL[export_traces_csv] Input: offset={}, limit={}, anonymize={}", offset, limit, anonymize);
+    require_role!(Role::Admin);
+    let result = trace_storage::export_traces_csv(offset, clamp_page_size(limit), anonymize);
+    ic_cdk::println!("CALL[export_traces_csv] Output: {:?} bytes", result.as_ref().map(|csv| csv.len()));
+    result
+}
+
+/// Export traces in a time window as newline-delimited JSON for analytics ingestion
+#[ic_cdk::query]
+#[candid_method(query)]
+fn export_traces_ndjson(start_ns: u64, end_ns: u64, start_trace_id: Option<String>) -> Result<String, String> {
+    ic_cdk::println!("CALL[export_traces_ndjson] Input: start_ns={}, end_ns={}, start_trace_id={:?}", start_ns, end_ns, start_trace_id);
+    require_role!(Role::Admin);
+    let result = trace_storage::export_traces_ndjson(start_ns, end_ns, start_trace_id);
+    ic_cdk::println!("CALL[export_traces_ndjson] Output: {} bytes", result.len());
+    Ok(result)
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_traces_paginated_cursor(cursor: Option<String>, limit: usize) -> CursorPage<TraceLog> {
+    ic_cdk::println!("CALL[get_traces_paginated_cursor] Input: cursor={:?}, limit={}", cursor, limit);
+    let result = trace_storage::get_traces_paginated_cursor(cursor, limit);
+    ic_cdk::println!("CALL[get_traces_paginated_cursor] Output: count={}, next_cursor={:?}", result.items.len(), result.next_cursor);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
 fn get_traces_by_protocol(protocol: String) -> Vec<TraceLog> {
     ic_cdk::println!("CALL[get_traces_by_protocol] Input: protocol={}", protocol);
     let result = trace_storage::get_traces_by_protocol_name(protocol);
@@ -429,6 +1069,7 @@ fn get_traces_by_protocol(protocol: String) -> Vec<TraceLog> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_traces_by_method(method: String) -> Vec<TraceLog> {
     ic_cdk::println!("CALL[get_traces_by_method] Input: method={}", method);
     let result = trace_storage::get_traces_by_method_name(method);
@@ -437,6 +1078,7 @@ fn get_traces_by_method(method: String) -> Vec<TraceLog> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_traces_by_status(status: String) -> Vec<TraceLog> {
     ic_cdk::println!("CALL[get_traces_by_status] Input: status={}", status);
     let result = trace_storage::get_traces_by_status(status, 0, u64::MAX);
@@ -445,7 +1087,9 @@ fn get_traces_by_status(status: String) -> Vec<TraceLog> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_traces_by_status_paginated(status: String, offset: u64, limit: u64) -> Vec<TraceLog> {
+    let limit = clamp_page_size(limit);
     ic_cdk::println!("CALL[get_traces_by_status_paginated] Input: status={}, offset={}, limit={}", status, offset, limit);
     let result = trace_storage::get_traces_by_status(status, offset, limit);
     ic_cdk::println!("CALL[get_traces_by_status_paginated] Output: count={}", result.len());
@@ -453,6 +1097,27 @@ fn get_traces_by_status_paginated(status: String, offset: u64, limit: u64) -> Ve
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
+fn get_traces_by_time_range(start_ns: u64, end_ns: u64, offset: u64, limit: u64) -> Vec<TraceLog> {
+    let limit = clamp_page_size(limit);
+    ic_cdk::println!("CALL[get_traces_by_time_range] Input: start_ns={}, end_ns={}, offset={}, limit={}", start_ns, end_ns, offset, limit);
+    let result = trace_storage::get_traces_by_time_range(start_ns, end_ns, offset, limit);
+    ic_cdk::println!("CALL[get_traces_by_time_range] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn rebuild_trace_time_index() -> Result<u64, String> {
+    ic_cdk::println!("CALL[rebuild_trace_time_index] Input: none");
+    require_role!(Role::Admin);
+    let result = trace_storage::rebuild_trace_time_index();
+    ic_cdk::println!("CALL[rebuild_trace_time_index] Output: {}", result);
+    Ok(result)
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
 fn get_traces_with_filters(
     protocols: Option<Vec<String>>,
     methods: Option<Vec<String>>,
@@ -481,6 +1146,7 @@ struct TraceStatisticsResult {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_traces_statistics() -> TraceStatistics {
     ic_cdk::println!("CALL[get_traces_statistics] Input: none");
     let result = trace_storage::get_traces_statistics(0, u64::MAX, u64::MAX);
@@ -490,6 +1156,7 @@ fn get_traces_statistics() -> TraceStatistics {
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn record_trace_call(
     trace_id: String,
     context_id: String,
@@ -501,8 +1168,9 @@ fn record_trace_call(
     output: IOValue,
     status: String,
     error_message: Option<String>,
-) -> Result<(), String> {
+) -> Result<String, String> {
     ic_cdk::println!("CALL[record_trace_call] Input: trace_id={}, context_id={}, protocol={}, method={}", trace_id, context_id, protocol, method);
+    trace_storage::check_trace_rate_limit(&caller().to_text())?;
     let result = trace_storage::record_trace_call(
         trace_id,
         context_id,
@@ -519,45 +1187,211 @@ fn record_trace_call(
     result
 }
 
+/// Call a registered agent's HTTPS endpoint on behalf of the caller, who pays the invocation fee
+#[ic_cdk::update]
+#[candid_method(update)]
+async fn invoke_agent(agent_name: String, method: String, payload: String) -> Result<String, String> {
+    ic_cdk::println!("CALL[invoke_agent] Input: agent_name={}, method={}, payload_len={}", agent_name, method, payload.len());
+    let caller_id = caller().to_text();
+    trace_storage::check_trace_rate_limit(&caller_id)?;
+    let result = agent_asset_types::invoke_agent(caller_id, agent_name, method, payload).await;
+    ic_cdk::println!("CALL[invoke_agent] Output: {:?}", result.as_ref().map(|body| body.len()));
+    result
+}
+
+/// Queue a message from one of the caller's agents to another agent; the caller pays the message fee
+#[ic_cdk::update]
+#[candid_method(update)]
+fn enqueue_agent_message(sender: String, receiver: String, payload: String, priority: u8) -> Result<u64, String> {
+    ic_cdk::println!("CALL[enqueue_agent_message] Input: sender={}, receiver={}, payload_len={}, priority={}", sender, receiver, payload.len(), priority);
+    let result = agent_message_types::enqueue_agent_message(&caller().to_text(), sender, receiver, payload, priority);
+    ic_cdk::println!("CALL[enqueue_agent_message] Output: {:?}", result);
+    result
+}
+
+/// Consume the next message for one of the caller's agents; None when the queue is empty or the caller does not own the agent
+#[ic_cdk::update]
+#[candid_method(update)]
+fn dequeue_agent_message(receiver_agent: String) -> Option<agent_message_types::AgentMessage> {
+    ic_cdk::println!("CALL[dequeue_agent_message] Input: receiver_agent={}", receiver_agent);
+    let result = agent_message_types::dequeue_agent_message(&caller().to_text(), receiver_agent)
+        .unwrap_or_else(|e| {
+            ic_cdk::println!("CALL[dequeue_agent_message] Error: {}", e);
+            None
+        });
+    ic_cdk::println!("CALL[dequeue_agent_message] Output: {:?}", result.as_ref().map(|message| message.id));
+    result
+}
+
+/// List pending messages for one of the caller's agents without consuming them
+#[ic_cdk::query]
+#[candid_method(query)]
+fn peek_agent_queue(receiver_agent: String, limit: u64) -> Vec<agent_message_types::AgentMessage> {
+    ic_cdk::println!("CALL[peek_agent_queue] Input: receiver_agent={}, limit={}", receiver_agent, limit);
+    let result = agent_message_types::peek_agent_queue(&caller().to_text(), receiver_agent, limit)
+        .unwrap_or_else(|e| {
+            ic_cdk::println!("CALL[peek_agent_queue] Error: {}", e);
+            Vec::new()
+        });
+    ic_cdk::println!("CALL[peek_agent_queue] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn set_rate_limit_config(calls_per_window: u64, window_seconds: u64) -> Result<(), String> {
+    ic_cdk::println!("CALL[set_rate_limit_config] Input: calls_per_window={}, window_seconds={}", calls_per_window, window_seconds);
+    require_role!(Role::Admin);
+    let result = trace_storage::set_rate_limit_config(calls_per_window, window_seconds);
+    ic_cdk::println!("CALL[set_rate_limit_config] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_rate_limit_status(principal_id: String) -> trace_storage::RateLimitEntry {
+    trace_storage::get_rate_limit_status(principal_id)
+}
+
+/// Instructions a bulk read may use before it returns a truncated result
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_instruction_budget() -> u64 {
+    types::get_instruction_budget()
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn set_instruction_budget(limit: u64) -> Result<(), String> {
+    ic_cdk::println!("CALL[set_instruction_budget] Input: limit={}", limit);
+    require_role!(Role::Admin);
+    let result = types::set_instruction_budget(limit);
+    ic_cdk::println!("CALL[set_instruction_budget] Output: {:?}", result);
+    result
+}
+
 // ==== AIO Protocol Index API ====
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn create_aio_index_from_json(name:String,json_str: String) -> Result<(), String> {
     ic_cdk::println!("CALL[create_aio_index_from_json] Input: name={}, json_str={}",  name, json_str);
     let manager = AioIndexManager::new();
-    let result = manager.create_from_json(&name,&json_str);
-    ic_cdk::println!("CALL[create_aio_index_from_json] Output: {:?}", result);
+    let result = manager.create_from_json(&name,&json_str);
+    ic_cdk::println!("CALL[create_aio_index_from_json] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn validate_aio_index_json(name: String, json_str: String) -> Result<(), String> {
+    ic_cdk::println!("CALL[validate_aio_index_json] Input: name={}", name);
+    let manager = AioIndexManager::new();
+    let result = manager.validate_json(&name, &json_str);
+    ic_cdk::println!("CALL[validate_aio_index_json] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_aio_index(id: String) -> Option<aio_protocal_types::AioIndex> {
+    ic_cdk::println!("CALL[get_aio_index] Input: id={}", id);
+    let manager = AioIndexManager::new();
+    let result = manager.read(&id);
+    ic_cdk::println!("CALL[get_aio_index] Output: exists={}", result.is_some());
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn clear_index_cache() {
+    ic_cdk::println!("CALL[clear_index_cache] Input: none");
+    require_role!(Role::Operator, trap);
+    aio_protocal_types::clear_index_cache();
+    ic_cdk::println!("CALL[clear_index_cache] Output: done");
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_all_aio_indices(start: Option<String>) -> PagedResult<aio_protocal_types::AioIndex> {
+    ic_cdk::println!("CALL[get_all_aio_indices] Input: start={:?}", start);
+    let manager = AioIndexManager::new();
+    let result = manager.list_all_guarded(&InstructionGuard::new(start));
+    ic_cdk::println!("CALL[get_all_aio_indices] Output: count={}, truncated={}", result.items_processed, result.truncated);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_aio_indices_paginated(offset: usize, limit: usize) -> Vec<aio_protocal_types::AioIndex> {
+    ic_cdk::println!("CALL[get_aio_indices_paginated] Input: offset={}, limit={}", offset, limit);
+    let manager = AioIndexManager::new();
+    let result = manager.get_indices_paginated(offset, limit);
+    ic_cdk::println!("CALL[get_aio_indices_paginated] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_aio_indices_by_transport(transport: String, offset: usize, limit: usize) -> Vec<aio_protocal_types::AioIndex> {
+    ic_cdk::println!("CALL[get_aio_indices_by_transport] Input: transport={}, offset={}, limit={}", transport, offset, limit);
+    let manager = AioIndexManager::new();
+    let result = manager.get_indices_by_transport(&transport, offset, limit);
+    ic_cdk::println!("CALL[get_aio_indices_by_transport] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_all_transports() -> Vec<String> {
+    let manager = AioIndexManager::new();
+    let result = manager.list_transports();
+    ic_cdk::println!("CALL[get_all_transports] Output: {:?}", result);
     result
 }
 
+#[ic_cdk::update]
+#[candid_method(update)]
+fn rebuild_aio_secondary_indices() -> Result<u64, String> {
+    ic_cdk::println!("CALL[rebuild_aio_secondary_indices] Input: none");
+    require_role!(Role::Admin);
+    let result = AioIndexManager::new().rebuild_secondary_indices();
+    ic_cdk::println!("CALL[rebuild_aio_secondary_indices] Output: {}", result);
+    Ok(result)
+}
+
 #[ic_cdk::query]
-fn get_aio_index(id: String) -> Option<aio_protocal_types::AioIndex> {
-    ic_cdk::println!("CALL[get_aio_index] Input: id={}", id);
+#[candid_method(query)]
+fn get_aio_indices_paginated_cursor(cursor: Option<String>, limit: usize) -> CursorPage<aio_protocal_types::AioIndex> {
+    ic_cdk::println!("CALL[get_aio_indices_paginated_cursor] Input: cursor={:?}, limit={}", cursor, limit);
     let manager = AioIndexManager::new();
-    let result = manager.read(&id);
-    ic_cdk::println!("CALL[get_aio_index] Output: exists={}", result.is_some());
+    let result = manager.get_indices_paginated_cursor(cursor, limit);
+    ic_cdk::println!("CALL[get_aio_indices_paginated_cursor] Output: count={}, next_cursor={:?}", result.items.len(), result.next_cursor);
     result
 }
 
 #[ic_cdk::query]
-fn get_all_aio_indices() -> Vec<aio_protocal_types::AioIndex> {
-    ic_cdk::println!("CALL[get_all_aio_indices] Input: none");
+#[candid_method(query)]
+fn search_aio_indices_by_method(method_name: String) -> Vec<aio_protocal_types::AioIndex> {
+    ic_cdk::println!("CALL[search_aio_indices_by_method] Input: method_name={}", method_name);
     let manager = AioIndexManager::new();
-    let result = manager.list_all();
-    ic_cdk::println!("CALL[get_all_aio_indices] Output: count={}", result.len());
+    let result = manager.search_by_method(&method_name);
+    ic_cdk::println!("CALL[search_aio_indices_by_method] Output: count={}", result.len());
     result
 }
 
 #[ic_cdk::query]
-fn get_aio_indices_paginated(offset: usize, limit: usize) -> Vec<aio_protocal_types::AioIndex> {
-    ic_cdk::println!("CALL[get_aio_indices_paginated] Input: offset={}, limit={}", offset, limit);
+#[candid_method(query)]
+fn search_aio_indices_by_methods_all(method_names: Vec<String>) -> Vec<aio_protocal_types::AioIndex> {
+    ic_cdk::println!("CALL[search_aio_indices_by_methods_all] Input: method_names={:?}", method_names);
     let manager = AioIndexManager::new();
-    let result = manager.get_indices_paginated(offset, limit);
-    ic_cdk::println!("CALL[get_aio_indices_paginated] Output: count={}", result.len());
+    let result = manager.search_by_methods_all(&method_names);
+    ic_cdk::println!("CALL[search_aio_indices_by_methods_all] Output: count={}", result.len());
     result
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn search_aio_indices_by_keyword(keyword: String) -> Vec<aio_protocal_types::AioIndex> {
     ic_cdk::println!("CALL[search_aio_indices_by_keyword] Input: keyword={}", keyword);
     let manager = AioIndexManager::new();
@@ -567,6 +1401,7 @@ fn search_aio_indices_by_keyword(keyword: String) -> Vec<aio_protocal_types::Aio
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn update_aio_index(id: String, json_str: String) -> Result<(), String> {
     let caller_id = caller().to_string();
     ic_cdk::println!("CALL[update_aio_index] Input: caller_id={}, id={}", caller_id, id);
@@ -603,7 +1438,45 @@ fn update_aio_index(id: String, json_str: String) -> Result<(), String> {
     }
 }
 
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_aio_index_history(id: String, limit: usize) -> Vec<aio_protocal_types::AioIndex> {
+    ic_cdk::println!("CALL[get_aio_index_history] Input: id={}, limit={}", id, limit);
+    let manager = AioIndexManager::new();
+    let result = manager.get_history(&id, limit);
+    ic_cdk::println!("CALL[get_aio_index_history] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_aio_index_version(id: String, version: u32) -> Option<aio_protocal_types::AioIndex> {
+    ic_cdk::println!("CALL[get_aio_index_version] Input: id={}, version={}", id, version);
+    let manager = AioIndexManager::new();
+    let result = manager.get_version(&id, version);
+    ic_cdk::println!("CALL[get_aio_index_version] Output: exists={}", result.is_some());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn list_aio_index_versions(id: String) -> Vec<u32> {
+    let manager = AioIndexManager::new();
+    manager.list_versions(&id)
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn find_duplicate_indices() -> Vec<(String, String)> {
+    ic_cdk::println!("CALL[find_duplicate_indices] Input: none");
+    let manager = AioIndexManager::new();
+    let result = manager.find_duplicate_indices();
+    ic_cdk::println!("CALL[find_duplicate_indices] Output: count={}", result.len());
+    result
+}
+
 #[ic_cdk::update]
+#[candid_method(update)]
 fn delete_aio_index(id: String) -> Result<(), String> {
     let caller_id = caller().to_string();
     ic_cdk::println!("CALL[delete_aio_index] Input: caller_id={}, id={}", caller_id, id);
@@ -613,7 +1486,19 @@ fn delete_aio_index(id: String) -> Result<(), String> {
     result
 }
 
+#[ic_cdk::update]
+#[candid_method(update)]
+fn delete_aio_indices(ids: Vec<String>) -> Vec<Result<(), String>> {
+    let caller_id = caller().to_string();
+    ic_cdk::println!("CALL[delete_aio_indices] Input: caller_id={}, ids={:?}", caller_id, ids);
+    let manager = AioIndexManager::new();
+    let result = manager.delete_many(&ids);
+    ic_cdk::println!("CALL[delete_aio_indices] Output: {:?}", result);
+    result
+}
+
 #[ic_cdk::query]
+#[candid_method(query)]
 fn export_aio_index_to_json(id: String) -> Result<String, String> {
     ic_cdk::println!("CALL[export_aio_index_to_json] Input: id={}", id);
     let manager = AioIndexManager::new();
@@ -643,6 +1528,17 @@ fn export_aio_index_to_json(id: String) -> Result<String, String> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
+fn generate_example_input(id: String, method: String) -> Result<String, String> {
+    ic_cdk::println!("CALL[generate_example_input] Input: id={}, method={}", id, method);
+    let manager = AioIndexManager::new();
+    let result = manager.generate_example_input(&id, &method);
+    ic_cdk::println!("CALL[generate_example_input] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
 fn get_aio_indices_count() -> usize {
     ic_cdk::println!("CALL[get_aio_indices_count] Input: none");
     let manager = AioIndexManager::new();
@@ -653,6 +1549,7 @@ fn get_aio_indices_count() -> usize {
 
 // Find the most suitable index item by keywords with strategy
 #[ic_cdk::query]
+#[candid_method(query)]
 fn revert_Index_find_by_keywords_strategy(keywords: Vec<String>) -> String {
     ic_cdk::println!("CALL[revert_Index_find_by_keywords_strategy] Input: keywords={:?}", keywords);
     let result = INVERTED_INDEX_STORE.with(|store| {
@@ -684,14 +1581,14 @@ fn now_ns() -> u64 { ic_cdk::api::time() }
 
 
 #[update]
+#[candid_method(update)]
 fn admin_set_bitpay_pos_token(token: String) {
-    if !ic_cdk::api::is_controller(&ic_cdk::api::caller()) {
-        ic_cdk::trap("Only controller can set POS token");
-    }
+    require_role!(Role::Controller, trap);
     bp_set_pos_token(token);
 }
 
 #[update]
+#[candid_method(update)]
 async fn create_order_and_invoice(args: CreateOrderArgs) -> Result<InvoiceResp, String> {
     if let Some(o) = order_types::get(&args.order_id) {
         if let (Some(id), Some(url)) = (&o.bitpay_invoice_id, &o.bitpay_invoice_url) {
@@ -746,6 +1643,7 @@ async fn create_order_and_invoice(args: CreateOrderArgs) -> Result<InvoiceResp,
 }
 
 #[query]
+#[candid_method(query)]
 fn get_order_by_id(order_id: String) -> Option<Order> {
     order_types::get(&order_id)
 }
@@ -817,11 +1715,13 @@ async fn http_request_update(req: HttpRequest) -> HttpResponse {
 // ==== Finance API ====
 
 #[ic_cdk::update]
+#[candid_method(update)]
 async fn get_account_info(principal_id: String) -> Option<AccountInfo> {
     token_economy::get_account_info(principal_id).await
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn add_account(principal_id: String) -> Result<AccountInfo, String> {
     ic_cdk::println!("CALL[add_account] Input: principal_id={}", principal_id);
     let result = token_economy::create_account(principal_id);
@@ -830,26 +1730,68 @@ fn add_account(principal_id: String) -> Result<AccountInfo, String> {
 }
 
 #[ic_cdk::query]
-fn get_all_accounts() -> Vec<AccountInfo> {
-    account_storage::get_all_accounts()
+#[candid_method(query)]
+fn get_all_accounts(start: Option<String>) -> PagedResult<AccountInfo> {
+    account_storage::get_all_accounts_guarded(&InstructionGuard::new(start))
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_accounts_paginated(offset: u64, limit: usize) -> Vec<AccountInfo> {
     account_storage::get_accounts_paginated(offset, limit)
 }
 
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_accounts_with_stake(offset: u64, limit: usize) -> Vec<(String, u64)> {
+    ic_cdk::println!("CALL[get_accounts_with_stake] Input: offset={}, limit={}", offset, limit);
+    let result = account_storage::get_accounts_with_stake(offset, limit);
+    ic_cdk::println!("CALL[get_accounts_with_stake] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn export_all_accounts_json(cursor: Option<String>, limit: usize) -> AccountsJsonPage {
+    ic_cdk::println!("CALL[export_all_accounts_json] Input: cursor={:?}, limit={}", cursor, limit);
+    require_role!(Role::Controller, trap);
+    match account_storage::export_all_accounts_json(caller().to_text(), cursor, limit) {
+        Ok(page) => page,
+        Err(e) => ic_cdk::trap(&e),
+    }
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn import_accounts_from_json(json: String, force: bool) -> Result<u64, String> {
+    ic_cdk::println!("CALL[import_accounts_from_json] Input: bytes={}, force={}", json.len(), force);
+    require_role!(Role::Controller);
+    let result = account_storage::import_accounts_from_json(caller().to_text(), json, force);
+    ic_cdk::println!("CALL[import_accounts_from_json] Output: {:?}", result);
+    result
+}
+
 #[ic_cdk::update]
-fn delete_account(principal_id: String) -> Result<(), String> {
-    account_storage::delete_account(principal_id)
+#[candid_method(update)]
+fn delete_account(principal_id: String, force: bool) -> Result<(), String> {
+    ic_cdk::println!("CALL[delete_account] Input: principal_id={}, force={}", principal_id, force);
+    require_profile_owner_or_admin(&principal_id)?;
+    if force {
+        require_role!(Role::Admin);
+    }
+    let result = account_storage::delete_account(principal_id, force);
+    ic_cdk::println!("CALL[delete_account] Output: {:?}", result);
+    result
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_balance_summary(principal_id: String) -> (u64, u64, u64, u64) {
     token_economy::get_balance_summary(principal_id)
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn stack_credit(principal_id: String,mcp_name:String, amount: u64) -> Result<AccountInfo, String> {
     println!("Input: stack_credit - principal_id: {}, amount: {}", principal_id, amount);
     let result = token_economy::stack_credits(principal_id, mcp_name, amount);
@@ -858,6 +1800,7 @@ fn stack_credit(principal_id: String,mcp_name:String, amount: u64) -> Result<Acc
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn unstack_credit(principal_id: String, amount: u64) -> Result<AccountInfo, String> {
     println!("Input: unstack_credit - principal_id: {}, amount: {}", principal_id, amount);
     let result = token_economy::unstack_credits(principal_id, amount);
@@ -865,78 +1808,260 @@ fn unstack_credit(principal_id: String, amount: u64) -> Result<AccountInfo, Stri
     result
 }
 
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_credit_token_rate() -> u64 {
+    token_economy::get_credit_token_rate()
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn set_credit_token_rate(credits_per_token: u64) -> Result<(), String> {
+    ic_cdk::println!("CALL[set_credit_token_rate] Input: credits_per_token={}", credits_per_token);
+    require_role!(Role::Admin);
+    let result = token_economy::set_credit_token_rate(ic_cdk::caller().to_text(), credits_per_token);
+    ic_cdk::println!("CALL[set_credit_token_rate] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn list_credit_fee_rules() -> Vec<token_economy_types::CreditFeeRule> {
+    token_economy::list_credit_fee_rules()
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_credit_fee_rule(service_pattern: String) -> Option<token_economy_types::CreditFeeRule> {
+    token_economy::get_credit_fee_rule(service_pattern)
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn set_credit_fee_rule(rule: token_economy_types::CreditFeeRule) -> Result<(), String> {
+    ic_cdk::println!("CALL[set_credit_fee_rule] Input: {:?}", rule);
+    require_role!(Role::Admin);
+    let result = token_economy::set_credit_fee_rule(rule);
+    ic_cdk::println!("CALL[set_credit_fee_rule] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn delete_credit_fee_rule(service_pattern: String) -> Result<(), String> {
+    ic_cdk::println!("CALL[delete_credit_fee_rule] Input: service_pattern={}", service_pattern);
+    require_role!(Role::Admin);
+    let result = token_economy::delete_credit_fee_rule(service_pattern);
+    ic_cdk::println!("CALL[delete_credit_fee_rule] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn airdrop_credits(recipients: Vec<String>, amount_each: u64, memo: Option<String>) -> Result<token_economy_types::AirdropResult, String> {
+    ic_cdk::println!("CALL[airdrop_credits] Input: recipients={}, amount_each={}, memo={:?}", recipients.len(), amount_each, memo);
+    require_role!(Role::Admin);
+    let result = token_economy::airdrop_credits(caller().to_text(), recipients, amount_each, memo);
+    ic_cdk::println!("CALL[airdrop_credits] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_airdrop_history(offset: u64, limit: u64) -> Result<Vec<token_economy_types::AirdropRecord>, String> {
+    let limit = clamp_page_size(limit);
+    ic_cdk::println!("CALL[get_airdrop_history] Input: offset={}, limit={}", offset, limit);
+    require_role!(Role::Admin);
+    let result = token_economy::get_airdrop_history(offset, limit);
+    ic_cdk::println!("CALL[get_airdrop_history] Output: count={}", result.len());
+    Ok(result)
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn convert_credits_to_tokens(principal_id: String, credit_amount: u64) -> Result<AccountInfo, String> {
+    ic_cdk::println!("CALL[convert_credits_to_tokens] Input: principal_id={}, credit_amount={}", principal_id, credit_amount);
+    require_profile_owner_or_admin(&principal_id)?;
+    let result = token_economy::convert_credits_to_tokens(principal_id, credit_amount);
+    ic_cdk::println!("CALL[convert_credits_to_tokens] Output: {:?}", result);
+    result
+}
+
 #[ic_cdk::update]
+#[candid_method(update)]
 fn add_token_balance(principal_id: String, amount: u64) -> Result<AccountInfo, String> {
     println!("Input: add_token_balance - principal_id: {}, amount: {}", principal_id, amount);
     let result = token_economy::update_account_balance(principal_id, amount as i64, 0);
+    if result.is_ok() {
+        token_economy::record_minted(amount);
+    }
     println!("Output: add_token_balance - result: {:?}", result);
     result
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_traces_by_operation(principal_id: String, operation: String) -> Vec<TraceItem> {
     trace_storage::get_traces_by_operation(principal_id, operation)
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_traces_by_time_period(principal_id: String, time_period: String) -> Vec<TraceItem> {
     trace_storage::get_traces_by_time_period(principal_id, time_period)
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_traces_sorted(principal_id: String, sort_by: String, ascending: bool) -> Vec<TraceItem> {
     trace_storage::get_traces_sorted(principal_id, sort_by, ascending)
 }
 
 // Token Economy API
 #[ic_cdk::update]
+#[candid_method(update)]
 fn init_emission_policy() {
     token_economy::init_emission_policy();
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn calculate_emission(principal_id: String) -> Result<u64, String> {
     token_economy::calculate_emission(&principal_id)
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_emission_policy() -> Result<EmissionPolicy, String> {
     token_economy::get_emission_policy()
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn update_emission_policy(policy: EmissionPolicy) -> Result<(), String> {
+    require_role!(Role::Admin);
     token_economy::update_emission_policy(policy)
 }
 
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_emission_snapshots_by_period(principal_id: String, start: u64, end: u64) -> Vec<token_economy_types::EmissionSnapshot> {
+    ic_cdk::println!("CALL[get_emission_snapshots_by_period] Input: principal_id={}, start={}, end={}", principal_id, start, end);
+    let result = token_economy::get_emission_snapshots_by_period(&principal_id, start, end);
+    ic_cdk::println!("CALL[get_emission_snapshots_by_period] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_emission_cooldown_ns() -> u64 {
+    token_economy::get_emission_cooldown_ns()
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn set_emission_cooldown_ns(cooldown_ns: u64) -> Result<(), String> {
+    ic_cdk::println!("CALL[set_emission_cooldown_ns] Input: cooldown_ns={}", cooldown_ns);
+    require_role!(Role::Admin);
+    let result = token_economy::set_emission_cooldown_ns(cooldown_ns);
+    ic_cdk::println!("CALL[set_emission_cooldown_ns] Output: {:?}", result);
+    result
+}
+
+/// Pay out a staked account's current emission, at most once per emission cooldown (operator only)
+#[ic_cdk::update]
+#[candid_method(update)]
+fn claim_emission(principal_id: String) -> Result<u64, String> {
+    require_role!(Role::Operator);
+    ic_cdk::println!("CALL[claim_emission] Input: principal_id={}", principal_id);
+    let result = token_economy::claim_emission(&principal_id);
+    ic_cdk::println!("CALL[claim_emission] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_min_stake_amount() -> u64 {
+    token_economy::get_min_stake_amount()
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn set_min_stake_amount(amount: u64) -> Result<(), String> {
+    ic_cdk::println!("CALL[set_min_stake_amount] Input: amount={}", amount);
+    require_role!(Role::Admin);
+    token_economy::set_min_stake_amount(amount)
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_settings() -> Vec<(String, settings::SettingValue)> {
+    settings::list_settings()
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn update_setting(key: String, value: settings::SettingValue) -> Result<(), String> {
+    ic_cdk::println!("CALL[update_setting] Input: key={}, value={:?}", key, value);
+    require_role!(Role::Admin);
+    let result = settings::update_setting(key, value);
+    ic_cdk::println!("CALL[update_setting] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_subscription_multiplier(plan: SubscriptionPlan) -> Result<f64, String> {
+    token_economy::get_subscription_multiplier(plan)
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn set_subscription_multiplier(plan: SubscriptionPlan, value: f64) -> Result<(), String> {
+    require_role!(Role::Admin);
+    token_economy::set_subscription_multiplier(plan, value)
+}
+
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_token_grant(recipient: String) -> bool {
     token_economy::get_token_grant(&recipient).is_some()
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn check_is_newuser(principal_id: String) -> bool {
     token_economy::get_token_grant(&principal_id).is_none()
 }
 
+#[ic_cdk::query]
+#[candid_method(query)]
+fn is_new_user(principal_id: String) -> bool {
+    token_economy::is_new_user(&principal_id)
+}
+
 
 #[ic_cdk::query]
-fn get_all_token_grants() -> Vec<TokenGrant> {
-    token_economy::get_all_token_grants()
+#[candid_method(query)]
+fn get_all_token_grants(start: Option<String>) -> PagedResult<TokenGrant> {
+    token_economy::get_all_token_grants_guarded(&InstructionGuard::new(start))
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_token_grants_paginated(offset: u64, limit: usize) -> Vec<TokenGrant> {
     token_economy::get_token_grants_paginated(offset, limit)
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_token_grants_by_recipient(recipient: String) -> Vec<TokenGrant> {
     token_economy::get_token_grants_by_recipient(&recipient)
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_token_grants_by_status(status: String) -> Vec<TokenGrant> {
     let grant_status = match status.as_str() {
         "Pending" => TokenGrantStatus::Pending,
@@ -949,73 +2074,107 @@ fn get_token_grants_by_status(status: String) -> Vec<TokenGrant> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_token_grants_count() -> u64 {
     token_economy::get_token_grants_count()
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_account_token_info(principal_id: String) -> Result<TokenInfo, String> {
     token_economy::get_account_token_info(&principal_id)
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn log_credit_usage(principal_id: String, amount: u64, service: String, metadata: Option<String>) -> Result<(), String> {
     token_economy::log_credit_usage(principal_id, amount, service, metadata)
 }
 
 // Token Activity API
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_token_activities(principal_id: String) -> Vec<TokenActivity> {
     token_economy::get_token_activities(&principal_id)
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_token_activities_paginated(principal_id: String, offset: u64, limit: usize) -> Vec<TokenActivity> {
+    let limit = clamp_page_size(limit as u64) as usize;
     token_economy::get_token_activities_paginated(&principal_id, offset, limit)
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
+fn get_token_activities_between(a: String, b: String, offset: u64, limit: usize) -> Vec<TokenActivity> {
+    token_economy::get_token_activities_between(&a, &b, offset, limit)
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
 fn get_token_activities_by_type(principal_id: String, activity_type: TokenActivityType) -> Vec<TokenActivity> {
     token_economy::get_token_activities_by_type(&principal_id, activity_type)
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_token_activities_by_time_period(principal_id: String, start_time: u64, end_time: u64) -> Vec<TokenActivity> {
     token_economy::get_token_activities_by_time_period(&principal_id, start_time, end_time)
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_token_activity_statistics(principal_id: String) -> (u64, u64, u64) {
     token_economy::get_token_activity_statistics(&principal_id)
 }
 
 // Credit Activity API
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_credit_activities(principal_id: String) -> Vec<CreditActivity> {
     token_economy::get_credit_activities(&principal_id)
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_credit_activities_paginated(principal_id: String, offset: u64, limit: usize) -> Vec<CreditActivity> {
+    let limit = clamp_page_size(limit as u64) as usize;
     token_economy::get_credit_activities_paginated(&principal_id, offset, limit)
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
+fn get_credit_usage_by_service(service: String, offset: u64, limit: usize) -> Vec<CreditActivity> {
+    token_economy::get_credit_usage_by_service(&service, offset, limit)
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_top_services_by_spend(limit: usize) -> Vec<(String, u64)> {
+    token_economy::get_top_services_by_spend(limit)
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
 fn get_credit_activities_by_type(principal_id: String, activity_type: CreditActivityType) -> Vec<CreditActivity> {
     token_economy::get_credit_activities_by_type(&principal_id, activity_type)
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_credit_activities_by_time_period(principal_id: String, start_time: u64, end_time: u64) -> Vec<CreditActivity> {
     token_economy::get_credit_activities_by_time_period(&principal_id, start_time, end_time)
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_credit_activity_statistics(principal_id: String) -> (u64, u64, u64) {
     token_economy::get_credit_activity_statistics(&principal_id)
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn use_credit(principal_id: String, amount: u64, service: String, metadata: Option<String>) -> Result<AccountInfo, String> {
     println!("Input: use_credit - principal_id: {}, amount: {}, service: {}", principal_id, amount, service);
     let result = token_economy::use_credits(principal_id, amount, service, metadata);
@@ -1024,6 +2183,69 @@ fn use_credit(principal_id: String, amount: u64, service: String, metadata: Opti
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
+fn delegate_credits(delegate: String, max_amount: u64, expires_at: u64) -> Result<(), String> {
+    let delegator = caller().to_string();
+    ic_cdk::println!("CALL[delegate_credits] Input: delegator={}, delegate={}, max_amount={}, expires_at={}", delegator, delegate, max_amount, expires_at);
+    let result = token_economy::delegate_credits(delegator, delegate, max_amount, expires_at);
+    ic_cdk::println!("CALL[delegate_credits] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn revoke_delegation(delegate: String) -> Result<(), String> {
+    let delegator = caller().to_string();
+    ic_cdk::println!("CALL[revoke_delegation] Input: delegator={}, delegate={}", delegator, delegate);
+    let result = token_economy::revoke_delegation(delegator, delegate);
+    ic_cdk::println!("CALL[revoke_delegation] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn use_credits_on_behalf(delegator: String, amount: u64, service: String, metadata: Option<String>) -> Result<AccountInfo, String> {
+    let delegate = caller().to_string();
+    ic_cdk::println!("CALL[use_credits_on_behalf] Input: delegate={}, delegator={}, amount={}, service={}", delegate, delegator, amount, service);
+    let result = token_economy::use_credits_on_behalf(delegate, delegator, amount, service, metadata);
+    ic_cdk::println!("CALL[use_credits_on_behalf] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn dispute_credit_usage(principal_id: String, activity_index: u64, reason: String) -> Result<(), String> {
+    ic_cdk::println!("CALL[dispute_credit_usage] Input: principal_id={}, activity_index={}", principal_id, activity_index);
+    require_profile_owner_or_admin(&principal_id)?;
+    let result = token_economy::dispute_credit_usage(principal_id, activity_index, reason);
+    ic_cdk::println!("CALL[dispute_credit_usage] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn resolve_dispute(principal_id: String, activity_index: u64, approve: bool) -> Result<(), String> {
+    ic_cdk::println!("CALL[resolve_dispute] Input: principal_id={}, activity_index={}, approve={}", principal_id, activity_index, approve);
+    require_role!(Role::Admin);
+    let result = token_economy::resolve_dispute(principal_id, activity_index, approve);
+    ic_cdk::println!("CALL[resolve_dispute] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_credit_dispute(principal_id: String, activity_index: u64) -> Option<CreditDispute> {
+    token_economy::get_credit_dispute(principal_id, activity_index)
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_credit_disputes_by_principal(principal_id: String) -> Vec<CreditDispute> {
+    token_economy::get_credit_disputes_by_principal(&principal_id)
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
 fn grant_token(grant: TokenGrant) -> Result<(), String> {
     println!("Input: grant_token - grant: {:?}", grant);
     
@@ -1033,19 +2255,22 @@ fn grant_token(grant: TokenGrant) -> Result<(), String> {
     let activity = TokenActivity {
         timestamp: ic_cdk::api::time() / 1_000_000,
         from: "system".to_string(),
-        to: grant.recipient,
+        to: grant.recipient.clone(),
         amount: grant.amount,
         activity_type: TokenActivityType::Grant,
         status: TokenTransferStatus::Completed,
         metadata: Some("Token grant".to_string()),
     };
     record_token_activity(activity)?;
+    token_economy::record_minted(grant.amount);
+    token_economy::notify_grant_recipient(&grant.recipient, "token")?;
     
     println!("Output: grant_token - result: {:?}", result);
     Ok(result)
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn transfer_token(from: String, to: String, amount: u64) -> Result<AccountInfo, String> {
     println!("Input: transfer_token - from: {}, to: {}, amount: {}", from, to, amount);
     let result = token_economy::transfer_tokens(from, to, amount);
@@ -1054,45 +2279,104 @@ fn transfer_token(from: String, to: String, amount: u64) -> Result<AccountInfo,
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
+fn burn_tokens(principal_id: String, amount: u64, reason: String) -> Result<AccountInfo, String> {
+    ic_cdk::println!("CALL[burn_tokens] Input: principal_id={}, amount={}, reason={}", principal_id, amount, reason);
+    require_profile_owner_or_admin(&principal_id)?;
+    let result = token_economy::burn_tokens(principal_id, amount, reason);
+    ic_cdk::println!("CALL[burn_tokens] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_total_burned() -> u64 {
+    token_economy::get_total_burned()
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_circulating_supply() -> u64 {
+    token_economy::get_circulating_supply()
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_economy_dashboard() -> token_economy_types::EconomyDashboard {
+    ic_cdk::println!("CALL[get_economy_dashboard] Input: none");
+    let result = token_economy::get_economy_dashboard();
+    ic_cdk::println!("CALL[get_economy_dashboard] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_transfer_fee_api() -> Option<token_economy_types::TransferFeeConfig> {
+    ic_cdk::println!("CALL[get_transfer_fee_api] Input: none");
+    let result = token_economy::get_transfer_fee_config();
+    ic_cdk::println!("CALL[get_transfer_fee_api] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn archive_activities_before(cutoff_ns: u64) -> Result<u64, String> {
+    ic_cdk::println!("CALL[archive_activities_before] Input: cutoff_ns={}", cutoff_ns);
+    require_role!(Role::Admin);
+    let result = token_economy::archive_activities_before(cutoff_ns);
+    ic_cdk::println!("CALL[archive_activities_before] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_activity_archive_summary(principal_id: String) -> Option<token_economy_types::ActivityArchiveSummary> {
+    ic_cdk::println!("CALL[get_activity_archive_summary] Input: principal_id={}", principal_id);
+    let result = token_economy::get_activity_archive_summary(&principal_id);
+    ic_cdk::println!("CALL[get_activity_archive_summary] Output: exists={}", result.is_some());
+    result
+}
+
+/// Admin sets the per-transfer fee and the treasury receiving it
+#[ic_cdk::update]
+#[candid_method(update)]
+fn set_transfer_fee_api(fee: u64, treasury: String) -> Result<(), String> {
+    ic_cdk::println!("CALL[set_transfer_fee_api] Input: fee={}, treasury={}", fee, treasury);
+    require_role!(Role::Admin);
+    let result = token_economy::set_transfer_fee(fee, treasury);
+    ic_cdk::println!("CALL[set_transfer_fee_api] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
 fn init_grant_policy(grant_policy: Option<GrantPolicy>) {
     token_economy::init_grant_policy(grant_policy);
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn create_and_claim_newuser_grant(principal_id: String) -> Result<u64, String> {
     println!("Input: create_and_claim_newuser_grant - principal_id: {}", principal_id);
-    
-    // Step 1: Check if grant exists and its status
-    if let Some(grant) = token_economy::get_token_grant(&principal_id) {
-        match grant.status {
-            TokenGrantStatus::Active => {
-                // Step 3: If grant is active, claim it
-                let claim_result = token_economy::claim_grant(&principal_id)?;
-                println!("Output: create_and_claim_newuser_grant - claimed amount: {}", claim_result);
-                Ok(claim_result)
-            },
-            _ => Err(format!("Grant exists but is not active. Current status: {:?}", grant.status))
-        }
-    } else {
-        // Step 2: No grant exists, create a new one
-        let new_grant = TokenGrant {
-            recipient: principal_id.clone(),
-            amount: 1000, // Default amount for new users
-            start_time: ic_cdk::api::time() / 1_000_000,
-            claimed_amount: 0,
-            status: TokenGrantStatus::Active,
-        };
-        
-        token_economy::create_token_grant(new_grant)?;
-        
-        // Step 3: Claim the newly created grant
-        let claim_result = token_economy::claim_grant(&principal_id)?;
-        println!("Output: create_and_claim_newuser_grant - claimed amount: {}", claim_result);
-        Ok(claim_result)
-    }
+    let result = token_economy::create_and_claim_newuser_grant(&principal_id);
+    println!("Output: create_and_claim_newuser_grant - result: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_last_claim_info(principal_id: String) -> Option<(u64, u64, u64)> {
+    token_economy::get_last_claim_info(&principal_id)
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_vested_amount(recipient: String) -> u64 {
+    token_economy::get_vested_amount(&recipient)
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn create_and_claim_newmcp_grant(principal_id: String, mcp_name: String) -> Result<u64, String> {
     ic_cdk::println!("Input: create_and_claim_newmcp_grant - principal_id: {}, mcp_name: {}", principal_id, mcp_name);
     
@@ -1101,7 +2385,7 @@ fn create_and_claim_newmcp_grant(principal_id: String, mcp_name: String) -> Resu
         recipient: principal_id.clone(),
         mcp_name: mcp_name.clone(),
         amount: 10000, // Default amount for new MCP
-        start_time: ic_cdk::api::time() / 10_000,
+        start_time: ic_cdk::api::time(),
         claimed_amount: 0,
         status: TokenGrantStatus::Active,
     };
@@ -1115,14 +2399,17 @@ fn create_and_claim_newmcp_grant(principal_id: String, mcp_name: String) -> Resu
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn create_mcp_grant(grant: NewMcpGrant) -> Result<(), String> {
     println!("Input: create_mcp_grant - grant: {:?}", grant);
-    let result = token_economy::create_mcp_grant(grant);
+    let result = token_economy::create_mcp_grant(grant.clone())
+        .and_then(|_| token_economy::notify_grant_recipient(&grant.recipient, &grant.mcp_name));
     println!("Output: create_mcp_grant - result: {:?}", result);
     result
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn claim_mcp_grant(principal_id: String) -> Result<u64, String> {
     println!("Input: claim_mcp_grant - principal_id: {}", principal_id);
     let result = token_economy::claim_mcp_grant(&principal_id);
@@ -1131,6 +2418,7 @@ fn claim_mcp_grant(principal_id: String) -> Result<u64, String> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_mcp_grant(recipient: String, mcp_name: String) -> Option<NewMcpGrant> {
     println!("Input: get_mcp_grant - recipient: {}, mcp_name: {}", recipient, mcp_name);
     let result = token_economy::get_mcp_grant(&recipient, &mcp_name);
@@ -1139,14 +2427,16 @@ fn get_mcp_grant(recipient: String, mcp_name: String) -> Option<NewMcpGrant> {
 }
 
 #[ic_cdk::query]
-fn get_all_mcp_grants() -> Vec<NewMcpGrant> {
-    println!("Input: get_all_mcp_grants");
-    let result = token_economy::get_all_mcp_grants();
-    println!("Output: get_all_mcp_grants - count: {}", result.len());
+#[candid_method(query)]
+fn get_all_mcp_grants(start: Option<String>) -> PagedResult<NewMcpGrant> {
+    println!("Input: get_all_mcp_grants - start: {:?}", start);
+    let result = token_economy::get_all_mcp_grants_guarded(&InstructionGuard::new(start));
+    println!("Output: get_all_mcp_grants - count: {}, truncated: {}", result.items_processed, result.truncated);
     result
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_mcp_grants_paginated(offset: u64, limit: usize) -> Vec<NewMcpGrant> {
     println!("Input: get_mcp_grants_paginated - offset: {}, limit: {}", offset, limit);
     let result = token_economy::get_mcp_grants_paginated(offset, limit);
@@ -1155,6 +2445,7 @@ fn get_mcp_grants_paginated(offset: u64, limit: usize) -> Vec<NewMcpGrant> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_mcp_grants_by_recipient(recipient: String) -> Vec<NewMcpGrant> {
     println!("Input: get_mcp_grants_by_recipient - recipient: {}", recipient);
     let result = token_economy::get_mcp_grants_by_recipient(&recipient);
@@ -1163,6 +2454,7 @@ fn get_mcp_grants_by_recipient(recipient: String) -> Vec<NewMcpGrant> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_mcp_grants_by_mcp(mcp_name: String) -> Vec<NewMcpGrant> {
     println!("Input: get_mcp_grants_by_mcp - mcp_name: {}", mcp_name);
     let result = token_economy::get_mcp_grants_by_mcp(&mcp_name);
@@ -1171,6 +2463,7 @@ fn get_mcp_grants_by_mcp(mcp_name: String) -> Vec<NewMcpGrant> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_mcp_grants_by_status(status: TokenGrantStatus) -> Vec<NewMcpGrant> {
     println!("Input: get_mcp_grants_by_status - status: {:?}", status);
     let result = token_economy::get_mcp_grants_by_status(&status);
@@ -1179,6 +2472,17 @@ fn get_mcp_grants_by_status(status: TokenGrantStatus) -> Vec<NewMcpGrant> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
+fn get_mcp_grants_filtered(status: Option<TokenGrantStatus>, mcp_name: Option<String>, offset: u64, limit: usize) -> Vec<NewMcpGrant> {
+    println!("Input: get_mcp_grants_filtered - status: {:?}, mcp_name: {:?}, offset: {}, limit: {}", status, mcp_name, offset, limit);
+    let limit = clamp_page_size(limit as u64) as usize;
+    let result = token_economy::get_mcp_grants_filtered(status, mcp_name, offset, limit);
+    println!("Output: get_mcp_grants_filtered - count: {}", result.len());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
 fn get_mcp_grants_count() -> u64 {
     println!("Input: get_mcp_grants_count");
     let result = token_economy::get_mcp_grants_count();
@@ -1187,6 +2491,7 @@ fn get_mcp_grants_count() -> u64 {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_mcp_stack_records_paginated(mcp_name: String, offset: u64, limit: u64) -> Vec<McpStackRecord> {
     ic_cdk::println!("CALL[get_mcp_stack_records_paginated] Input: mcp_name={}, offset={}, limit={}", mcp_name, offset, limit);
     let result = mcp_asset_types::get_mcp_stack_records_paginated(mcp_name, offset, limit);
@@ -1195,7 +2500,27 @@ fn get_mcp_stack_records_paginated(mcp_name: String, offset: u64, limit: u64) ->
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
+fn get_mcp_stack_events(mcp_name: String, start_ns: u64, end_ns: u64, offset: u64, limit: u64) -> Vec<mcp_asset_types::McpStackEvent> {
+    ic_cdk::println!("CALL[get_mcp_stack_events] Input: mcp_name={}, start_ns={}, end_ns={}, offset={}, limit={}", mcp_name, start_ns, end_ns, offset, limit);
+    let result = mcp_asset_types::get_mcp_stack_events(mcp_name, start_ns, end_ns, offset, limit);
+    ic_cdk::println!("CALL[get_mcp_stack_events] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_mcp_stake_at_time(mcp_name: String, time_ns: u64) -> u64 {
+    ic_cdk::println!("CALL[get_mcp_stake_at_time] Input: mcp_name={}, time_ns={}", mcp_name, time_ns);
+    let result = mcp_asset_types::get_mcp_stake_at_time(mcp_name, time_ns);
+    ic_cdk::println!("CALL[get_mcp_stake_at_time] Output: {}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
 fn get_traces_by_agentname_paginated(agent_name: String, offset: u64, limit: u64) -> Vec<TraceLog> {
+    let limit = clamp_page_size(limit);
     ic_cdk::println!("CALL[get_traces_by_agentname_paginated] Input: agent_name={}, offset={}, limit={}", agent_name, offset, limit);
     let result = trace_storage::get_traces_by_agentname_paginated(agent_name, offset, limit);
     ic_cdk::println!("CALL[get_traces_by_agentname_paginated] Output: count={}", result.len());
@@ -1203,6 +2528,7 @@ fn get_traces_by_agentname_paginated(agent_name: String, offset: u64, limit: u64
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn cal_unclaim_rewards(principal_id: String) -> u64 {
     ic_cdk::println!("CALL[cal_unclaim_rewards] Input: principal_id={}", principal_id);
     let principal = Principal::from_text(&principal_id)
@@ -1213,6 +2539,7 @@ fn cal_unclaim_rewards(principal_id: String) -> u64 {
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 async fn claim_rewards(principal_id: String) -> Result<u64, String> {
     let principal = Principal::from_text(&principal_id)
         .map_err(|e| format!("Invalid principal ID: {}", e))?;
@@ -1230,11 +2557,38 @@ async fn claim_rewards(principal_id: String) -> Result<u64, String> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_total_aiotoken_claimable() -> u64 {
     mining_reword::get_total_aiotoken_claimable()
 }
 
+/// Current halving epoch of mining block rewards
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_current_mining_epoch() -> u64 {
+    mining_reword::get_current_mining_epoch()
+}
+
+/// Base reward of the next mined block
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_current_block_reward() -> u64 {
+    mining_reword::get_current_block_reward()
+}
+
+/// Replace the mining reward halving schedule (admin only)
+#[ic_cdk::update]
+#[candid_method(update)]
+fn set_halving_schedule(schedule: mining_reword::HalvingSchedule) -> Result<(), String> {
+    ic_cdk::println!("CALL[set_halving_schedule] Input: schedule={:?}", schedule);
+    require_role!(Role::Admin);
+    let result = mining_reword::set_halving_schedule(schedule);
+    ic_cdk::println!("CALL[set_halving_schedule] Output: {:?}", result);
+    result
+}
+
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_total_stacked_credits() -> u64 {
     ic_cdk::println!("CALL[get_total_stacked_credits] Input: none");
     let result = mcp_asset_types::get_total_stacked_credits();
@@ -1243,6 +2597,7 @@ fn get_total_stacked_credits() -> u64 {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_stacked_record_group_by_stack_amount() -> Vec<mcp_asset_types::StackPositionRecord> {
     ic_cdk::println!("CALL[get_stacked_record_group_by_stack_amount] Input: none");
     let result = mcp_asset_types::get_stacked_record_group_by_stack_amount();
@@ -1251,6 +2606,26 @@ fn get_stacked_record_group_by_stack_amount() -> Vec<mcp_asset_types::StackPosit
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
+fn get_top_stakers(limit: usize) -> Vec<(String, u64)> {
+    let limit = clamp_page_size(limit as u64) as usize;
+    ic_cdk::println!("CALL[get_top_stakers] Input: limit={}", limit);
+    let result = mcp_asset_types::get_top_stakers(limit);
+    ic_cdk::println!("CALL[get_top_stakers] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_leaderboard_history(weeks_back: u32) -> Vec<mcp_asset_types::WeeklyLeaderboardSnapshot> {
+    ic_cdk::println!("CALL[get_leaderboard_history] Input: weeks_back={}", weeks_back);
+    let result = mcp_asset_types::get_leaderboard_history(weeks_back);
+    ic_cdk::println!("CALL[get_leaderboard_history] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
 fn get_all_mcp_names() -> Vec<String> {
     ic_cdk::println!("CALL[get_all_mcp_names]");
     let result = mcp_asset_types::get_all_mcp_names();
@@ -1259,6 +2634,7 @@ fn get_all_mcp_names() -> Vec<String> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_mcp_rewards_paginated(offset: u64, limit: u64) -> Vec<RewardEntry> {
     ic_cdk::println!("CALL[get_mcp_rewards_paginated] Input: offset={}, limit={}", offset, limit);
     let result = mining_reword::get_all_mcp_rewards_paginated(offset, limit);
@@ -1266,8 +2642,19 @@ fn get_mcp_rewards_paginated(offset: u64, limit: u64) -> Vec<RewardEntry> {
     result
 }
 
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_rewards_paginated_by_claimed(claimed: bool, offset: u64, limit: u64) -> Vec<RewardEntry> {
+    let limit = clamp_page_size(limit);
+    ic_cdk::println!("CALL[get_rewards_paginated_by_claimed] Input: claimed={}, offset={}, limit={}", claimed, offset, limit);
+    let result = mining_reword::get_rewards_paginated_by_claimed(claimed, offset, limit);
+    ic_cdk::println!("CALL[get_rewards_paginated_by_claimed] Output: count={}", result.len());
+    result
+}
+
 /// Query how many Credits can be exchanged for 1 ICP
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_credits_per_icp_api() -> u64 {
     ic_cdk::println!("CALL[get_credits_per_icp_api] Input: none");
     let result = get_credits_per_icp();
@@ -1277,6 +2664,7 @@ fn get_credits_per_icp_api() -> u64 {
 
 /// Admin updates ICP/USD price
 #[ic_cdk::update]
+#[candid_method(update)]
 fn update_icp_usd_price_api(new_price: f64) -> Result<(), String> {
     let caller = ic_cdk::caller();
     ic_cdk::println!("CALL[update_icp_usd_price_api] Input: caller={}, new_price={}", caller, new_price);
@@ -1287,6 +2675,7 @@ fn update_icp_usd_price_api(new_price: f64) -> Result<(), String> {
 
 /// Simulate recharge, returns the number of Credits that can be obtained
 #[ic_cdk::query]
+#[candid_method(query)]
 fn simulate_credit_from_icp_api(icp_amount: f64) -> u64 {
     ic_cdk::println!("CALL[simulate_credit_from_icp_api] Input: icp_amount={}", icp_amount);
     let result = simulate_credit_from_icp(icp_amount);
@@ -1296,16 +2685,18 @@ fn simulate_credit_from_icp_api(icp_amount: f64) -> u64 {
 
 /// Actual recharge, writes recharge record and updates user balance
 #[ic_cdk::update]
-fn recharge_and_convert_credits_api(icp_amount: f64) -> u64 {
+#[candid_method(update)]
+async fn recharge_and_convert_credits_api(block_index: u64) -> Result<u64, String> {
     let caller = ic_cdk::caller();
-    ic_cdk::println!("CALL[recharge_and_convert_credits_api] Input: caller={}, icp_amount={}", caller, icp_amount);
-    let result = recharge_and_convert_credits(caller, icp_amount);
-    ic_cdk::println!("CALL[recharge_and_convert_credits_api] Output: {}", result);
+    ic_cdk::println!("CALL[recharge_and_convert_credits_api] Input: caller={}, block_index={}", caller, block_index);
+    let result = token_economy::recharge_with_icp_block(caller, block_index).await;
+    ic_cdk::println!("CALL[recharge_and_convert_credits_api] Output: {:?}", result);
     result
 }
 
 /// Query user Credit balance
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_user_credit_balance_api(principal: String) -> u64 {
     ic_cdk::println!("CALL[get_user_credit_balance_api] Input: principal={}", principal);
     let p = Principal::from_text(&principal).unwrap_or(Principal::anonymous());
@@ -1316,6 +2707,7 @@ fn get_user_credit_balance_api(principal: String) -> u64 {
 
 /// Paginated query of recharge records
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_recharge_history_api(principal: String, offset: u64, limit: u64) -> Vec<token_economy_types::RechargeRecord> {
     ic_cdk::println!("CALL[get_recharge_history_api] Input: principal={}, offset={}, limit={}", principal, offset, limit);
     let p = Principal::from_text(&principal).unwrap_or(Principal::anonymous());
@@ -1324,7 +2716,31 @@ fn get_recharge_history_api(principal: String, offset: u64, limit: u64) -> Vec<t
     result
 }
 
+/// Recharge records of all users for reconciliation (admin only)
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_all_recharge_records_paginated(offset: u64, limit: u64) -> Vec<token_economy_types::RechargeRecord> {
+    let limit = clamp_page_size(limit);
+    ic_cdk::println!("CALL[get_all_recharge_records_paginated] Input: offset={}, limit={}", offset, limit);
+    require_role!(Role::Admin, trap);
+    let result = token_economy::get_all_recharge_records_paginated(offset, limit);
+    ic_cdk::println!("CALL[get_all_recharge_records_paginated] Output: count={}", result.len());
+    result
+}
+
+/// Aggregate recharge totals: (record_count, total_icp, total_credits)
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_recharge_totals_api(principal: String) -> (u64, f64, u64) {
+    ic_cdk::println!("CALL[get_recharge_totals_api] Input: principal={}", principal);
+    let p = Principal::from_text(&principal).unwrap_or(Principal::anonymous());
+    let result = token_economy::get_recharge_totals(p);
+    ic_cdk::println!("CALL[get_recharge_totals_api] Output: {:?}", result);
+    result
+}
+
 #[ic_cdk::update]
+#[candid_method(update)]
 fn add_recharge_principal_account_api(item: RechargePrincipalAccount) -> Result<(), String> {
     ic_cdk::println!("CALL[add_recharge_principal_account_api] Input: item={:?}", item);
     let result = token_economy::add_recharge_principal_account(item);
@@ -1333,6 +2749,7 @@ fn add_recharge_principal_account_api(item: RechargePrincipalAccount) -> Result<
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_recharge_principal_account_api() -> Option<RechargePrincipalAccount> {
     ic_cdk::println!("CALL[get_recharge_principal_account_api] Input: none");
     let result = token_economy::get_recharge_principal_account();
@@ -1341,6 +2758,7 @@ fn get_recharge_principal_account_api() -> Option<RechargePrincipalAccount> {
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn update_recharge_principal_account_api(item: RechargePrincipalAccount) -> Result<(), String> {
     ic_cdk::println!("CALL[update_recharge_principal_account_api] Input: item={:?}", item);
     let result = token_economy::update_recharge_principal_account(item);
@@ -1349,6 +2767,7 @@ fn update_recharge_principal_account_api(item: RechargePrincipalAccount) -> Resu
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn delete_recharge_principal_account_api() -> Result<(), String> {
     ic_cdk::println!("CALL[delete_recharge_principal_account_api] Input: none");
     let result = token_economy::delete_recharge_principal_account();
@@ -1357,6 +2776,7 @@ fn delete_recharge_principal_account_api() -> Result<(), String> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn list_recharge_principal_accounts_api() -> Vec<RechargePrincipalAccount> {
     ic_cdk::println!("CALL[list_recharge_principal_accounts_api] Input: none");
     let result = token_economy::list_recharge_principal_accounts();
@@ -1367,6 +2787,7 @@ fn list_recharge_principal_accounts_api() -> Vec<RechargePrincipalAccount> {
 // ==== User Profile API ====
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn upsert_user_profile(profile: UserProfile) -> Result<u64, String> {
     ic_cdk::println!("CALL[upsert_user_profile] Input: profile={:?}", profile);
     let result = society_profile_types::upsert_user_profile(profile);
@@ -1374,9 +2795,23 @@ fn upsert_user_profile(profile: UserProfile) -> Result<u64, String> {
     result
 }
 
+#[ic_cdk::query]
+#[candid_method(query)]
+fn export_user_data(principal_id: String, offset: u64, limit: u64) -> Result<String, String> {
+    ic_cdk::println!("CALL[export_user_data] Input: principal_id={}, offset={}, limit={}", principal_id, offset, limit);
+    let caller = ic_cdk::api::caller();
+    if caller.to_text() != principal_id && !ic_cdk::api::is_controller(&caller) {
+        return Err("No permission: only the user or an admin can export user data".to_string());
+    }
+    let result = user_data_export::export_user_data(&principal_id, offset, limit);
+    ic_cdk::println!("CALL[export_user_data] Output: {} bytes", result.len());
+    Ok(result)
+}
+
 // ==== Email Registration API ====
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn generate_principal_from_email_password(email: String, password: String) -> String {
     ic_cdk::println!("CALL[generate_principal_from_email_password] Input: email={}", email);
     let result = society_profile_types::generate_principal_from_email_password(email, password);
@@ -1385,6 +2820,7 @@ fn generate_principal_from_email_password(email: String, password: String) -> St
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn register_user_with_email(email: String, password: String, nickname: String) -> Result<String, String> {
     ic_cdk::println!("CALL[register_user_with_email] Input: email={}, nickname={}", email, nickname);
     let result = society_profile_types::register_user_with_email(email, password, nickname);
@@ -1394,6 +2830,7 @@ fn register_user_with_email(email: String, password: String, nickname: String) -
 
 /// Authenticate user with email and password
 #[ic_cdk::update]
+#[candid_method(update)]
 fn authenticate_user_with_email_password(email: String, password: String) -> Result<String, String> {
     ic_cdk::println!("CALL[authenticate_user_with_email_password] Input: email={}", email);
     let result = society_profile_types::authenticate_user_with_email_password(email, password);
@@ -1406,6 +2843,7 @@ fn authenticate_user_with_email_password(email: String, password: String) -> Res
 
 /// Change user password
 #[ic_cdk::update]
+#[candid_method(update)]
 fn change_user_password(principal_id: String, old_password: String, new_password: String) -> Result<UserProfile, String> {
     ic_cdk::println!("CALL[change_user_password] Input: principal_id={}", principal_id);
     let result = society_profile_types::change_user_password(principal_id, old_password, new_password);
@@ -1417,6 +2855,7 @@ fn change_user_password(principal_id: String, old_password: String, new_password
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_user_profile_by_principal(principal_id: String) -> Option<UserProfile> {
     ic_cdk::println!("CALL[get_user_profile_by_principal] Input: principal_id={}", principal_id);
     let result = society_profile_types::get_user_profile_by_principal(principal_id);
@@ -1425,6 +2864,7 @@ fn get_user_profile_by_principal(principal_id: String) -> Option<UserProfile> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_user_profile_by_user_id(user_id: String) -> Option<UserProfile> {
     ic_cdk::println!("CALL[get_user_profile_by_user_id] Input: user_id={}", user_id);
     let result = society_profile_types::get_user_profile_by_user_id(user_id);
@@ -1433,6 +2873,7 @@ fn get_user_profile_by_user_id(user_id: String) -> Option<UserProfile> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_user_profile_by_email(email: String) -> Option<UserProfile> {
     ic_cdk::println!("CALL[get_user_profile_by_email] Input: email={}", email);
     let result = society_profile_types::get_user_profile_by_email(email);
@@ -1440,7 +2881,28 @@ fn get_user_profile_by_email(email: String) -> Option<UserProfile> {
     result
 }
 
+#[ic_cdk::query]
+#[candid_method(query)]
+fn search_users_by_nickname(query: String, offset: u64, limit: u64) -> Vec<PublicUserProfile> {
+    let limit = clamp_page_size(limit);
+    ic_cdk::println!("CALL[search_users_by_nickname] Input: query={}, offset={}, limit={}", query, offset, limit);
+    let result = society_profile_types::search_users_by_nickname(query, offset, limit);
+    ic_cdk::println!("CALL[search_users_by_nickname] Output: count={}", result.len());
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn search_users_fuzzy_nickname(query: String, max_distance: u32, limit: u64) -> Vec<PublicUserProfile> {
+    let limit = clamp_page_size(limit);
+    ic_cdk::println!("CALL[search_users_fuzzy_nickname] Input: query={}, max_distance={}, limit={}", query, max_distance, limit);
+    let result = society_profile_types::search_users_fuzzy_nickname(query, max_distance, limit as usize);
+    ic_cdk::println!("CALL[search_users_fuzzy_nickname] Output: count={}", result.len());
+    result
+}
+
 #[ic_cdk::update]
+#[candid_method(update)]
 fn update_user_nickname(principal_id: String, nickname: String) -> Result<UserProfile, String> {
     ic_cdk::println!("CALL[update_user_nickname] Input: principal_id={}, nickname={}", principal_id, nickname);
     let result = society_profile_types::update_user_nickname(principal_id, nickname);
@@ -1448,23 +2910,95 @@ fn update_user_nickname(principal_id: String, nickname: String) -> Result<UserPr
     result
 }
 
+// Profile owners manage their own avatar; anyone else needs the admin role
+fn require_profile_owner_or_admin(principal_id: &str) -> Result<(), String> {
+    if caller().to_text() == principal_id {
+        return Ok(());
+    }
+    require_role!(Role::Admin);
+    Ok(())
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn upload_user_avatar(principal_id: String, image_bytes: Vec<u8>, mime_type: String) -> Result<String, String> {
+    ic_cdk::println!("CALL[upload_user_avatar] Input: principal_id={}, bytes={}, mime_type={}", principal_id, image_bytes.len(), mime_type);
+    require_profile_owner_or_admin(&principal_id)?;
+    let result = society_profile_types::upload_user_avatar(principal_id, image_bytes, mime_type);
+    ic_cdk::println!("CALL[upload_user_avatar] Output: {:?}", result);
+    result
+}
+
 #[ic_cdk::query]
+#[candid_method(query)]
+fn get_user_avatar(principal_id: String) -> Option<Vec<u8>> {
+    ic_cdk::println!("CALL[get_user_avatar] Input: principal_id={}", principal_id);
+    let result = society_profile_types::get_user_avatar(principal_id);
+    ic_cdk::println!("CALL[get_user_avatar] Output: bytes={:?}", result.as_ref().map(|bytes| bytes.len()));
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn delete_user_avatar(principal_id: String) -> Result<(), String> {
+    ic_cdk::println!("CALL[delete_user_avatar] Input: principal_id={}", principal_id);
+    require_profile_owner_or_admin(&principal_id)?;
+    let result = society_profile_types::delete_user_avatar(principal_id);
+    ic_cdk::println!("CALL[delete_user_avatar] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
 fn get_user_profiles_paginated(offset: u64, limit: u64) -> Vec<UserProfile> {
+    let limit = clamp_page_size(limit);
     ic_cdk::println!("CALL[get_user_profiles_paginated] Input: offset={}, limit={}", offset, limit);
     let result = society_profile_types::get_user_profiles_paginated(offset, limit as usize);
     ic_cdk::println!("CALL[get_user_profiles_paginated] Output: count={}", result.len());
     result
 }
 
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_user_profiles_admin(include_deleted: bool, offset: u64, limit: u64) -> Result<Vec<UserProfile>, String> {
+    let limit = clamp_page_size(limit);
+    ic_cdk::println!("CALL[get_user_profiles_admin] Input: include_deleted={}, offset={}, limit={}", include_deleted, offset, limit);
+    require_role!(Role::Admin);
+    let result = society_profile_types::get_user_profiles_admin(include_deleted, offset, limit as usize);
+    ic_cdk::println!("CALL[get_user_profiles_admin] Output: count={}", result.len());
+    Ok(result)
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_user_profiles_paginated_cursor(cursor: Option<String>, limit: usize) -> Result<CursorPage<UserProfile>, String> {
+    ic_cdk::println!("CALL[get_user_profiles_paginated_cursor] Input: cursor={:?}, limit={}", cursor, limit);
+    let result = society_profile_types::get_user_profiles_paginated_cursor(cursor, limit);
+    ic_cdk::println!("CALL[get_user_profiles_paginated_cursor] Output: {:?}", result.as_ref().map(|p| (p.items.len(), p.next_cursor.clone())));
+    result
+}
+
 #[ic_cdk::update]
-fn delete_user_profile(principal_id: String) -> Result<bool, String> {
-    ic_cdk::println!("CALL[delete_user_profile] Input: principal_id={}", principal_id);
-    let result = society_profile_types::delete_user_profile(principal_id);
+#[candid_method(update)]
+fn delete_user_profile(principal_id: String, cascade: bool) -> Result<bool, String> {
+    ic_cdk::println!("CALL[delete_user_profile] Input: principal_id={}, cascade={}", principal_id, cascade);
+    let result = society_profile_types::delete_user_profile(principal_id, cascade);
     ic_cdk::println!("CALL[delete_user_profile] Output: {:?}", result);
     result
 }
 
+#[ic_cdk::update]
+#[candid_method(update)]
+fn rebuild_profile_indices() -> Result<u64, String> {
+    ic_cdk::println!("CALL[rebuild_profile_indices] Input: none");
+    require_role!(Role::Admin);
+    let result = society_profile_types::rebuild_profile_indices();
+    ic_cdk::println!("CALL[rebuild_profile_indices] Output: {:?}", result);
+    result
+}
+
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_total_user_profiles() -> u64 {
     ic_cdk::println!("CALL[get_total_user_profiles] Input: none");
     let result = society_profile_types::get_total_user_profiles();
@@ -1474,9 +3008,10 @@ fn get_total_user_profiles() -> u64 {
 
 // ==== Contact API ====
 
-use society_profile_types::{Contact, ContactType, ContactStatus, ChatMessage, MessageMode, NotificationItem};
+use society_profile_types::{Contact, ContactType, ContactStatus, ChatMessage, MessageMode, NotificationItem, ReadReceipt, GroupChat, EmojiPack};
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn upsert_contact(contact: Contact) -> Result<u64, String> {
     ic_cdk::println!("CALL[upsert_contact] Input: contact={:?}", contact);
     let result = society_profile_types::upsert_contact(contact);
@@ -1485,6 +3020,7 @@ fn upsert_contact(contact: Contact) -> Result<u64, String> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_contacts_by_owner(owner_principal_id: String) -> Vec<Contact> {
     ic_cdk::println!("CALL[get_contacts_by_owner] Input: owner_principal_id={}", owner_principal_id);
     let result = society_profile_types::get_contacts_by_owner(owner_principal_id);
@@ -1493,7 +3029,9 @@ fn get_contacts_by_owner(owner_principal_id: String) -> Vec<Contact> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_contacts_by_owner_paginated(owner_principal_id: String, offset: u64, limit: u64) -> Vec<Contact> {
+    let limit = clamp_page_size(limit);
     ic_cdk::println!("CALL[get_contacts_by_owner_paginated] Input: owner_principal_id={}, offset={}, limit={}", owner_principal_id, offset, limit);
     let result = society_profile_types::get_contacts_by_owner_paginated(owner_principal_id, offset, limit as usize);
     ic_cdk::println!("CALL[get_contacts_by_owner_paginated] Output: count={}", result.len());
@@ -1501,6 +3039,7 @@ fn get_contacts_by_owner_paginated(owner_principal_id: String, offset: u64, limi
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_contact_by_id(contact_id: u64) -> Option<Contact> {
     ic_cdk::println!("CALL[get_contact_by_id] Input: contact_id={}", contact_id);
     let result = society_profile_types::get_contact_by_id(contact_id);
@@ -1509,6 +3048,7 @@ fn get_contact_by_id(contact_id: u64) -> Option<Contact> {
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_contact_by_principal_ids(owner_principal_id: String, contact_principal_id: String) -> Option<Contact> {
     ic_cdk::println!("CALL[get_contact_by_principal_ids] Input: owner_principal_id={}, contact_principal_id={}", owner_principal_id, contact_principal_id);
     let result = society_profile_types::get_contact_by_principal_ids(owner_principal_id, contact_principal_id);
@@ -1517,6 +3057,7 @@ fn get_contact_by_principal_ids(owner_principal_id: String, contact_principal_id
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn search_contacts_by_name(owner_principal_id: String, name_query: String) -> Vec<Contact> {
     ic_cdk::println!("CALL[search_contacts_by_name] Input: owner_principal_id={}, name_query={}", owner_principal_id, name_query);
     let result = society_profile_types::search_contacts_by_name(owner_principal_id, name_query);
@@ -1525,6 +3066,7 @@ fn search_contacts_by_name(owner_principal_id: String, name_query: String) -> Ve
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn update_contact_status(owner_principal_id: String, contact_principal_id: String, new_status: ContactStatus) -> Result<Contact, String> {
     ic_cdk::println!("CALL[update_contact_status] Input: owner_principal_id={}, contact_principal_id={}, new_status={:?}", owner_principal_id, contact_principal_id, new_status);
     let result = society_profile_types::update_contact_status(owner_principal_id, contact_principal_id, new_status);
@@ -1533,6 +3075,7 @@ fn update_contact_status(owner_principal_id: String, contact_principal_id: Strin
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn update_contact_nickname(owner_principal_id: String, contact_principal_id: String, nickname: String) -> Result<Contact, String> {
     ic_cdk::println!("CALL[update_contact_nickname] Input: owner_principal_id={}, contact_principal_id={}, nickname={}", owner_principal_id, contact_principal_id, nickname);
     let result = society_profile_types::update_contact_nickname(owner_principal_id, contact_principal_id, nickname);
@@ -1541,6 +3084,7 @@ fn update_contact_nickname(owner_principal_id: String, contact_principal_id: Str
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn update_contact_devices(owner_principal_id: String, contact_principal_id: String, devices: Vec<String>) -> Result<Contact, String> {
     ic_cdk::println!("CALL[update_contact_devices] Input: owner_principal_id={}, contact_principal_id={}, devices={:?}", owner_principal_id, contact_principal_id, devices);
     let result = society_profile_types::update_contact_devices(owner_principal_id, contact_principal_id, devices);
@@ -1549,6 +3093,7 @@ fn update_contact_devices(owner_principal_id: String, contact_principal_id: Stri
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn update_contact_online_status(owner_principal_id: String, contact_principal_id: String, is_online: bool) -> Result<Contact, String> {
     ic_cdk::println!("CALL[update_contact_online_status] Input: owner_principal_id={}, contact_principal_id={}, is_online={}", owner_principal_id, contact_principal_id, is_online);
     let result = society_profile_types::update_contact_online_status(owner_principal_id, contact_principal_id, is_online);
@@ -1557,6 +3102,7 @@ fn update_contact_online_status(owner_principal_id: String, contact_principal_id
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn delete_contact(owner_principal_id: String, contact_principal_id: String) -> Result<bool, String> {
     ic_cdk::println!("CALL[delete_contact] Input: owner_principal_id={}, contact_principal_id={}", owner_principal_id, contact_principal_id);
     let result = society_profile_types::delete_contact(owner_principal_id, contact_principal_id);
@@ -1565,6 +3111,7 @@ fn delete_contact(owner_principal_id: String, contact_principal_id: String) -> R
 }
 
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_total_contacts_by_owner(owner_principal_id: String) -> u64 {
     ic_cdk::println!("CALL[get_total_contacts_by_owner] Input: owner_principal_id={}", owner_principal_id);
     let result = society_profile_types::get_total_contacts_by_owner(owner_principal_id);
@@ -1573,6 +3120,7 @@ fn get_total_contacts_by_owner(owner_principal_id: String) -> u64 {
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn create_contact_from_principal_id(owner_principal_id: String, contact_principal_id: String, nickname: Option<String>) -> Result<u64, String> {
     ic_cdk::println!("CALL[create_contact_from_principal_id] Input: owner_principal_id={}, contact_principal_id={}, nickname={:?}", owner_principal_id, contact_principal_id, nickname);
     let result = society_profile_types::create_contact_from_principal_id(owner_principal_id, contact_principal_id, nickname);
@@ -1580,9 +3128,28 @@ fn create_contact_from_principal_id(owner_principal_id: String, contact_principa
     result
 }
 
+#[ic_cdk::update]
+#[candid_method(update)]
+fn create_agent_contact(owner_principal_id: String, agent_index: u64, nickname: Option<String>) -> Result<u64, String> {
+    ic_cdk::println!("CALL[create_agent_contact] Input: owner_principal_id={}, agent_index={}, nickname={:?}", owner_principal_id, agent_index, nickname);
+    let result = society_profile_types::create_agent_contact(owner_principal_id, agent_index, nickname);
+    ic_cdk::println!("CALL[create_agent_contact] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_agent_contacts_by_owner(owner_principal_id: String) -> Vec<Contact> {
+    ic_cdk::println!("CALL[get_agent_contacts_by_owner] Input: owner_principal_id={}", owner_principal_id);
+    let result = society_profile_types::get_agent_contacts_by_owner(owner_principal_id);
+    ic_cdk::println!("CALL[get_agent_contacts_by_owner] Output: count={}", result.len());
+    result
+}
+
 // ==== User Device Management API ====
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn add_user_device(principal_id: String, device_id: String) -> Result<UserProfile, String> {
     ic_cdk::println!("CALL[add_user_device] Input: principal_id={}, device_id={}", principal_id, device_id);
     let result = society_profile_types::add_user_device(principal_id, device_id);
@@ -1591,6 +3158,7 @@ fn add_user_device(principal_id: String, device_id: String) -> Result<UserProfil
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn remove_user_device(principal_id: String, device_id: String) -> Result<UserProfile, String> {
     ic_cdk::println!("CALL[remove_user_device] Input: principal_id={}, device_id={}", principal_id, device_id);
     let result = society_profile_types::remove_user_device(principal_id, device_id);
@@ -1599,6 +3167,7 @@ fn remove_user_device(principal_id: String, device_id: String) -> Result<UserPro
 }
 
 #[ic_cdk::update]
+#[candid_method(update)]
 fn update_user_devices(principal_id: String, devices: Vec<String>) -> Result<UserProfile, String> {
     ic_cdk::println!("CALL[update_user_devices] Input: principal_id={}, devices={:?}", principal_id, devices);
     let result = society_profile_types::update_user_devices(principal_id, devices);
@@ -1610,6 +3179,7 @@ fn update_user_devices(principal_id: String, devices: Vec<String>) -> Result<Use
 
 /// Generate social pair key from two principal IDs
 #[ic_cdk::query]
+#[candid_method(query)]
 fn generate_social_pair_key(principal1: String, principal2: String) -> String {
     ic_cdk::println!("CALL[generate_social_pair_key] Input: principal1={}, principal2={}", principal1, principal2);
     let result = society_profile_types::generate_social_pair_key(principal1, principal2);
@@ -1619,20 +3189,23 @@ fn generate_social_pair_key(principal1: String, principal2: String) -> String {
 
 /// Send a chat message between two users
 #[ic_cdk::update]
+#[candid_method(update)]
 fn send_chat_message(
     sender_principal: String,
     receiver_principal: String,
     content: String,
     mode: MessageMode,
+    ttl_ns: Option<u64>,
 ) -> Result<u64, String> {
-    ic_cdk::println!("CALL[send_chat_message] Input: sender={}, receiver={}, mode={:?}", sender_principal, receiver_principal, mode);
-    let result = society_profile_types::add_chat_message(sender_principal, receiver_principal, content, mode);
+    ic_cdk::println!("CALL[send_chat_message] Input: sender={}, receiver={}, mode={:?}, ttl_ns={:?}", sender_principal, receiver_principal, mode, ttl_ns);
+    let result = society_profile_types::add_chat_message(sender_principal, receiver_principal, content, mode, ttl_ns);
     ic_cdk::println!("CALL[send_chat_message] Output: {:?}", result);
     result
 }
 
 /// Get recent chat messages (last 5 messages) between two users
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_recent_chat_messages(principal1: String, principal2: String) -> Vec<ChatMessage> {
     ic_cdk::println!("CALL[get_recent_chat_messages] Input: principal1={}, principal2={}", principal1, principal2);
     let result = society_profile_types::get_recent_chat_messages(principal1, principal2);
@@ -1640,8 +3213,19 @@ fn get_recent_chat_messages(principal1: String, principal2: String) -> Vec<ChatM
     result
 }
 
+/// Get the last n chat messages between two users
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_recent_chat_messages_n(principal1: String, principal2: String, n: u64) -> Vec<ChatMessage> {
+    ic_cdk::println!("CALL[get_recent_chat_messages_n] Input: principal1={}, principal2={}, n={}", principal1, principal2, n);
+    let result = society_profile_types::get_recent_chat_messages_n(principal1, principal2, n as usize);
+    ic_cdk::println!("CALL[get_recent_chat_messages_n] Output: count={}", result.len());
+    result
+}
+
 /// Get paginated chat messages between two users
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_chat_messages_paginated(
     principal1: String,
     principal2: String,
@@ -1654,8 +3238,24 @@ fn get_chat_messages_paginated(
     result
 }
 
+/// Get paginated chat messages between two users, leaving out messages whose TTL has elapsed
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_chat_messages_excluding_expired(
+    principal1: String,
+    principal2: String,
+    offset: u64,
+    limit: u64,
+) -> Vec<ChatMessage> {
+    ic_cdk::println!("CALL[get_chat_messages_excluding_expired] Input: principal1={}, principal2={}, offset={}, limit={}", principal1, principal2, offset, limit);
+    let result = society_profile_types::get_chat_messages_excluding_expired(principal1, principal2, offset, limit as usize);
+    ic_cdk::println!("CALL[get_chat_messages_excluding_expired] Output: count={}", result.len());
+    result
+}
+
 /// Get total message count between two users
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_chat_message_count(principal1: String, principal2: String) -> u64 {
     ic_cdk::println!("CALL[get_chat_message_count] Input: principal1={}, principal2={}", principal1, principal2);
     let result = society_profile_types::get_chat_message_count(principal1, principal2);
@@ -1663,8 +3263,143 @@ fn get_chat_message_count(principal1: String, principal2: String) -> u64 {
     result
 }
 
+/// Create a group chat owned by the caller
+#[ic_cdk::update]
+#[candid_method(update)]
+fn create_group_chat(name: String, members: Vec<String>) -> Result<String, String> {
+    let owner_principal = caller().to_string();
+    ic_cdk::println!("CALL[create_group_chat] Input: owner_principal={}, name={}, members={:?}", owner_principal, name, members);
+    let result = society_profile_types::create_group_chat(owner_principal, name, members);
+    ic_cdk::println!("CALL[create_group_chat] Output: {:?}", result);
+    result
+}
+
+/// Get a group chat by key
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_group_chat(group_key: String) -> Option<GroupChat> {
+    ic_cdk::println!("CALL[get_group_chat] Input: group_key={}", group_key);
+    let result = society_profile_types::get_group_chat(group_key);
+    ic_cdk::println!("CALL[get_group_chat] Output: exists={}", result.is_some());
+    result
+}
+
+/// Add a member to a group chat
+#[ic_cdk::update]
+#[candid_method(update)]
+fn add_group_member(group_key: String, member_principal: String) -> Result<GroupChat, String> {
+    let caller_principal = caller().to_string();
+    ic_cdk::println!("CALL[add_group_member] Input: group_key={}, caller_principal={}, member_principal={}", group_key, caller_principal, member_principal);
+    let result = society_profile_types::add_group_member(group_key, caller_principal, member_principal);
+    ic_cdk::println!("CALL[add_group_member] Output: {:?}", result.is_ok());
+    result
+}
+
+/// Remove a member from a group chat
+#[ic_cdk::update]
+#[candid_method(update)]
+fn remove_group_member(group_key: String, member_principal: String) -> Result<GroupChat, String> {
+    let caller_principal = caller().to_string();
+    ic_cdk::println!("CALL[remove_group_member] Input: group_key={}, caller_principal={}, member_principal={}", group_key, caller_principal, member_principal);
+    let result = society_profile_types::remove_group_member(group_key, caller_principal, member_principal);
+    ic_cdk::println!("CALL[remove_group_member] Output: {:?}", result.is_ok());
+    result
+}
+
+/// Send a message to a group chat as the caller
+#[ic_cdk::update]
+#[candid_method(update)]
+fn send_group_message(group_key: String, content: String, mode: MessageMode) -> Result<u64, String> {
+    let sender_principal = caller().to_string();
+    ic_cdk::println!("CALL[send_group_message] Input: group_key={}, sender={}, mode={:?}", group_key, sender_principal, mode);
+    let result = society_profile_types::send_group_message(group_key, sender_principal, content, mode);
+    ic_cdk::println!("CALL[send_group_message] Output: {:?}", result);
+    result
+}
+
+/// Get paginated group chat messages
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_group_messages_paginated(group_key: String, offset: u64, limit: u64) -> Vec<ChatMessage> {
+    ic_cdk::println!("CALL[get_group_messages_paginated] Input: group_key={}, offset={}, limit={}", group_key, offset, limit);
+    let result = society_profile_types::get_group_messages_paginated(group_key, offset, limit as usize);
+    ic_cdk::println!("CALL[get_group_messages_paginated] Output: count={}", result.len());
+    result
+}
+
+/// Create a multi-party chat thread with the caller as creator
+#[ic_cdk::update]
+#[candid_method(update)]
+fn create_chat_thread(participants: Vec<String>, name: Option<String>) -> Result<String, String> {
+    let creator = caller().to_string();
+    ic_cdk::println!("CALL[create_chat_thread] Input: creator={}, participants={:?}, name={:?}", creator, participants, name);
+    let result = society_profile_types::create_chat_thread(creator, participants, name);
+    ic_cdk::println!("CALL[create_chat_thread] Output: {:?}", result);
+    result
+}
+
+/// Post a message to a chat thread as the caller
+#[ic_cdk::update]
+#[candid_method(update)]
+fn add_thread_message(thread_id: String, content: String, mode: MessageMode) -> Result<u64, String> {
+    let sender = caller().to_string();
+    ic_cdk::println!("CALL[add_thread_message] Input: thread_id={}, sender={}, mode={:?}", thread_id, sender, mode);
+    let result = society_profile_types::add_thread_message(thread_id, sender, content, mode);
+    ic_cdk::println!("CALL[add_thread_message] Output: {:?}", result);
+    result
+}
+
+/// Get paginated chat thread messages
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_thread_messages_paginated(thread_id: String, offset: u64, limit: u64) -> Vec<ChatMessage> {
+    let limit = clamp_page_size(limit);
+    ic_cdk::println!("CALL[get_thread_messages_paginated] Input: thread_id={}, offset={}, limit={}", thread_id, offset, limit);
+    let result = society_profile_types::get_thread_messages_paginated(thread_id, offset, limit);
+    ic_cdk::println!("CALL[get_thread_messages_paginated] Output: count={}", result.len());
+    result
+}
+
+/// Register an emoji/sticker pack owned by the caller
+#[ic_cdk::update]
+#[candid_method(update)]
+fn create_emoji_pack(name: String, emoji_ids: Vec<String>, is_public: bool) -> Result<String, String> {
+    let owner = caller().to_string();
+    ic_cdk::println!("CALL[create_emoji_pack] Input: owner={}, name={}, emoji_count={}, is_public={}", owner, name, emoji_ids.len(), is_public);
+    let result = society_profile_types::create_emoji_pack(owner, name, emoji_ids, is_public);
+    ic_cdk::println!("CALL[create_emoji_pack] Output: {:?}", result);
+    result
+}
+
+/// Get an emoji pack by ID
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_emoji_pack(id: String) -> Option<EmojiPack> {
+    society_profile_types::get_emoji_pack(id)
+}
+
+/// Get paginated public emoji packs
+#[ic_cdk::query]
+#[candid_method(query)]
+fn list_public_emoji_packs(offset: u64, limit: usize) -> Vec<EmojiPack> {
+    let limit = clamp_page_size(limit as u64) as usize;
+    society_profile_types::list_public_emoji_packs(offset, limit)
+}
+
+/// Delete an emoji pack owned by the caller
+#[ic_cdk::update]
+#[candid_method(update)]
+fn delete_emoji_pack(id: String) -> Result<(), String> {
+    let caller_principal = caller().to_string();
+    ic_cdk::println!("CALL[delete_emoji_pack] Input: id={}, caller={}", id, caller_principal);
+    let result = society_profile_types::delete_emoji_pack(id, caller_principal);
+    ic_cdk::println!("CALL[delete_emoji_pack] Output: {:?}", result);
+    result
+}
+
 /// Pop notification from queue for specific receiver
 #[ic_cdk::update]
+#[candid_method(update)]
 fn pop_notification(receiver_principal: String) -> Option<NotificationItem> {
     ic_cdk::println!("CALL[pop_notification] Input: receiver_principal={}", receiver_principal);
     let result = society_profile_types::pop_notification(receiver_principal);
@@ -1672,8 +3407,50 @@ fn pop_notification(receiver_principal: String) -> Option<NotificationItem> {
     result
 }
 
+/// Pop up to `max` notifications for a receiver, oldest first
+#[ic_cdk::update]
+#[candid_method(update)]
+fn drain_notifications(receiver_principal: String, max: usize) -> Vec<NotificationItem> {
+    let max = clamp_page_size(max as u64) as usize;
+    ic_cdk::println!("CALL[drain_notifications] Input: receiver_principal={}, max={}", receiver_principal, max);
+    let result = society_profile_types::drain_notifications(receiver_principal, max);
+    ic_cdk::println!("CALL[drain_notifications] Output: count={}", result.len());
+    result
+}
+
+/// Mark the conversation with another user as read up to the latest message
+#[ic_cdk::update]
+#[candid_method(update)]
+fn mark_chat_read(reader_principal: String, other_principal: String) -> Result<ReadReceipt, String> {
+    ic_cdk::println!("CALL[mark_chat_read] Input: reader_principal={}, other_principal={}", reader_principal, other_principal);
+    let result = society_profile_types::mark_chat_read(reader_principal, other_principal);
+    ic_cdk::println!("CALL[mark_chat_read] Output: {:?}", result);
+    result
+}
+
+/// Get the delivered/read state of a conversation for its owner
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_read_receipt(owner_principal: String, other_principal: String) -> ReadReceipt {
+    ic_cdk::println!("CALL[get_read_receipt] Input: owner_principal={}, other_principal={}", owner_principal, other_principal);
+    let result = society_profile_types::get_read_receipt(owner_principal, other_principal);
+    ic_cdk::println!("CALL[get_read_receipt] Output: {:?}", result);
+    result
+}
+
+/// Get the index of the last message the owner has read
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_last_read_index(owner_principal: String, other_principal: String) -> Option<u64> {
+    ic_cdk::println!("CALL[get_last_read_index] Input: owner_principal={}, other_principal={}", owner_principal, other_principal);
+    let result = society_profile_types::get_last_read_index(owner_principal, other_principal);
+    ic_cdk::println!("CALL[get_last_read_index] Output: {:?}", result);
+    result
+}
+
 /// Get all notifications for a receiver (without removing them)
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_notifications_for_receiver(receiver_principal: String) -> Vec<NotificationItem> {
     ic_cdk::println!("CALL[get_notifications_for_receiver] Input: receiver_principal={}", receiver_principal);
     let result = society_profile_types::get_notifications_for_receiver(receiver_principal);
@@ -1683,6 +3460,7 @@ fn get_notifications_for_receiver(receiver_principal: String) -> Vec<Notificatio
 
 /// Clear all notifications for a specific social pair and receiver
 #[ic_cdk::update]
+#[candid_method(update)]
 fn clear_notifications_for_pair(
     social_pair_key: String,
     receiver_principal: String,
@@ -1693,10 +3471,40 @@ fn clear_notifications_for_pair(
     result
 }
 
+/// Notification preferences of the caller (defaults when never set)
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_notification_preferences() -> society_profile_types::NotificationPreferences {
+    society_profile_types::get_notification_preferences(caller().to_string())
+}
+
+/// Save the caller's notification preferences; `principal_id` in the record is ignored
+#[ic_cdk::update]
+#[candid_method(update)]
+fn set_notification_preferences(preferences: society_profile_types::NotificationPreferences) -> Result<(), String> {
+    let principal_id = caller().to_string();
+    ic_cdk::println!("CALL[set_notification_preferences] Input: principal_id={}, preferences={:?}", principal_id, preferences);
+    let result = society_profile_types::set_notification_preferences(principal_id, preferences);
+    ic_cdk::println!("CALL[set_notification_preferences] Output: {:?}", result);
+    result
+}
+
+/// Reset the caller's notification preferences to the defaults
+#[ic_cdk::update]
+#[candid_method(update)]
+fn delete_notification_preferences() -> bool {
+    let principal_id = caller().to_string();
+    ic_cdk::println!("CALL[delete_notification_preferences] Input: principal_id={}", principal_id);
+    let result = society_profile_types::delete_notification_preferences(principal_id);
+    ic_cdk::println!("CALL[delete_notification_preferences] Output: {}", result);
+    result
+}
+
 // ==== Pixel Creation API ====
 
 /// Create a new pixel art project
 #[ic_cdk::update]
+#[candid_method(update)]
 fn create_pixel_project(principal_id: String, source: PixelArtSource, message: Option<String>) -> Result<ProjectId, String> {
     ic_cdk::println!("CALL[create_pixel_project] Input: principal_id={}, source width={}, height={}, message={:?}", 
                      principal_id, source.width, source.height, message);
@@ -1707,26 +3515,76 @@ fn create_pixel_project(principal_id: String, source: PixelArtSource, message: O
     result
 }
 
+/// Fork an existing project into a new project owned by the given principal
+#[ic_cdk::update]
+#[candid_method(update)]
+fn fork_pixel_project(principal_id: String, source_project_id: ProjectId, message: Option<String>) -> Result<ProjectId, String> {
+    ic_cdk::println!("CALL[fork_pixel_project] Input: principal_id={}, source_project_id={}, message={:?}",
+                     principal_id, source_project_id, message);
+    let forker = Principal::from_text(&principal_id)
+        .map_err(|e| format!("Invalid principal ID: {}", e))?;
+    let result = pixel_creation_types::fork_project(forker, source_project_id, message);
+    ic_cdk::println!("CALL[fork_pixel_project] Output: {:?}", result);
+    result
+}
+
 /// Save a new version to an existing project
 #[ic_cdk::update]
+#[candid_method(update)]
 fn save_pixel_version(
     principal_id: String,
     project_id: ProjectId,
     source: PixelArtSource,
     message: Option<String>,
-    if_match_version: Option<String>
+    if_match_version: Option<String>,
+    lock_token: Option<String>,
 ) -> Result<VersionId, String> {
     ic_cdk::println!("CALL[save_pixel_version] Input: principal_id={}, project_id={}, message={:?}, if_match_version={:?}", 
                      principal_id, project_id, message, if_match_version);
     let caller = Principal::from_text(&principal_id)
         .map_err(|e| format!("Invalid principal ID: {}", e))?;
-    let result = pixel_creation_types::save_version(caller, project_id, source, message, if_match_version);
+    let result = pixel_creation_types::save_version(caller, project_id.clone(), source, message, if_match_version, lock_token);
+    if let Ok(version_id) = &result {
+        device_delivery_types::enqueue_project_deliveries(&project_id, version_id);
+    }
     ic_cdk::println!("CALL[save_pixel_version] Output: {:?}", result);
     result
 }
 
+/// Lock a project for exclusive editing; saves must pass the returned token until it expires
+#[ic_cdk::update]
+#[candid_method(update)]
+fn acquire_edit_lock(principal_id: String, project_id: ProjectId, duration_ns: u64) -> Result<String, String> {
+    ic_cdk::println!("CALL[acquire_edit_lock] Input: principal_id={}, project_id={}, duration_ns={}", principal_id, project_id, duration_ns);
+    let caller = Principal::from_text(&principal_id)
+        .map_err(|e| format!("Invalid principal ID: {}", e))?;
+    let result = pixel_creation_types::acquire_edit_lock(caller, project_id, duration_ns, ic_cdk::api::time());
+    ic_cdk::println!("CALL[acquire_edit_lock] Output: {:?}", result.is_ok());
+    result
+}
+
+/// Release a project edit lock held by the given principal
+#[ic_cdk::update]
+#[candid_method(update)]
+fn release_edit_lock(principal_id: String, project_id: ProjectId, lock_token: String) -> Result<(), String> {
+    ic_cdk::println!("CALL[release_edit_lock] Input: principal_id={}, project_id={}", principal_id, project_id);
+    let caller = Principal::from_text(&principal_id)
+        .map_err(|e| format!("Invalid principal ID: {}", e))?;
+    let result = pixel_creation_types::release_edit_lock(caller, project_id, lock_token);
+    ic_cdk::println!("CALL[release_edit_lock] Output: {:?}", result);
+    result
+}
+
+/// Current unexpired edit lock of a project
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_edit_lock(project_id: ProjectId) -> Option<pixel_creation_types::EditLock> {
+    pixel_creation_types::get_edit_lock(project_id, ic_cdk::api::time())
+}
+
 /// Get a project by ID
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_pixel_project(project_id: ProjectId) -> Option<Project> {
     ic_cdk::println!("CALL[get_pixel_project] Input: project_id={}", project_id);
     let result = pixel_creation_types::get_project(project_id);
@@ -1734,8 +3592,29 @@ fn get_pixel_project(project_id: ProjectId) -> Option<Project> {
     result
 }
 
+/// Get projects forked directly from a project
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_pixel_project_forks(project_id: ProjectId) -> Vec<Project> {
+    ic_cdk::println!("CALL[get_pixel_project_forks] Input: project_id={}", project_id);
+    let result = pixel_creation_types::get_project_forks(project_id);
+    ic_cdk::println!("CALL[get_pixel_project_forks] Output: count={}", result.len());
+    result
+}
+
+/// Get a project and the chain of projects it was forked from
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_fork_chain(project_id: ProjectId) -> Vec<Project> {
+    ic_cdk::println!("CALL[get_fork_chain] Input: project_id={}", project_id);
+    let result = pixel_creation_types::get_fork_chain(project_id);
+    ic_cdk::println!("CALL[get_fork_chain] Output: depth={}", result.len());
+    result
+}
+
 /// Get a specific version of a project
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_pixel_version(project_id: ProjectId, version_id: VersionId) -> Option<Version> {
     ic_cdk::println!("CALL[get_pixel_version] Input: project_id={}, version_id={}", project_id, version_id);
     let result = pixel_creation_types::get_version(project_id, version_id);
@@ -1745,6 +3624,7 @@ fn get_pixel_version(project_id: ProjectId, version_id: VersionId) -> Option<Ver
 
 /// Get current source of a project
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_pixel_current_source(project_id: ProjectId) -> Option<PixelArtSource> {
     ic_cdk::println!("CALL[get_pixel_current_source] Input: project_id={}", project_id);
     let result = pixel_creation_types::get_current_source(project_id);
@@ -1752,8 +3632,19 @@ fn get_pixel_current_source(project_id: ProjectId) -> Option<PixelArtSource> {
     result
 }
 
+/// Get the current version id of a project, to pass as if_match_version when saving
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_pixel_current_version_id(project_id: ProjectId) -> Option<VersionId> {
+    ic_cdk::println!("CALL[get_pixel_current_version_id] Input: project_id={}", project_id);
+    let result = pixel_creation_types::get_current_version_id(project_id);
+    ic_cdk::println!("CALL[get_pixel_current_version_id] Output: {:?}", result);
+    result
+}
+
 /// Export project for IoT device in compact JSON format
 #[ic_cdk::query]
+#[candid_method(query)]
 fn export_pixel_for_device(project_id: ProjectId, version_id: Option<VersionId>) -> Result<String, String> {
     ic_cdk::println!("CALL[export_pixel_for_device] Input: project_id={}, version_id={:?}", project_id, version_id);
     let result = pixel_creation_types::export_for_device(project_id, version_id);
@@ -1764,8 +3655,43 @@ fn export_pixel_for_device(project_id: ProjectId, version_id: Option<VersionId>)
     result
 }
 
+/// Push every new version of a project to the device's registered endpoint
+#[ic_cdk::update]
+#[candid_method(update)]
+fn subscribe_device_to_project(device_id: String, project_id: ProjectId) -> Result<(), String> {
+    let principal_id = caller().to_string();
+    ic_cdk::println!("CALL[subscribe_device_to_project] Input: device_id={}, project_id={}, principal_id={}", device_id, project_id, principal_id);
+    let result = device_delivery_types::subscribe_device_to_project(device_id, project_id, principal_id);
+    ic_cdk::println!("CALL[subscribe_device_to_project] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn unsubscribe_device_from_project(device_id: String, project_id: ProjectId) -> Result<(), String> {
+    let principal_id = caller().to_string();
+    ic_cdk::println!("CALL[unsubscribe_device_from_project] Input: device_id={}, project_id={}, principal_id={}", device_id, project_id, principal_id);
+    let result = device_delivery_types::unsubscribe_device_from_project(device_id, project_id, principal_id);
+    ic_cdk::println!("CALL[unsubscribe_device_from_project] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_project_device_subscriptions(project_id: ProjectId) -> Vec<device_delivery_types::DeviceProjectSubscription> {
+    device_delivery_types::get_project_device_subscriptions(project_id)
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_device_delivery_log(device_id: String, offset: u64, limit: u64) -> Vec<device_delivery_types::DeliveryLogEntry> {
+    let limit = clamp_page_size(limit);
+    device_delivery_types::get_device_delivery_log(device_id, offset, limit)
+}
+
 /// List projects by owner with pagination
 #[ic_cdk::query]
+#[candid_method(query)]
 fn list_pixel_projects_by_owner(owner: Principal, page: u32, page_size: u32) -> Vec<Project> {
     ic_cdk::println!("CALL[list_pixel_projects_by_owner] Input: owner={}, page={}, page_size={}", owner, page, page_size);
     let result = pixel_creation_types::list_projects_by_owner(owner, page, page_size);
@@ -1775,6 +3701,7 @@ fn list_pixel_projects_by_owner(owner: Principal, page: u32, page_size: u32) ->
 
 /// Get project count by owner
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_pixel_project_count_by_owner(owner: Principal) -> u64 {
     ic_cdk::println!("CALL[get_pixel_project_count_by_owner] Input: owner={}", owner);
     let result = pixel_creation_types::get_project_count_by_owner(owner);
@@ -1784,6 +3711,7 @@ fn get_pixel_project_count_by_owner(owner: Principal) -> u64 {
 
 /// Delete a project (only by owner)
 #[ic_cdk::update]
+#[candid_method(update)]
 fn delete_pixel_project(principal_id: String, project_id: ProjectId) -> Result<bool, String> {
     ic_cdk::println!("CALL[delete_pixel_project] Input: principal_id={}, project_id={}", principal_id, project_id);
     let caller = Principal::from_text(&principal_id)
@@ -1795,6 +3723,7 @@ fn delete_pixel_project(principal_id: String, project_id: ProjectId) -> Result<b
 
 /// Get all projects with pagination
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_pixel_projects_paginated(offset: u64, limit: u64) -> Vec<Project> {
     ic_cdk::println!("CALL[get_pixel_projects_paginated] Input: offset={}, limit={}", offset, limit);
     let result = pixel_creation_types::get_projects_paginated(offset, limit as usize);
@@ -1804,6 +3733,7 @@ fn get_pixel_projects_paginated(offset: u64, limit: u64) -> Vec<Project> {
 
 /// Get total project count
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_total_pixel_project_count() -> u64 {
     ic_cdk::println!("CALL[get_total_pixel_project_count] Input: none");
     let result = pixel_creation_types::get_total_project_count();
@@ -1813,10 +3743,11 @@ fn get_total_pixel_project_count() -> u64 {
 
 // ==== Device Management API ====
 
-use device_types::{DeviceInfo, DeviceType, DeviceStatus, DeviceCapability, DeviceFilter, DeviceListResponse, DeviceService};
+use device_types::{DeviceInfo, DeviceType, DeviceStatus, DeviceCapability, DeviceFilter, DeviceListResponse, DeviceService, DeviceTelemetry, FirmwareRelease};
 
 /// Add a new device
 #[ic_cdk::update]
+#[candid_method(update)]
 fn add_device(device_info: DeviceInfo) -> Result<u64, String> {
     ic_cdk::println!("CALL[add_device] Input: device_info={:?}", device_info);
     
@@ -1835,6 +3766,7 @@ fn add_device(device_info: DeviceInfo) -> Result<u64, String> {
 
 /// Get device by ID
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_device_by_id(device_id: String) -> Option<DeviceInfo> {
     ic_cdk::println!("CALL[get_device_by_id] Input: device_id={}", device_id);
     let result = DeviceService::get_device_by_id(&device_id);
@@ -1844,6 +3776,7 @@ fn get_device_by_id(device_id: String) -> Option<DeviceInfo> {
 
 /// Get devices by owner
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_devices_by_owner(owner: String) -> Vec<DeviceInfo> {
     ic_cdk::println!("CALL[get_devices_by_owner] Input: owner={}", owner);
     let principal = Principal::from_text(&owner).unwrap_or(Principal::anonymous());
@@ -1854,6 +3787,7 @@ fn get_devices_by_owner(owner: String) -> Vec<DeviceInfo> {
 
 /// Update device information
 #[ic_cdk::update]
+#[candid_method(update)]
 fn update_device(device_id: String, updated_device: DeviceInfo) -> Result<(), String> {
     ic_cdk::println!("CALL[update_device] Input: device_id={}, updated_device={:?}", device_id, updated_device);
     let result = DeviceService::update_device(&device_id, updated_device);
@@ -1863,6 +3797,7 @@ fn update_device(device_id: String, updated_device: DeviceInfo) -> Result<(), St
 
 /// Delete device
 #[ic_cdk::update]
+#[candid_method(update)]
 fn delete_device(device_id: String) -> Result<(), String> {
     ic_cdk::println!("CALL[delete_device] Input: device_id={}", device_id);
     let result = DeviceService::delete_device(&device_id);
@@ -1872,6 +3807,7 @@ fn delete_device(device_id: String) -> Result<(), String> {
 
 /// Get all devices with pagination
 #[ic_cdk::query]
+#[candid_method(query)]
 fn get_all_devices(offset: u64, limit: u64) -> DeviceListResponse {
     ic_cdk::println!("CALL[get_all_devices] Input: offset={}, limit={}", offset, limit);
     let result = DeviceService::get_all_devices(offset, limit);
@@ -1881,6 +3817,7 @@ fn get_all_devices(offset: u64, limit: u64) -> DeviceListResponse {
 
 /// Search devices with filters
 #[ic_cdk::query]
+#[candid_method(query)]
 fn search_devices(filter: DeviceFilter) -> Vec<DeviceInfo> {
     ic_cdk::println!("CALL[search_devices] Input: filter={:?}", filter);
     let result = DeviceService::search_devices(filter);
@@ -1890,6 +3827,7 @@ fn search_devices(filter: DeviceFilter) -> Vec<DeviceInfo> {
 
 /// Update device status
 #[ic_cdk::update]
+#[candid_method(update)]
 fn update_device_status(device_id: String, status: DeviceStatus) -> Result<(), String> {
     ic_cdk::println!("CALL[update_device_status] Input: device_id={}, status={:?}", device_id, status);
     let result = DeviceService::update_device_status(&device_id, status);
@@ -1899,6 +3837,7 @@ fn update_device_status(device_id: String, status: DeviceStatus) -> Result<(), S
 
 /// Update device last seen time
 #[ic_cdk::update]
+#[candid_method(update)]
 fn update_device_last_seen(device_id: String) -> Result<(), String> {
     ic_cdk::println!("CALL[update_device_last_seen] Input: device_id={}", device_id);
     let result = DeviceService::update_last_seen(&device_id);
@@ -1906,4 +3845,286 @@ fn update_device_last_seen(device_id: String) -> Result<(), String> {
     result
 }
 
+/// Report the firmware version running on a device
+#[ic_cdk::update]
+#[candid_method(update)]
+fn report_firmware_version(device_id: String, version: String) -> Result<(), String> {
+    ic_cdk::println!("CALL[report_firmware_version] Input: device_id={}, version={}", device_id, version);
+    let result = DeviceService::report_firmware_version(&device_id, version);
+    ic_cdk::println!("CALL[report_firmware_version] Output: {:?}", result);
+    result
+}
+
+/// Record a metric reading reported by a device
+#[ic_cdk::update]
+#[candid_method(update)]
+fn record_device_telemetry(device_id: String, metric_key: String, metric_value: f64, unit: Option<String>) -> Result<(), String> {
+    ic_cdk::println!("CALL[record_device_telemetry] Input: device_id={}, metric_key={}, metric_value={}, unit={:?}", device_id, metric_key, metric_value, unit);
+    let result = DeviceService::record_telemetry(&device_id, metric_key, metric_value, unit);
+    ic_cdk::println!("CALL[record_device_telemetry] Output: {:?}", result);
+    result
+}
+
+/// Get up to `limit` of a device's telemetry readings within a time range (inclusive, nanoseconds)
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_device_telemetry(device_id: String, start_ns: u64, end_ns: u64, limit: u64, start: Option<String>) -> PagedResult<DeviceTelemetry> {
+    ic_cdk::println!("CALL[get_device_telemetry] Input: device_id={}, start_ns={}, end_ns={}, limit={}, start={:?}", device_id, start_ns, end_ns, limit, start);
+    let limit = clamp_page_size(limit);
+    if limit == 0 {
+        return PagedResult { items: Vec::new(), truncated: false, items_processed: 0, next_start: None };
+    }
+    let result = DeviceService::get_telemetry(&device_id, start_ns, end_ns, &InstructionGuard::new(start).with_max_items(limit));
+    ic_cdk::println!("CALL[get_device_telemetry] Output: count={}, truncated={}", result.items.len(), result.truncated);
+    result
+}
+
+/// Get the latest reading of one metric for a device
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_device_latest_telemetry(device_id: String, metric_key: String) -> Option<DeviceTelemetry> {
+    ic_cdk::println!("CALL[get_device_latest_telemetry] Input: device_id={}, metric_key={}", device_id, metric_key);
+    let result = DeviceService::get_latest_telemetry(&device_id, &metric_key);
+    ic_cdk::println!("CALL[get_device_latest_telemetry] Output: {:?}", result);
+    result
+}
+
+/// List devices running a given firmware version
+#[ic_cdk::query]
+#[candid_method(query)]
+fn list_devices_by_firmware(version: String) -> Vec<DeviceInfo> {
+    ic_cdk::println!("CALL[list_devices_by_firmware] Input: version={}", version);
+    let result = DeviceService::list_devices_by_firmware(&version);
+    ic_cdk::println!("CALL[list_devices_by_firmware] Output: count={}", result.len());
+    result
+}
+
+/// Publish a firmware release for a device type (admin only)
+#[ic_cdk::update]
+#[candid_method(update)]
+fn register_firmware_release(device_type: DeviceType, version: String, download_url: String, release_notes: String) -> Result<(), String> {
+    require_role!(Role::Admin);
+    ic_cdk::println!("CALL[register_firmware_release] Input: device_type={:?}, version={}, download_url={}", device_type, version, download_url);
+    let result = DeviceService::register_firmware_release(device_type, version, download_url, release_notes);
+    ic_cdk::println!("CALL[register_firmware_release] Output: {:?}", result);
+    result
+}
+
+/// Get the latest firmware release for a device if it is newer than the device's reported version
+#[ic_cdk::query]
+#[candid_method(query)]
+fn check_firmware_updates(device_id: String) -> Option<FirmwareRelease> {
+    ic_cdk::println!("CALL[check_firmware_updates] Input: device_id={}", device_id);
+    let result = DeviceService::check_firmware_updates(&device_id);
+    ic_cdk::println!("CALL[check_firmware_updates] Output: {:?}", result);
+    result
+}
+
+// ==== Monitoring API ====
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct CanisterStats {
+    total_agents: u64,
+    total_mcps: u64,
+    total_traces: u64,
+    total_users: u64,
+    total_accounts: u64,
+    total_token_grants: u64,
+    total_mcp_grants: u64,
+    total_devices: u64,
+    total_pixel_projects: u64,
+    total_chat_pairs: u64,
+    stable_memory_used_pages: u64,
+    heap_memory_used_bytes: u64,
+    ic_time_ns: u64,
+}
+
+// Wasm linear memory in bytes; 0 when not running as wasm (unit tests)
+fn heap_memory_used_bytes() -> u64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        core::arch::wasm32::memory_size(0) as u64 * 65536
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        0
+    }
+}
+
+/// System-wide counters for monitoring; every value is a collection length, no scans
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_canister_stats() -> CanisterStats {
+    use crate::stable_mem_storage::*;
+
+    let stats = CanisterStats {
+        total_agents: AGENT_ITEMS.with(|s| s.borrow().len()),
+        total_mcps: MCP_ITEMS.with(|s| s.borrow().len()),
+        total_traces: TRACE_STORAGE.with(|s| s.borrow().len()),
+        total_users: USER_PROFILES.with(|s| s.borrow().len()),
+        total_accounts: ACCOUNTS.with(|s| s.borrow().len()),
+        total_token_grants: NEWUSER_GRANTS.with(|s| s.borrow().len()),
+        total_mcp_grants: NEWMCP_GRANTS.with(|s| s.borrow().len()),
+        total_devices: DEVICES.with(|s| s.borrow().len()),
+        total_pixel_projects: PIXEL_PROJECTS.with(|s| s.borrow().len()),
+        total_chat_pairs: CHAT_HISTORIES.with(|s| s.borrow().len()),
+        stable_memory_used_pages: ic_cdk::api::stable::stable64_size(),
+        heap_memory_used_bytes: heap_memory_used_bytes(),
+        ic_time_ns: ic_cdk::api::time(),
+    };
+    ic_cdk::println!("CALL[get_canister_stats] Output: {:?}, instructions={}", stats, ic_cdk::api::performance_counter(0));
+    stats
+}
+
+// ==== Access Control API ====
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_role(principal: Principal) -> Role {
+    access_control::get_role(&principal)
+}
+
+#[ic_cdk::query]
+#[candid_method(query)]
+fn list_roles() -> Vec<(Principal, Role)> {
+    access_control::list_roles()
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn grant_role(principal: Principal, role: Role) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    ic_cdk::println!("CALL[grant_role] Input: caller={}, principal={}, role={:?}", caller, principal, role);
+    let result = access_control::grant_role(&caller, principal, role);
+    ic_cdk::println!("CALL[grant_role] Output: {:?}", result);
+    result
+}
+
+#[ic_cdk::update]
+#[candid_method(update)]
+fn revoke_role(principal: Principal) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    ic_cdk::println!("CALL[revoke_role] Input: caller={}, principal={}", caller, principal);
+    let result = access_control::revoke_role(&caller, principal);
+    ic_cdk::println!("CALL[revoke_role] Output: {:?}", result);
+    result
+}
+
+// Timers and other heap state are dropped by an upgrade; snapshot them into UPGRADE_STATE
+#[ic_cdk::pre_upgrade]
+fn pre_upgrade() {
+    access_control::pre_upgrade();
+    let mining_timer_running = MINING_TIMER_ID.with(|id| id.borrow().is_some());
+    let chat_cleanup_timer_running = CHAT_CLEANUP_TIMER_ID.with(|id| id.borrow().is_some());
+    let webhook_timer_running = WEBHOOK_TIMER_ID.with(|id| id.borrow().is_some());
+    let device_delivery_timer_running = DEVICE_DELIVERY_TIMER_ID.with(|id| id.borrow().is_some());
+    stable_mem_storage::UPGRADE_STATE.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let state = types::UpgradeState {
+            mining_timer_running,
+            chat_cleanup_timer_running: Some(chat_cleanup_timer_running),
+            last_upgrade_time: cell.get().last_upgrade_time,
+            webhook_timer_running: Some(webhook_timer_running),
+            device_delivery_timer_running: Some(device_delivery_timer_running),
+        };
+        ic_cdk::println!("[upgrade] pre_upgrade: saving {:?}", state);
+        cell.set(state).expect("Failed to save upgrade state");
+    });
+}
+
+// raw_rand cannot be awaited inside init or post_upgrade, so seeding runs from a one-off timer
+fn schedule_export_salt_seed() {
+    ic_cdk_timers::set_timer(Duration::ZERO, || ic_cdk::spawn(trace_storage::seed_export_salt()));
+}
+
+#[ic_cdk::init]
+fn init() {
+    schedule_export_salt_seed();
+}
+
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    access_control::post_upgrade();
+    token_economy::migrate_legacy_grants();
+    token_economy::seed_total_minted();
+    society_profile_types::backfill_nickname_index();
+    society_profile_types::backfill_agent_contact_index();
+    types::migrate_legacy_instruction_budget();
+    mcp_asset_types::seed_mcp_stack_events();
+    mcp_asset_types::seed_staker_stakes();
+    trace_storage::seed_agent_execution_totals();
+    agent_asset_types::seed_agent_trigram_index();
+    schedule_export_salt_seed();
+
+    let mut state = stable_mem_storage::UPGRADE_STATE.with(|cell| cell.borrow().get().clone());
+    ic_cdk::println!("[upgrade] post_upgrade: restoring {:?}", state);
+    if state.mining_timer_running {
+        start_mining_timer();
+    }
+    if state.chat_cleanup_timer_running == Some(true) {
+        start_chat_cleanup_timer();
+    }
+    if state.webhook_timer_running == Some(true) {
+        start_webhook_timer();
+    }
+    if state.device_delivery_timer_running == Some(true) {
+        start_device_delivery_timer();
+    }
+
+    state.last_upgrade_time = ic_cdk::api::time();
+    stable_mem_storage::UPGRADE_STATE.with(|cell| {
+        cell.borrow_mut().set(state).expect("Failed to save upgrade state");
+    });
+}
+
+/// Time (ns) of the most recent upgrade, 0 if the canister has never been upgraded
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_last_upgrade_time() -> u64 {
+    stable_mem_storage::UPGRADE_STATE.with(|cell| cell.borrow().get().last_upgrade_time)
+}
+
+// ==== Candid Interface ====
+
+/// The canister's Candid service description, built from the `candid_method` annotations above
+#[ic_cdk::query]
+#[candid_method(query)]
+fn get_candid_interface() -> String {
+    __export_service()
+}
+
+// bitpay's `transform` query is collected too and names its argument type unqualified
+use ic_cdk::api::management_canister::http_request::TransformArgs;
+
+// Must stay below every `candid_method` so all endpoints are collected
+candid::export_service!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stable_mem_storage::TRACE_STORAGE;
+    use crate::types::MAX_PAGE_SIZE;
+
+    #[test]
+    fn test_paginated_endpoint_caps_absurd_limit() {
+        TRACE_STORAGE.with(|storage| {
+            let mut storage = storage.borrow_mut();
+            for i in 0..(MAX_PAGE_SIZE + 20) {
+                let trace_id = format!("trace_{:04}", i);
+                storage.insert(trace_id.clone(), TraceLog { trace_id, context_id: "ctx".to_string(), calls: vec![] });
+            }
+        });
+
+        assert_eq!(get_traces_paginated(0, usize::MAX).len(), MAX_PAGE_SIZE);
+        assert_eq!(get_traces_paginated(MAX_PAGE_SIZE as u64, usize::MAX).len(), 20);
+    }
 
+    #[test]
+    fn test_candid_interface_parses() {
+        let interface = get_candid_interface();
+        let prog: candid_parser::IDLProg = interface.parse().expect("generated Candid interface should parse");
+        assert!(prog.actor.is_some());
+        assert!(interface.contains("get_candid_interface"));
+        assert!(interface.contains("http_request_update"));
+    }
+}
@@ -1,14 +1,51 @@
 use candid::{CandidType, Decode, Encode};
+use ic_cdk::api::management_canister::http_request::{
+    http_request, CanisterHttpRequestArgument, HttpMethod, TransformContext,
+};
 use ic_stable_structures::storable::Bound;
 use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableVec};
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::cell::RefCell;
-use crate::stable_mem_storage::{MCP_ITEMS, USER_MCP_INDEX, MCP_STACK_RECORDS};
+use crate::stable_mem_storage::{MCP_ITEMS, USER_MCP_INDEX, MCP_STACK_RECORDS, MCP_STACK_EVENTS, MCP_HEALTH, RATINGS, MCP_TAG_INDEX, LEADERBOARD_HISTORY, STAKER_NET_STAKE, STAKER_RANK};
+use crate::aio_protocal_types::StringVec;
+use crate::types::{CursorPage, InstructionGuard, PagedResult, clamp_page_limit, clamp_page_size, cursor_key_range, time};
+use crate::webhook_types::WebhookEventType;
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
+const WEEK_NS: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+/// Number of stakers kept in each weekly leaderboard snapshot
+const LEADERBOARD_SNAPSHOT_SIZE: usize = 100;
+/// Failed health checks in a row after which a published MCP is deprecated
+pub const MCP_HEALTH_MAX_FAILURES: u32 = 5;
+/// Outcalls made per health check round; later rounds continue from where the last one stopped
+const MCP_HEALTH_CHECKS_PER_ROUND: usize = 20;
+
+thread_local! {
+    // Set while a health check round is awaiting its HTTP outcalls, so a slow round is not overlapped by the next tick
+    static MCP_HEALTH_CHECK_RUNNING: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+    // Name of the last MCP checked; the next round starts after it
+    static MCP_HEALTH_CHECK_CURSOR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// Clears MCP_HEALTH_CHECK_RUNNING when the round ends, including when a trap after an await
+// makes the runtime drop the round's future
+struct HealthCheckRound;
+
+impl HealthCheckRound {
+    fn start() -> Option<Self> {
+        (!MCP_HEALTH_CHECK_RUNNING.with(|running| running.replace(true))).then_some(HealthCheckRound)
+    }
+}
+
+impl Drop for HealthCheckRound {
+    fn drop(&mut self) {
+        MCP_HEALTH_CHECK_RUNNING.with(|running| running.set(false));
+    }
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct McpItem {
     pub id: u64,  // nat64 in Candid, must be non-optional
@@ -26,6 +63,101 @@ pub struct McpItem {
     pub prompts: bool,  // bool in Candid
     pub tools: bool,  // bool in Candid
     pub sampling: bool,  // bool in Candid
+    pub deleted_at: Option<u64>,  // opt nat64 in Candid; set when soft-deleted
+    pub tags: Vec<String>,  // vec text in Candid; normalized to lowercase on write
+    pub status: Option<McpStatus>,  // opt McpStatus in Candid; Published when omitted, only Published items are listed
+    pub created_at: Option<u64>,  // opt nat64 in Candid; set on insert, None for items stored before it was recorded
+}
+
+impl McpItem {
+    /// Lifecycle status, treating records and requests without one as Published
+    pub fn status(&self) -> McpStatus {
+        self.status.unwrap_or(McpStatus::Published)
+    }
+
+    /// Whether the item shows up in listings and searches
+    pub fn is_listed(&self) -> bool {
+        self.deleted_at.is_none() && self.status() == McpStatus::Published
+    }
+}
+
+/// Publication lifecycle of an MCP item: Draft -> Published -> Deprecated -> Archived
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum McpStatus {
+    Draft,
+    Published,
+    Deprecated,
+    Archived,
+}
+
+impl McpStatus {
+    pub fn can_transition_to(self, next: McpStatus) -> bool {
+        matches!(
+            (self, next),
+            (McpStatus::Draft, McpStatus::Published)
+                | (McpStatus::Published, McpStatus::Deprecated)
+                | (McpStatus::Deprecated, McpStatus::Archived)
+        )
+    }
+
+    /// Whether inverted index entries may still be written for an item in this status
+    pub fn is_indexable(self) -> bool {
+        matches!(self, McpStatus::Draft | McpStatus::Published)
+    }
+}
+
+// McpItem layout before tags were added, used to decode records stored by older versions
+#[derive(CandidType, Deserialize)]
+struct LegacyMcpItem {
+    id: u64,
+    name: String,
+    description: String,
+    author: String,
+    owner: String,
+    git_repo: String,
+    exec_file: Option<String>,
+    homepage: Option<String>,
+    remote_endpoint: Option<String>,
+    mcp_type: String,
+    community_body: Option<String>,
+    resources: bool,
+    prompts: bool,
+    tools: bool,
+    sampling: bool,
+    deleted_at: Option<u64>,
+}
+
+impl From<LegacyMcpItem> for McpItem {
+    fn from(item: LegacyMcpItem) -> Self {
+        Self {
+            id: item.id,
+            name: item.name,
+            description: item.description,
+            author: item.author,
+            owner: item.owner,
+            git_repo: item.git_repo,
+            exec_file: item.exec_file,
+            homepage: item.homepage,
+            remote_endpoint: item.remote_endpoint,
+            mcp_type: item.mcp_type,
+            community_body: item.community_body,
+            resources: item.resources,
+            prompts: item.prompts,
+            tools: item.tools,
+            sampling: item.sampling,
+            deleted_at: item.deleted_at,
+            tags: Vec::new(),
+            status: None,
+            created_at: None,
+        }
+    }
+}
+
+/// How `get_mcp_items_by_tags` combines several tags
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum TagMatchMode {
+    Any,
+    All,
 }
 
 impl Default for McpItem {
@@ -46,10 +178,22 @@ impl Default for McpItem {
             prompts: false,
             tools: false,
             sampling: false,
+            deleted_at: None,
+            tags: Vec::new(),
+            status: None,
+            created_at: None,
         }
     }
 }
 
+/// Result of the periodic reachability checks of an MCP's remote endpoint
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct McpHealth {
+    pub last_check_ns: u64,
+    pub consecutive_failures: u32,
+    pub is_healthy: bool,
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, Copy, PartialEq, Eq)]
 pub enum StackStatus {
     Stacked,
@@ -65,6 +209,55 @@ pub struct McpStackRecord {
     pub stack_status: StackStatus,
 }
 
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum StackEventType {
+    Stack,
+    Unstack,
+}
+
+/// One stake change of an MCP; `balance_after` is the MCP's total stake once it applied
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct McpStackEvent {
+    pub mcp_name: String,
+    pub principal_id: String,
+    pub delta: i64,
+    pub balance_after: u64,
+    pub event_type: StackEventType,
+    pub timestamp: u64,
+}
+
+// Stack events are keyed by MCP first so one MCP's timeline is a contiguous range
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct McpStackEventKey {
+    pub mcp_name: String,
+    pub timestamp: u64,
+}
+
+// Staker ranking is keyed by inverted stake first so the largest stakes come first,
+// ties in principal order
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct StakerRankKey {
+    pub inverted_stake: u64,
+    pub principal_id: String,
+}
+
+impl StakerRankKey {
+    fn new(principal_id: &str, stake: u64) -> Self {
+        Self { inverted_stake: u64::MAX - stake, principal_id: principal_id.to_string() }
+    }
+
+    fn stake(&self) -> u64 {
+        u64::MAX - self.inverted_stake
+    }
+}
+
+/// Top stakers as of the first snapshot taken in a week
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WeeklyLeaderboardSnapshot {
+    pub week_start_ns: u64,
+    pub entries: Vec<(String, u64)>, // (principal_id, net staked credits), highest first
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct StackPositionRecord {
     pub id: u64,
@@ -72,6 +265,23 @@ pub struct StackPositionRecord {
     pub stack_amount: u64,
 }
 
+/// A user's score (1-5) and review of a published MCP
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RatingEntry {
+    pub reviewer: String,
+    pub mcp_name: String,
+    pub score: u8,
+    pub comment: String,
+    pub timestamp: u64,
+}
+
+// Ratings are keyed by MCP first so one MCP's ratings form a contiguous range
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RatingKey {
+    pub mcp_name: String,
+    pub reviewer: String,
+}
+
 // Define the key for user data association
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct UserMcpKey {
@@ -120,6 +330,9 @@ impl ic_stable_structures::Storable for McpItem {
                 item
             },
             Err(e) => {
+                if let Ok(legacy) = Decode!(bytes.as_ref(), LegacyMcpItem) {
+                    return legacy.into();
+                }
                 ic_cdk::println!("[ERROR] Storable::from_bytes - Failed to decode: {:?}", e);
                 ic_cdk::println!("[ERROR] Storable::from_bytes - Raw bytes: {:?}", bytes);
                 
@@ -143,6 +356,18 @@ impl ic_stable_structures::Storable for McpItem {
     const BOUND: Bound = Bound::Bounded { max_size: 20000 * 1024, is_fixed_size: false };
 }
 
+impl ic_stable_structures::Storable for WeeklyLeaderboardSnapshot {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self).expect("Failed to encode WeeklyLeaderboardSnapshot"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode WeeklyLeaderboardSnapshot")
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 1024 * 32, is_fixed_size: false };
+}
+
 impl ic_stable_structures::Storable for McpStackRecord {
     fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
         Cow::Owned(Encode!(&self).expect("Failed to encode McpStackRecord"))
@@ -155,6 +380,81 @@ impl ic_stable_structures::Storable for McpStackRecord {
     const BOUND: Bound = Bound::Bounded { max_size: 1024 * 4, is_fixed_size: false };
 }
 
+impl ic_stable_structures::Storable for McpHealth {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self).expect("Failed to encode McpHealth"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode McpHealth")
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 256, is_fixed_size: false };
+}
+
+impl ic_stable_structures::Storable for McpStackEventKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.mcp_name, &self.timestamp).expect("Failed to encode McpStackEventKey"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let (mcp_name, timestamp) = Decode!(bytes.as_ref(), String, u64).expect("Failed to decode McpStackEventKey");
+        Self { mcp_name, timestamp }
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false };
+}
+
+impl ic_stable_structures::Storable for McpStackEvent {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self).expect("Failed to encode McpStackEvent"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode McpStackEvent")
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 1024 * 4, is_fixed_size: false };
+}
+
+impl ic_stable_structures::Storable for StakerRankKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.inverted_stake, &self.principal_id).expect("Failed to encode StakerRankKey"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let (inverted_stake, principal_id) = Decode!(bytes.as_ref(), u64, String).expect("Failed to decode StakerRankKey");
+        Self { inverted_stake, principal_id }
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false };
+}
+
+impl ic_stable_structures::Storable for RatingKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.mcp_name, &self.reviewer).expect("Failed to encode RatingKey"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let (mcp_name, reviewer) = Decode!(bytes.as_ref(), String, String).expect("Failed to decode RatingKey");
+        Self { mcp_name, reviewer }
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false };
+}
+
+impl ic_stable_structures::Storable for RatingEntry {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self).expect("Failed to encode RatingEntry"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode RatingEntry")
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 1024 * 8, is_fixed_size: false };
+}
+
 impl ic_stable_structures::Storable for StackStatus {
     fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
         Cow::Owned(Encode!(&self).expect("Failed to encode StackStatus"))
@@ -187,6 +487,10 @@ pub fn add_mcp_item(mcp: McpItem, caller_id: String) -> Result<String, String> {
     if mcp.git_repo.trim().is_empty() {
         return Err("MCP git repository cannot be empty".to_string());
     }
+
+    if !matches!(mcp.status(), McpStatus::Draft | McpStatus::Published) {
+        return Err("New MCP items must be Draft or Published".to_string());
+    }
     
     // Validate MCP type
     if !["stdio", "http", "sse"].contains(&mcp.mcp_type.as_str()) {
@@ -236,6 +540,10 @@ pub fn add_mcp_item(mcp: McpItem, caller_id: String) -> Result<String, String> {
         
         let mut mcp_item = mcp.clone();
         mcp_item.owner = caller_id.clone();
+        mcp_item.deleted_at = None;
+        mcp_item.tags = normalize_tags(&mcp.tags);
+        mcp_item.status = Some(mcp.status());
+        mcp_item.created_at = Some(time());
         
         // Set id to current length + 1 to ensure it's never 0
         mcp_item.id = items.len() as u64 + 1;
@@ -254,25 +562,116 @@ pub fn add_mcp_item(mcp: McpItem, caller_id: String) -> Result<String, String> {
             };
             user_index.insert(key, ());
         });
+        add_to_tag_index(&mcp_item.name, &mcp_item.tags);
         
         Ok(mcp_item.name)  // Return the name as the identifier
+    }).inspect(|name| {
+        crate::webhook_types::enqueue_mcp_event(name, WebhookEventType::Created);
     })
 }
 
-/// Get an MCP item by name
+/// Confirmation returned to clients after an MCP item is stored
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct McpCreateResult {
+    pub name: String,
+    pub owner: String,
+    pub created_at_ns: u64,
+}
+
+/// Add an MCP item and report the stored owner and creation time
+pub fn add_mcp_item_with_result(mcp: McpItem, caller_id: String) -> Result<McpCreateResult, String> {
+    let name = add_mcp_item(mcp, caller_id)?;
+    let stored = get_mcp_item(name.clone()).ok_or_else(|| format!("MCP with name '{}' not found after insert", name))?;
+    Ok(McpCreateResult {
+        name,
+        owner: stored.owner,
+        created_at_ns: stored.created_at.unwrap_or_default(),
+    })
+}
+
+/// Get an MCP item by name (soft-deleted items are hidden)
 pub fn get_mcp_item(name: String) -> Option<McpItem> {
     MCP_ITEMS.with(|items| {
-        items.borrow().get(&name)
+        items.borrow().get(&name).filter(|item| item.deleted_at.is_none())
     })
 }
 
-/// Get all MCP items
-pub fn get_all_mcp_items() -> Vec<McpItem> {
+/// MCP items whose status is in `statuses` (any status when None), read only while the instruction budget lasts
+pub fn get_all_mcp_items_guarded(include_deleted: bool, statuses: Option<Vec<McpStatus>>, guard: &InstructionGuard) -> PagedResult<McpItem> {
     MCP_ITEMS.with(|items| {
-        items.borrow().iter().map(|(_, item)| item).collect()
+        guard.collect_matching(items.borrow().range(cursor_key_range(guard.start_after())), |item| {
+            (include_deleted || item.deleted_at.is_none())
+                && statuses.as_ref().is_none_or(|statuses| statuses.contains(&item.status()))
+        })
+    })
+}
+
+/// Get all MCP items, optionally including soft-deleted ones
+pub fn get_all_mcp_items_admin(include_deleted: bool) -> Vec<McpItem> {
+    MCP_ITEMS.with(|items| {
+        items.borrow().iter()
+            .map(|(_, item)| item)
+            .filter(|item| include_deleted || item.deleted_at.is_none())
+            .collect()
     })
 }
 
+/// Owner of an MCP item, including soft-deleted ones
+pub fn get_mcp_owner(name: String) -> Option<String> {
+    MCP_ITEMS.with(|items| items.borrow().get(&name).map(|item| item.owner))
+}
+
+/// Whether the MCP exists but is soft-deleted or not Published. Its index entries are kept
+/// so it can be restored or published, so searches use this to hide them.
+pub fn is_mcp_hidden(name: &str) -> bool {
+    MCP_ITEMS.with(|items| items.borrow().get(&name.to_string()).is_some_and(|item| !item.is_listed()))
+}
+
+/// Soft-delete an MCP item by setting its deleted_at timestamp
+pub fn delete_soft(name: String) -> Result<(), String> {
+    MCP_ITEMS.with(|items| {
+        let mut items = items.borrow_mut();
+        let mut item = items.get(&name).ok_or_else(|| format!("MCP with name '{}' not found", name))?;
+        if item.deleted_at.is_some() {
+            return Err(format!("MCP with name '{}' is already deleted", name));
+        }
+        item.deleted_at = Some(time());
+        items.insert(name.clone(), item);
+        Ok(())
+    })?;
+    crate::webhook_types::enqueue_mcp_event(&name, WebhookEventType::Deleted);
+    Ok(())
+}
+
+/// Restore a soft-deleted MCP item
+pub fn restore_mcp_item(name: String) -> Result<(), String> {
+    MCP_ITEMS.with(|items| {
+        let mut items = items.borrow_mut();
+        let mut item = items.get(&name).ok_or_else(|| format!("MCP with name '{}' not found", name))?;
+        if item.deleted_at.is_none() {
+            return Err(format!("MCP with name '{}' is not deleted", name));
+        }
+        item.deleted_at = None;
+        items.insert(name, item);
+        Ok(())
+    })
+}
+
+/// Physically remove MCP tombstones deleted before the cutoff; returns the removed names
+/// so the caller can drop their inverted and protocol index entries as well
+pub fn purge_deleted_older_than(cutoff_ns: u64) -> Vec<String> {
+    let expired: Vec<String> = MCP_ITEMS.with(|items| {
+        items.borrow().iter()
+            .filter(|(_, item)| item.deleted_at.is_some_and(|t| t < cutoff_ns))
+            .map(|(name, _)| name)
+            .collect()
+    });
+    for name in &expired {
+        let _ = delete_mcp_item(name.clone());
+    }
+    expired
+}
+
 /// Get all MCP items owned by a specific user
 pub fn get_user_mcp_items(owner: String) -> Vec<McpItem> {
     let mut result = Vec::new();
@@ -282,7 +681,7 @@ pub fn get_user_mcp_items(owner: String) -> Vec<McpItem> {
         
         // Create range bounds for this user
         let start_key = UserMcpKey { owner: owner.clone(), mcp_name: String::new() };
-        let end_key = UserMcpKey { owner: owner.clone(), mcp_name: String::from_utf8(vec![255; 100]).unwrap() };
+        let end_key = UserMcpKey { owner: owner.clone(), mcp_name: char::MAX.to_string().repeat(100) };
         
         // Get all items in range
         for (key, _) in index.range(start_key..=end_key) {
@@ -306,6 +705,9 @@ pub fn update_mcp_item(name: String, mut mcp: McpItem) -> Result<(), String> {
         }
         
         let existing = items.get(&name).unwrap();
+        if existing.deleted_at.is_some() {
+            return Err(format!("MCP with name '{}' has been deleted", name));
+        }
         
         // Check if the caller is the owner
         if existing.owner != mcp.owner {
@@ -315,10 +717,157 @@ pub fn update_mcp_item(name: String, mut mcp: McpItem) -> Result<(), String> {
         // Keep the name, owner, and id from the existing item
         mcp.name = name.clone();
         mcp.id = existing.id;  // Preserve the existing id
+        mcp.deleted_at = None;
+        mcp.tags = normalize_tags(&mcp.tags);
+        mcp.status = existing.status;  // Status only changes through transition_mcp_status
+        mcp.created_at = existing.created_at;
         
-        items.insert(name, mcp);
+        remove_from_tag_index(&name, &existing.tags);
+        add_to_tag_index(&name, &mcp.tags);
+        items.insert(name.clone(), mcp);
         Ok(())
-    })
+    })?;
+    crate::webhook_types::enqueue_mcp_event(&name, WebhookEventType::Updated);
+    Ok(())
+}
+
+/// Move an MCP item along its lifecycle; only Draft->Published, Published->Deprecated
+/// and Deprecated->Archived are allowed
+pub fn transition_mcp_status(name: String, new_status: McpStatus) -> Result<(), String> {
+    MCP_ITEMS.with(|items| {
+        let mut items = items.borrow_mut();
+        let mut item = items.get(&name)
+            .filter(|item| item.deleted_at.is_none())
+            .ok_or_else(|| format!("MCP with name '{}' not found", name))?;
+        if !item.status().can_transition_to(new_status) {
+            return Err(format!("Cannot move MCP '{}' from {:?} to {:?}", name, item.status(), new_status));
+        }
+        item.status = Some(new_status);
+        items.insert(name.clone(), item);
+        Ok(())
+    })?;
+    crate::webhook_types::enqueue_mcp_event(&name, WebhookEventType::Updated);
+    Ok(())
+}
+
+/// Record the outcome of one health check. A published MCP is deprecated once
+/// `MCP_HEALTH_MAX_FAILURES` checks in a row have failed, and published again by the first
+/// successful check after that. MCPs deprecated by their owner are left alone.
+pub fn record_mcp_health_check(mcp_name: String, success: bool, now: u64) -> McpHealth {
+    let previous_failures = get_mcp_health(mcp_name.clone()).map_or(0, |health| health.consecutive_failures);
+    let health = McpHealth {
+        last_check_ns: now,
+        consecutive_failures: if success { 0 } else { previous_failures.saturating_add(1) },
+        is_healthy: success,
+    };
+    MCP_HEALTH.with(|store| store.borrow_mut().insert(mcp_name.clone(), health.clone()));
+
+    let status = get_mcp_item(mcp_name.clone()).map(|item| item.status());
+    if health.consecutive_failures >= MCP_HEALTH_MAX_FAILURES && status == Some(McpStatus::Published) {
+        if let Err(e) = transition_mcp_status(mcp_name.clone(), McpStatus::Deprecated) {
+            ic_cdk::println!("Failed to deprecate unhealthy MCP {}: {}", mcp_name, e);
+        }
+    } else if success && previous_failures >= MCP_HEALTH_MAX_FAILURES && status == Some(McpStatus::Deprecated) {
+        republish_recovered_mcp(&mcp_name);
+    }
+    health
+}
+
+// Undo a health deprecation. Bypasses transition_mcp_status on purpose: owners cannot move an
+// MCP back from Deprecated, but a deprecation the checks made themselves can be reverted.
+fn republish_recovered_mcp(mcp_name: &str) {
+    MCP_ITEMS.with(|items| {
+        let mut items = items.borrow_mut();
+        if let Some(mut item) = items.get(&mcp_name.to_string()) {
+            item.status = Some(McpStatus::Published);
+            items.insert(mcp_name.to_string(), item);
+        }
+    });
+    crate::webhook_types::enqueue_mcp_event(mcp_name, WebhookEventType::Updated);
+}
+
+// Whether the health checks look at this MCP: published ones, and ones they deprecated themselves
+fn needs_health_check(item: &McpItem) -> bool {
+    match item.status() {
+        McpStatus::Published => true,
+        McpStatus::Deprecated => get_mcp_health(item.name.clone())
+            .is_some_and(|health| health.consecutive_failures >= MCP_HEALTH_MAX_FAILURES),
+        _ => false,
+    }
+}
+
+/// Up to `max` (name, endpoint) pairs to check next, continuing after the previous round's
+/// last MCP and wrapping around to the start of the name order
+fn next_health_check_targets(max: usize) -> Vec<(String, String)> {
+    let cursor = MCP_HEALTH_CHECK_CURSOR.with(|cursor| cursor.borrow().clone());
+    let targets: Vec<(String, String)> = MCP_ITEMS.with(|items| {
+        let items = items.borrow();
+        let wrapped = cursor.clone().map(|name| items.range(..=name));
+        items.range(cursor_key_range(cursor))
+            .chain(wrapped.into_iter().flatten())
+            .filter(|(_, item)| item.deleted_at.is_none() && needs_health_check(item))
+            .filter_map(|(name, item)| {
+                let endpoint = item.remote_endpoint?.trim().to_string();
+                endpoint.starts_with("https://").then_some((name, endpoint))
+            })
+            .take(max)
+            .collect()
+    });
+    if let Some((name, _)) = targets.last() {
+        MCP_HEALTH_CHECK_CURSOR.with(|cursor| *cursor.borrow_mut() = Some(name.clone()));
+    }
+    targets
+}
+
+pub fn get_mcp_health(mcp_name: String) -> Option<McpHealth> {
+    MCP_HEALTH.with(|store| store.borrow().get(&mcp_name))
+}
+
+/// Live MCPs whose latest health check failed, in name order
+pub fn get_unhealthy_mcps(offset: u64, limit: usize) -> Vec<McpItem> {
+    let names: Vec<String> = MCP_HEALTH.with(|store| {
+        store.borrow()
+            .iter()
+            .filter(|(_, health)| !health.is_healthy)
+            .map(|(name, _)| name)
+            .collect()
+    });
+    names.into_iter()
+        .filter_map(get_mcp_item)
+        .skip(offset as usize)
+        .take(limit)
+        .collect()
+}
+
+/// GET the remote endpoints of the next `MCP_HEALTH_CHECKS_PER_ROUND` checked MCPs and record
+/// whether they answered. Any response below 500 counts as healthy; MCPs without an HTTPS
+/// endpoint are not checked. A call made while an earlier round is still running returns
+/// without checking anything.
+pub async fn run_mcp_health_checks() {
+    let Some(_round) = HealthCheckRound::start() else {
+        ic_cdk::println!("Skipping MCP health checks: previous round still running");
+        return;
+    };
+
+    for (name, url) in next_health_check_targets(MCP_HEALTH_CHECKS_PER_ROUND) {
+        let arg = CanisterHttpRequestArgument {
+            url,
+            method: HttpMethod::GET,
+            headers: vec![],
+            body: None,
+            max_response_bytes: Some(2_048),
+            transform: Some(TransformContext::from_name("transform".to_string(), vec![])),
+        };
+        let cycles = crate::http_outcall::outcall_cycles(&arg);
+        let success = match http_request(arg, cycles).await {
+            Ok((resp,)) => resp.status < 500u16,
+            Err((code, msg)) => {
+                ic_cdk::println!("Health check of MCP {} failed: {:?} {}", name, code, msg);
+                false
+            }
+        };
+        record_mcp_health_check(name, success, time());
+    }
 }
 
 /// Get MCP items with pagination
@@ -327,7 +876,12 @@ pub fn get_mcp_items_paginated(offset: u64, limit: u64) -> Vec<McpItem> {
     
     MCP_ITEMS.with(|items| {
         let items = items.borrow();
-        let total_items = items.len() as u64;
+        // Only live, published items are paginated; soft-deleted ones are skipped
+        let keys: Vec<String> = items.iter()
+            .filter(|(_, item)| item.is_listed())
+            .map(|(key, _)| key.clone())
+            .collect();
+        let total_items = keys.len() as u64;
         
         ic_cdk::println!("[DEBUG] total items {}", total_items);
         
@@ -343,8 +897,6 @@ pub fn get_mcp_items_paginated(offset: u64, limit: u64) -> Vec<McpItem> {
         let end = std::cmp::min(offset + limit, total_items);
         ic_cdk::println!("[DEBUG] offset {} end {}", offset, end);
         
-        // Get all keys first
-        let keys: Vec<String> = items.iter().map(|(key, _)| key.clone()).collect();
         ic_cdk::println!("[DEBUG] Total keys: {}", keys.len());
         
         // Get the slice of keys we need
@@ -367,6 +919,20 @@ pub fn get_mcp_items_paginated(offset: u64, limit: u64) -> Vec<McpItem> {
     })
 }
 
+/// Get MCP items ordered by name, starting after the cursor name
+pub fn get_mcp_items_paginated_cursor(cursor: Option<String>, limit: usize) -> CursorPage<McpItem> {
+    let limit = clamp_page_limit(limit);
+    MCP_ITEMS.with(|items| {
+        let entries = items
+            .borrow()
+            .range(cursor_key_range(cursor))
+            .filter(|(_, item)| item.is_listed())
+            .take(limit + 1)
+            .collect();
+        CursorPage::from_entries(entries, limit)
+    })
+}
+
 /// Get MCP items for a specific user with pagination
 pub fn get_user_mcp_items_paginated(owner: String, offset: u64, limit: usize) -> Vec<McpItem> {
     let user_items = get_user_mcp_items(owner);
@@ -379,7 +945,7 @@ pub fn get_user_mcp_items_paginated(owner: String, offset: u64, limit: usize) ->
     user_items[offset as usize..end].to_vec()
 }
 
-/// Delete an MCP item by name
+/// Permanently delete an MCP item by name (used when purging tombstones)
 pub fn delete_mcp_item(name: String) -> Result<(), String> {
     MCP_ITEMS.with(|items| {
         let mut items = items.borrow_mut();
@@ -401,6 +967,7 @@ pub fn delete_mcp_item(name: String) -> Result<(), String> {
             };
             user_index.remove(&key);
         });
+        remove_from_tag_index(&name, &item.tags);
         
         // Remove the item
         items.remove(&name);
@@ -408,10 +975,99 @@ pub fn delete_mcp_item(name: String) -> Result<(), String> {
     })
 }
 
+// Lowercase, trim and dedup tags, dropping empty ones
+fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = tags.iter()
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    normalized.sort();
+    normalized.dedup();
+    normalized
+}
+
+fn add_to_tag_index(name: &str, tags: &[String]) {
+    MCP_TAG_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for tag in tags {
+            let mut names = index.get(tag).map(|v| v.0).unwrap_or_default();
+            if !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+                index.insert(tag.clone(), StringVec(names));
+            }
+        }
+    });
+}
+
+fn remove_from_tag_index(name: &str, tags: &[String]) {
+    MCP_TAG_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for tag in tags {
+            if let Some(StringVec(mut names)) = index.get(tag) {
+                names.retain(|n| n != name);
+                if names.is_empty() {
+                    index.remove(tag);
+                } else {
+                    index.insert(tag.clone(), StringVec(names));
+                }
+            }
+        }
+    });
+}
+
+/// Get published MCP items carrying any (or all) of the given tags, ordered by name
+pub fn get_mcp_items_by_tags(tags: Vec<String>, mode: TagMatchMode) -> Vec<McpItem> {
+    let tags = normalize_tags(&tags);
+    if tags.is_empty() {
+        return Vec::new();
+    }
+
+    let mut names: Option<std::collections::BTreeSet<String>> = None;
+    MCP_TAG_INDEX.with(|index| {
+        let index = index.borrow();
+        for tag in &tags {
+            let tagged: std::collections::BTreeSet<String> = index.get(tag)
+                .map(|v| v.0.into_iter().collect())
+                .unwrap_or_default();
+            names = Some(match (names.take(), mode) {
+                (None, _) => tagged,
+                (Some(acc), TagMatchMode::Any) => acc.union(&tagged).cloned().collect(),
+                (Some(acc), TagMatchMode::All) => acc.intersection(&tagged).cloned().collect(),
+            });
+        }
+    });
+
+    names.unwrap_or_default()
+        .into_iter()
+        .filter_map(get_mcp_item)
+        .filter(|item| item.is_listed())
+        .collect()
+}
+
+/// All tags used by live MCP items with their usage counts, most used first
+pub fn get_all_mcp_tags() -> Vec<(String, u64)> {
+    let mut tags: Vec<(String, u64)> = MCP_TAG_INDEX.with(|index| {
+        index.borrow().iter()
+            .map(|(tag, names)| {
+                let count = names.0.into_iter().filter(|name| get_mcp_item(name.clone()).is_some()).count() as u64;
+                (tag, count)
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect()
+    });
+    tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    tags
+}
+
 /// Create a stack record for an MCP
 pub fn stack_mcp(mcp_name: String, principal_id: String, stack_amount: u64) -> Result<(), String> {
+    let min_stake_amount = crate::token_economy::get_min_stake_amount();
+    if stack_amount < min_stake_amount {
+        return Err(format!("Minimum stake amount is {}", min_stake_amount));
+    }
+
     // Get the current timestamp
-    let stack_time = ic_cdk::api::time();
+    let stack_time = time();
     
     // Create a new stack record
     let stack_record = McpStackRecord {
@@ -422,6 +1078,9 @@ pub fn stack_mcp(mcp_name: String, principal_id: String, stack_amount: u64) -> R
         stack_status: StackStatus::Stacked,
     };
 
+    record_stack_event(&stack_record.mcp_name, &stack_record.principal_id, StackEventType::Stack, stack_amount, stack_time);
+    adjust_staker_stake(&stack_record.principal_id, |stake| stake.saturating_add(stack_amount));
+
     // Store the stack record
     MCP_STACK_RECORDS.with(|records| {
         let mut records = records.borrow_mut();
@@ -434,7 +1093,7 @@ pub fn stack_mcp(mcp_name: String, principal_id: String, stack_amount: u64) -> R
 /// Create an unstack record for an MCP
 pub fn unstack_mcp(mcp_name: String, principal_id: String, stack_amount: u64) -> Result<(), String> {
     // Get the current timestamp
-    let stack_time = ic_cdk::api::time();
+    let stack_time = time();
     
     // Create a new unstack record
     let unstack_record = McpStackRecord {
@@ -445,6 +1104,9 @@ pub fn unstack_mcp(mcp_name: String, principal_id: String, stack_amount: u64) ->
         stack_status: StackStatus::Unstacked,
     };
 
+    record_stack_event(&unstack_record.mcp_name, &unstack_record.principal_id, StackEventType::Unstack, stack_amount, stack_time);
+    adjust_staker_stake(&unstack_record.principal_id, |stake| stake.saturating_sub(stack_amount));
+
     // Store the unstack record
     MCP_STACK_RECORDS.with(|records| {
         let mut records = records.borrow_mut();
@@ -454,6 +1116,146 @@ pub fn unstack_mcp(mcp_name: String, principal_id: String, stack_amount: u64) ->
     })
 }
 
+/// Update a principal's net stake across all MCPs and move it to its new place in the ranking.
+/// Principals with nothing staked are dropped from both maps.
+fn adjust_staker_stake(principal_id: &str, update: impl FnOnce(u64) -> u64) {
+    let before = STAKER_NET_STAKE.with(|stakes| stakes.borrow().get(&principal_id.to_string())).unwrap_or(0);
+    let after = update(before);
+    STAKER_NET_STAKE.with(|stakes| {
+        let mut stakes = stakes.borrow_mut();
+        if after == 0 {
+            stakes.remove(&principal_id.to_string());
+        } else {
+            stakes.insert(principal_id.to_string(), after);
+        }
+    });
+    STAKER_RANK.with(|rank| {
+        let mut rank = rank.borrow_mut();
+        if before > 0 {
+            rank.remove(&StakerRankKey::new(principal_id, before));
+        }
+        if after > 0 {
+            rank.insert(StakerRankKey::new(principal_id, after), ());
+        }
+    });
+}
+
+/// Build the per-principal net stakes from MCP_STACK_RECORDS when upgrading from a release
+/// that summed the records on every ranking query. Does nothing once any stake is tracked.
+pub fn seed_staker_stakes() {
+    if !STAKER_NET_STAKE.with(|stakes| stakes.borrow().is_empty()) {
+        return;
+    }
+    let mut totals: std::collections::BTreeMap<String, i128> = std::collections::BTreeMap::new();
+    MCP_STACK_RECORDS.with(|records| {
+        for (_, record) in records.borrow().iter() {
+            let total = totals.entry(record.principal_id).or_default();
+            match record.stack_status {
+                StackStatus::Stacked => *total += record.stack_amount as i128,
+                StackStatus::Unstacked => *total -= record.stack_amount as i128,
+            }
+        }
+    });
+    for (principal_id, total) in totals.into_iter().filter(|(_, total)| *total > 0) {
+        adjust_staker_stake(&principal_id, |_| total.min(u64::MAX as i128) as u64);
+    }
+}
+
+/// Append a stake change to the MCP's timeline. Events landing on an occupied timestamp
+/// move to the next free nanosecond so none is overwritten.
+fn record_stack_event(mcp_name: &str, principal_id: &str, event_type: StackEventType, amount: u64, now: u64) {
+    let balance_before = get_mcp_stake_at_time(mcp_name.to_string(), u64::MAX);
+    let (delta, balance_after) = match event_type {
+        StackEventType::Stack => (amount as i64, balance_before.saturating_add(amount)),
+        StackEventType::Unstack => (-(amount as i64), balance_before.saturating_sub(amount)),
+    };
+    MCP_STACK_EVENTS.with(|events| {
+        let mut events = events.borrow_mut();
+        let mut key = McpStackEventKey { mcp_name: mcp_name.to_string(), timestamp: now };
+        while events.contains_key(&key) {
+            key.timestamp = key.timestamp.saturating_add(1);
+        }
+        let timestamp = key.timestamp;
+        events.insert(key, McpStackEvent {
+            mcp_name: mcp_name.to_string(),
+            principal_id: principal_id.to_string(),
+            delta,
+            balance_after,
+            event_type,
+            timestamp,
+        });
+    });
+}
+
+/// Give every MCP staked before the timeline existed an opening event carrying its net stake
+/// from MCP_STACK_RECORDS, dated at its latest record. Runs only while the timeline is empty,
+/// so the records are scanned once, on the first upgrade that has the timeline.
+pub fn seed_mcp_stack_events() {
+    if !MCP_STACK_EVENTS.with(|events| events.borrow().is_empty()) {
+        return;
+    }
+    let mut opening: std::collections::BTreeMap<String, (i128, u64)> = std::collections::BTreeMap::new();
+    MCP_STACK_RECORDS.with(|records| {
+        for (_, record) in records.borrow().iter() {
+            let (total, latest) = opening.entry(record.mcp_name).or_default();
+            match record.stack_status {
+                StackStatus::Stacked => *total += record.stack_amount as i128,
+                StackStatus::Unstacked => *total -= record.stack_amount as i128,
+            }
+            *latest = (*latest).max(record.stack_time);
+        }
+    });
+
+    MCP_STACK_EVENTS.with(|events| {
+        let mut events = events.borrow_mut();
+        for (mcp_name, (total, latest)) in opening {
+            if total <= 0 {
+                continue;
+            }
+            let balance = total.min(i64::MAX as i128) as u64;
+            events.insert(McpStackEventKey { mcp_name: mcp_name.clone(), timestamp: latest }, McpStackEvent {
+                mcp_name,
+                principal_id: String::new(),
+                delta: balance as i64,
+                balance_after: balance,
+                event_type: StackEventType::Stack,
+                timestamp: latest,
+            });
+        }
+    });
+}
+
+/// Stake changes of an MCP with `start_ns <= timestamp <= end_ns`, oldest first, paged by
+/// `offset` and `limit` (at most MAX_PAGE_SIZE)
+pub fn get_mcp_stack_events(mcp_name: String, start_ns: u64, end_ns: u64, offset: u64, limit: u64) -> Vec<McpStackEvent> {
+    if start_ns > end_ns {
+        return Vec::new();
+    }
+    let start = McpStackEventKey { mcp_name: mcp_name.clone(), timestamp: start_ns };
+    let end = McpStackEventKey { mcp_name, timestamp: end_ns };
+    MCP_STACK_EVENTS.with(|events| {
+        events.borrow()
+            .range(start..=end)
+            .skip(offset as usize)
+            .take(clamp_page_size(limit) as usize)
+            .map(|(_, event)| event)
+            .collect()
+    })
+}
+
+/// Total stake of an MCP right after the last event at or before `time_ns`; 0 before its first stake
+pub fn get_mcp_stake_at_time(mcp_name: String, time_ns: u64) -> u64 {
+    let start = McpStackEventKey { mcp_name: mcp_name.clone(), timestamp: 0 };
+    let end = McpStackEventKey { mcp_name, timestamp: time_ns };
+    MCP_STACK_EVENTS.with(|events| {
+        events.borrow()
+            .range(start..=end)
+            .next_back()
+            .map(|(_, event)| event.balance_after)
+            .unwrap_or(0)
+    })
+}
+
 /// Get paginated stack records for a specific MCP, ordered by stack_time desc and stack_status (Stacked first)
 pub fn get_mcp_stack_records_paginated(mcp_name: String, offset: u64, limit: u64) -> Vec<McpStackRecord> {
     MCP_STACK_RECORDS.with(|records| {
@@ -536,6 +1338,405 @@ pub fn get_stacked_record_group_by_stack_amount() -> Vec<StackPositionRecord> {
 /// Get all MCP names
 pub fn get_all_mcp_names() -> Vec<String> {
     MCP_ITEMS.with(|items| {
-        items.borrow().iter().map(|(_, item)| item.name.clone()).collect()
+        items.borrow().iter()
+            .filter(|(_, item)| item.deleted_at.is_none())
+            .map(|(_, item)| item.name.clone())
+            .collect()
+    })
+}
+
+/// Principals ranked by net staked credits (stakes minus unstakes across all MCPs), highest first
+pub fn get_top_stakers(limit: usize) -> Vec<(String, u64)> {
+    // The rank index is already in ranking order, so only the first `limit` entries are read
+    STAKER_RANK.with(|rank| {
+        rank.borrow().iter()
+            .take(limit)
+            .map(|(key, _)| (key.principal_id.clone(), key.stake()))
+            .collect()
+    })
+}
+
+/// Store the current top stakers for the week containing `now`; only the first call per week
+/// records a snapshot. Returns whether a snapshot was taken.
+pub fn record_weekly_leaderboard_snapshot(now: u64) -> bool {
+    let week_start_ns = now - now % WEEK_NS;
+    if LEADERBOARD_HISTORY.with(|history| history.borrow().contains_key(&week_start_ns)) {
+        return false;
+    }
+    let snapshot = WeeklyLeaderboardSnapshot {
+        week_start_ns,
+        entries: get_top_stakers(LEADERBOARD_SNAPSHOT_SIZE),
+    };
+    LEADERBOARD_HISTORY.with(|history| {
+        history.borrow_mut().insert(week_start_ns, snapshot);
+    });
+    true
+}
+
+/// The most recent `weeks_back` weekly snapshots, newest first
+pub fn get_leaderboard_history(weeks_back: u32) -> Vec<WeeklyLeaderboardSnapshot> {
+    LEADERBOARD_HISTORY.with(|history| {
+        history.borrow()
+            .iter()
+            .rev()
+            .take(weeks_back as usize)
+            .map(|(_, snapshot)| snapshot)
+            .collect()
     })
 }
+
+/// Rate an MCP (score 1-5). Each principal rates an MCP once and owners cannot rate their own MCP
+pub fn add_mcp_rating(reviewer: String, mcp_name: String, score: u8, comment: String) -> Result<(), String> {
+    if !(1..=5).contains(&score) {
+        return Err("Score must be between 1 and 5".to_string());
+    }
+    let comment = crate::sanitize::sanitize_and_bound(&comment, crate::sanitize::MAX_REVIEW_COMMENT_LEN)?;
+
+    let mcp = get_mcp_item(mcp_name.clone())
+        .ok_or_else(|| format!("MCP with name '{}' not found", mcp_name))?;
+    if mcp.owner == reviewer {
+        return Err("MCP owners cannot rate their own MCP".to_string());
+    }
+
+    RATINGS.with(|ratings| {
+        let mut ratings = ratings.borrow_mut();
+        let key = RatingKey { mcp_name: mcp_name.clone(), reviewer: reviewer.clone() };
+        if ratings.contains_key(&key) {
+            return Err(format!("'{}' has already rated MCP '{}'", reviewer, mcp_name));
+        }
+        ratings.insert(key, RatingEntry {
+            reviewer,
+            mcp_name,
+            score,
+            comment,
+            timestamp: time(),
+        });
+        Ok(())
+    })
+}
+
+/// Get one page of an MCP's ratings, ordered by reviewer
+pub fn get_mcp_ratings(mcp_name: String, offset: u64, limit: usize) -> Vec<RatingEntry> {
+    let start = RatingKey { mcp_name: mcp_name.clone(), reviewer: String::new() };
+    RATINGS.with(|ratings| {
+        ratings.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.mcp_name == mcp_name)
+            .skip(offset as usize)
+            .take(limit)
+            .map(|(_, entry)| entry)
+            .collect()
+    })
+}
+
+/// Average score of an MCP, or None if nobody has rated it yet
+pub fn get_mcp_average_rating(mcp_name: String) -> Option<f32> {
+    let start = RatingKey { mcp_name: mcp_name.clone(), reviewer: String::new() };
+    let (count, total) = RATINGS.with(|ratings| {
+        ratings.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.mcp_name == mcp_name)
+            .fold((0u64, 0u64), |(count, total), (_, entry)| (count + 1, total + entry.score as u64))
+    });
+    if count == 0 {
+        None
+    } else {
+        Some(total as f32 / count as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_mcp_item_with_result_reports_caller_owner() {
+        let mcp = McpItem {
+            name: "weather_mcp".to_string(),
+            description: "Weather tools".to_string(),
+            author: "tester".to_string(),
+            owner: "someone_else".to_string(),
+            git_repo: "https://github.com/example/weather".to_string(),
+            mcp_type: "stdio".to_string(),
+            ..McpItem::default()
+        };
+
+        let result = add_mcp_item_with_result(mcp, "caller_principal".to_string()).unwrap();
+        assert_eq!(result.name, "weather_mcp");
+        assert_eq!(result.owner, "caller_principal");
+        let stored = get_mcp_item("weather_mcp".to_string()).unwrap();
+        assert_eq!(stored.owner, result.owner);
+        assert_eq!(stored.created_at, Some(result.created_at_ns));
+    }
+
+    #[test]
+    fn test_mcp_tag_filtering() {
+        let tagged = |name: &str, tags: &[&str]| McpItem {
+            name: name.to_string(),
+            description: "Tagged tools".to_string(),
+            author: "tester".to_string(),
+            git_repo: "https://github.com/example/tagged".to_string(),
+            mcp_type: "stdio".to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..McpItem::default()
+        };
+        add_mcp_item(tagged("tag_a", &["Weather", " maps ", "weather"]), "owner".to_string()).unwrap();
+        add_mcp_item(tagged("tag_b", &["weather"]), "owner".to_string()).unwrap();
+        add_mcp_item(tagged("tag_c", &["search"]), "owner".to_string()).unwrap();
+        assert_eq!(get_mcp_item("tag_a".to_string()).unwrap().tags, vec!["maps".to_string(), "weather".to_string()]);
+
+        let names = |items: Vec<McpItem>| items.into_iter().map(|i| i.name).collect::<Vec<_>>();
+        let query = vec!["weather".to_string(), "MAPS".to_string()];
+        assert_eq!(names(get_mcp_items_by_tags(query.clone(), TagMatchMode::Any)), vec!["tag_a", "tag_b"]);
+        assert_eq!(names(get_mcp_items_by_tags(query.clone(), TagMatchMode::All)), vec!["tag_a"]);
+        assert!(get_mcp_items_by_tags(Vec::new(), TagMatchMode::Any).is_empty());
+
+        let mut update = tagged("tag_b", &["search"]);
+        update.owner = "owner".to_string();
+        update_mcp_item("tag_b".to_string(), update).unwrap();
+        assert_eq!(names(get_mcp_items_by_tags(vec!["weather".to_string()], TagMatchMode::Any)), vec!["tag_a"]);
+
+        delete_soft("tag_a".to_string()).unwrap();
+        assert_eq!(get_all_mcp_tags(), vec![("search".to_string(), 2)]);
+    }
+
+    #[test]
+    fn test_mcp_ratings() {
+        let mcp = McpItem {
+            name: "rated_mcp".to_string(),
+            description: "Rated tools".to_string(),
+            author: "tester".to_string(),
+            git_repo: "https://github.com/example/rated".to_string(),
+            mcp_type: "http".to_string(),
+            ..McpItem::default()
+        };
+        add_mcp_item(mcp, "owner".to_string()).unwrap();
+        assert_eq!(get_mcp_average_rating("rated_mcp".to_string()), None);
+
+        assert!(add_mcp_rating("owner".to_string(), "rated_mcp".to_string(), 5, String::new()).is_err());
+        assert!(add_mcp_rating("alice".to_string(), "rated_mcp".to_string(), 0, String::new()).is_err());
+        assert!(add_mcp_rating("alice".to_string(), "rated_mcp".to_string(), 6, String::new()).is_err());
+        assert!(add_mcp_rating("alice".to_string(), "missing_mcp".to_string(), 3, String::new()).is_err());
+
+        add_mcp_rating("alice".to_string(), "rated_mcp".to_string(), 4, " useful ".to_string()).unwrap();
+        add_mcp_rating("bob".to_string(), "rated_mcp".to_string(), 5, String::new()).unwrap();
+        assert!(add_mcp_rating("alice".to_string(), "rated_mcp".to_string(), 1, String::new()).is_err());
+
+        let ratings = get_mcp_ratings("rated_mcp".to_string(), 0, 10);
+        assert_eq!(ratings.len(), 2);
+        assert_eq!(ratings[0].reviewer, "alice");
+        assert_eq!(ratings[0].comment, "useful");
+        assert_eq!(get_mcp_ratings("rated_mcp".to_string(), 1, 10).len(), 1);
+        assert_eq!(get_mcp_average_rating("rated_mcp".to_string()), Some(4.5));
+    }
+
+    #[test]
+    fn test_failed_health_checks_deprecate_mcp() {
+        add_mcp_item(McpItem {
+            name: "flaky_mcp".to_string(),
+            description: "Sometimes down".to_string(),
+            author: "tester".to_string(),
+            git_repo: "https://github.com/example/mcp".to_string(),
+            mcp_type: "sse".to_string(),
+            remote_endpoint: Some("https://flaky.example.com".to_string()),
+            ..Default::default()
+        }, "owner".to_string()).unwrap();
+        assert!(get_mcp_health("flaky_mcp".to_string()).is_none());
+
+        record_mcp_health_check("flaky_mcp".to_string(), false, 10);
+        record_mcp_health_check("flaky_mcp".to_string(), false, 20);
+        assert_eq!(get_unhealthy_mcps(0, 10).len(), 1);
+        assert_eq!(record_mcp_health_check("flaky_mcp".to_string(), true, 30),
+                   McpHealth { last_check_ns: 30, consecutive_failures: 0, is_healthy: true });
+        assert!(get_unhealthy_mcps(0, 10).is_empty());
+
+        for i in 1..MCP_HEALTH_MAX_FAILURES {
+            record_mcp_health_check("flaky_mcp".to_string(), false, 30 + i as u64);
+            assert_eq!(get_mcp_item("flaky_mcp".to_string()).unwrap().status(), McpStatus::Published);
+        }
+        let health = record_mcp_health_check("flaky_mcp".to_string(), false, 100);
+        assert_eq!(health.consecutive_failures, MCP_HEALTH_MAX_FAILURES);
+        assert_eq!(get_mcp_item("flaky_mcp".to_string()).unwrap().status(), McpStatus::Deprecated);
+        assert_eq!(get_unhealthy_mcps(0, 10)[0].name, "flaky_mcp");
+        assert!(get_unhealthy_mcps(1, 10).is_empty());
+
+        // A deprecated MCP keeps being checked and comes back once its endpoint answers again
+        assert_eq!(next_health_check_targets(10), vec![("flaky_mcp".to_string(), "https://flaky.example.com".to_string())]);
+        record_mcp_health_check("flaky_mcp".to_string(), true, 110);
+        assert!(get_unhealthy_mcps(0, 10).is_empty());
+        assert_eq!(get_mcp_item("flaky_mcp".to_string()).unwrap().status(), McpStatus::Published);
+
+        // Owner deprecations are not undone by healthy checks
+        transition_mcp_status("flaky_mcp".to_string(), McpStatus::Deprecated).unwrap();
+        assert!(next_health_check_targets(10).is_empty());
+        record_mcp_health_check("flaky_mcp".to_string(), true, 120);
+        assert_eq!(get_mcp_item("flaky_mcp".to_string()).unwrap().status(), McpStatus::Deprecated);
+    }
+
+    #[test]
+    fn test_health_check_targets_rotate() {
+        for name in ["rot_a", "rot_b", "rot_c"] {
+            add_mcp_item(McpItem {
+                name: name.to_string(),
+                description: "Remote".to_string(),
+                author: "tester".to_string(),
+                git_repo: "https://github.com/example/mcp".to_string(),
+                mcp_type: "sse".to_string(),
+                remote_endpoint: Some(format!("https://{}.example.com", name)),
+                ..Default::default()
+            }, "owner".to_string()).unwrap();
+        }
+        let names = |targets: Vec<(String, String)>| targets.into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+
+        assert_eq!(names(next_health_check_targets(2)), vec!["rot_a", "rot_b"]);
+        assert_eq!(names(next_health_check_targets(2)), vec!["rot_c", "rot_a"]);
+        assert_eq!(names(next_health_check_targets(5)), vec!["rot_b", "rot_c", "rot_a"]);
+    }
+
+    #[test]
+    fn test_stack_events_track_cumulative_stake() {
+        record_stack_event("timeline_mcp", "alice", StackEventType::Stack, 500, 100);
+        record_stack_event("timeline_mcp", "bob", StackEventType::Stack, 200, 200);
+        record_stack_event("timeline_mcp", "alice", StackEventType::Unstack, 300, 200);
+        record_stack_event("other_mcp", "alice", StackEventType::Stack, 50, 150);
+
+        let events = get_mcp_stack_events("timeline_mcp".to_string(), 0, u64::MAX, 0, 100);
+        assert_eq!(events.iter().map(|e| (e.delta, e.balance_after, e.timestamp)).collect::<Vec<_>>(),
+                   vec![(500, 500, 100), (200, 700, 200), (-300, 400, 201)]);
+        assert_eq!(get_mcp_stack_events("timeline_mcp".to_string(), 150, 200, 0, 100).len(), 1);
+        assert!(get_mcp_stack_events("timeline_mcp".to_string(), 300, 200, 0, 100).is_empty());
+        let page: Vec<i64> = get_mcp_stack_events("timeline_mcp".to_string(), 0, u64::MAX, 1, 1).iter().map(|e| e.delta).collect();
+        assert_eq!(page, vec![200]);
+
+        assert_eq!(get_mcp_stake_at_time("timeline_mcp".to_string(), 99), 0);
+        assert_eq!(get_mcp_stake_at_time("timeline_mcp".to_string(), 150), 500);
+        assert_eq!(get_mcp_stake_at_time("timeline_mcp".to_string(), 200), 700);
+        assert_eq!(get_mcp_stake_at_time("timeline_mcp".to_string(), u64::MAX), 400);
+        assert_eq!(get_mcp_stake_at_time("other_mcp".to_string(), u64::MAX), 50);
+
+        stack_mcp("fresh_mcp".to_string(), "carol".to_string(), 100).unwrap();
+        unstack_mcp("fresh_mcp".to_string(), "carol".to_string(), 40).unwrap();
+        let events = get_mcp_stack_events("fresh_mcp".to_string(), 0, u64::MAX, 0, 100);
+        assert_eq!(events.iter().map(|e| e.event_type).collect::<Vec<_>>(), vec![StackEventType::Stack, StackEventType::Unstack]);
+        assert_eq!(get_mcp_stake_at_time("fresh_mcp".to_string(), u64::MAX), 60);
+    }
+
+    #[test]
+    fn test_seed_mcp_stack_events_from_records() {
+        MCP_STACK_RECORDS.with(|records| {
+            let mut records = records.borrow_mut();
+            for (id, (status, amount, stack_time)) in [(StackStatus::Stacked, 300, 10), (StackStatus::Stacked, 200, 30), (StackStatus::Unstacked, 100, 20)].into_iter().enumerate() {
+                records.insert(id as u64, McpStackRecord {
+                    principal_id: "alice".to_string(),
+                    mcp_name: "legacy_mcp".to_string(),
+                    stack_time,
+                    stack_amount: amount,
+                    stack_status: status,
+                });
+            }
+        });
+
+        seed_mcp_stack_events();
+        assert_eq!(get_mcp_stake_at_time("legacy_mcp".to_string(), 29), 0);
+        assert_eq!(get_mcp_stake_at_time("legacy_mcp".to_string(), 30), 400);
+
+        // Seeding again does not add a second opening event
+        seed_mcp_stack_events();
+        assert_eq!(get_mcp_stack_events("legacy_mcp".to_string(), 0, u64::MAX, 0, 100).len(), 1);
+        record_stack_event("legacy_mcp", "bob", StackEventType::Stack, 100, 40);
+        assert_eq!(get_mcp_stake_at_time("legacy_mcp".to_string(), u64::MAX), 500);
+    }
+
+    #[test]
+    fn test_top_stakers_and_weekly_snapshots() {
+        for (principal, amount) in [("whale", 500), ("minnow", 100), ("shrimp", 200), ("whale", 300)] {
+            stack_mcp("leaderboard_mcp".to_string(), principal.to_string(), amount).unwrap();
+        }
+        unstack_mcp("leaderboard_mcp".to_string(), "shrimp".to_string(), 200).unwrap();
+
+        assert_eq!(get_top_stakers(10), vec![("whale".to_string(), 800), ("minnow".to_string(), 100)]);
+        assert_eq!(get_top_stakers(1).len(), 1);
+
+        assert!(record_weekly_leaderboard_snapshot(WEEK_NS + 5));
+        assert!(!record_weekly_leaderboard_snapshot(2 * WEEK_NS - 1));
+        assert!(record_weekly_leaderboard_snapshot(3 * WEEK_NS));
+
+        let history = get_leaderboard_history(5);
+        assert_eq!(history.iter().map(|s| s.week_start_ns).collect::<Vec<_>>(), vec![3 * WEEK_NS, WEEK_NS]);
+        assert_eq!(history[1].entries[0], ("whale".to_string(), 800));
+        assert_eq!(get_leaderboard_history(1).len(), 1);
+    }
+
+    #[test]
+    fn test_seed_staker_stakes_from_records() {
+        MCP_STACK_RECORDS.with(|records| {
+            let mut records = records.borrow_mut();
+            for (id, (principal, status, amount)) in [("alice", StackStatus::Stacked, 300), ("bob", StackStatus::Stacked, 300), ("alice", StackStatus::Unstacked, 100), ("carol", StackStatus::Stacked, 50), ("carol", StackStatus::Unstacked, 50)].into_iter().enumerate() {
+                records.insert(id as u64, McpStackRecord {
+                    principal_id: principal.to_string(),
+                    mcp_name: "legacy_mcp".to_string(),
+                    stack_time: id as u64,
+                    stack_amount: amount,
+                    stack_status: status,
+                });
+            }
+        });
+
+        seed_staker_stakes();
+        assert_eq!(get_top_stakers(10), vec![("bob".to_string(), 300), ("alice".to_string(), 200)]);
+
+        // Later stake changes move the principal within the ranking
+        stack_mcp("legacy_mcp".to_string(), "alice".to_string(), 200).unwrap();
+        seed_staker_stakes();
+        assert_eq!(get_top_stakers(10), vec![("alice".to_string(), 400), ("bob".to_string(), 300)]);
+    }
+
+    #[test]
+    fn test_mcp_status_transitions_and_filtering() {
+        let mcp = McpItem {
+            name: "draft_mcp".to_string(),
+            description: "Lifecycle tools".to_string(),
+            author: "tester".to_string(),
+            git_repo: "https://github.com/example/lifecycle".to_string(),
+            mcp_type: "stdio".to_string(),
+            tags: vec!["lifecycle".to_string()],
+            status: Some(McpStatus::Draft),
+            ..McpItem::default()
+        };
+        assert!(add_mcp_item(McpItem { name: "archived_mcp".to_string(), status: Some(McpStatus::Archived), ..mcp.clone() }, "owner".to_string()).is_err());
+        add_mcp_item(McpItem { name: "unset_mcp".to_string(), status: None, ..mcp.clone() }, "owner".to_string()).unwrap();
+        assert_eq!(get_mcp_item("unset_mcp".to_string()).unwrap().status, Some(McpStatus::Published));
+        add_mcp_item(mcp, "owner".to_string()).unwrap();
+        let tagged = || get_mcp_items_by_tags(vec!["lifecycle".to_string()], TagMatchMode::Any).into_iter().map(|i| i.name).collect::<Vec<_>>();
+        assert_eq!(tagged(), vec!["unset_mcp".to_string()]);
+        let name = "draft_mcp".to_string();
+        let listed = |statuses: Option<Vec<McpStatus>>| {
+            let statuses = statuses.unwrap_or_else(|| vec![McpStatus::Published]);
+            get_all_mcp_items_guarded(false, Some(statuses), &InstructionGuard::new(None)).items.iter().any(|i| i.name == name)
+        };
+
+        assert!(!listed(None));
+        assert!(listed(Some(vec![McpStatus::Draft])));
+        assert!(transition_mcp_status(name.clone(), McpStatus::Deprecated).is_err());
+        transition_mcp_status(name.clone(), McpStatus::Published).unwrap();
+        assert!(listed(None));
+        assert_eq!(tagged().len(), 2);
+
+        transition_mcp_status(name.clone(), McpStatus::Deprecated).unwrap();
+        assert!(!listed(None));
+        let entry = crate::aio_invert_index_types::InvertedIndexItem {
+            keyword: "lifecycle".to_string(),
+            keyword_group: "tools".to_string(),
+            mcp_name: name.clone(),
+            method_name: "run".to_string(),
+            source_field: "description".to_string(),
+            confidence: 0.9,
+            standard_match: "exact".to_string(),
+        };
+        assert!(crate::aio_invert_index_types::store_inverted_index(serde_json::to_string(&vec![entry]).unwrap()).is_err());
+
+        transition_mcp_status(name.clone(), McpStatus::Archived).unwrap();
+        assert!(transition_mcp_status(name.clone(), McpStatus::Published).is_err());
+        assert_eq!(get_mcp_item(name).unwrap().status(), McpStatus::Archived);
+    }
+}
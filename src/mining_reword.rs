@@ -3,7 +3,7 @@ use ic_cdk::api::time;
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::collections::HashMap;
-use crate::stable_mem_storage::{MINING_REWARD_POLICY, REWARD_ENTRIES, USER_REWARD_INDEX, MCP_REWARD_INDEX};
+use crate::stable_mem_storage::{MINING_REWARD_POLICY, REWARD_ENTRIES, USER_REWARD_INDEX, MCP_REWARD_INDEX, HALVING_SCHEDULE, TOTAL_BLOCKS_MINED, MINED_TRACES};
 use crate::token_economy_types::RewardIdList;
 use ic_stable_structures::storable::Bound;
 use std::borrow::Cow;
@@ -26,6 +26,25 @@ pub struct MiningRewardPolicy {
     pub quarters: Vec<QuarterRewardConfig>,
 }
 
+// Block reward halving: the reward per block is initial_reward >> epoch, where an epoch is
+// epoch_blocks mined blocks and the epoch stops advancing after halving_count halvings
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct HalvingSchedule {
+    pub epoch_blocks: u64,
+    pub initial_reward: u64,
+    pub halving_count: u64,
+}
+
+impl Default for HalvingSchedule {
+    fn default() -> Self {
+        HalvingSchedule {
+            epoch_blocks: 210_000,
+            initial_reward: 300_000,
+            halving_count: 32,
+        }
+    }
+}
+
 // Kappa tier structure
 #[derive(CandidType, Clone, Debug, Serialize, Deserialize)]
 pub struct KappaTier {
@@ -51,6 +70,13 @@ pub struct RewardEntry {
     pub status: String,
 }
 
+impl RewardEntry {
+    // Claim state is carried by `status` ("pending" until claim_rewards marks it "claimed")
+    pub fn is_claimed(&self) -> bool {
+        self.status == "claimed"
+    }
+}
+
 // Call record
 #[derive(CandidType, Clone, Debug, Serialize, Deserialize)]
 pub struct CallRecord {
@@ -118,6 +144,19 @@ impl ic_stable_structures::Storable for UserRewardKey {
     const BOUND: Bound = Bound::Bounded { max_size: 1024 * 32, is_fixed_size: false };
 }
 
+// Implement Storable for HalvingSchedule
+impl ic_stable_structures::Storable for HalvingSchedule {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode HalvingSchedule"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode HalvingSchedule")
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 256, is_fixed_size: false };
+}
+
 // Default mining configuration
 pub fn default_mining_config() -> MiningRewardPolicy {
     let mut quarters = Vec::new();
@@ -168,33 +207,17 @@ pub fn init_mining_policy() {
     });
 }
 
-// Calculate user rewards
+// Calculate user rewards; the base reward per call is the current block reward of the
+// halving schedule, the same one perdic_mining pays out
 pub fn calculate_user_rewards_for_mcp(
     mcp_name: String,
     calls: Vec<CallRecord>,
     stakes: Vec<StakeRecord>,
-    current_quarter: u32,
 ) -> Vec<RewardEntry> {
     let mut reward_entries = Vec::new();
     let mut next_reward_id = 0u64;
     
-    // Get base reward for current quarter
-    let policy = MINING_REWARD_POLICY.with(|p| {
-        let policy_store = p.borrow();
-        if policy_store.is_empty() {
-            // Initialize policy if empty
-            drop(policy_store); // Release the borrow before mutating
-            init_mining_policy();
-            p.borrow().get(&"default".to_string()).unwrap()
-        } else {
-            policy_store.get(&"default".to_string()).unwrap_or_else(default_mining_config)
-        }
-    });
-    
-    let quarter_config = policy.quarters
-        .iter()
-        .find(|q| q.quarter == current_quarter)
-        .expect("Invalid quarter");
+    let base_reward = get_current_block_reward();
     
     // Calculate total stake for MCP
     let total_stake: u64 = stakes.iter()
@@ -216,7 +239,7 @@ pub fn calculate_user_rewards_for_mcp(
                 .unwrap_or(1.0);
             
             // Calculate reward
-            let reward = (quarter_config.base_reward as f32 * kappa * call.quality_score) as u64;
+            let reward = (base_reward as f32 * kappa * call.quality_score) as u64;
             
             let reward_entry = RewardEntry {
                 principal_id: stake.principal_id,
@@ -366,6 +389,66 @@ fn calculate_quality_score(stake_ratio: f32) -> f32 {
     }
 }
 
+// Block halving schedule
+pub fn get_halving_schedule() -> HalvingSchedule {
+    HALVING_SCHEDULE.with(|cell| cell.borrow().get().clone())
+}
+
+pub fn set_halving_schedule(schedule: HalvingSchedule) -> Result<(), String> {
+    if schedule.epoch_blocks == 0 {
+        return Err("Epoch length must be at least one block".to_string());
+    }
+    if schedule.initial_reward == 0 {
+        return Err("Initial reward must be greater than zero".to_string());
+    }
+    if schedule.halving_count >= 64 {
+        return Err("Halving count must be less than 64".to_string());
+    }
+    HALVING_SCHEDULE.with(|cell| {
+        cell.borrow_mut().set(schedule)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to store halving schedule: {:?}", e))
+    })
+}
+
+pub fn get_total_blocks_mined() -> u64 {
+    TOTAL_BLOCKS_MINED.with(|cell| *cell.borrow().get())
+}
+
+pub fn get_current_mining_epoch() -> u64 {
+    let schedule = get_halving_schedule();
+    (get_total_blocks_mined() / schedule.epoch_blocks).min(schedule.halving_count)
+}
+
+pub fn get_current_block_reward() -> u64 {
+    let schedule = get_halving_schedule();
+    schedule.initial_reward.checked_shr(get_current_mining_epoch() as u32).unwrap_or(0)
+}
+
+// Reward of the next block, then advance the block counter past it
+fn mine_block() -> u64 {
+    let reward = get_current_block_reward();
+    TOTAL_BLOCKS_MINED.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let mined = *cell.get();
+        cell.set(mined + 1).expect("Failed to update total blocks mined");
+    });
+    reward
+}
+
+// Mine the block for a trace the first time it is rewarded; None if the trace already has one.
+// Traces are never deleted, so MINED_TRACES is not pruned either; it grows by one small
+// entry per rewarded trace, in step with trace storage.
+fn mine_block_for_trace(trace_id: &str) -> Option<u64> {
+    if MINED_TRACES.with(|traces| traces.borrow().contains_key(&trace_id.to_string())) {
+        return None;
+    }
+    let height = get_total_blocks_mined();
+    let reward = mine_block();
+    MINED_TRACES.with(|traces| traces.borrow_mut().insert(trace_id.to_string(), height));
+    Some(reward)
+}
+
 // Predict mining rewards for all pages
 pub fn perdic_mining() -> Result<Vec<RewardEntry>, String> {
     ic_cdk::println!("[perdic_mining] Starting mining reward prediction");
@@ -374,10 +457,6 @@ pub fn perdic_mining() -> Result<Vec<RewardEntry>, String> {
     let limit = 100u64; // Process 100 records per page
     let mut has_more = true;
     
-    // Get current quarter
-    let current_quarter = 1u32; // This should be calculated based on actual time
-    ic_cdk::println!("[perdic_mining] Current quarter: {}", current_quarter);
-    
     while has_more {
         ic_cdk::println!("[perdic_mining] Processing page with offset: {}", offset);
         // Get paginated TraceItems
@@ -425,13 +504,21 @@ pub fn perdic_mining() -> Result<Vec<RewardEntry>, String> {
             }
             ic_cdk::println!("[perdic_mining] Total stake for MCP {}: {}", mcp_name, total_stake);
             
-            // Get mining policy
-            let policy = get_mining_policy();
-            let quarter_config = policy.quarters
-                .iter()
-                .find(|q| q.quarter == current_quarter)
-                .ok_or_else(|| format!("Invalid quarter configuration: {}", current_quarter))?;
-            ic_cdk::println!("[perdic_mining] Using base reward: {} for quarter {}", quarter_config.base_reward, current_quarter);
+            // Each distinct successful trace mines one block, once; its reward follows the halving
+            // schedule. Traces mined by an earlier run are skipped, so they are never rewarded twice.
+            let mut block_rewards: HashMap<String, u64> = HashMap::new();
+            let mut mined_traces = Vec::new();
+            for trace in traces.iter().filter(|trace| trace.status == "ok") {
+                if block_rewards.contains_key(&trace.trace_id) {
+                    continue;
+                }
+                if let Some(reward) = mine_block_for_trace(&trace.trace_id) {
+                    block_rewards.insert(trace.trace_id.clone(), reward);
+                    mined_traces.push(*trace);
+                }
+            }
+            ic_cdk::println!("[perdic_mining] Mined {} blocks in epoch {}, next block reward: {}",
+                block_rewards.len(), get_current_mining_epoch(), get_current_block_reward());
             
             // Calculate rewards for each stack record
             for stack_record in stack_records {
@@ -444,17 +531,13 @@ pub fn perdic_mining() -> Result<Vec<RewardEntry>, String> {
                 ic_cdk::println!("[perdic_mining] Stack record - Principal: {}, Stake ratio: {}, Quality score: {}", 
                     stack_record.principal_id, stake_ratio, quality_score);
                 
-                // Calculate reward for each trace
-                for trace in &traces {
-                    // Recheck trace status
-                    if trace.status != "ok" {
-                        continue;
-                    }
-
+                // Calculate reward for each trace mined in this run
+                for trace in &mined_traces {
                     let principal_id = candid::Principal::from_text(&stack_record.principal_id)
                         .unwrap_or_else(|_| candid::Principal::anonymous());
 
-                    let reward = (quarter_config.base_reward as f32 * quality_score) as u64;
+                    let base_reward = block_rewards.get(&trace.trace_id).copied().unwrap_or(0);
+                    let reward = (base_reward as f32 * quality_score) as u64;
                     ic_cdk::println!("[perdic_mining] Calculating reward for trace {} - Amount: {}", trace.trace_id, reward);
                     
                     let reward_entry = RewardEntry {
@@ -749,6 +832,19 @@ pub fn get_all_mcp_rewards_paginated(offset: u64, limit: u64) -> Vec<RewardEntry
     all_rewards
 }
 
+// Get reward entries that are (or are not yet) claimed, with pagination
+pub fn get_rewards_paginated_by_claimed(claimed: bool, offset: u64, limit: u64) -> Vec<RewardEntry> {
+    REWARD_ENTRIES.with(|entries| {
+        entries.borrow()
+            .iter()
+            .map(|(_, entry)| entry)
+            .filter(|entry| entry.is_claimed() == claimed)
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect()
+    })
+}
+
 // Get total count of all MCP rewards
 pub fn get_total_mcp_rewards_count() -> u64 {
     let mut total_count = 0u64;
@@ -761,3 +857,54 @@ pub fn get_total_mcp_rewards_count() -> u64 {
     
     total_count
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_rewards_paginated_by_claimed() {
+        let principal = Principal::from_slice(&[3]);
+        REWARD_ENTRIES.with(|entries| {
+            let mut entries = entries.borrow_mut();
+            for (id, status) in ["pending", "claimed", "pending", "claimed", "pending"].iter().enumerate() {
+                entries.insert(id as u64, RewardEntry {
+                    principal_id: principal,
+                    mcp_name: "mcp".to_string(),
+                    reward_amount: 10,
+                    block_id: id as u64,
+                    status: status.to_string(),
+                });
+            }
+        });
+
+        let unclaimed = get_rewards_paginated_by_claimed(false, 0, 10);
+        assert_eq!(unclaimed.iter().map(|e| e.block_id).collect::<Vec<_>>(), vec![0, 2, 4]);
+        let claimed = get_rewards_paginated_by_claimed(true, 0, 10);
+        assert_eq!(claimed.iter().map(|e| e.block_id).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(get_rewards_paginated_by_claimed(false, 1, 1)[0].block_id, 2);
+    }
+
+    #[test]
+    fn test_halving_schedule_scales_block_reward() {
+        assert!(set_halving_schedule(HalvingSchedule { epoch_blocks: 0, initial_reward: 100, halving_count: 2 }).is_err());
+        assert!(set_halving_schedule(HalvingSchedule { epoch_blocks: 2, initial_reward: 100, halving_count: 64 }).is_err());
+        set_halving_schedule(HalvingSchedule { epoch_blocks: 2, initial_reward: 100, halving_count: 2 }).unwrap();
+
+        assert_eq!(get_current_mining_epoch(), 0);
+        let rewards: Vec<u64> = (0..7).map(|_| mine_block()).collect();
+        // Two blocks per epoch, halving stops after the second halving
+        assert_eq!(rewards, vec![100, 100, 50, 50, 25, 25, 25]);
+        assert_eq!(get_total_blocks_mined(), 7);
+        assert_eq!(get_current_mining_epoch(), 2);
+        assert_eq!(get_current_block_reward(), 25);
+    }
+
+    #[test]
+    fn test_trace_mines_only_one_block() {
+        assert_eq!(mine_block_for_trace("trace_a"), Some(get_halving_schedule().initial_reward));
+        assert_eq!(mine_block_for_trace("trace_a"), None);
+        assert!(mine_block_for_trace("trace_b").is_some());
+        assert_eq!(get_total_blocks_mined(), 2);
+    }
+}
@@ -4,7 +4,10 @@ use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
-use crate::stable_mem_storage::{PIXEL_PROJECTS, PROJECT_OWNER_INDEX};
+use crate::stable_mem_storage::{PIXEL_PROJECTS, PROJECT_OWNER_INDEX, PROJECT_FORK_INDEX, EDIT_LOCKS};
+use crate::types::time;
+#[cfg(not(test))]
+use ic_cdk::api::instruction_counter;
 // Removed getrandom import - using IC-native randomness instead
 
 /// Project identifier - unique string ID for each pixel art project
@@ -28,6 +31,13 @@ pub struct SourceMeta {
     pub tags: Option<Vec<String>>,
 }
 
+/// How the main pixel matrix of a source is kept in stable memory
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionMode {
+    None,
+    RunLengthEncoding,
+}
+
 /// Pixel art source data structure
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct PixelArtSource {
@@ -37,6 +47,8 @@ pub struct PixelArtSource {
     pub pixels: Vec<Vec<u16>>,         // palette indices (width x height) in row-major
     pub frames: Option<Vec<Frame>>,    // optional animation frames
     pub metadata: Option<SourceMeta>,
+    pub compression: Option<CompressionMode>, // None is stored uncompressed
+    pub compressed_pixels: Option<Vec<u8>>,   // set only in storage, `pixels` is then empty
 }
 
 /// Version of a pixel art project
@@ -58,6 +70,7 @@ pub struct Project {
     pub updated_at: u64,
     pub current_version: Version,
     pub history: Vec<Version>,          // append-only history, latest also in current_version
+    pub forked_from: Option<ProjectId>, // source project when created by fork_project
 }
 
 /// Project owner key for indexing
@@ -67,6 +80,24 @@ pub struct ProjectOwnerKey {
     pub project_id: String,
 }
 
+/// Fork index key: a parent's forks form a contiguous range
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProjectForkKey {
+    pub parent_id: ProjectId,
+    pub fork_id: ProjectId,
+}
+
+/// Exclusive edit session on a project; saves need the token until `expires_at` (nanoseconds)
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct EditLock {
+    pub lock_token: String,
+    pub holder: String,                 // Principal text of the lock holder
+    pub expires_at: u64,
+}
+
+/// Longest edit lock a single acquire can take
+pub const MAX_EDIT_LOCK_NS: u64 = 30 * 60 * 1_000_000_000;
+
 /// Compact export format for IoT devices
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct CompactPixelArt {
@@ -99,6 +130,18 @@ impl ic_stable_structures::Storable for Project {
     const BOUND: Bound = Bound::Bounded { max_size: 5 * 1024 * 1024, is_fixed_size: false }; // 5MB for large pixel art projects
 }
 
+impl ic_stable_structures::Storable for EditLock {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 512, is_fixed_size: false };
+}
+
 impl ic_stable_structures::Storable for ProjectOwnerKey {
     fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
         Cow::Owned(Encode!(&self.owner, &self.project_id).unwrap())
@@ -112,25 +155,50 @@ impl ic_stable_structures::Storable for ProjectOwnerKey {
     const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false };
 }
 
+impl ic_stable_structures::Storable for ProjectForkKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.parent_id, &self.fork_id).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let (parent_id, fork_id) = Decode!(bytes.as_ref(), String, String).unwrap();
+        Self { parent_id, fork_id }
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false };
+}
+
+// Tests run at a fixed time, so a moving counter keeps generated ids distinct
+#[cfg(test)]
+fn instruction_counter() -> u64 {
+    thread_local! {
+        static COUNTER: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+    }
+    COUNTER.with(|counter| {
+        counter.set(counter.get() + 1);
+        counter.get()
+    })
+}
+
 /// Generate new project ID using timestamp and IC-native randomness
 pub fn new_project_id(caller: Principal) -> ProjectId {
-    let timestamp = ic_cdk::api::time() / 1_000_000; // Convert to seconds
+    let timestamp = time() / 1_000_000; // Convert to seconds
     let mut hasher = DefaultHasher::new();
     timestamp.hash(&mut hasher);
     caller.hash(&mut hasher);
     // Add instruction counter for additional entropy
-    ic_cdk::api::instruction_counter().hash(&mut hasher);
+    instruction_counter().hash(&mut hasher);
     format!("proj_{}_{:x}", timestamp, hasher.finish())
 }
 
 /// Generate new version ID using timestamp and IC-native randomness
 pub fn new_version_id(caller: Principal) -> VersionId {
-    let timestamp = ic_cdk::api::time() / 1_000_000; // Convert to seconds
+    let timestamp = time() / 1_000_000; // Convert to seconds
     let mut hasher = DefaultHasher::new();
     timestamp.hash(&mut hasher);
     caller.hash(&mut hasher);
     // Add instruction counter for additional entropy
-    ic_cdk::api::instruction_counter().hash(&mut hasher);
+    instruction_counter().hash(&mut hasher);
     // Add a different salt for version vs project IDs
     "version".hash(&mut hasher);
     format!("ver_{}_{:x}", timestamp, hasher.finish())
@@ -242,13 +310,90 @@ fn calculate_estimated_size(source: &PixelArtSource) -> usize {
     size
 }
 
+/// Run-length encode the main pixel matrix as row-major (run length: u16, palette index: u16)
+/// pairs, little-endian. Animation frames are left as they are.
+pub fn compress_pixels(source: &PixelArtSource) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut run: Option<(u16, u16)> = None;
+    for &pixel in source.pixels.iter().flatten() {
+        run = match run {
+            Some((len, value)) if value == pixel && len < u16::MAX => Some((len + 1, value)),
+            Some((len, value)) => {
+                data.extend_from_slice(&len.to_le_bytes());
+                data.extend_from_slice(&value.to_le_bytes());
+                Some((1, pixel))
+            }
+            None => Some((1, pixel)),
+        };
+    }
+    if let Some((len, value)) = run {
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data
+}
+
+/// Expand data written by `compress_pixels` into a `width` x `height` source holding only
+/// the pixels. Missing pixels are filled with index 0 and surplus ones are dropped.
+pub fn decompress_pixels(data: &[u8], width: u32, height: u32) -> PixelArtSource {
+    let total = width as usize * height as usize;
+    let mut flat: Vec<u16> = Vec::with_capacity(total);
+    for chunk in data.chunks_exact(4) {
+        let len = u16::from_le_bytes([chunk[0], chunk[1]]) as usize;
+        let value = u16::from_le_bytes([chunk[2], chunk[3]]);
+        flat.extend(std::iter::repeat_n(value, len.min(total - flat.len())));
+    }
+    flat.resize(total, 0);
+
+    PixelArtSource {
+        width,
+        height,
+        palette: Vec::new(),
+        pixels: flat.chunks(width.max(1) as usize).map(|row| row.to_vec()).collect(),
+        frames: None,
+        metadata: None,
+        compression: None,
+        compressed_pixels: None,
+    }
+}
+
+/// Prepare a validated source for storage, compressing its pixels if requested
+fn pack_source(mut source: PixelArtSource) -> PixelArtSource {
+    source.compressed_pixels = None;
+    if source.compression == Some(CompressionMode::RunLengthEncoding) {
+        source.compressed_pixels = Some(compress_pixels(&source));
+        source.pixels = Vec::new();
+    }
+    source
+}
+
+/// Restore the pixel matrix of a stored source
+fn unpack_source(mut source: PixelArtSource) -> PixelArtSource {
+    if let Some(data) = source.compressed_pixels.take() {
+        source.pixels = decompress_pixels(&data, source.width, source.height).pixels;
+    }
+    source
+}
+
+fn unpack_version(version: Version) -> Version {
+    Version { source: unpack_source(version.source), ..version }
+}
+
+fn unpack_project(project: Project) -> Project {
+    Project {
+        current_version: unpack_version(project.current_version),
+        history: project.history.into_iter().map(unpack_version).collect(),
+        ..project
+    }
+}
+
 /// Create a new project with initial version
 pub fn create_project(caller: Principal, source: PixelArtSource, message: Option<String>) -> Result<ProjectId, String> {
     // Validate input
     validate_pixel_art_source(&source)?;
     validate_payload_size(&source)?;
     
-    let current_time = ic_cdk::api::time() / 1_000_000; // Convert to seconds
+    let current_time = time() / 1_000_000; // Convert to seconds
     let project_id = new_project_id(caller);
     let version_id = new_version_id(caller);
     
@@ -257,7 +402,7 @@ pub fn create_project(caller: Principal, source: PixelArtSource, message: Option
         created_at: current_time,
         editor: caller,
         message,
-        source,
+        source: pack_source(source),
     };
     
     let project = Project {
@@ -267,6 +412,7 @@ pub fn create_project(caller: Principal, source: PixelArtSource, message: Option
         updated_at: current_time,
         current_version: initial_version.clone(),
         history: vec![initial_version],
+        forked_from: None,
     };
     
     // Store in stable memory
@@ -287,19 +433,162 @@ pub fn create_project(caller: Principal, source: PixelArtSource, message: Option
     Ok(project_id)
 }
 
-/// Save a new version to an existing project
+/// Fork a project: the source's current version becomes the first version of a new project
+/// owned by `forker`. The source owner is notified with the new project id as the notification key.
+pub fn fork_project(forker: Principal, source_project_id: ProjectId, message: Option<String>) -> Result<ProjectId, String> {
+    let source_project = get_project(source_project_id.clone())
+        .ok_or("Source project not found".to_string())?;
+
+    let current_time = time() / 1_000_000; // Convert to seconds
+    let project_id = new_project_id(forker);
+
+    let initial_version = Version {
+        version_id: new_version_id(forker),
+        created_at: current_time,
+        editor: forker,
+        message,
+        source: pack_source(source_project.current_version.source),
+    };
+
+    let project = Project {
+        project_id: project_id.clone(),
+        owner: forker,
+        created_at: current_time,
+        updated_at: current_time,
+        current_version: initial_version.clone(),
+        history: vec![initial_version],
+        forked_from: Some(source_project_id.clone()),
+    };
+
+    PIXEL_PROJECTS.with(|projects| {
+        projects.borrow_mut().insert(project_id.clone(), project);
+    });
+    PROJECT_OWNER_INDEX.with(|index| {
+        index.borrow_mut().insert(ProjectOwnerKey {
+            owner: forker,
+            project_id: project_id.clone(),
+        }, ());
+    });
+    PROJECT_FORK_INDEX.with(|index| {
+        index.borrow_mut().insert(ProjectForkKey {
+            parent_id: source_project_id,
+            fork_id: project_id.clone(),
+        }, ());
+    });
+
+    if source_project.owner != forker {
+        crate::society_profile_types::push_notification(project_id.clone(), source_project.owner.to_text(), 0, crate::society_profile_types::NotificationCategory::System)?;
+    }
+
+    Ok(project_id)
+}
+
+/// Projects forked directly from `project_id`, read through the fork index
+pub fn get_project_forks(project_id: ProjectId) -> Vec<Project> {
+    let start = ProjectForkKey { parent_id: project_id.clone(), fork_id: String::new() };
+    let fork_ids: Vec<ProjectId> = PROJECT_FORK_INDEX.with(|index| {
+        index.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.parent_id == project_id)
+            .map(|(key, _)| key.fork_id)
+            .collect()
+    });
+    fork_ids.into_iter().filter_map(get_project).collect()
+}
+
+/// The project followed by each ancestor it was forked from, nearest first.
+/// Stops at the original project or at an ancestor that has since been deleted.
+pub fn get_fork_chain(project_id: ProjectId) -> Vec<Project> {
+    let mut chain: Vec<Project> = Vec::new();
+    let mut next = Some(project_id);
+    while let Some(id) = next {
+        // Guard against cycles in corrupted data
+        if chain.iter().any(|p| p.project_id == id) {
+            break;
+        }
+        match get_project(id) {
+            Some(project) => {
+                next = project.forked_from.clone();
+                chain.push(project);
+            }
+            None => break,
+        }
+    }
+    chain
+}
+
+/// Take the edit lock of a project owned by `caller` for `duration_ns`, returning the token
+/// that saves must present while the lock is held
+pub fn acquire_edit_lock(caller: Principal, project_id: ProjectId, duration_ns: u64, now: u64) -> Result<String, String> {
+    if duration_ns == 0 || duration_ns > MAX_EDIT_LOCK_NS {
+        return Err(format!("Lock duration must be between 1 and {} nanoseconds", MAX_EDIT_LOCK_NS));
+    }
+    let project = get_project(project_id.clone()).ok_or("Project not found".to_string())?;
+    if project.owner != caller {
+        return Err("Only project owner can lock the project".to_string());
+    }
+    if let Some(lock) = get_edit_lock(project_id.clone(), now) {
+        return Err(format!("Project is locked by {} until {}", lock.holder, lock.expires_at));
+    }
+
+    let mut hasher = DefaultHasher::new();
+    (&project_id, caller, now, duration_ns).hash(&mut hasher);
+    let lock_token = format!("lock_{:x}", hasher.finish());
+    EDIT_LOCKS.with(|locks| {
+        locks.borrow_mut().insert(project_id, EditLock {
+            lock_token: lock_token.clone(),
+            holder: caller.to_text(),
+            expires_at: now.saturating_add(duration_ns),
+        });
+    });
+    Ok(lock_token)
+}
+
+/// Release an edit lock; only its holder with the matching token can release it
+pub fn release_edit_lock(caller: Principal, project_id: ProjectId, lock_token: String) -> Result<(), String> {
+    EDIT_LOCKS.with(|locks| {
+        let mut locks = locks.borrow_mut();
+        let lock = locks.get(&project_id).ok_or("Project is not locked".to_string())?;
+        if lock.holder != caller.to_text() || lock.lock_token != lock_token {
+            return Err("Only the lock holder can release the lock".to_string());
+        }
+        locks.remove(&project_id);
+        Ok(())
+    })
+}
+
+/// The project's edit lock if it has not expired at `now`
+pub fn get_edit_lock(project_id: ProjectId, now: u64) -> Option<EditLock> {
+    EDIT_LOCKS.with(|locks| locks.borrow().get(&project_id))
+        .filter(|lock| lock.expires_at > now)
+}
+
+/// Reject saves that do not hold an unexpired lock on the project
+fn check_edit_lock(caller: Principal, project_id: &ProjectId, lock_token: Option<&str>, now: u64) -> Result<(), String> {
+    match get_edit_lock(project_id.clone(), now) {
+        Some(lock) if lock.holder != caller.to_text() || lock_token != Some(lock.lock_token.as_str()) => {
+            Err(format!("Project is locked by {} until {}", lock.holder, lock.expires_at))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Save a new version to an existing project. While an edit lock is held, only saves
+/// presenting its token are accepted.
 pub fn save_version(
     caller: Principal,
     project_id: ProjectId, 
     source: PixelArtSource, 
     message: Option<String>,
-    if_match_version: Option<String>
+    if_match_version: Option<String>,
+    lock_token: Option<String>,
 ) -> Result<VersionId, String> {
     // Validate input
     validate_pixel_art_source(&source)?;
     validate_payload_size(&source)?;
+    check_edit_lock(caller, &project_id, lock_token.as_deref(), time())?;
     
-    let current_time = ic_cdk::api::time() / 1_000_000; // Convert to seconds
+    let current_time = time() / 1_000_000; // Convert to seconds
     
     PIXEL_PROJECTS.with(|projects| {
         let mut projects = projects.borrow_mut();
@@ -325,7 +614,7 @@ pub fn save_version(
                 created_at: current_time,
                 editor: caller,
                 message,
-                source,
+                source: pack_source(source),
             };
             
             // Update project
@@ -347,7 +636,7 @@ pub fn save_version(
 pub fn get_project(project_id: ProjectId) -> Option<Project> {
     PIXEL_PROJECTS.with(|projects| {
         let projects = projects.borrow();
-        projects.get(&project_id)
+        projects.get(&project_id).map(unpack_project)
     })
 }
 
@@ -358,13 +647,13 @@ pub fn get_version(project_id: ProjectId, version_id: VersionId) -> Option<Versi
         if let Some(project) = projects.get(&project_id) {
             // Check current version first
             if project.current_version.version_id == version_id {
-                return Some(project.current_version.clone());
+                return Some(unpack_version(project.current_version));
             }
             
             // Search in history
-            project.history.iter()
+            project.history.into_iter()
                 .find(|v| v.version_id == version_id)
-                .cloned()
+                .map(unpack_version)
         } else {
             None
         }
@@ -376,7 +665,16 @@ pub fn get_current_source(project_id: ProjectId) -> Option<PixelArtSource> {
     PIXEL_PROJECTS.with(|projects| {
         let projects = projects.borrow();
         projects.get(&project_id)
-            .map(|project| project.current_version.source.clone())
+            .map(|project| unpack_source(project.current_version.source))
+    })
+}
+
+/// Get the current version id of a project, for use as `if_match_version`
+pub fn get_current_version_id(project_id: ProjectId) -> Option<VersionId> {
+    PIXEL_PROJECTS.with(|projects| {
+        projects.borrow()
+            .get(&project_id)
+            .map(|project| project.current_version.version_id)
     })
 }
 
@@ -431,7 +729,7 @@ pub fn list_projects_by_owner(owner: Principal, page: u32, page_size: u32) -> Ve
         for (_, project) in projects_store.iter() {
             if project.owner == owner {
                 if count >= skip && collected < page_size {
-                    projects.push(project.clone());
+                    projects.push(unpack_project(project));
                     collected += 1;
                 }
                 count += 1;
@@ -481,9 +779,16 @@ pub fn delete_project(caller: Principal, project_id: ProjectId) -> Result<bool,
                 let mut index = index.borrow_mut();
                 index.remove(&ProjectOwnerKey { 
                     owner: caller, 
-                    project_id 
+                    project_id: project_id.clone() 
                 });
             });
+
+            // Remove from its parent's forks
+            if let Some(parent_id) = project.forked_from {
+                PROJECT_FORK_INDEX.with(|index| {
+                    index.borrow_mut().remove(&ProjectForkKey { parent_id, fork_id: project_id });
+                });
+            }
             
             Ok(true)
         } else {
@@ -502,7 +807,7 @@ pub fn get_projects_paginated(offset: u64, limit: usize) -> Vec<Project> {
         
         for (_, project) in projects_store.iter() {
             if count >= offset && projects.len() < limit {
-                projects.push(project.clone());
+                projects.push(unpack_project(project));
             }
             count += 1;
             
@@ -522,9 +827,54 @@ pub fn get_total_project_count() -> u64 {
     })
 }
 
+/// Small projects for unit tests in this and other modules
+#[cfg(test)]
+pub(crate) mod test_fixtures {
+    use super::*;
+
+    /// A 1x1 black canvas
+    pub fn fixture_source() -> PixelArtSource {
+        PixelArtSource {
+            width: 1,
+            height: 1,
+            palette: vec!["#000000".to_string()],
+            pixels: vec![vec![0]],
+            frames: None,
+            metadata: None,
+            compression: None,
+            compressed_pixels: None,
+        }
+    }
+
+    pub fn fixture_version(version_id: &str, editor: Principal) -> Version {
+        Version {
+            version_id: version_id.to_string(),
+            created_at: 0,
+            editor,
+            message: None,
+            source: fixture_source(),
+        }
+    }
+
+    /// Store a project whose current version is the last one in `history`
+    pub fn insert_fixture_project(project_id: &str, owner: Principal, history: Vec<Version>) {
+        let project = Project {
+            project_id: project_id.to_string(),
+            owner,
+            created_at: 0,
+            updated_at: 0,
+            current_version: history.last().cloned().expect("fixture project needs a version"),
+            history,
+            forked_from: None,
+        };
+        PIXEL_PROJECTS.with(|projects| projects.borrow_mut().insert(project_id.to_string(), project));
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::test_fixtures::{fixture_source, fixture_version, insert_fixture_project};
 
     #[test]
     fn test_pixel_art_creation_and_export() {
@@ -543,6 +893,8 @@ mod tests {
                 description: Some("A simple test pattern".to_string()),
                 tags: Some(vec!["test".to_string(), "pattern".to_string()]),
             }),
+            compression: None,
+            compressed_pixels: None,
         };
 
         // Validate the source
@@ -559,6 +911,15 @@ mod tests {
         assert_ne!(project_id, version_id);
     }
 
+    #[test]
+    fn test_current_version_id_tracks_latest_save() {
+        let owner = Principal::anonymous();
+        insert_fixture_project("proj_current", owner, vec![fixture_version("ver_1", owner), fixture_version("ver_2", owner)]);
+
+        assert_eq!(get_current_version_id("proj_current".to_string()), Some("ver_2".to_string()));
+        assert_eq!(get_current_version_id("proj_missing".to_string()), None);
+    }
+
     #[test]
     fn test_validation_errors() {
         // Test invalid dimensions
@@ -569,6 +930,8 @@ mod tests {
             pixels: vec![],
             frames: None,
             metadata: None,
+            compression: None,
+            compressed_pixels: None,
         };
         
         assert!(validate_pixel_art_source(&source).is_err());
@@ -601,6 +964,8 @@ mod tests {
             ],
             frames: None,
             metadata: None,
+            compression: None,
+            compressed_pixels: None,
         };
 
         let compact = CompactPixelArt {
@@ -617,4 +982,94 @@ mod tests {
         assert!(json.contains("#000000"));
         assert!(json.contains("#FFFFFF"));
     }
+
+    #[test]
+    fn test_fork_lookups_follow_forked_from() {
+        let owner = Principal::anonymous();
+        let forker = Principal::from_slice(&[1]);
+        let root = create_project(owner, fixture_source(), None).unwrap();
+        let child = fork_project(forker, root.clone(), Some("fork".to_string())).unwrap();
+        let sibling = fork_project(owner, root.clone(), None).unwrap();
+        let grandchild = fork_project(owner, child.clone(), None).unwrap();
+
+        let ids = |projects: Vec<Project>| -> Vec<String> { projects.into_iter().map(|p| p.project_id).collect() };
+        let mut expected = vec![child.clone(), sibling.clone()];
+        expected.sort();
+        assert_eq!(ids(get_project_forks(root.clone())), expected);
+        assert_eq!(get_project(child.clone()).unwrap().owner, forker);
+        assert_eq!(ids(get_fork_chain(grandchild.clone())), vec![grandchild, child.clone(), root.clone()]);
+        assert!(get_fork_chain("proj_missing".to_string()).is_empty());
+
+        // A deleted fork drops out of its parent's forks
+        assert_eq!(delete_project(owner, sibling), Ok(true));
+        assert_eq!(ids(get_project_forks(root)), vec![child]);
+    }
+
+    #[test]
+    fn test_edit_lock_blocks_other_sessions_until_expiry() {
+        let owner = Principal::anonymous();
+        insert_fixture_project("proj_locked", owner, vec![fixture_version("ver_locked", owner)]);
+        let project_id = "proj_locked".to_string();
+        let other = Principal::management_canister();
+
+        assert!(acquire_edit_lock(other, project_id.clone(), 100, 0).is_err());
+        assert!(acquire_edit_lock(owner, project_id.clone(), 0, 0).is_err());
+        let token = acquire_edit_lock(owner, project_id.clone(), 100, 0).unwrap();
+        assert!(acquire_edit_lock(owner, project_id.clone(), 100, 50).is_err());
+
+        assert!(check_edit_lock(owner, &project_id, None, 50).is_err());
+        assert!(check_edit_lock(owner, &project_id, Some("lock_wrong"), 50).is_err());
+        assert!(check_edit_lock(owner, &project_id, Some(token.as_str()), 50).is_ok());
+        assert!(check_edit_lock(owner, &project_id, None, 100).is_ok());
+
+        assert!(release_edit_lock(owner, project_id.clone(), "lock_wrong".to_string()).is_err());
+        release_edit_lock(owner, project_id.clone(), token).unwrap();
+        assert!(get_edit_lock(project_id, 0).is_none());
+    }
+
+    #[test]
+    fn test_run_length_encoding_benchmark_solid_canvas() {
+        let raw = PixelArtSource {
+            width: 64,
+            height: 64,
+            palette: vec!["#FF0000".to_string()],
+            pixels: vec![vec![0; 64]; 64],
+            frames: None,
+            metadata: None,
+            compression: Some(CompressionMode::None),
+            compressed_pixels: None,
+        };
+        let rle = PixelArtSource { compression: Some(CompressionMode::RunLengthEncoding), ..raw.clone() };
+
+        let stored_raw = Encode!(&pack_source(raw.clone())).unwrap();
+        let stored_rle = Encode!(&pack_source(rle.clone())).unwrap();
+        assert!(stored_rle.len() * 10 < stored_raw.len());
+
+        // Instruction counts need the canister performance counter, so the cost of reading a
+        // stored source back is compared on native time instead, best of several runs
+        let read_cost = |stored: &[u8]| {
+            (0..20).map(|_| {
+                let started = std::time::Instant::now();
+                let source = unpack_source(Decode!(stored, PixelArtSource).unwrap());
+                assert_eq!(source.pixels.len(), 64);
+                started.elapsed()
+            }).min().unwrap()
+        };
+        assert!(read_cost(&stored_rle) <= read_cost(&stored_raw));
+
+        // 4096 pixels fit in a single run
+        assert_eq!(compress_pixels(&rle), vec![0x00, 0x10, 0x00, 0x00]);
+        assert_eq!(decompress_pixels(&compress_pixels(&rle), 64, 64).pixels, raw.pixels);
+
+        let owner = Principal::anonymous();
+        let version = Version { source: pack_source(rle), ..fixture_version("ver_rle", owner) };
+        assert!(version.source.pixels.is_empty());
+        insert_fixture_project("proj_rle", owner, vec![version]);
+
+        let source = get_current_source("proj_rle".to_string()).unwrap();
+        assert_eq!(source.pixels, raw.pixels);
+        assert_eq!(source.compression, Some(CompressionMode::RunLengthEncoding));
+        assert!(source.compressed_pixels.is_none());
+        assert_eq!(get_version("proj_rle".to_string(), "ver_rle".to_string()).unwrap().source.pixels, raw.pixels);
+    }
 }
@@ -0,0 +1,31 @@
+// Shared clean-up and length bounds for user-supplied strings
+
+pub const MAX_NAME_LEN: usize = 64;
+pub const MAX_NICKNAME_LEN: usize = 64;
+pub const MAX_REVIEW_COMMENT_LEN: usize = 1000;
+
+/// Trim surrounding whitespace, drop control characters and reject input
+/// longer than `max_len` characters
+pub fn sanitize_and_bound(input: &str, max_len: usize) -> Result<String, String> {
+    let cleaned: String = input.trim().chars().filter(|c| !c.is_control()).collect();
+    let len = cleaned.chars().count();
+    if len > max_len {
+        return Err(format!("Input too long: {} characters (max {})", len, max_len));
+    }
+    Ok(cleaned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_and_bound() {
+        assert_eq!(sanitize_and_bound("  Alice\u{0007} ", MAX_NAME_LEN), Ok("Alice".to_string()));
+        assert_eq!(sanitize_and_bound(&"é".repeat(MAX_NAME_LEN), MAX_NAME_LEN).unwrap().chars().count(), MAX_NAME_LEN);
+        assert_eq!(
+            sanitize_and_bound(&"a".repeat(MAX_NAME_LEN + 1), MAX_NAME_LEN),
+            Err(format!("Input too long: {} characters (max {})", MAX_NAME_LEN + 1, MAX_NAME_LEN))
+        );
+    }
+}
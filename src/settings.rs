@@ -0,0 +1,157 @@
+use candid::{CandidType, Decode, Encode};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use crate::stable_mem_storage::SETTINGS;
+
+/// Value of a tunable; each setting keeps the variant of its default
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum SettingValue {
+    Nat(u64),
+    Float(f64),
+}
+
+impl Storable for SettingValue {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 64, is_fixed_size: false };
+}
+
+// Setting keys
+pub const MIN_STAKE_AMOUNT: &str = "min_stake_amount";
+// The three emission defaults seed the emission policy in init_emission_policy only; changing
+// them later has no effect on the stored policy, which is tuned through its own endpoints
+pub const DEFAULT_BASE_RATE: &str = "default_base_rate";
+pub const DEFAULT_KAPPA_FACTOR: &str = "default_kappa_factor";
+pub const DEFAULT_STAKING_BONUS: &str = "default_staking_bonus";
+pub const DEFAULT_ICP_USD_PRICE: &str = "default_icp_usd_price";
+pub const DEFAULT_CREDIT_USD_PRICE: &str = "default_credit_usd_price";
+pub const EMISSION_COOLDOWN_NS: &str = "emission_cooldown_ns";
+pub const NEWUSER_GRANT_AMOUNT: &str = "newuser_grant_amount";
+pub const NEWUSER_GRANT_CLIFF_NS: &str = "newuser_grant_cliff_ns";
+pub const NEWUSER_GRANT_VESTING_NS: &str = "newuser_grant_vesting_ns";
+pub const TRACE_RATE_LIMIT_CALLS: &str = "trace_rate_limit_calls";
+pub const TRACE_RATE_LIMIT_WINDOW_NS: &str = "trace_rate_limit_window_ns";
+pub const AGENT_INVOKE_FEE: &str = "agent_invoke_fee";
+pub const AGENT_MESSAGE_FEE: &str = "agent_message_fee";
+
+const DAY_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Every known setting with the value used until an admin overrides it
+const DEFAULTS: &[(&str, SettingValue)] = &[
+    (MIN_STAKE_AMOUNT, SettingValue::Nat(100)), // Minimum amount of credits to stake
+    (DEFAULT_BASE_RATE, SettingValue::Nat(100)),
+    (DEFAULT_KAPPA_FACTOR, SettingValue::Float(1.0)),
+    (DEFAULT_STAKING_BONUS, SettingValue::Float(0.1)),
+    (DEFAULT_ICP_USD_PRICE, SettingValue::Float(5.5)),
+    (DEFAULT_CREDIT_USD_PRICE, SettingValue::Float(0.0001)),
+    (EMISSION_COOLDOWN_NS, SettingValue::Nat(DAY_NS)),
+    (NEWUSER_GRANT_AMOUNT, SettingValue::Nat(1000)),
+    (NEWUSER_GRANT_CLIFF_NS, SettingValue::Nat(30 * DAY_NS)),
+    (NEWUSER_GRANT_VESTING_NS, SettingValue::Nat(365 * DAY_NS)),
+    (TRACE_RATE_LIMIT_CALLS, SettingValue::Nat(100)), // record_trace_call calls per caller per window
+    (TRACE_RATE_LIMIT_WINDOW_NS, SettingValue::Nat(60 * 1_000_000_000)),
+    (AGENT_INVOKE_FEE, SettingValue::Nat(10)), // credits per invoke_agent call, before fee rules
+    (AGENT_MESSAGE_FEE, SettingValue::Nat(1)), // credits per enqueued agent message, before fee rules
+];
+
+fn default_value(key: &str) -> Option<SettingValue> {
+    DEFAULTS.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+/// Current value of a setting (stored override or default); None for unknown keys
+pub fn get_setting(key: &str) -> Option<SettingValue> {
+    let default = default_value(key)?;
+    Some(SETTINGS.with(|settings| settings.borrow().get(&key.to_string())).unwrap_or(default))
+}
+
+/// All settings with their current values, in declaration order
+pub fn list_settings() -> Vec<(String, SettingValue)> {
+    DEFAULTS.iter()
+        .filter_map(|(key, _)| get_setting(key).map(|value| (key.to_string(), value)))
+        .collect()
+}
+
+pub fn get_u64(key: &str) -> u64 {
+    match get_setting(key) {
+        Some(SettingValue::Nat(value)) => value,
+        other => panic!("Setting '{}' is not a Nat: {:?}", key, other),
+    }
+}
+
+pub fn get_f64(key: &str) -> f64 {
+    match get_setting(key) {
+        Some(SettingValue::Float(value)) => value,
+        other => panic!("Setting '{}' is not a Float: {:?}", key, other),
+    }
+}
+
+pub fn set_u64(key: &str, value: u64) -> Result<(), String> {
+    update_setting(key.to_string(), SettingValue::Nat(value))
+}
+
+// Key-specific checks on top of the type check in update_setting
+fn validate_setting(key: &str, value: SettingValue) -> Result<(), String> {
+    match (key, value) {
+        (MIN_STAKE_AMOUNT, SettingValue::Nat(0)) => Err("Minimum stake amount must be greater than zero".to_string()),
+        (TRACE_RATE_LIMIT_CALLS | TRACE_RATE_LIMIT_WINDOW_NS, SettingValue::Nat(0)) => {
+            Err("Rate limit calls and window must be greater than zero".to_string())
+        }
+        // Both prices divide the conversion rate, so zero would make it infinite
+        (DEFAULT_ICP_USD_PRICE | DEFAULT_CREDIT_USD_PRICE, SettingValue::Float(price)) if price <= 0.0 => {
+            Err("Default USD prices must be greater than zero".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Store a new value for a known setting; the value must have the same type as the default
+/// and pass the setting's own validation
+pub fn update_setting(key: String, value: SettingValue) -> Result<(), String> {
+    let default = default_value(&key).ok_or_else(|| format!("Unknown setting '{}'", key))?;
+    match (default, value) {
+        (SettingValue::Nat(_), SettingValue::Nat(_)) => {}
+        (SettingValue::Float(_), SettingValue::Float(v)) if v.is_finite() && v >= 0.0 => {}
+        (SettingValue::Float(_), SettingValue::Float(_)) => {
+            return Err(format!("Setting '{}' must be a finite, non-negative number", key));
+        }
+        _ => return Err(format!("Setting '{}' expects a value of the same type as {:?}", key, default)),
+    }
+    validate_setting(&key, value)?;
+    SETTINGS.with(|settings| {
+        settings.borrow_mut().insert(key, value);
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_setting_validates_and_persists() {
+        assert_eq!(get_u64(MIN_STAKE_AMOUNT), 100);
+        assert!(update_setting("no_such_setting".to_string(), SettingValue::Nat(1)).is_err());
+        assert!(update_setting(MIN_STAKE_AMOUNT.to_string(), SettingValue::Float(1.0)).is_err());
+        assert!(update_setting(DEFAULT_ICP_USD_PRICE.to_string(), SettingValue::Float(f64::NAN)).is_err());
+        assert!(update_setting(DEFAULT_ICP_USD_PRICE.to_string(), SettingValue::Float(0.0)).is_err());
+        assert!(update_setting(DEFAULT_CREDIT_USD_PRICE.to_string(), SettingValue::Float(0.0)).is_err());
+        assert!(update_setting("exchange_ratio".to_string(), SettingValue::Float(1.0)).is_err());
+        assert!(set_u64(MIN_STAKE_AMOUNT, 0).is_err());
+        assert!(update_setting(TRACE_RATE_LIMIT_CALLS.to_string(), SettingValue::Nat(0)).is_err());
+        assert!(update_setting(TRACE_RATE_LIMIT_WINDOW_NS.to_string(), SettingValue::Nat(0)).is_err());
+        assert_eq!(get_u64(TRACE_RATE_LIMIT_CALLS), 100);
+
+        set_u64(MIN_STAKE_AMOUNT, 25).unwrap();
+        assert_eq!(get_u64(MIN_STAKE_AMOUNT), 25);
+        assert_eq!(SETTINGS.with(|s| s.borrow().get(&MIN_STAKE_AMOUNT.to_string())), Some(SettingValue::Nat(25)));
+        assert!(list_settings().contains(&(MIN_STAKE_AMOUNT.to_string(), SettingValue::Nat(25))));
+    }
+}
@@ -1,12 +1,14 @@
 use candid::{CandidType, Decode, Encode, Principal};
 use ic_stable_structures::storable::Bound;
-use ic_stable_structures::{StableBTreeMap, StableVec};
+use ic_stable_structures::StableBTreeMap;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use sha2::{Sha256, Digest};
-use crate::stable_mem_storage::{USER_PROFILES, PRINCIPAL_INDEX, USER_ID_INDEX, EMAIL_INDEX};
+use crate::stable_mem_storage::{USER_PROFILES, PRINCIPAL_INDEX, USER_ID_INDEX, EMAIL_INDEX, NICKNAME_INDEX, USER_AVATARS};
+use crate::sanitize::{sanitize_and_bound, MAX_NAME_LEN, MAX_NICKNAME_LEN};
+use crate::types::{CursorPage, clamp_page_limit, cursor_start_index, time};
 
 // User profile data structure for society profile management
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -25,6 +27,27 @@ pub struct UserProfile {
     pub created_at: u64,
     pub updated_at: u64,
     pub metadata: Option<String>,       // Additional metadata as JSON
+    pub deleted_at: Option<u64>,        // Set when the profile is deleted
+}
+
+/// The part of a profile anyone may look up; credentials and contact details stay private
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PublicUserProfile {
+    pub user_id: String,
+    pub principal_id: String,
+    pub nickname: String,
+    pub picture: Option<String>,
+}
+
+impl From<UserProfile> for PublicUserProfile {
+    fn from(profile: UserProfile) -> Self {
+        Self {
+            user_id: profile.user_id,
+            principal_id: profile.principal_id,
+            nickname: profile.nickname,
+            picture: profile.picture,
+        }
+    }
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -109,13 +132,37 @@ impl ic_stable_structures::Storable for EmailKey {
     const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false };
 }
 
+// Nickname index entry: one key per (lowercased nickname, USER_PROFILES index)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NicknameKey {
+    pub nickname: String,
+    pub profile_index: u64,
+}
+
+impl ic_stable_structures::Storable for NicknameKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.nickname, &self.profile_index).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let (nickname, profile_index) = Decode!(bytes.as_ref(), String, u64).unwrap();
+        Self { nickname, profile_index }
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false };
+}
+
 // Import storage from stable_mem_storage
 
 /// Add or update a user profile
 pub fn upsert_user_profile(profile: UserProfile) -> Result<u64, String> {
-    let current_time = ic_cdk::api::time();
+    let current_time = time();
     let mut updated_profile = profile;
     updated_profile.updated_at = current_time;
+    updated_profile.nickname = sanitize_and_bound(&updated_profile.nickname, MAX_NICKNAME_LEN)?;
+    if let Some(name) = &updated_profile.name {
+        updated_profile.name = Some(sanitize_and_bound(name, MAX_NAME_LEN)?);
+    }
     
     // Set created_at if it's a new profile
     if updated_profile.created_at == 0 {
@@ -128,10 +175,34 @@ pub fn upsert_user_profile(profile: UserProfile) -> Result<u64, String> {
         idx.get(&PrincipalKey { principal_id: updated_profile.principal_id.clone() }).map(|idx| idx)
     });
     
+    // The nickname index entry is keyed by the old nickname, which the overwrite below loses
+    let previous_nickname = existing_index.and_then(get_user_profile).map(|stored| stored.nickname);
+
     let result = USER_PROFILES.with(|profiles| -> Result<u64, String> {
         let mut profiles = profiles.borrow_mut();
         
         if let Some(existing_index) = existing_index {
+            // Make sure the indexed slot really holds this principal before overwriting it
+            let deleted = crate::stable_mem_storage::DELETED_PROFILE_SLOTS.with(|slots| slots.borrow().contains_key(&existing_index));
+            match profiles.get(existing_index) {
+                Some(stored) if stored.principal_id == updated_profile.principal_id && !deleted => {}
+                Some(stored) => {
+                    return Err(format!(
+                        "Profile index mismatch: slot {} holds principal {}{}, expected {}. Run rebuild_profile_indices",
+                        existing_index,
+                        stored.principal_id,
+                        if deleted { " (deleted)" } else { "" },
+                        updated_profile.principal_id
+                    ));
+                }
+                None => {
+                    return Err(format!(
+                        "Profile index mismatch: slot {} does not exist. Run rebuild_profile_indices",
+                        existing_index
+                    ));
+                }
+            }
+
             // Update existing profile
             profiles.set(existing_index, &updated_profile);
             Ok(existing_index)
@@ -148,6 +219,9 @@ pub fn upsert_user_profile(profile: UserProfile) -> Result<u64, String> {
     if let Some(existing_index) = existing_index {
         // Update existing profile indices
         update_indices(&updated_profile, existing_index)?;
+        if let Some(previous) = previous_nickname.filter(|previous| !previous.eq_ignore_ascii_case(&updated_profile.nickname)) {
+            remove_nickname_index(&previous, existing_index);
+        }
     } else {
         // Create new profile indices
         create_indices(&updated_profile, result)?;
@@ -192,12 +266,12 @@ pub fn get_user_profile_by_email(email: String) -> Option<UserProfile> {
     })
 }
 
-/// Get a user profile by index
+/// Get a user profile by index (deleted profiles are hidden)
 pub fn get_user_profile(index: u64) -> Option<UserProfile> {
     USER_PROFILES.with(|profiles| {
         let profiles = profiles.borrow();
         if index < profiles.len() {
-            profiles.get(index)
+            profiles.get(index).filter(|profile| profile.deleted_at.is_none())
         } else {
             None
         }
@@ -206,6 +280,7 @@ pub fn get_user_profile(index: u64) -> Option<UserProfile> {
 
 /// Update user nickname
 pub fn update_user_nickname(principal_id: String, nickname: String) -> Result<UserProfile, String> {
+    let nickname = sanitize_and_bound(&nickname, MAX_NICKNAME_LEN)?;
     // First get the profile index to avoid borrowing conflicts
     let profile_index = PRINCIPAL_INDEX.with(|index| {
         let index = index.borrow();
@@ -306,44 +381,266 @@ pub fn update_user_devices(principal_id: String, devices: Vec<String>) -> Result
 
 /// Get all user profiles with pagination
 pub fn get_user_profiles_paginated(offset: u64, limit: usize) -> Vec<UserProfile> {
+    get_user_profiles_admin(false, offset, limit)
+}
+
+/// Get user profiles with pagination, optionally including deleted ones
+pub fn get_user_profiles_admin(include_deleted: bool, offset: u64, limit: usize) -> Vec<UserProfile> {
     USER_PROFILES.with(|profiles| {
         let profiles = profiles.borrow();
-        let total_profiles = profiles.len();
-        
-        if offset >= total_profiles {
-            return Vec::new();
+        (0..profiles.len())
+            .filter_map(|i| profiles.get(i))
+            .filter(|profile| include_deleted || profile.deleted_at.is_none())
+            .skip(offset as usize)
+            .take(limit)
+            .collect()
+    })
+}
+
+/// Largest avatar image accepted by `upload_user_avatar`
+pub const MAX_AVATAR_BYTES: usize = 256 * 1024;
+
+/// Canister-relative picture URL of a stored avatar
+pub fn avatar_url(principal_id: &str) -> String {
+    format!("avatar://{}", principal_id)
+}
+
+/// Store a PNG or JPEG avatar for the profile and point `picture` at it, returning the URL
+pub fn upload_user_avatar(principal_id: String, image_bytes: Vec<u8>, mime_type: String) -> Result<String, String> {
+    let signature: &[u8] = match mime_type.trim().to_ascii_lowercase().as_str() {
+        "image/png" => b"\x89PNG\r\n\x1a\n",
+        "image/jpeg" => &[0xFF, 0xD8, 0xFF],
+        _ => return Err("Avatar must be image/png or image/jpeg".to_string()),
+    };
+    if image_bytes.len() > MAX_AVATAR_BYTES {
+        return Err(format!("Avatar is {} bytes, the limit is {} bytes", image_bytes.len(), MAX_AVATAR_BYTES));
+    }
+    if !image_bytes.starts_with(signature) {
+        return Err(format!("Avatar data is not a valid {}", mime_type.trim()));
+    }
+    let mut profile = get_user_profile_by_principal(principal_id.clone())
+        .ok_or("User profile not found".to_string())?;
+
+    let url = avatar_url(&principal_id);
+    USER_AVATARS.with(|avatars| avatars.borrow_mut().insert(principal_id, image_bytes));
+    profile.picture = Some(url.clone());
+    upsert_user_profile(profile)?;
+    Ok(url)
+}
+
+pub fn get_user_avatar(principal_id: String) -> Option<Vec<u8>> {
+    USER_AVATARS.with(|avatars| avatars.borrow().get(&principal_id))
+}
+
+/// Remove the stored avatar, clearing `picture` if it still points at it
+pub fn delete_user_avatar(principal_id: String) -> Result<(), String> {
+    USER_AVATARS.with(|avatars| avatars.borrow_mut().remove(&principal_id))
+        .ok_or("User has no avatar".to_string())?;
+    if let Some(mut profile) = get_user_profile_by_principal(principal_id.clone()) {
+        if profile.picture.as_deref() == Some(avatar_url(&principal_id).as_str()) {
+            profile.picture = None;
+            upsert_user_profile(profile)?;
         }
-        
-        let end = std::cmp::min(offset + limit as u64, total_profiles);
-        let mut result = Vec::new();
-        
-        for i in offset..end {
-            if let Some(profile) = profiles.get(i) {
-                result.push(profile);
+    }
+    Ok(())
+}
+
+/// Scrub personal data from profiles deleted before the cutoff. The slot itself stays
+/// because profile indices are positions in the vector.
+pub fn purge_deleted_profiles_older_than(cutoff_ns: u64) -> u64 {
+    USER_PROFILES.with(|profiles| {
+        let profiles = profiles.borrow_mut();
+        let mut purged = 0;
+        for i in 0..profiles.len() {
+            let profile = match profiles.get(i) {
+                Some(profile) => profile,
+                None => continue,
+            };
+            let expired = profile.deleted_at.is_some_and(|t| t < cutoff_ns);
+            if !expired || profile.principal_id.is_empty() {
+                continue;
             }
+            USER_AVATARS.with(|avatars| avatars.borrow_mut().remove(&profile.principal_id));
+            profiles.set(i, &UserProfile {
+                user_id: String::new(),
+                principal_id: String::new(),
+                name: None,
+                nickname: String::new(),
+                login_method: profile.login_method.clone(),
+                login_status: LoginStatus::Unauthenticated,
+                email: None,
+                picture: None,
+                wallet_address: None,
+                devices: Vec::new(),
+                passwd: None,
+                created_at: profile.created_at,
+                updated_at: profile.updated_at,
+                metadata: None,
+                deleted_at: profile.deleted_at,
+            });
+            purged += 1;
         }
-        
-        result
+        purged
+    })
+}
+
+/// Get user profiles after the given cursor (the index of the last profile already seen)
+pub fn get_user_profiles_paginated_cursor(cursor: Option<String>, limit: usize) -> Result<CursorPage<UserProfile>, String> {
+    let start = cursor_start_index(&cursor)?;
+    let limit = clamp_page_limit(limit);
+    USER_PROFILES.with(|profiles| {
+        let profiles = profiles.borrow();
+        let entries = (start..profiles.len())
+            .filter_map(|i| profiles.get(i).map(|profile| (i.to_string(), profile)))
+            .filter(|(_, profile)| profile.deleted_at.is_none())
+            .take(limit + 1)
+            .collect();
+        Ok(CursorPage::from_entries(entries, limit))
     })
 }
 
 /// Delete a user profile
-pub fn delete_user_profile(principal_id: String) -> Result<bool, String> {
-    PRINCIPAL_INDEX.with(|index| {
-        if let Some(profile_index) = index.borrow().get(&PrincipalKey { principal_id: principal_id.clone() }) {
-            // Remove from indices
-            remove_indices(principal_id.clone())?;
-            
-            // Note: We don't actually remove from the main storage to maintain referential integrity
-            // Instead, we mark it as deleted or keep it for audit purposes
-            
-            Ok(true)
+///
+/// With `cascade`, the user's contact relationships are tombstoned on both sides
+/// (status set to `Deleted` and removed from the lookup indices) and any pending
+/// notifications addressed to the user, or about chats with their contacts, are cleared.
+///
+/// Devices, accounts, token/credit activities and traces are always preserved so
+/// financial records stay available for audit.
+pub fn delete_user_profile(principal_id: String, cascade: bool) -> Result<bool, String> {
+    let profile_index = PRINCIPAL_INDEX.with(|index| {
+        index.borrow().get(&PrincipalKey { principal_id: principal_id.clone() })
+    });
+
+    let profile_index = match profile_index {
+        Some(index) => index,
+        None => return Ok(false),
+    };
+
+    // Remove from indices
+    remove_indices(principal_id.clone())?;
+    crate::stable_mem_storage::DELETED_PROFILE_SLOTS.with(|slots| {
+        slots.borrow_mut().insert(profile_index, ());
+    });
+    USER_PROFILES.with(|profiles| {
+        let profiles = profiles.borrow_mut();
+        if let Some(mut profile) = profiles.get(profile_index) {
+            profile.deleted_at = Some(time());
+            profiles.set(profile_index, &profile);
+        }
+    });
+
+    // Note: We don't actually remove from the main storage to maintain referential integrity
+    // Instead, we mark it as deleted or keep it for audit purposes
+
+    if cascade {
+        let peers = tombstone_contacts_of(&principal_id)?;
+        clear_notifications_for_user(&principal_id, &peers);
+    }
+
+    Ok(true)
+}
+
+/// Mark every contact record involving the principal as deleted, in both directions.
+/// Returns the principals on the other side of those relationships.
+fn tombstone_contacts_of(principal_id: &str) -> Result<Vec<String>, String> {
+    let related: Vec<(u64, Contact)> = crate::stable_mem_storage::CONTACTS.with(|contacts| {
+        let contacts = contacts.borrow();
+        (0..contacts.len())
+            .filter_map(|i| contacts.get(i).map(|c| (i, c)))
+            .filter(|(_, c)| c.owner_principal_id == principal_id || c.contact_principal_id == principal_id)
+            .collect()
+    });
+
+    let mut peers = Vec::new();
+    for (index, mut contact) in related {
+        remove_contact_indices(contact.owner_principal_id.clone(), contact.contact_principal_id.clone())?;
+
+        let peer = if contact.owner_principal_id == principal_id {
+            contact.contact_principal_id.clone()
         } else {
-            Ok(false)
+            contact.owner_principal_id.clone()
+        };
+        if !peers.contains(&peer) {
+            peers.push(peer);
+        }
+
+        contact.status = ContactStatus::Deleted;
+        crate::stable_mem_storage::CONTACTS.with(|contacts| {
+            contacts.borrow_mut().set(index, &contact);
+        });
+    }
+
+    Ok(peers)
+}
+
+/// Remove notifications addressed to the principal or belonging to a chat with one of the peers
+fn clear_notifications_for_user(principal_id: &str, peers: &[String]) -> u64 {
+    let pair_keys: Vec<String> = peers.iter()
+        .map(|peer| generate_social_pair_key(principal_id.to_string(), peer.clone()))
+        .collect();
+
+    crate::stable_mem_storage::NOTIFICATION_QUEUE.with(|queue| {
+        let mut queue = queue.borrow_mut();
+        let keys_to_remove: Vec<NotificationKey> = queue.iter()
+            .filter(|(_, n)| n.to_who == principal_id || pair_keys.contains(&n.social_pair_key))
+            .map(|(key, _)| key)
+            .collect();
+
+        for key in &keys_to_remove {
+            queue.remove(key);
         }
+        keys_to_remove.len() as u64
     })
 }
 
+/// Clear and rebuild the principal, user ID, email and nickname indices from USER_PROFILES.
+/// Deleted slots are skipped; when a principal occupies several slots the latest one wins.
+/// Returns the number of profiles indexed.
+pub fn rebuild_profile_indices() -> Result<u64, String> {
+    fn clear<K: ic_stable_structures::Storable + Ord + Clone>(
+        map: &mut StableBTreeMap<K, u64, crate::stable_mem_storage::Memory>,
+    ) {
+        let keys: Vec<K> = map.iter().map(|(k, _)| k).collect();
+        for key in keys {
+            map.remove(&key);
+        }
+    }
+
+    PRINCIPAL_INDEX.with(|idx| clear(&mut idx.borrow_mut()));
+    USER_ID_INDEX.with(|idx| clear(&mut idx.borrow_mut()));
+    EMAIL_INDEX.with(|idx| clear(&mut idx.borrow_mut()));
+    NICKNAME_INDEX.with(|idx| {
+        let mut idx = idx.borrow_mut();
+        let keys: Vec<NicknameKey> = idx.iter().map(|(k, _)| k).collect();
+        for key in keys {
+            idx.remove(&key);
+        }
+    });
+
+    let total = USER_PROFILES.with(|profiles| profiles.borrow().len());
+    let mut indexed = std::collections::BTreeMap::new();
+    for index in 0..total {
+        // A slot is deleted if it is recorded as such or the profile carries a deletion time;
+        // profiles deleted before the slot set existed only have the latter
+        let deleted = crate::stable_mem_storage::DELETED_PROFILE_SLOTS.with(|slots| slots.borrow().contains_key(&index));
+        if deleted {
+            continue;
+        }
+        let Some(profile) = USER_PROFILES.with(|profiles| profiles.borrow().get(index)) else { continue };
+        if profile.deleted_at.is_some() {
+            continue;
+        }
+        indexed.insert(profile.principal_id.clone(), (index, profile));
+    }
+
+    for (index, profile) in indexed.values() {
+        create_indices(profile, *index)?;
+    }
+
+    Ok(indexed.len() as u64)
+}
+
 /// Get total number of user profiles
 pub fn get_total_user_profiles() -> u64 {
     USER_PROFILES.with(|profiles| profiles.borrow().len())
@@ -370,6 +667,8 @@ fn create_indices(profile: &UserProfile, index: u64) -> Result<(), String> {
             idx.insert(EmailKey { email: email.clone() }, index);
         });
     }
+
+    add_nickname_index(&profile.nickname, index);
     
     Ok(())
 }
@@ -411,12 +710,103 @@ fn remove_indices(principal_id: String) -> Result<(), String> {
                     idx.remove(&EmailKey { email: email.clone() });
                 });
             }
+
+            remove_nickname_index(&profile.nickname, index);
         }
     }
     
     Ok(())
 }
 
+fn add_nickname_index(nickname: &str, index: u64) {
+    if nickname.trim().is_empty() {
+        return;
+    }
+    let key = NicknameKey { nickname: nickname.to_lowercase(), profile_index: index };
+    NICKNAME_INDEX.with(|idx| idx.borrow_mut().insert(key, ()));
+}
+
+fn remove_nickname_index(nickname: &str, index: u64) {
+    let key = NicknameKey { nickname: nickname.to_lowercase(), profile_index: index };
+    NICKNAME_INDEX.with(|idx| idx.borrow_mut().remove(&key));
+}
+
+/// Fill the nickname index from USER_PROFILES when it is empty, e.g. on the first upgrade
+/// after it was introduced. Returns the number of entries added.
+pub fn backfill_nickname_index() -> u64 {
+    if NICKNAME_INDEX.with(|idx| !idx.borrow().is_empty()) {
+        return 0;
+    }
+    let total = USER_PROFILES.with(|profiles| profiles.borrow().len());
+    let mut added = 0u64;
+    for index in 0..total {
+        let deleted = crate::stable_mem_storage::DELETED_PROFILE_SLOTS.with(|slots| slots.borrow().contains_key(&index));
+        if deleted {
+            continue;
+        }
+        if let Some(profile) = get_user_profile(index).filter(|profile| !profile.nickname.trim().is_empty()) {
+            add_nickname_index(&profile.nickname, index);
+            added += 1;
+        }
+    }
+    added
+}
+
+/// Live profiles behind the nickname index entries whose key passes `matches`, in index key order
+fn profiles_for_nicknames(matches: impl Fn(&str) -> bool) -> Vec<(String, UserProfile)> {
+    let entries: Vec<NicknameKey> = NICKNAME_INDEX.with(|idx| {
+        idx.borrow().iter().map(|(key, _)| key).filter(|key| matches(&key.nickname)).collect()
+    });
+    entries.into_iter()
+        .filter_map(|key| get_user_profile(key.profile_index).map(|profile| (key.nickname, profile)))
+        .filter(|(key, profile)| profile.deleted_at.is_none() && profile.nickname.to_lowercase() == *key)
+        .collect()
+}
+
+/// Profiles whose nickname contains `query`, ignoring case
+pub fn search_users_by_nickname(query: String, offset: u64, limit: u64) -> Vec<PublicUserProfile> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    profiles_for_nicknames(|nickname| nickname.contains(&query))
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit as usize)
+        .map(|(_, profile)| profile.into())
+        .collect()
+}
+
+/// Profiles whose nickname is within `max_distance` edits of `query`, ignoring case, closest first
+pub fn search_users_fuzzy_nickname(query: String, max_distance: u32, limit: usize) -> Vec<PublicUserProfile> {
+    let query: Vec<char> = query.trim().to_lowercase().chars().take(MAX_NICKNAME_LEN).collect();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let mut matches: Vec<(usize, UserProfile)> = profiles_for_nicknames(|nickname| {
+        levenshtein(&nickname.chars().collect::<Vec<_>>(), &query) <= max_distance as usize
+    })
+        .into_iter()
+        .map(|(key, profile)| (levenshtein(&key.chars().collect::<Vec<_>>(), &query), profile))
+        .collect();
+    matches.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.nickname.cmp(&b.1.nickname)));
+    matches.into_iter().take(limit).map(|(_, profile)| profile.into()).collect()
+}
+
+/// Edit distance (insertions, deletions, substitutions) between two strings
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
 // ==== Contact Management ====
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -434,6 +824,7 @@ pub struct Contact {
     pub created_at: u64,                  // Creation time
     pub updated_at: u64,                  // Update time
     pub metadata: Option<String>,         // Additional metadata (JSON format)
+    pub agent_index: Option<u64>,         // Linked AgentItem for ContactType::Agent
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -442,6 +833,7 @@ pub enum ContactType {
     System,     // System
     Business,   // Business
     Family,     // Family
+    Agent,      // AI agent
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -504,10 +896,30 @@ impl ic_stable_structures::Storable for ContactNameKey {
     const BOUND: Bound = Bound::Bounded { max_size: 2048, is_fixed_size: false };
 }
 
+/// Links an agent to a contact that points at it, so agent changes reach its contacts without a scan
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AgentContactKey {
+    pub agent_index: u64,
+    pub contact_id: u64,
+}
+
+impl ic_stable_structures::Storable for AgentContactKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.agent_index, &self.contact_id).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let (agent_index, contact_id) = Decode!(bytes.as_ref(), u64, u64).unwrap();
+        Self { agent_index, contact_id }
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 64, is_fixed_size: false };
+}
+
 // Contact management functions
 /// Add or update contact
 pub fn upsert_contact(contact: Contact) -> Result<u64, String> {
-    let current_time = ic_cdk::api::time();
+    let current_time = time();
     let mut updated_contact = contact;
     updated_contact.updated_at = current_time;
     
@@ -532,36 +944,48 @@ pub fn upsert_contact(contact: Contact) -> Result<u64, String> {
         }
     }
     
-    // Use contact storage from stable_mem_storage
-    crate::stable_mem_storage::CONTACTS.with(|contacts| {
-        let mut contacts = contacts.borrow_mut();
-        
-        // Check if contact already exists
-        if let Some(existing_index) = crate::stable_mem_storage::CONTACT_OWNER_INDEX.with(|idx| {
-            idx.borrow().get(&ContactOwnerKey { 
-                owner_principal_id: updated_contact.owner_principal_id.clone(),
-                contact_principal_id: updated_contact.contact_principal_id.clone()
-            })
-        }) {
-            // Update existing contact
-            contacts.set(existing_index, &updated_contact);
-            
-            // Update indices
-            update_contact_indices(&updated_contact, existing_index)?;
-            
-            Ok(existing_index)
-        } else {
-            // Add new contact
+    // Check if contact already exists
+    let existing_index = crate::stable_mem_storage::CONTACT_OWNER_INDEX.with(|idx| {
+        idx.borrow().get(&ContactOwnerKey { 
+            owner_principal_id: updated_contact.owner_principal_id.clone(),
+            contact_principal_id: updated_contact.contact_principal_id.clone()
+        })
+    });
+
+    if let Some(existing_index) = existing_index {
+        // Merge into the existing contact: fields the incoming record leaves unset keep their stored values
+        if let Some(existing) = get_contact_by_id(existing_index) {
+            updated_contact.nickname = updated_contact.nickname.or(existing.nickname);
+            updated_contact.avatar = updated_contact.avatar.or(existing.avatar);
+            updated_contact.metadata = updated_contact.metadata.or(existing.metadata);
+            updated_contact.agent_index = updated_contact.agent_index.or(existing.agent_index);
+            updated_contact.created_at = existing.created_at;
+        }
+
+        // Update indices while the stored record still carries the old name
+        update_contact_indices(&updated_contact, existing_index)?;
+
+        // Update existing contact
+        crate::stable_mem_storage::CONTACTS.with(|contacts| {
+            contacts.borrow_mut().set(existing_index, &updated_contact);
+        });
+
+        Ok(existing_index)
+    } else {
+        // Add new contact
+        let index = crate::stable_mem_storage::CONTACTS.with(|contacts| {
+            let contacts = contacts.borrow_mut();
             let index = contacts.len();
             contacts.push(&updated_contact)
                 .map_err(|e| format!("Failed to store contact: {:?}", e))?;
-            
-            // Create indices
-            create_contact_indices(&updated_contact, index)?;
-            
-            Ok(index)
-        }
-    })
+            Ok::<u64, String>(index)
+        })?;
+
+        // Create indices
+        create_contact_indices(&updated_contact, index)?;
+
+        Ok(index)
+    }
 }
 
 /// Create contact from principal ID (for adding friends) - creates bidirectional relationship
@@ -612,6 +1036,7 @@ pub fn create_contact_from_principal_id(
         created_at: 0,
         updated_at: 0,
         metadata: None,
+        agent_index: None,
     };
     
     // Create contact record for contact -> owner (bidirectional)
@@ -629,23 +1054,59 @@ pub fn create_contact_from_principal_id(
         created_at: 0,
         updated_at: 0,
         metadata: None,
+        agent_index: None,
     };
     
     // Insert both contact records
-    let owner_contact_index = upsert_contact(owner_to_contact)?;
-    
+    insert_contact_pair(owner_to_contact, contact_to_owner, upsert_contact)
+}
+
+/// Insert both sides of a contact relationship. If the reverse insert fails, the
+/// forward record is rolled back to its previous state so no one-way relationship remains.
+fn insert_contact_pair<F>(forward: Contact, reverse: Contact, mut insert: F) -> Result<u64, String>
+where
+    F: FnMut(Contact) -> Result<u64, String>,
+{
+    let previous = get_contact_by_principal_ids(forward.owner_principal_id.clone(), forward.contact_principal_id.clone());
+    let owner = forward.owner_principal_id.clone();
+    let contact = forward.contact_principal_id.clone();
+
+    let owner_contact_index = insert(forward)?;
+
     // If the first insertion succeeds, insert the reverse relationship
-    match upsert_contact(contact_to_owner) {
+    match insert(reverse) {
         Ok(_) => Ok(owner_contact_index),
         Err(e) => {
-            // If the second insertion fails, we should ideally rollback the first one
-            // However, since we don't have transaction support, we'll log the error
-            // and return the original error
+            rollback_contact(owner_contact_index, owner, contact, previous)?;
             Err(format!("Failed to create bidirectional contact relationship: {}", e))
         }
     }
 }
 
+/// Restore a contact slot to its previous record, or tombstone it if it was newly created
+fn rollback_contact(index: u64, owner_principal_id: String, contact_principal_id: String, previous: Option<Contact>) -> Result<(), String> {
+    remove_contact_indices(owner_principal_id, contact_principal_id)?;
+
+    let restored = match previous {
+        Some(prev) => {
+            create_contact_indices(&prev, index)?;
+            Some(prev)
+        }
+        None => get_contact_by_id(index).map(|mut c| {
+            c.status = ContactStatus::Deleted;
+            c
+        }),
+    };
+
+    if let Some(contact) = restored {
+        crate::stable_mem_storage::CONTACTS.with(|contacts| {
+            contacts.borrow_mut().set(index, &contact);
+        });
+    }
+
+    Ok(())
+}
+
 /// Get all contacts by owner principal ID
 pub fn get_contacts_by_owner(owner_principal_id: String) -> Vec<Contact> {
     let mut contacts = Vec::new();
@@ -740,6 +1201,7 @@ pub fn update_contact_status(owner_principal_id: String, contact_principal_id: S
 
 /// Update contact nickname
 pub fn update_contact_nickname(owner_principal_id: String, contact_principal_id: String, nickname: String) -> Result<Contact, String> {
+    let nickname = sanitize_and_bound(&nickname, MAX_NICKNAME_LEN)?;
     if let Some(mut contact) = get_contact_by_principal_ids(owner_principal_id.clone(), contact_principal_id.clone()) {
         contact.nickname = Some(nickname);
         contact.updated_at = ic_cdk::api::time();
@@ -802,6 +1264,91 @@ pub fn get_total_contacts_by_owner(owner_principal_id: String) -> u64 {
     get_contacts_by_owner(owner_principal_id).len() as u64
 }
 
+/// Add an agent to the owner's contacts. Agent contacts use "agent:<index>" as their
+/// contact principal so they never collide with a user contact.
+pub fn create_agent_contact(owner_principal_id: String, agent_index: u64, nickname: Option<String>) -> Result<u64, String> {
+    let agent = crate::agent_asset_types::get_agent_item(agent_index).ok_or("Agent not found")?;
+    let nickname = nickname.map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+
+    upsert_contact(Contact {
+        id: 0, // Will be set by storage
+        owner_principal_id,
+        contact_principal_id: agent_contact_principal_id(agent_index),
+        name: agent.name,
+        nickname,
+        contact_type: ContactType::Agent,
+        status: ContactStatus::Active,
+        avatar: agent.image_url,
+        devices: Vec::new(),
+        is_online: false,
+        created_at: 0,
+        updated_at: 0,
+        metadata: None,
+        agent_index: Some(agent_index),
+    })
+}
+
+/// Get the owner's contacts that link to agents
+pub fn get_agent_contacts_by_owner(owner_principal_id: String) -> Vec<Contact> {
+    get_contacts_by_owner(owner_principal_id)
+        .into_iter()
+        .filter(|contact| contact.contact_type == ContactType::Agent)
+        .collect()
+}
+
+fn agent_contact_principal_id(agent_index: u64) -> String {
+    format!("agent:{}", agent_index)
+}
+
+/// Refresh contacts linked to an agent after it changes: `Some` copies the agent's name and
+/// image, `None` (agent deleted) marks them deleted. Returns the number of contacts touched.
+pub fn sync_agent_contacts(agent_index: u64, agent: Option<&crate::agent_asset_types::AgentItem>) -> u64 {
+    let contact_ids: Vec<u64> = crate::stable_mem_storage::AGENT_CONTACT_INDEX.with(|idx| {
+        let start = AgentContactKey { agent_index, contact_id: 0 };
+        let end = AgentContactKey { agent_index, contact_id: u64::MAX };
+        idx.borrow().range(start..=end).map(|(key, _)| key.contact_id).collect()
+    });
+    let linked: Vec<(u64, Contact)> = contact_ids.into_iter()
+        .filter_map(|id| get_contact_by_id(id).map(|contact| (id, contact)))
+        .filter(|(_, contact)| contact.contact_type == ContactType::Agent && contact.agent_index == Some(agent_index))
+        .collect();
+
+    let now = time();
+    let mut touched = 0;
+    for (index, mut contact) in linked {
+        match agent {
+            Some(agent) => {
+                let deleted = contact.status == ContactStatus::Deleted;
+                if !deleted && contact.name == agent.name && contact.avatar == agent.image_url {
+                    continue;
+                }
+                if deleted {
+                    contact.status = ContactStatus::Active;
+                }
+                contact.name = agent.name.clone();
+                contact.avatar = agent.image_url.clone();
+                // Re-key the name index while the stored record still carries the old name
+                if let Err(e) = update_contact_indices(&contact, index) {
+                    ic_cdk::println!("Failed to update indices of agent contact {}: {}", index, e);
+                    continue;
+                }
+            }
+            None => {
+                if contact.status == ContactStatus::Deleted {
+                    continue;
+                }
+                contact.status = ContactStatus::Deleted;
+            }
+        }
+        contact.updated_at = now;
+        crate::stable_mem_storage::CONTACTS.with(|contacts| {
+            contacts.borrow_mut().set(index, &contact);
+        });
+        touched += 1;
+    }
+    touched
+}
+
 // Contact index management helper functions
 fn create_contact_indices(contact: &Contact, index: u64) -> Result<(), String> {
     // Create owner-contact index
@@ -821,6 +1368,12 @@ fn create_contact_indices(contact: &Contact, index: u64) -> Result<(), String> {
             name: contact.name.clone()
         }, index);
     });
+
+    if let Some(agent_index) = contact.agent_index {
+        crate::stable_mem_storage::AGENT_CONTACT_INDEX.with(|idx| {
+            idx.borrow_mut().insert(AgentContactKey { agent_index, contact_id: index }, ());
+        });
+    }
     
     Ok(())
 }
@@ -834,7 +1387,13 @@ fn update_contact_indices(contact: &Contact, index: u64) -> Result<(), String> {
 }
 
 fn remove_contact_indices(owner_principal_id: String, contact_principal_id: String) -> Result<(), String> {
-    if let Some(contact) = get_contact_by_principal_ids(owner_principal_id.clone(), contact_principal_id.clone()) {
+    let contact_index = crate::stable_mem_storage::CONTACT_OWNER_INDEX.with(|idx| {
+        idx.borrow().get(&ContactOwnerKey {
+            owner_principal_id: owner_principal_id.clone(),
+            contact_principal_id: contact_principal_id.clone()
+        })
+    });
+    if let Some((index, contact)) = contact_index.and_then(|index| get_contact_by_id(index).map(|contact| (index, contact))) {
         // Remove from owner-contact index
         crate::stable_mem_storage::CONTACT_OWNER_INDEX.with(|idx| {
             let mut idx = idx.borrow_mut();
@@ -852,11 +1411,44 @@ fn remove_contact_indices(owner_principal_id: String, contact_principal_id: Stri
                 name: contact.name
             });
         });
+
+        if let Some(agent_index) = contact.agent_index {
+            crate::stable_mem_storage::AGENT_CONTACT_INDEX.with(|idx| {
+                idx.borrow_mut().remove(&AgentContactKey { agent_index, contact_id: index });
+            });
+        }
     }
     
     Ok(())
 }
 
+/// Fill the agent contact index from CONTACTS when it is empty, e.g. on the first upgrade
+/// after it was introduced. Returns the number of entries added.
+pub fn backfill_agent_contact_index() -> u64 {
+    if crate::stable_mem_storage::AGENT_CONTACT_INDEX.with(|idx| !idx.borrow().is_empty()) {
+        return 0;
+    }
+    let total = crate::stable_mem_storage::CONTACTS.with(|contacts| contacts.borrow().len());
+    let mut added = 0u64;
+    for index in 0..total {
+        let Some(contact) = get_contact_by_id(index) else { continue };
+        // Contacts removed with delete_contact have no owner index entry and stay unlinked
+        let live = crate::stable_mem_storage::CONTACT_OWNER_INDEX.with(|idx| {
+            idx.borrow().get(&ContactOwnerKey {
+                owner_principal_id: contact.owner_principal_id.clone(),
+                contact_principal_id: contact.contact_principal_id.clone()
+            }) == Some(index)
+        });
+        if let (Some(agent_index), true) = (contact.agent_index, live) {
+            crate::stable_mem_storage::AGENT_CONTACT_INDEX.with(|idx| {
+                idx.borrow_mut().insert(AgentContactKey { agent_index, contact_id: index }, ());
+            });
+            added += 1;
+        }
+    }
+    added
+}
+
 // ==== Social Chat System ====
 
 /// Message content mode for different data types
@@ -889,6 +1481,14 @@ pub struct ChatMessage {
     pub content: String,        // Message content (base64 for non-text modes, JSON for PixelArt)
     pub mode: MessageMode,      // Content type
     pub timestamp: u64,         // Message timestamp
+    pub ttl_ns: Option<u64>,    // Lifetime after timestamp; None keeps the message forever
+}
+
+impl ChatMessage {
+    /// Whether the message's TTL has elapsed at `now`
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.ttl_ns.is_some_and(|ttl| now > self.timestamp.saturating_add(ttl))
+    }
 }
 
 /// Social pair key for chat between two users
@@ -915,27 +1515,138 @@ pub struct NotificationItem {
     pub timestamp: u64,            // Notification timestamp
 }
 
-/// Notification queue key
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct NotificationKey {
-    pub notification_id: String,   // Unique notification identifier
+/// What a notification is about; receivers can opt out per category
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotificationCategory {
+    Chat,
+    System,
+    Grant,
+    Device,
 }
 
-// Implement Storable traits
-impl ic_stable_structures::Storable for SocialPairKey {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        Cow::Owned(Encode!(&self.pair_key).unwrap())
+/// A receiver's notification opt-outs. Quiet hours are UTC hours (0-23); the window
+/// runs from start up to end and may wrap past midnight.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NotificationPreferences {
+    pub principal_id: String,
+    pub receive_chat: bool,
+    pub receive_system: bool,
+    pub receive_grant: bool,
+    pub receive_device: bool,
+    pub quiet_hours_start: Option<u32>,
+    pub quiet_hours_end: Option<u32>,
+}
+
+impl NotificationPreferences {
+    /// Preferences of a principal who never set any: everything on, no quiet hours
+    pub fn default_for(principal_id: String) -> Self {
+        Self {
+            principal_id,
+            receive_chat: true,
+            receive_system: true,
+            receive_grant: true,
+            receive_device: true,
+            quiet_hours_start: None,
+            quiet_hours_end: None,
+        }
     }
 
-    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        let pair_key = Decode!(bytes.as_ref(), String).unwrap();
-        Self { pair_key }
+    pub fn receives(&self, category: NotificationCategory) -> bool {
+        match category {
+            NotificationCategory::Chat => self.receive_chat,
+            NotificationCategory::System => self.receive_system,
+            NotificationCategory::Grant => self.receive_grant,
+            NotificationCategory::Device => self.receive_device,
+        }
     }
 
-    const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false };
-}
-
-impl ic_stable_structures::Storable for ChatHistory {
+    pub fn in_quiet_hours(&self, now_ns: u64) -> bool {
+        let (Some(start), Some(end)) = (self.quiet_hours_start, self.quiet_hours_end) else {
+            return false;
+        };
+        let hour = ((now_ns / 3_600_000_000_000) % 24) as u32;
+        if start <= end {
+            hour >= start && hour < end
+        } else {
+            hour >= start || hour < end
+        }
+    }
+}
+
+/// Notification queue key
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NotificationKey {
+    pub notification_id: String,   // Unique notification identifier
+}
+
+/// Delivery/read state of a conversation from one participant's side
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReadReceipt {
+    pub delivered_index: Option<u64>,  // Last message index delivered to the owner
+    pub read_index: Option<u64>,       // Last message index read by the owner
+}
+
+/// Read receipt key: the reading party and the other side of the conversation
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ReadReceiptKey {
+    pub owner_principal_id: String,
+    pub other_principal_id: String,
+}
+
+/// Group chat with a generated key; messages are stored in CHAT_HISTORIES under the group key
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct GroupChat {
+    pub group_key: String,             // Generated group identifier
+    pub name: String,                  // Group display name
+    pub owner_principal_id: String,    // Creator, allowed to manage membership
+    pub members: Vec<String>,          // Member principal IDs (owner included)
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// Multi-party message thread; its messages are stored one per entry in THREAD_MESSAGES
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ChatThread {
+    pub thread_id: String,             // Generated thread identifier
+    pub participants: Vec<String>,     // Participant principal IDs (creator included)
+    pub name: Option<String>,          // Optional display name
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+// Keyed by thread first so one thread's messages form a contiguous range in posting order
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ThreadMessageKey {
+    pub thread_id: String,
+    pub seq: u64,
+}
+
+/// Registered set of custom emoji/stickers usable in `MessageMode::Emoji` messages
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct EmojiPack {
+    pub id: String,                    // Generated pack identifier
+    pub name: String,                  // Pack display name
+    pub owner: String,                 // Creator principal ID, the only one allowed to delete it
+    pub emoji_ids: Vec<String>,        // Emoji/sticker identifiers in display order
+    pub is_public: bool,               // Listed in list_public_emoji_packs when true
+    pub created_at: u64,
+}
+
+// Implement Storable traits
+impl ic_stable_structures::Storable for SocialPairKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.pair_key).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let pair_key = Decode!(bytes.as_ref(), String).unwrap();
+        Self { pair_key }
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false };
+}
+
+impl ic_stable_structures::Storable for ChatHistory {
     fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
@@ -960,6 +1671,92 @@ impl ic_stable_structures::Storable for NotificationKey {
     const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false };
 }
 
+impl ic_stable_structures::Storable for ReadReceiptKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.owner_principal_id, &self.other_principal_id).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let (owner_principal_id, other_principal_id) = Decode!(bytes.as_ref(), String, String).unwrap();
+        Self { owner_principal_id, other_principal_id }
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 2048, is_fixed_size: false };
+}
+
+impl ic_stable_structures::Storable for ReadReceipt {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 256, is_fixed_size: false };
+}
+
+impl ic_stable_structures::Storable for GroupChat {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 64 * 1024, is_fixed_size: false }; // 64KB for group metadata
+}
+
+impl ic_stable_structures::Storable for ChatThread {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 10 * 1024 * 1024, is_fixed_size: false }; // 10MB, same as chat history
+}
+
+impl ic_stable_structures::Storable for ThreadMessageKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.thread_id, &self.seq).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let (thread_id, seq) = Decode!(bytes.as_ref(), String, u64).unwrap();
+        Self { thread_id, seq }
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 256, is_fixed_size: false };
+}
+
+impl ic_stable_structures::Storable for ChatMessage {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl ic_stable_structures::Storable for EmojiPack {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 64 * 1024, is_fixed_size: false };
+}
+
 impl ic_stable_structures::Storable for NotificationItem {
     fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
         Cow::Owned(Encode!(self).unwrap())
@@ -972,8 +1769,23 @@ impl ic_stable_structures::Storable for NotificationItem {
     const BOUND: Bound = Bound::Bounded { max_size: 2 * 1024, is_fixed_size: false }; // 2KB for notifications
 }
 
+impl ic_stable_structures::Storable for NotificationPreferences {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 512, is_fixed_size: false };
+}
+
 // Social chat system functions
 
+const DEFAULT_RECENT_MESSAGES: usize = 5;
+const MAX_RECENT_MESSAGES: usize = 100;
+
 /// Generate deterministic social pair key from two principal IDs
 /// This algorithm ensures the same key regardless of sender/receiver order
 pub fn generate_social_pair_key(principal1: String, principal2: String) -> String {
@@ -996,15 +1808,17 @@ pub fn add_chat_message(
     receiver_principal: String,
     content: String,
     mode: MessageMode,
+    ttl_ns: Option<u64>,
 ) -> Result<u64, String> {
     let pair_key = generate_social_pair_key(sender_principal.clone(), receiver_principal.clone());
-    let current_time = ic_cdk::api::time();
+    let current_time = time();
     
     let new_message = ChatMessage {
         send_by: sender_principal,
         content,
         mode,
         timestamp: current_time,
+        ttl_ns,
     };
     
     // Get or create chat history
@@ -1031,20 +1845,26 @@ pub fn add_chat_message(
     });
     
     // Push notification to queue
-    push_notification(pair_key, receiver_principal, message_index as u64)?;
+    push_notification(pair_key, receiver_principal, message_index as u64, NotificationCategory::Chat)?;
     
     Ok(message_index as u64)
 }
 
 /// Get recent chat messages (last 5 messages)
 pub fn get_recent_chat_messages(principal1: String, principal2: String) -> Vec<ChatMessage> {
+    get_recent_chat_messages_n(principal1, principal2, DEFAULT_RECENT_MESSAGES)
+}
+
+/// Get the last `n` chat messages, capped at MAX_RECENT_MESSAGES
+pub fn get_recent_chat_messages_n(principal1: String, principal2: String, n: usize) -> Vec<ChatMessage> {
     let pair_key = generate_social_pair_key(principal1, principal2);
+    let n = n.min(MAX_RECENT_MESSAGES);
     
     crate::stable_mem_storage::CHAT_HISTORIES.with(|histories| {
         let histories = histories.borrow();
         if let Some(chat_history) = histories.get(&SocialPairKey { pair_key }) {
             let messages = &chat_history.messages;
-            let start_index = if messages.len() > 5 { messages.len() - 5 } else { 0 };
+            let start_index = messages.len().saturating_sub(n);
             messages[start_index..].to_vec()
         } else {
             Vec::new()
@@ -1081,6 +1901,67 @@ pub fn get_chat_messages_paginated(
     })
 }
 
+/// Get paginated chat messages, skipping messages whose TTL has elapsed but not yet been cleaned up
+pub fn get_chat_messages_excluding_expired(
+    principal1: String,
+    principal2: String,
+    offset: u64,
+    limit: usize,
+) -> Vec<ChatMessage> {
+    let pair_key = generate_social_pair_key(principal1, principal2);
+    let now = time();
+
+    crate::stable_mem_storage::CHAT_HISTORIES.with(|histories| {
+        histories.borrow()
+            .get(&SocialPairKey { pair_key })
+            .map(|chat_history| chat_history.messages.into_iter()
+                .filter(|message| !message.is_expired(now))
+                .skip(offset as usize)
+                .take(limit)
+                .collect())
+            .unwrap_or_default()
+    })
+}
+
+/// Chat histories scanned per cleanup sweep
+pub const CHAT_CLEANUP_BATCH: usize = 100;
+
+thread_local! {
+    // Last history visited by the cleanup sweep; heap only, so an upgrade restarts from the first key
+    static CHAT_CLEANUP_CURSOR: std::cell::RefCell<Option<SocialPairKey>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Blank the content of expired messages in up to `max_histories` chat histories, resuming
+/// after the history the previous sweep stopped at, and return how many were blanked.
+/// Expired messages stay in place as empty tombstones so message indices held by
+/// notifications and read receipts keep pointing at the same message.
+pub fn cleanup_expired_messages(now: u64, max_histories: usize) -> u64 {
+    let cursor = CHAT_CLEANUP_CURSOR.with(|cursor| cursor.borrow().clone());
+    crate::stable_mem_storage::CHAT_HISTORIES.with(|histories| {
+        let mut histories = histories.borrow_mut();
+        let batch: Vec<(SocialPairKey, ChatHistory)> = match &cursor {
+            Some(after) => histories.range((std::ops::Bound::Excluded(after.clone()), std::ops::Bound::Unbounded)).take(max_histories).collect(),
+            None => histories.iter().take(max_histories).collect(),
+        };
+        let next_cursor = if batch.len() < max_histories { None } else { batch.last().map(|(key, _)| key.clone()) };
+
+        let mut removed = 0u64;
+        for (key, mut history) in batch {
+            let mut changed = false;
+            for message in history.messages.iter_mut().filter(|m| m.is_expired(now) && !m.content.is_empty()) {
+                message.content.clear();
+                changed = true;
+                removed += 1;
+            }
+            if changed {
+                histories.insert(key, history);
+            }
+        }
+        CHAT_CLEANUP_CURSOR.with(|cursor| *cursor.borrow_mut() = next_cursor);
+        removed
+    })
+}
+
 /// Get total message count for a social pair
 pub fn get_chat_message_count(principal1: String, principal2: String) -> u64 {
     let pair_key = generate_social_pair_key(principal1, principal2);
@@ -1095,15 +1976,435 @@ pub fn get_chat_message_count(principal1: String, principal2: String) -> u64 {
     })
 }
 
+// Read receipt functions
+
+/// Look up the sender of a message in a social pair's history
+fn get_pair_sender(pair_key: &str, message_id: u64) -> Option<String> {
+    crate::stable_mem_storage::CHAT_HISTORIES.with(|histories| {
+        histories.borrow()
+            .get(&SocialPairKey { pair_key: pair_key.to_string() })
+            .and_then(|history| history.messages.get(message_id as usize).map(|m| m.send_by.clone()))
+    })
+}
+
+/// Move the owner's delivered/read markers forward; markers never move backwards
+fn advance_read_receipt(owner: &str, other: &str, delivered: Option<u64>, read: Option<u64>) -> ReadReceipt {
+    let key = ReadReceiptKey {
+        owner_principal_id: owner.to_string(),
+        other_principal_id: other.to_string(),
+    };
+
+    crate::stable_mem_storage::READ_RECEIPTS.with(|receipts| {
+        let mut receipts = receipts.borrow_mut();
+        let mut receipt = receipts.get(&key).unwrap_or_default();
+        receipt.delivered_index = receipt.delivered_index.max(delivered).max(read);
+        receipt.read_index = receipt.read_index.max(read);
+        receipts.insert(key, receipt.clone());
+        receipt
+    })
+}
+
+/// Mark every message in the conversation with `other` as read by `reader`
+pub fn mark_chat_read(reader_principal: String, other_principal: String) -> Result<ReadReceipt, String> {
+    let count = get_chat_message_count(reader_principal.clone(), other_principal.clone());
+    if count == 0 {
+        return Err("No chat history found".to_string());
+    }
+    Ok(advance_read_receipt(&reader_principal, &other_principal, None, Some(count - 1)))
+}
+
+/// Get the owner's read receipt for the conversation with `other`
+pub fn get_read_receipt(owner_principal: String, other_principal: String) -> ReadReceipt {
+    crate::stable_mem_storage::READ_RECEIPTS.with(|receipts| {
+        receipts.borrow()
+            .get(&ReadReceiptKey { owner_principal_id: owner_principal, other_principal_id: other_principal })
+            .unwrap_or_default()
+    })
+}
+
+/// Get the index of the last message the owner has read (None if nothing has been read yet)
+pub fn get_last_read_index(owner_principal: String, other_principal: String) -> Option<u64> {
+    get_read_receipt(owner_principal, other_principal).read_index
+}
+
+// Group chat functions
+
+const MAX_GROUP_MEMBERS: usize = 100;
+
+/// Generate a group key from the creator, group name and creation time
+pub fn generate_group_key(owner_principal: &str, name: &str, created_at: u64) -> String {
+    let mut hasher = DefaultHasher::new();
+    format!("{}:{}:{}", owner_principal, name, created_at).hash(&mut hasher);
+    format!("group_{}", hasher.finish())
+}
+
+/// Create a group chat; the owner is always a member
+pub fn create_group_chat(owner_principal: String, name: String, members: Vec<String>) -> Result<String, String> {
+    let name = sanitize_and_bound(&name, MAX_NAME_LEN)?;
+    if name.is_empty() {
+        return Err("Group name cannot be empty".to_string());
+    }
+
+    let mut all_members = vec![owner_principal.clone()];
+    for member in members {
+        if !all_members.contains(&member) {
+            all_members.push(member);
+        }
+    }
+    if all_members.len() > MAX_GROUP_MEMBERS {
+        return Err(format!("Group cannot have more than {} members", MAX_GROUP_MEMBERS));
+    }
+
+    let current_time = time();
+    let group_key = generate_group_key(&owner_principal, &name, current_time);
+
+    crate::stable_mem_storage::GROUP_CHATS.with(|groups| {
+        let mut groups = groups.borrow_mut();
+        if groups.contains_key(&group_key) {
+            return Err("Group chat already exists".to_string());
+        }
+        groups.insert(group_key.clone(), GroupChat {
+            group_key: group_key.clone(),
+            name,
+            owner_principal_id: owner_principal,
+            members: all_members,
+            created_at: current_time,
+            updated_at: current_time,
+        });
+        Ok(group_key)
+    })
+}
+
+/// Get a group chat by key
+pub fn get_group_chat(group_key: String) -> Option<GroupChat> {
+    crate::stable_mem_storage::GROUP_CHATS.with(|groups| groups.borrow().get(&group_key))
+}
+
+fn save_group_chat(mut group: GroupChat) -> GroupChat {
+    group.updated_at = time();
+    crate::stable_mem_storage::GROUP_CHATS.with(|groups| {
+        groups.borrow_mut().insert(group.group_key.clone(), group.clone());
+    });
+    group
+}
+
+/// Add a member to a group (owner only)
+pub fn add_group_member(group_key: String, caller_principal: String, member_principal: String) -> Result<GroupChat, String> {
+    let mut group = get_group_chat(group_key).ok_or("Group chat not found")?;
+    if group.owner_principal_id != caller_principal {
+        return Err("Only the group owner can manage members".to_string());
+    }
+    if group.members.contains(&member_principal) {
+        return Err("User is already a group member".to_string());
+    }
+    if group.members.len() >= MAX_GROUP_MEMBERS {
+        return Err(format!("Group cannot have more than {} members", MAX_GROUP_MEMBERS));
+    }
+    group.members.push(member_principal);
+    Ok(save_group_chat(group))
+}
+
+/// Remove a member from a group (owner, or the member leaving on their own)
+pub fn remove_group_member(group_key: String, caller_principal: String, member_principal: String) -> Result<GroupChat, String> {
+    let mut group = get_group_chat(group_key).ok_or("Group chat not found")?;
+    if group.owner_principal_id != caller_principal && caller_principal != member_principal {
+        return Err("Only the group owner can manage members".to_string());
+    }
+    if member_principal == group.owner_principal_id {
+        return Err("Group owner cannot be removed".to_string());
+    }
+    let before = group.members.len();
+    group.members.retain(|m| m != &member_principal);
+    if group.members.len() == before {
+        return Err("User is not a group member".to_string());
+    }
+    Ok(save_group_chat(group))
+}
+
+/// Send a message to a group; every member except the sender gets a notification
+pub fn send_group_message(
+    group_key: String,
+    sender_principal: String,
+    content: String,
+    mode: MessageMode,
+) -> Result<u64, String> {
+    let group = get_group_chat(group_key.clone()).ok_or("Group chat not found")?;
+    if !group.members.contains(&sender_principal) {
+        return Err("Sender is not a group member".to_string());
+    }
+
+    let current_time = time();
+    let new_message = ChatMessage {
+        send_by: sender_principal.clone(),
+        content,
+        mode,
+        timestamp: current_time,
+        ttl_ns: None,
+    };
+
+    let message_index = crate::stable_mem_storage::CHAT_HISTORIES.with(|histories| {
+        let mut histories = histories.borrow_mut();
+        let key = SocialPairKey { pair_key: group_key.clone() };
+        let mut chat_history = histories.get(&key).unwrap_or_else(|| ChatHistory {
+            social_pair_key: group_key.clone(),
+            messages: Vec::new(),
+            created_at: current_time,
+            updated_at: current_time,
+        });
+        chat_history.messages.push(new_message);
+        chat_history.updated_at = current_time;
+        let index = chat_history.messages.len() - 1;
+        histories.insert(key, chat_history);
+        index as u64
+    });
+
+    for member in group.members.iter().filter(|m| **m != sender_principal) {
+        push_notification(group_key.clone(), member.clone(), message_index, NotificationCategory::Chat)?;
+    }
+
+    Ok(message_index)
+}
+
+/// Get paginated messages of a group chat
+pub fn get_group_messages_paginated(group_key: String, offset: u64, limit: usize) -> Vec<ChatMessage> {
+    crate::stable_mem_storage::CHAT_HISTORIES.with(|histories| {
+        let histories = histories.borrow();
+        if let Some(chat_history) = histories.get(&SocialPairKey { pair_key: group_key }) {
+            chat_history.messages.iter()
+                .skip(offset as usize)
+                .take(limit)
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        }
+    })
+}
+
+/// Create a message thread; the creator is always a participant
+pub fn create_chat_thread(creator: String, participants: Vec<String>, name: Option<String>) -> Result<String, String> {
+    let name = match name {
+        Some(name) => Some(sanitize_and_bound(&name, MAX_NAME_LEN)?).filter(|n| !n.is_empty()),
+        None => None,
+    };
+
+    let mut all_participants = vec![creator.clone()];
+    for participant in participants {
+        if !participant.is_empty() && !all_participants.contains(&participant) {
+            all_participants.push(participant);
+        }
+    }
+    if all_participants.len() < 2 {
+        return Err("Thread needs at least one participant besides the creator".to_string());
+    }
+    if all_participants.len() > MAX_GROUP_MEMBERS {
+        return Err(format!("Thread cannot have more than {} participants", MAX_GROUP_MEMBERS));
+    }
+
+    let current_time = time();
+    let mut hasher = DefaultHasher::new();
+    format!("{}:{}:{:?}:{}", creator, all_participants.join(","), name, current_time).hash(&mut hasher);
+    let thread_id = format!("thread_{}", hasher.finish());
+
+    crate::stable_mem_storage::CHAT_THREADS.with(|threads| {
+        let mut threads = threads.borrow_mut();
+        if threads.contains_key(&thread_id) {
+            return Err("Chat thread already exists".to_string());
+        }
+        threads.insert(thread_id.clone(), ChatThread {
+            thread_id: thread_id.clone(),
+            participants: all_participants,
+            name,
+            created_at: current_time,
+            updated_at: current_time,
+        });
+        Ok(thread_id)
+    })
+}
+
+/// Post a message to a thread (participants only); other participants get a notification
+pub fn add_thread_message(thread_id: String, sender: String, content: String, mode: MessageMode) -> Result<u64, String> {
+    let current_time = time();
+    let participants = crate::stable_mem_storage::CHAT_THREADS.with(|threads| {
+        let mut threads = threads.borrow_mut();
+        let mut thread = threads.get(&thread_id).ok_or("Chat thread not found")?;
+        if !thread.participants.contains(&sender) {
+            return Err("Sender is not a thread participant".to_string());
+        }
+        thread.updated_at = current_time;
+        let participants = thread.participants.clone();
+        threads.insert(thread_id.clone(), thread);
+        Ok::<_, String>(participants)
+    })?;
+
+    let message_index = crate::stable_mem_storage::THREAD_MESSAGES.with(|messages| {
+        let mut messages = messages.borrow_mut();
+        let seq = messages.range(thread_message_range(&thread_id, 0)).next_back().map_or(0, |(key, _)| key.seq + 1);
+        messages.insert(ThreadMessageKey { thread_id: thread_id.clone(), seq }, ChatMessage {
+            send_by: sender.clone(),
+            content,
+            mode,
+            timestamp: current_time,
+            ttl_ns: None,
+        });
+        seq
+    });
+
+    for participant in participants.iter().filter(|p| **p != sender) {
+        push_notification(thread_id.clone(), participant.clone(), message_index, NotificationCategory::Chat)?;
+    }
+
+    Ok(message_index)
+}
+
+// Keys of a thread's messages from sequence number `from` on
+fn thread_message_range(thread_id: &str, from: u64) -> std::ops::RangeInclusive<ThreadMessageKey> {
+    ThreadMessageKey { thread_id: thread_id.to_string(), seq: from }..=ThreadMessageKey { thread_id: thread_id.to_string(), seq: u64::MAX }
+}
+
+/// Get paginated messages of a thread in chronological order
+pub fn get_thread_messages_paginated(thread_id: String, offset: u64, limit: u64) -> Vec<ChatMessage> {
+    crate::stable_mem_storage::THREAD_MESSAGES.with(|messages| {
+        messages.borrow()
+            .range(thread_message_range(&thread_id, offset))
+            .take(limit as usize)
+            .map(|(_, message)| message)
+            .collect()
+    })
+}
+
+// Emoji pack functions
+
+const MAX_EMOJIS_PER_PACK: usize = 200;
+const MAX_EMOJI_ID_LEN: usize = 128;
+
+/// Register an emoji pack owned by `owner`; emoji IDs must be non-empty and unique within the pack
+pub fn create_emoji_pack(owner: String, name: String, emoji_ids: Vec<String>, is_public: bool) -> Result<String, String> {
+    let name = sanitize_and_bound(&name, MAX_NAME_LEN)?;
+    if name.is_empty() {
+        return Err("Emoji pack name cannot be empty".to_string());
+    }
+    if emoji_ids.is_empty() {
+        return Err("Emoji pack must contain at least one emoji".to_string());
+    }
+    if emoji_ids.len() > MAX_EMOJIS_PER_PACK {
+        return Err(format!("Emoji pack cannot have more than {} emoji", MAX_EMOJIS_PER_PACK));
+    }
+
+    let mut validated: Vec<String> = Vec::with_capacity(emoji_ids.len());
+    for emoji_id in emoji_ids {
+        let emoji_id = emoji_id.trim().to_string();
+        if emoji_id.is_empty() {
+            return Err("Emoji IDs cannot be empty".to_string());
+        }
+        if emoji_id.len() > MAX_EMOJI_ID_LEN {
+            return Err(format!("Emoji ID exceeds maximum length of {} bytes", MAX_EMOJI_ID_LEN));
+        }
+        if validated.contains(&emoji_id) {
+            return Err(format!("Duplicate emoji ID '{}'", emoji_id));
+        }
+        validated.push(emoji_id);
+    }
+
+    let current_time = time();
+    let mut hasher = DefaultHasher::new();
+    format!("{}:{}:{}", owner, name, current_time).hash(&mut hasher);
+    let pack_id = format!("emoji_{}", hasher.finish());
+
+    crate::stable_mem_storage::EMOJI_PACKS.with(|packs| {
+        let mut packs = packs.borrow_mut();
+        if packs.contains_key(&pack_id) {
+            return Err("Emoji pack already exists".to_string());
+        }
+        packs.insert(pack_id.clone(), EmojiPack {
+            id: pack_id.clone(),
+            name,
+            owner,
+            emoji_ids: validated,
+            is_public,
+            created_at: current_time,
+        });
+        Ok(pack_id)
+    })
+}
+
+/// Get an emoji pack by ID
+pub fn get_emoji_pack(id: String) -> Option<EmojiPack> {
+    crate::stable_mem_storage::EMOJI_PACKS.with(|packs| packs.borrow().get(&id))
+}
+
+/// Get paginated public emoji packs, ordered by pack ID
+pub fn list_public_emoji_packs(offset: u64, limit: usize) -> Vec<EmojiPack> {
+    crate::stable_mem_storage::EMOJI_PACKS.with(|packs| {
+        packs.borrow()
+            .iter()
+            .filter(|(_, pack)| pack.is_public)
+            .skip(offset as usize)
+            .take(limit)
+            .map(|(_, pack)| pack)
+            .collect()
+    })
+}
+
+/// Delete an emoji pack (owner only)
+pub fn delete_emoji_pack(id: String, caller_principal: String) -> Result<(), String> {
+    crate::stable_mem_storage::EMOJI_PACKS.with(|packs| {
+        let mut packs = packs.borrow_mut();
+        let pack = packs.get(&id).ok_or("Emoji pack not found")?;
+        if pack.owner != caller_principal {
+            return Err("Only the pack owner can delete it".to_string());
+        }
+        packs.remove(&id);
+        Ok(())
+    })
+}
+
+// Notification preference functions
+
+/// Stored preferences of a principal, or the all-enabled defaults
+pub fn get_notification_preferences(principal_id: String) -> NotificationPreferences {
+    crate::stable_mem_storage::NOTIFICATION_PREFS.with(|prefs| {
+        prefs.borrow().get(&principal_id)
+    }).unwrap_or_else(|| NotificationPreferences::default_for(principal_id))
+}
+
+/// Save a principal's preferences; quiet hours must be set together and lie in 0-23
+pub fn set_notification_preferences(principal_id: String, mut preferences: NotificationPreferences) -> Result<(), String> {
+    match (preferences.quiet_hours_start, preferences.quiet_hours_end) {
+        (None, None) => {}
+        (Some(start), Some(end)) if start < 24 && end < 24 => {}
+        (Some(_), Some(_)) => return Err("Quiet hours must be between 0 and 23".to_string()),
+        _ => return Err("Quiet hours need both a start and an end".to_string()),
+    }
+    preferences.principal_id = principal_id.clone();
+    crate::stable_mem_storage::NOTIFICATION_PREFS.with(|prefs| {
+        prefs.borrow_mut().insert(principal_id, preferences);
+    });
+    Ok(())
+}
+
+/// Drop a principal's preferences so the defaults apply again; returns whether any were stored
+pub fn delete_notification_preferences(principal_id: String) -> bool {
+    crate::stable_mem_storage::NOTIFICATION_PREFS.with(|prefs| {
+        prefs.borrow_mut().remove(&principal_id).is_some()
+    })
+}
+
 // Notification queue functions
 
-/// Push notification to queue
+/// Push notification to queue. Skipped (still Ok) when the receiver opted out of the
+/// category or is inside their quiet hours.
 pub fn push_notification(
     social_pair_key: String,
     receiver_principal: String,
     message_id: u64,
+    category: NotificationCategory,
 ) -> Result<(), String> {
-    let current_time = ic_cdk::api::time();
+    let current_time = time();
+    let preferences = get_notification_preferences(receiver_principal.clone());
+    if !preferences.receives(category) || preferences.in_quiet_hours(current_time) {
+        return Ok(());
+    }
     let notification_id = format!("{}:{}:{}", social_pair_key, receiver_principal, current_time);
     
     let notification = NotificationItem {
@@ -1123,7 +2424,7 @@ pub fn push_notification(
 
 /// Pop notification from queue for specific receiver
 pub fn pop_notification(receiver_principal: String) -> Option<NotificationItem> {
-    crate::stable_mem_storage::NOTIFICATION_QUEUE.with(|queue| {
+    let popped = crate::stable_mem_storage::NOTIFICATION_QUEUE.with(|queue| {
         let mut queue = queue.borrow_mut();
         
         // Find the first notification for this receiver
@@ -1144,7 +2445,47 @@ pub fn pop_notification(receiver_principal: String) -> Option<NotificationItem>
         }
         
         result
-    })
+    });
+
+    if let Some(notification) = popped.as_ref() {
+        mark_notification_delivered(notification);
+    }
+
+    popped
+}
+
+// Popping a notification delivers the message to the receiver (1:1 chats only)
+fn mark_notification_delivered(notification: &NotificationItem) {
+    if !notification.social_pair_key.starts_with("social_pair_") {
+        return;
+    }
+    if let Some(sender) = get_pair_sender(&notification.social_pair_key, notification.message_id) {
+        advance_read_receipt(&notification.to_who, &sender, Some(notification.message_id), None);
+    }
+}
+
+/// Pop up to `max` notifications for a receiver, oldest first, in a single queue update
+pub fn drain_notifications(receiver_principal: String, max: usize) -> Vec<NotificationItem> {
+    let drained: Vec<NotificationItem> = crate::stable_mem_storage::NOTIFICATION_QUEUE.with(|queue| {
+        let mut queue = queue.borrow_mut();
+
+        let mut pending: Vec<(NotificationKey, NotificationItem)> = queue.iter()
+            .filter(|(_, notification)| notification.to_who == receiver_principal)
+            .collect();
+        pending.sort_by(|(a_key, a), (b_key, b)| a.timestamp.cmp(&b.timestamp).then_with(|| a_key.cmp(b_key)));
+        pending.truncate(max);
+
+        for (key, _) in &pending {
+            queue.remove(key);
+        }
+        pending.into_iter().map(|(_, notification)| notification).collect()
+    });
+
+    for notification in &drained {
+        mark_notification_delivered(notification);
+    }
+
+    drained
 }
 
 /// Get all notifications for a receiver (without removing them)
@@ -1208,6 +2549,7 @@ mod tests {
             created_at: time(),
             updated_at: time(),
             metadata: Some("Test metadata".to_string()),
+            deleted_at: None,
         };
 
         assert_eq!(profile.user_id, "user123");
@@ -1234,6 +2576,7 @@ mod tests {
             created_at: 0,
             updated_at: 0,
             metadata: Some("Test metadata".to_string()),
+            agent_index: None,
         };
 
         assert_eq!(contact.owner_principal_id, "owner123");
@@ -1291,6 +2634,440 @@ mod tests {
         assert_eq!(key.name, "Test Contact");
     }
 
+    fn store_test_profile(principal_id: &str) {
+        let profile = UserProfile {
+            user_id: format!("user_{}", principal_id),
+            principal_id: principal_id.to_string(),
+            name: Some(principal_id.to_string()),
+            nickname: principal_id.to_string(),
+            login_method: LoginMethod::II,
+            login_status: LoginStatus::Authenticated,
+            email: None,
+            picture: None,
+            wallet_address: None,
+            devices: vec![],
+            passwd: None,
+            created_at: 1,
+            updated_at: 1,
+            metadata: None,
+            deleted_at: None,
+        };
+        let index = USER_PROFILES.with(|p| {
+            let p = p.borrow_mut();
+            let index = p.len();
+            p.push(&profile).unwrap();
+            index
+        });
+        create_indices(&profile, index).unwrap();
+    }
+
+    fn store_test_contact(owner: &str, contact: &str) {
+        let contact = Contact {
+            id: 0,
+            owner_principal_id: owner.to_string(),
+            contact_principal_id: contact.to_string(),
+            name: contact.to_string(),
+            nickname: None,
+            contact_type: ContactType::Friend,
+            status: ContactStatus::Active,
+            avatar: None,
+            devices: vec![],
+            is_online: false,
+            created_at: 1,
+            updated_at: 1,
+            metadata: None,
+            agent_index: None,
+        };
+        let index = crate::stable_mem_storage::CONTACTS.with(|c| {
+            let c = c.borrow_mut();
+            let index = c.len();
+            c.push(&contact).unwrap();
+            index
+        });
+        create_contact_indices(&contact, index).unwrap();
+    }
+
+    #[test]
+    fn test_delete_user_profile_cascade_removes_contacts_bidirectionally() {
+        store_test_profile("alice");
+        store_test_profile("bob");
+        store_test_profile("carol");
+        store_test_contact("alice", "bob");
+        store_test_contact("bob", "alice");
+        store_test_contact("bob", "carol");
+
+        let pair_key = generate_social_pair_key("alice".to_string(), "bob".to_string());
+        crate::stable_mem_storage::NOTIFICATION_QUEUE.with(|q| {
+            q.borrow_mut().insert(
+                NotificationKey { notification_id: "n1".to_string() },
+                NotificationItem { social_pair_key: pair_key, to_who: "bob".to_string(), message_id: 0, timestamp: 1 },
+            );
+        });
+
+        assert_eq!(delete_user_profile("alice".to_string(), true), Ok(true));
+
+        assert!(get_user_profile_by_principal("alice".to_string()).is_none());
+        assert!(get_contact_by_principal_ids("alice".to_string(), "bob".to_string()).is_none());
+        assert!(get_contact_by_principal_ids("bob".to_string(), "alice".to_string()).is_none());
+        assert!(get_contact_by_principal_ids("bob".to_string(), "carol".to_string()).is_some());
+        assert!(get_contacts_by_owner("bob".to_string()).iter()
+            .filter(|c| c.contact_principal_id == "alice")
+            .all(|c| c.status == ContactStatus::Deleted));
+        assert!(get_notifications_for_receiver("bob".to_string()).is_empty());
+    }
+
+    #[test]
+    fn test_contact_pair_rolls_back_on_reverse_failure() {
+        let forward = Contact {
+            id: 0,
+            owner_principal_id: "dave".to_string(),
+            contact_principal_id: "erin".to_string(),
+            name: "erin".to_string(),
+            nickname: None,
+            contact_type: ContactType::Friend,
+            status: ContactStatus::Active,
+            avatar: None,
+            devices: vec![],
+            is_online: false,
+            created_at: 1,
+            updated_at: 1,
+            metadata: None,
+            agent_index: None,
+        };
+        let reverse = Contact {
+            owner_principal_id: "erin".to_string(),
+            contact_principal_id: "dave".to_string(),
+            name: "dave".to_string(),
+            ..forward.clone()
+        };
+
+        let mut calls = 0;
+        let result = insert_contact_pair(forward, reverse, |contact| {
+            calls += 1;
+            if calls == 2 {
+                return Err("simulated failure".to_string());
+            }
+            store_test_contact(&contact.owner_principal_id, &contact.contact_principal_id);
+            Ok(0)
+        });
+
+        assert!(result.is_err());
+        assert!(get_contact_by_principal_ids("dave".to_string(), "erin".to_string()).is_none());
+        assert!(get_contact_by_principal_ids("erin".to_string(), "dave".to_string()).is_none());
+        assert_eq!(get_contact_by_id(0).unwrap().status, ContactStatus::Deleted);
+    }
+
+    fn store_test_chat(principal1: &str, principal2: &str, senders: &[&str]) {
+        let pair_key = generate_social_pair_key(principal1.to_string(), principal2.to_string());
+        let history = ChatHistory {
+            social_pair_key: pair_key.clone(),
+            messages: senders.iter().enumerate().map(|(i, sender)| ChatMessage {
+                send_by: sender.to_string(),
+                content: format!("message {}", i),
+                mode: MessageMode::Text,
+                timestamp: i as u64,
+                ttl_ns: None,
+            }).collect(),
+            created_at: 0,
+            updated_at: 0,
+        };
+        crate::stable_mem_storage::CHAT_HISTORIES.with(|h| {
+            h.borrow_mut().insert(SocialPairKey { pair_key }, history);
+        });
+    }
+
+    #[test]
+    fn test_mark_chat_read_advances_reader_only() {
+        store_test_chat("alice", "bob", &["alice", "alice", "alice"]);
+
+        let receipt = mark_chat_read("bob".to_string(), "alice".to_string()).unwrap();
+        assert_eq!(receipt.read_index, Some(2));
+        assert_eq!(get_last_read_index("bob".to_string(), "alice".to_string()), Some(2));
+        assert_eq!(get_last_read_index("alice".to_string(), "bob".to_string()), None);
+        assert_eq!(get_read_receipt("alice".to_string(), "bob".to_string()), ReadReceipt::default());
+
+        assert!(mark_chat_read("bob".to_string(), "nobody".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_get_recent_chat_messages_n() {
+        store_test_chat("carol", "dave", &["carol"; 10]);
+
+        let recent = get_recent_chat_messages_n("carol".to_string(), "dave".to_string(), 3);
+        assert_eq!(recent.len(), 3);
+        assert_eq!(recent[0].content, "message 7");
+        assert_eq!(recent[2].content, "message 9");
+        assert_eq!(get_recent_chat_messages("dave".to_string(), "carol".to_string()).len(), 5);
+        assert_eq!(get_recent_chat_messages_n("carol".to_string(), "dave".to_string(), 1000).len(), 10);
+    }
+
+    #[test]
+    fn test_cleanup_expired_messages_prunes_only_elapsed_ttls() {
+        store_test_chat("erin", "frank", &["erin", "frank", "erin"]);
+        let pair_key = SocialPairKey { pair_key: generate_social_pair_key("erin".to_string(), "frank".to_string()) };
+        crate::stable_mem_storage::CHAT_HISTORIES.with(|h| {
+            let mut h = h.borrow_mut();
+            let mut history = h.get(&pair_key).unwrap();
+            history.messages[0].ttl_ns = Some(10);   // expires after 10
+            history.messages[2].ttl_ns = Some(100);  // expires after 102
+            h.insert(pair_key.clone(), history);
+        });
+
+        assert_eq!(get_chat_messages_excluding_expired("erin".to_string(), "frank".to_string(), 0, 10).len(), 3);
+        assert_eq!(cleanup_expired_messages(10, CHAT_CLEANUP_BATCH), 0);
+        assert_eq!(cleanup_expired_messages(50, CHAT_CLEANUP_BATCH), 1);
+        assert_eq!(cleanup_expired_messages(50, CHAT_CLEANUP_BATCH), 0);
+
+        // The expired message is blanked in place, so later indices do not shift
+        let remaining = get_chat_messages_paginated("erin".to_string(), "frank".to_string(), 0, 10);
+        assert_eq!(remaining.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), vec!["", "message 1", "message 2"]);
+        assert!(remaining[2].is_expired(103));
+        assert!(!remaining[1].is_expired(u64::MAX));
+        assert!(remaining[0].is_expired(50));
+    }
+
+    #[test]
+    fn test_group_message_notifies_other_members() {
+        let group_key = create_group_chat(
+            "alice".to_string(),
+            "Team".to_string(),
+            vec!["bob".to_string(), "carol".to_string()],
+        ).unwrap();
+        assert_eq!(get_group_chat(group_key.clone()).unwrap().members.len(), 3);
+
+        let index = send_group_message(group_key.clone(), "alice".to_string(), "hello".to_string(), MessageMode::Text).unwrap();
+        assert_eq!(index, 0);
+
+        assert!(get_notifications_for_receiver("alice".to_string()).is_empty());
+        for member in ["bob", "carol"] {
+            let notifications = get_notifications_for_receiver(member.to_string());
+            assert_eq!(notifications.len(), 1);
+            assert_eq!(notifications[0].social_pair_key, group_key);
+        }
+
+        let messages = get_group_messages_paginated(group_key.clone(), 0, 10);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "hello");
+
+        assert!(send_group_message(group_key, "mallory".to_string(), "hi".to_string(), MessageMode::Text).is_err());
+    }
+
+    #[test]
+    fn test_emoji_pack_validation_and_owner_delete() {
+        assert!(create_emoji_pack("alice".to_string(), "Cats".to_string(), vec![], true).is_err());
+        assert!(create_emoji_pack("alice".to_string(), "Cats".to_string(), vec!["cat_1".to_string(), " ".to_string()], true).is_err());
+        assert!(create_emoji_pack("alice".to_string(), "Cats".to_string(), vec!["cat_1".to_string(), "cat_1".to_string()], true).is_err());
+
+        let public_id = create_emoji_pack("alice".to_string(), "Cats".to_string(), vec!["cat_1".to_string(), "cat_2".to_string()], true).unwrap();
+        let private_id = create_emoji_pack("alice".to_string(), "Secret".to_string(), vec!["s_1".to_string()], false).unwrap();
+        assert_eq!(get_emoji_pack(private_id.clone()).unwrap().emoji_ids, vec!["s_1".to_string()]);
+
+        let listed = list_public_emoji_packs(0, 10);
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, public_id);
+
+        assert!(delete_emoji_pack(public_id.clone(), "bob".to_string()).is_err());
+        delete_emoji_pack(public_id.clone(), "alice".to_string()).unwrap();
+        assert!(get_emoji_pack(public_id).is_none());
+    }
+
+    #[test]
+    fn test_chat_thread_only_participants_can_post() {
+        assert!(create_chat_thread("alice".to_string(), vec!["alice".to_string()], None).is_err());
+
+        let thread_id = create_chat_thread(
+            "alice".to_string(),
+            vec!["bob".to_string(), "carol".to_string(), "bob".to_string()],
+            Some("Trip".to_string()),
+        ).unwrap();
+
+        assert_eq!(add_thread_message(thread_id.clone(), "alice".to_string(), "first".to_string(), MessageMode::Text).unwrap(), 0);
+        assert_eq!(add_thread_message(thread_id.clone(), "carol".to_string(), "second".to_string(), MessageMode::Text).unwrap(), 1);
+        assert!(add_thread_message(thread_id.clone(), "mallory".to_string(), "spam".to_string(), MessageMode::Text).is_err());
+        assert!(add_thread_message("thread_missing".to_string(), "alice".to_string(), "hi".to_string(), MessageMode::Text).is_err());
+
+        let page = get_thread_messages_paginated(thread_id.clone(), 1, 10);
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].send_by, "carol");
+        assert_eq!(get_thread_messages_paginated(thread_id.clone(), 0, 1)[0].content, "first");
+        assert!(get_notifications_for_receiver("bob".to_string()).iter().any(|n| n.social_pair_key == thread_id));
+        assert!(get_notifications_for_receiver("mallory".to_string()).is_empty());
+    }
+
+    #[test]
+    fn test_upsert_contact_preserves_unset_fields() {
+        store_test_contact("frank", "grace");
+        let existing = get_contact_by_principal_ids("frank".to_string(), "grace".to_string()).unwrap();
+        upsert_contact(Contact {
+            nickname: Some("Gracie".to_string()),
+            avatar: Some("avatar.png".to_string()),
+            metadata: Some("{\"tag\":\"work\"}".to_string()),
+            agent_index: None,
+            ..existing.clone()
+        }).unwrap();
+
+        upsert_contact(Contact {
+            name: "Grace H.".to_string(),
+            nickname: None,
+            avatar: None,
+            metadata: None,
+            agent_index: None,
+            created_at: 0,
+            ..existing
+        }).unwrap();
+
+        let merged = get_contact_by_principal_ids("frank".to_string(), "grace".to_string()).unwrap();
+        assert_eq!(merged.name, "Grace H.");
+        assert_eq!(merged.nickname, Some("Gracie".to_string()));
+        assert_eq!(merged.avatar, Some("avatar.png".to_string()));
+        assert_eq!(merged.metadata, Some("{\"tag\":\"work\"}".to_string()));
+        assert_eq!(merged.created_at, 1);
+        assert_eq!(search_contacts_by_name("frank".to_string(), "Grace H.".to_string()).len(), 1);
+    }
+
+    #[test]
+    fn test_drain_notifications_oldest_first() {
+        crate::stable_mem_storage::NOTIFICATION_QUEUE.with(|q| {
+            let mut q = q.borrow_mut();
+            // Key order deliberately differs from timestamp order
+            for (id, timestamp) in [("e", 1), ("d", 2), ("c", 3), ("b", 4), ("a", 5)] {
+                q.insert(
+                    NotificationKey { notification_id: id.to_string() },
+                    NotificationItem { social_pair_key: "group_test".to_string(), to_who: "dave".to_string(), message_id: timestamp, timestamp },
+                );
+            }
+            q.insert(
+                NotificationKey { notification_id: "other".to_string() },
+                NotificationItem { social_pair_key: "group_test".to_string(), to_who: "erin".to_string(), message_id: 0, timestamp: 0 },
+            );
+        });
+
+        let drained = drain_notifications("dave".to_string(), 3);
+        assert_eq!(drained.iter().map(|n| n.timestamp).collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        let remaining = get_notifications_for_receiver("dave".to_string());
+        assert_eq!(remaining.len(), 2);
+        assert!(remaining.iter().all(|n| n.timestamp > 3));
+        assert_eq!(get_notifications_for_receiver("erin".to_string()).len(), 1);
+    }
+
+    #[test]
+    fn test_rebuild_profile_indices_repairs_lookups() {
+        store_test_profile("alice");
+        store_test_profile("bob");
+        store_test_profile("carol");
+        assert_eq!(delete_user_profile("carol".to_string(), false), Ok(true));
+        // Deleted without a slot record, as by earlier releases
+        store_test_profile("dave");
+        USER_PROFILES.with(|profiles| {
+            let profiles = profiles.borrow_mut();
+            let index = profiles.len() - 1;
+            let mut profile = profiles.get(index).unwrap();
+            profile.deleted_at = Some(1);
+            profiles.set(index, &profile);
+        });
+
+        // Corrupt the indices: drop one entry and point another at the wrong slot
+        PRINCIPAL_INDEX.with(|idx| {
+            let mut idx = idx.borrow_mut();
+            idx.remove(&PrincipalKey { principal_id: "alice".to_string() });
+            idx.insert(PrincipalKey { principal_id: "bob".to_string() }, 0);
+        });
+        assert!(get_user_profile_by_principal("alice".to_string()).is_none());
+
+        assert_eq!(rebuild_profile_indices(), Ok(2));
+        assert_eq!(get_user_profile_by_principal("alice".to_string()).unwrap().principal_id, "alice");
+        assert_eq!(get_user_profile_by_principal("bob".to_string()).unwrap().principal_id, "bob");
+        assert_eq!(get_user_profile_by_user_id("user_bob".to_string()).unwrap().principal_id, "bob");
+        assert!(get_user_profile_by_principal("carol".to_string()).is_none());
+        assert!(get_user_profile_by_principal("dave".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_user_avatar_upload_and_delete() {
+        store_test_profile("alice");
+        let png = [b"\x89PNG\r\n\x1a\n".as_slice(), &[0u8; 16]].concat();
+
+        assert!(upload_user_avatar("alice".to_string(), png.clone(), "image/gif".to_string()).is_err());
+        assert!(upload_user_avatar("alice".to_string(), png.clone(), "image/jpeg".to_string()).is_err());
+        assert!(upload_user_avatar("alice".to_string(), vec![0x89; MAX_AVATAR_BYTES + 1], "image/png".to_string()).is_err());
+        assert!(upload_user_avatar("nobody".to_string(), png.clone(), "image/png".to_string()).is_err());
+
+        assert_eq!(upload_user_avatar("alice".to_string(), png.clone(), "image/png".to_string()), Ok("avatar://alice".to_string()));
+        assert_eq!(get_user_avatar("alice".to_string()), Some(png));
+        assert_eq!(get_user_profile_by_principal("alice".to_string()).unwrap().picture.as_deref(), Some("avatar://alice"));
+
+        let jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0];
+        upload_user_avatar("alice".to_string(), jpeg.clone(), "image/jpeg".to_string()).unwrap();
+        assert_eq!(get_user_avatar("alice".to_string()), Some(jpeg));
+
+        delete_user_avatar("alice".to_string()).unwrap();
+        assert!(get_user_avatar("alice".to_string()).is_none());
+        assert!(get_user_profile_by_principal("alice".to_string()).unwrap().picture.is_none());
+        assert!(delete_user_avatar("alice".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_search_users_by_nickname() {
+        for principal in ["alice", "Alicia", "bob", "malice"] {
+            store_test_profile(principal);
+        }
+        let names = |profiles: Vec<PublicUserProfile>| profiles.into_iter().map(|p| p.nickname).collect::<Vec<_>>();
+
+        assert_eq!(names(search_users_by_nickname("ALI".to_string(), 0, 10)), vec!["alice", "Alicia", "malice"]);
+        assert_eq!(names(search_users_by_nickname("ali".to_string(), 1, 1)), vec!["Alicia"]);
+        assert!(search_users_by_nickname(" ".to_string(), 0, 10).is_empty());
+
+        assert_eq!(names(search_users_fuzzy_nickname("alise".to_string(), 1, 10)), vec!["alice"]);
+        assert_eq!(names(search_users_fuzzy_nickname("alice".to_string(), 2, 10)), vec!["alice", "malice", "Alicia"]);
+        assert_eq!(names(search_users_fuzzy_nickname("alice".to_string(), 2, 1)), vec!["alice"]);
+
+        // Renaming moves the index entry; deleting drops it
+        let mut bob = get_user_profile_by_principal("bob".to_string()).unwrap();
+        bob.nickname = "Alister".to_string();
+        upsert_user_profile(bob).unwrap();
+        assert!(search_users_by_nickname("bob".to_string(), 0, 10).is_empty());
+        assert_eq!(names(search_users_by_nickname("alis".to_string(), 0, 10)), vec!["Alister"]);
+        delete_user_profile("malice".to_string(), false).unwrap();
+        assert_eq!(names(search_users_by_nickname("ali".to_string(), 0, 10)), vec!["alice", "Alicia", "Alister"]);
+
+        // Empty nicknames are never indexed; a cleared index is rebuilt from the live profiles
+        let mut alicia = get_user_profile_by_principal("Alicia".to_string()).unwrap();
+        alicia.nickname = String::new();
+        upsert_user_profile(alicia).unwrap();
+        assert!(NICKNAME_INDEX.with(|idx| idx.borrow().iter().all(|(key, _)| !key.nickname.is_empty())));
+        NICKNAME_INDEX.with(|idx| {
+            let keys: Vec<NicknameKey> = idx.borrow().iter().map(|(key, _)| key).collect();
+            keys.iter().for_each(|key| { idx.borrow_mut().remove(key); });
+        });
+        assert_eq!(backfill_nickname_index(), 2);
+        assert_eq!(backfill_nickname_index(), 0);
+        assert_eq!(names(search_users_by_nickname("ali".to_string(), 0, 10)), vec!["alice", "Alister"]);
+    }
+
+    #[test]
+    fn test_upsert_detects_index_slot_mismatch() {
+        store_test_profile("alice");
+        store_test_profile("bob");
+
+        // Point bob's principal index at alice's slot
+        PRINCIPAL_INDEX.with(|idx| {
+            idx.borrow_mut().insert(PrincipalKey { principal_id: "bob".to_string() }, 0);
+        });
+
+        let mut bob = get_user_profile(1).unwrap();
+        bob.nickname = "Bobby".to_string();
+        let result = upsert_user_profile(bob);
+        assert!(result.unwrap_err().contains("Profile index mismatch"));
+        assert_eq!(get_user_profile(0).unwrap().principal_id, "alice");
+        assert_eq!(get_user_profile(0).unwrap().nickname, "alice");
+
+        let mut alice = get_user_profile(0).unwrap();
+        alice.nickname = "Ali".to_string();
+        assert_eq!(upsert_user_profile(alice), Ok(0));
+        assert_eq!(get_user_profile(0).unwrap().nickname, "Ali");
+    }
+
     #[test]
     fn test_social_pair_key_generation() {
         let key1 = generate_social_pair_key("alice".to_string(), "bob".to_string());
@@ -1364,6 +3141,7 @@ mod tests {
             created_at: 1700000000,
             updated_at: 1700000000,
             metadata: None,
+            agent_index: None,
         };
         
         let contact_to_owner = Contact {
@@ -1380,6 +3158,7 @@ mod tests {
             created_at: 1700000000,
             updated_at: 1700000000,
             metadata: None,
+            agent_index: None,
         };
         
         // 验证数据结构正确性
@@ -1427,6 +3206,40 @@ mod tests {
         assert_eq!(same_principal.clone(), same_principal);
         // 在实际应用中，应该阻止用户添加自己为联系人
     }
+
+    #[test]
+    fn test_notification_preferences_filter_pushes() {
+        let receiver = "prefs_receiver".to_string();
+        push_notification("pair_1".to_string(), receiver.clone(), 1, NotificationCategory::Chat).unwrap();
+        assert_eq!(get_notifications_for_receiver(receiver.clone()).len(), 1);
+
+        let mut prefs = NotificationPreferences::default_for("ignored".to_string());
+        prefs.receive_chat = false;
+        prefs.quiet_hours_start = Some(23);
+        assert!(set_notification_preferences(receiver.clone(), prefs.clone()).is_err());
+        prefs.quiet_hours_end = Some(24);
+        assert!(set_notification_preferences(receiver.clone(), prefs.clone()).is_err());
+        prefs.quiet_hours_end = Some(1);
+        set_notification_preferences(receiver.clone(), prefs).unwrap();
+        assert_eq!(get_notification_preferences(receiver.clone()).principal_id, receiver);
+
+        // The test clock sits at midnight, inside the 23:00-01:00 window
+        push_notification("pair_2".to_string(), receiver.clone(), 2, NotificationCategory::System).unwrap();
+        assert_eq!(get_notifications_for_receiver(receiver.clone()).len(), 1);
+
+        let mut prefs = get_notification_preferences(receiver.clone());
+        prefs.quiet_hours_start = None;
+        prefs.quiet_hours_end = None;
+        set_notification_preferences(receiver.clone(), prefs).unwrap();
+        push_notification("pair_3".to_string(), receiver.clone(), 3, NotificationCategory::Chat).unwrap();
+        push_notification("pair_4".to_string(), receiver.clone(), 4, NotificationCategory::System).unwrap();
+        let ids: Vec<u64> = get_notifications_for_receiver(receiver.clone()).iter().map(|n| n.message_id).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&4));
+
+        assert!(delete_notification_preferences(receiver.clone()));
+        assert!(get_notification_preferences(receiver).receive_chat);
+    }
 }
 
 // ==== Email Registration System ====
@@ -1571,6 +3384,7 @@ pub fn register_user_with_email(
         created_at: ic_cdk::api::time(),
         updated_at: ic_cdk::api::time(),
         metadata: Some("email_registration".to_string()),
+        deleted_at: None,
     };
     
     // Store user profile
@@ -1,13 +1,13 @@
 // Centralized stable memory storage for all modules
-use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableVec};
+use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableCell, StableVec};
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use std::cell::RefCell;
 use crate::mining_reword::{MiningRewardPolicy, RewardEntry, UserRewardKey};
 use crate::token_economy_types::RewardIdList;
 use crate::account_storage::AccountKey;
 use crate::token_economy_types::AccountInfo;
-use crate::pixel_creation_types::{Project, ProjectOwnerKey};
-use crate::device_types::{DeviceInfo, DeviceOwnerKey, DeviceIdKey};
+use crate::pixel_creation_types::{Project, ProjectOwnerKey, ProjectForkKey};
+use crate::device_types::{DeviceInfo, DeviceOwnerKey, DeviceIdKey, DeviceTelemetry, DeviceTelemetryKey, FirmwareRelease, FirmwareReleaseKey};
 use crate::types::Order;
 
 // Type alias for memory
@@ -46,6 +46,59 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(33)))
         )
     );
+    // MCP name -> latest remote endpoint health check
+    pub static MCP_HEALTH: RefCell<StableBTreeMap<String, crate::mcp_asset_types::McpHealth, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(50)))
+        )
+    );
+    // (mcp_name, timestamp ns) -> stake change, for point-in-time stake lookups
+    pub static MCP_STACK_EVENTS: RefCell<StableBTreeMap<crate::mcp_asset_types::McpStackEventKey, crate::mcp_asset_types::McpStackEvent, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(48)))
+        )
+    );
+    // principal -> net stake across all MCPs
+    pub static STAKER_NET_STAKE: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(116)))
+        )
+    );
+    // (inverted net stake, principal) -> (), largest stakers first
+    pub static STAKER_RANK: RefCell<StableBTreeMap<crate::mcp_asset_types::StakerRankKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(117)))
+        )
+    );
+    // Week start (ns) -> top stakers snapshot
+    pub static LEADERBOARD_HISTORY: RefCell<StableBTreeMap<u64, crate::mcp_asset_types::WeeklyLeaderboardSnapshot, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(23)))
+        )
+    );
+    pub static RATINGS: RefCell<StableBTreeMap<crate::mcp_asset_types::RatingKey, crate::mcp_asset_types::RatingEntry, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(38)))
+        )
+    );
+    // Endorsements of agents and MCPs, keyed by target then endorser
+    pub static ENDORSEMENTS: RefCell<StableBTreeMap<crate::endorsement_types::EndorsementKey, crate::endorsement_types::Endorsement, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(22)))
+        )
+    );
+    // MCP name -> indices of agents that declare it as a dependency
+    pub static AGENT_MCP_INDEX: RefCell<StableBTreeMap<String, crate::aio_protocal_types::StringVec, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14)))
+        )
+    );
+    // MCP tag -> names of MCP items carrying that tag
+    pub static MCP_TAG_INDEX: RefCell<StableBTreeMap<String, crate::aio_protocal_types::StringVec, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13)))
+        )
+    );
 
     // Inverted Index
     pub static INVERTED_INDEX_STORE: RefCell<crate::aio_invert_index_types::InvertedIndexStore> = RefCell::new(
@@ -80,6 +133,30 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6)))
         )
     );
+    // Transport (lowercased) -> ids of indices supporting it
+    pub static TRANSPORT_INDEX: RefCell<StableBTreeMap<String, crate::aio_protocal_types::StringVec, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(20)))
+        )
+    );
+    // Method name -> ids of indices defining it
+    pub static METHOD_INDEX: RefCell<StableBTreeMap<String, crate::aio_protocal_types::StringVec, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(29)))
+        )
+    );
+    // Retained revisions of each index; prior revisions double as its history.
+    // MemoryId 16 held the earlier snapshot-list history and is no longer read.
+    pub static AIO_INDEX_VERSIONS: RefCell<StableBTreeMap<crate::aio_protocal_types::AioIndexVersionKey, crate::aio_protocal_types::AioIndex, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(41)))
+        )
+    );
+    pub static AIO_INDEX_HASHES: RefCell<StableBTreeMap<String, String, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(17)))
+        )
+    );
 
     // Token Economy
     pub static EMISSION_POLICY: RefCell<StableBTreeMap<String, crate::token_economy_types::EmissionPolicy, Memory>> = RefCell::new(
@@ -141,8 +218,134 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11)))
         )
     );
+    // (creation time, trace id) -> (), for time-range scans over TRACE_STORAGE
+    pub static TRACE_TIME_INDEX: RefCell<StableBTreeMap<crate::trace_storage::TraceTimeKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(30)))
+        )
+    );
+    pub static RATE_LIMIT_MAP: RefCell<StableBTreeMap<String, crate::trace_storage::RateLimitEntry, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(40)))
+        )
+    );
+    // (agent name, trace id) -> (), for range scans over the traces that called an agent
+    pub static AGENT_TRACE_INDEX: RefCell<StableBTreeMap<crate::trace_storage::AgentTraceKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(47)))
+        )
+    );
+    // agent name -> running call totals behind get_agent_execution_stats
+    pub static AGENT_EXECUTION_TOTALS: RefCell<StableBTreeMap<String, crate::trace_storage::AgentExecutionTotals, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(115)))
+        )
+    );
+    // Secret salt for export pseudonyms, seeded from raw_rand; empty until the first seeding completes
+    pub static EXPORT_SALT: RefCell<StableCell<Vec<u8>, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(119))),
+            Vec::new()
+        ).unwrap()
+    );
+    // Monotonic sequence used by generate_trace_id
+    pub static TRACE_COUNTER: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12))),
+            0
+        ).unwrap()
+    );
+
+    // Instruction budget for guarded bulk reads
+    pub static INSTRUCTION_LIMIT: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(97))),
+            crate::types::DEFAULT_INSTRUCTION_LIMIT
+        ).unwrap()
+    );
+
+    // Inter-agent message queue
+    pub static AGENT_MESSAGES: RefCell<StableBTreeMap<crate::agent_message_types::AgentMessageKey, crate::agent_message_types::AgentMessage, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(95)))
+        )
+    );
+    pub static AGENT_MESSAGE_COUNTER: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(96))),
+            0
+        ).unwrap()
+    );
+
+    // Webhooks
+    pub static WEBHOOK_SUBSCRIPTIONS: RefCell<StableBTreeMap<String, crate::webhook_types::WebhookSubscription, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(44)))
+        )
+    );
+    // Pending deliveries keyed by enqueue sequence number, oldest first.
+    // MemoryId 45 held the earlier vector-backed queue and is no longer read.
+    pub static WEBHOOK_QUEUE: RefCell<StableBTreeMap<u64, crate::webhook_types::WebhookDeliveryTask, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(109)))
+        )
+    );
+
+    // Token supply counters
+    pub static TOTAL_MINTED: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(42))),
+            0
+        ).unwrap()
+    );
+    pub static TOTAL_BURNED: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(43))),
+            0
+        ).unwrap()
+    );
+    // Set once seed_total_minted has run, so later upgrades never re-seed the minted counter
+    pub static TOTAL_MINTED_SEEDED: RefCell<StableCell<bool, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(121))),
+            false
+        ).unwrap()
+    );
 
     // Credit Exchange & Recharge
+    pub static CREDIT_TOKEN_RATE: RefCell<StableCell<crate::token_economy_types::CreditTokenRate, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(18))),
+            crate::token_economy_types::CreditTokenRate::default()
+        ).unwrap()
+    );
+    // Mining block reward halving
+    pub static HALVING_SCHEDULE: RefCell<StableCell<crate::mining_reword::HalvingSchedule, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(93))),
+            crate::mining_reword::HalvingSchedule::default()
+        ).unwrap()
+    );
+    pub static TOTAL_BLOCKS_MINED: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(94))),
+            0
+        ).unwrap()
+    );
+    // Trace id -> height of the block it mined; each trace mines at most one block.
+    // Kept for as long as the traces themselves, which are never deleted
+    pub static MINED_TRACES: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(99)))
+        )
+    );
+    // Timer flag and prices snapshotted across upgrades
+    pub static UPGRADE_STATE: RefCell<StableCell<crate::types::UpgradeState, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(19))),
+            crate::types::UpgradeState::default()
+        ).unwrap()
+    );
     pub static CREDIT_CONVERT_CONTRACT: RefCell<StableBTreeMap<String, crate::token_economy_types::CreditConvertContract, Memory>> = RefCell::new(
         StableBTreeMap::init(
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(51)))
@@ -158,6 +361,49 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(53)))
         ).unwrap()
     );
+    pub static TRANSFER_FEE_CONFIG: RefCell<StableBTreeMap<String, crate::token_economy_types::TransferFeeConfig, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(54)))
+        )
+    );
+    pub static ACTIVITY_ARCHIVE: RefCell<StableBTreeMap<String, crate::token_economy_types::ActivityArchiveSummary, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(55)))
+        )
+    );
+    // MemoryId 56 held the earlier emission tunables map and is no longer read.
+    pub static EMISSION_SNAPSHOTS: RefCell<StableBTreeMap<crate::token_economy_types::EmissionSnapshotKey, crate::token_economy_types::EmissionSnapshot, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(57)))
+        )
+    );
+    // Admin-tunable settings keyed by name, see settings.rs
+    pub static SETTINGS: RefCell<StableBTreeMap<String, crate::settings::SettingValue, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(59)))
+        )
+    );
+    pub static CREDIT_DELEGATIONS: RefCell<StableBTreeMap<crate::token_economy_types::CreditDelegationKey, crate::token_economy_types::CreditDelegation, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(58)))
+        )
+    );
+    pub static CREDIT_DISPUTES: RefCell<StableBTreeMap<crate::token_economy_types::CreditDisputeKey, crate::token_economy_types::CreditDispute, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(39)))
+        )
+    );
+    pub static CREDIT_FEE_RULES: RefCell<StableBTreeMap<String, crate::token_economy_types::CreditFeeRule, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(46)))
+        )
+    );
+    // Airdrop id -> bulk credit distribution record
+    pub static AIRDROP_HISTORY: RefCell<StableBTreeMap<u64, crate::token_economy_types::AirdropRecord, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(87)))
+        )
+    );
 
     pub static ACCOUNTS: RefCell<StableBTreeMap<AccountKey, AccountInfo, Memory>> = RefCell::new(
         StableBTreeMap::init(
@@ -186,6 +432,12 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(63)))
         )
     );
+    // Profile slots removed by delete_user_profile; skipped when rebuilding indices
+    pub static DELETED_PROFILE_SLOTS: RefCell<StableBTreeMap<u64, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(64)))
+        )
+    );
 
     // Contact Storage
     pub static CONTACTS: RefCell<StableVec<crate::society_profile_types::Contact, Memory>> = RefCell::new(
@@ -203,6 +455,11 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(72)))
         )
     );
+    pub static AGENT_CONTACT_INDEX: RefCell<StableBTreeMap<crate::society_profile_types::AgentContactKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(113)))
+        )
+    );
 
     // Social Chat Storage
     pub static CHAT_HISTORIES: RefCell<StableBTreeMap<crate::society_profile_types::SocialPairKey, crate::society_profile_types::ChatHistory, Memory>> = RefCell::new(
@@ -215,6 +472,49 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(81)))
         )
     );
+    pub static READ_RECEIPTS: RefCell<StableBTreeMap<crate::society_profile_types::ReadReceiptKey, crate::society_profile_types::ReadReceipt, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(82)))
+        )
+    );
+    pub static GROUP_CHATS: RefCell<StableBTreeMap<String, crate::society_profile_types::GroupChat, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(83)))
+        )
+    );
+    pub static CHAT_THREADS: RefCell<StableBTreeMap<String, crate::society_profile_types::ChatThread, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(84)))
+        )
+    );
+    pub static THREAD_MESSAGES: RefCell<StableBTreeMap<crate::society_profile_types::ThreadMessageKey, crate::society_profile_types::ChatMessage, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(114)))
+        )
+    );
+    pub static EMOJI_PACKS: RefCell<StableBTreeMap<String, crate::society_profile_types::EmojiPack, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(85)))
+        )
+    );
+    // (lowercased nickname, USER_PROFILES index) for every profile with a non-empty nickname.
+    // MemoryId 89 held the earlier nickname -> indices layout and is no longer read.
+    pub static NICKNAME_INDEX: RefCell<StableBTreeMap<crate::society_profile_types::NicknameKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(98)))
+        )
+    );
+    // Principal -> avatar image bytes (PNG or JPEG, at most 256 KB)
+    pub static USER_AVATARS: RefCell<StableBTreeMap<String, Vec<u8>, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(49)))
+        )
+    );
+    pub static NOTIFICATION_PREFS: RefCell<StableBTreeMap<String, crate::society_profile_types::NotificationPreferences, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(86)))
+        )
+    );
 
     // Pixel Creation Storage
     pub static PIXEL_PROJECTS: RefCell<StableBTreeMap<String, Project, Memory>> = RefCell::new(
@@ -227,6 +527,17 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(91)))
         )
     );
+    // (parent project, fork project) -> (), behind get_project_forks
+    pub static PROJECT_FORK_INDEX: RefCell<StableBTreeMap<ProjectForkKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(118)))
+        )
+    );
+    pub static EDIT_LOCKS: RefCell<StableBTreeMap<String, crate::pixel_creation_types::EditLock, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(92)))
+        )
+    );
 
     // Device Storage
     pub static DEVICES: RefCell<StableVec<DeviceInfo, Memory>> = RefCell::new(
@@ -244,6 +555,35 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(102)))
         )
     );
+    pub static DEVICE_TELEMETRY: RefCell<StableBTreeMap<DeviceTelemetryKey, DeviceTelemetry, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(104)))
+        )
+    );
+    pub static FIRMWARE_RELEASES: RefCell<StableBTreeMap<FirmwareReleaseKey, FirmwareRelease, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(108)))
+        )
+    );
+
+    // Device push delivery of pixel projects
+    pub static DEVICE_PROJECT_SUBSCRIPTIONS: RefCell<StableBTreeMap<crate::device_delivery_types::DeviceProjectKey, crate::device_delivery_types::DeviceProjectSubscription, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(105)))
+        )
+    );
+    // Pending pushes keyed by enqueue sequence number, and delivery attempts grouped by device.
+    // MemoryIds 106 and 107 held the earlier vector-backed queue and log and are no longer read.
+    pub static DEVICE_DELIVERY_QUEUE: RefCell<StableBTreeMap<u64, crate::device_delivery_types::DeviceDeliveryTask, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(110)))
+        )
+    );
+    pub static DELIVERY_LOG: RefCell<StableBTreeMap<crate::device_delivery_types::DeliveryLogKey, crate::device_delivery_types::DeliveryLogEntry, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(112)))
+        )
+    );
 
     // Order Storage
     pub static ORDERS: RefCell<StableBTreeMap<String, Order, Memory>> = RefCell::new(
@@ -251,4 +591,26 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(103)))
         )
     );
-} 
\ No newline at end of file
+
+    // Access control: roles granted to principals
+    pub static ROLES: RefCell<StableBTreeMap<candid::Principal, crate::access_control::Role, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7)))
+        )
+    );
+
+    // Agent full-text search: (trigram, agent id) -> ().
+    // MemoryId 8 held the earlier trigram -> id list map and is no longer read.
+    pub static AGENT_TRIGRAM_INDEX: RefCell<StableBTreeMap<crate::agent_asset_types::AgentTrigramKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(120)))
+        )
+    );
+
+    // ICP ledger block index -> recharge record id, guards against reusing a transfer
+    pub static RECHARGE_BLOCKS: RefCell<StableBTreeMap<u64, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9)))
+        )
+    );
+}
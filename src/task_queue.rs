@@ -0,0 +1,21 @@
+use ic_stable_structures::{StableBTreeMap, Storable};
+use crate::stable_mem_storage::Memory;
+
+// FIFO queues are stored as a map from enqueue sequence number to task, so the oldest tasks
+// can be removed without rewriting the rest
+
+/// Append a task after the newest one; returns false (dropping the task) once `max_len` tasks are queued
+pub fn push_task<T: Storable>(queue: &mut StableBTreeMap<u64, T, Memory>, task: T, max_len: u64) -> bool {
+    if queue.len() >= max_len {
+        return false;
+    }
+    let seq = queue.last_key_value().map_or(0, |(seq, _)| seq + 1);
+    queue.insert(seq, task);
+    true
+}
+
+/// Remove up to `max` tasks from the front of the queue, oldest first
+pub fn take_batch<T: Storable>(queue: &mut StableBTreeMap<u64, T, Memory>, max: usize) -> Vec<T> {
+    let seqs: Vec<u64> = queue.iter().take(max).map(|(seq, _)| seq).collect();
+    seqs.into_iter().filter_map(|seq| queue.remove(&seq)).collect()
+}
@@ -1,5 +1,4 @@
 use candid::{CandidType, Principal, Decode, Encode};
-use ic_cdk::api::time;
 use ic_stable_structures::StableBTreeMap;
 use std::cell::RefCell;
 use num_traits::ToPrimitive;
@@ -20,26 +19,26 @@ use ic_stable_structures::DefaultMemoryImpl;
 use std::borrow::Cow;
 use serde::{Serialize, Deserialize};
 use crate::mcp_asset_types;
-use crate::stable_mem_storage::{NEWUSER_GRANTS, NEWMCP_GRANTS, TOKEN_ACTIVITIES, CREDIT_ACTIVITIES, EMISSION_POLICY, GRANT_POLICIES, CREDIT_CONVERT_CONTRACT, RECHARGE_RECORDS, RECHARGE_PRINCIPAL_ACCOUNTS};
+use crate::stable_mem_storage::{NEWUSER_GRANTS, NEWMCP_GRANTS, TOKEN_ACTIVITIES, CREDIT_ACTIVITIES, EMISSION_POLICY, GRANT_POLICIES, CREDIT_CONVERT_CONTRACT, RECHARGE_RECORDS, RECHARGE_BLOCKS, RECHARGE_PRINCIPAL_ACCOUNTS, TRANSFER_FEE_CONFIG, ACTIVITY_ARCHIVE, EMISSION_SNAPSHOTS, CREDIT_DELEGATIONS, CREDIT_TOKEN_RATE, CREDIT_DISPUTES, CREDIT_FEE_RULES, AIRDROP_HISTORY, TOTAL_MINTED, TOTAL_BURNED, TOTAL_MINTED_SEEDED};
+use crate::settings;
+use crate::types::{InstructionGuard, PagedResult, cursor_key_range, time};
 
 // Re-export NumTokens for public use
 pub use icrc_ledger_types::icrc1::transfer::NumTokens;
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
-// Constants
-const EXCHANGE_RATIO: f64 = 1.0; // 1 AIO = 1 Credit
-const STAKING_PERIOD: u64 = 30 * 24 * 60 * 60 * 1_000_000_000; // 30 days in nanoseconds
-const MIN_STAKE_AMOUNT: u64 = 100; // Minimum amount of credits to stake
+// Constants (admin-tunable values live in the settings module)
 const MAX_KAPPA: f64 = 2.0; // Maximum kappa multiplier
 const BASE_KAPPA: f64 = 1.0; // Base kappa multiplier
-const DEFAULT_BASE_RATE: u64 = 100;
-const DEFAULT_KAPPA_FACTOR: f64 = 1.0;
-const DEFAULT_STAKING_BONUS: f64 = 0.1;
 const ADMIN_PRINCIPAL: &str = "aaaaa-aa"; // TODO: Replace with actual admin Principal
-const DEFAULT_ICP_USD_PRICE: f64 = 5.5;
-const DEFAULT_CREDIT_USD_PRICE: f64 = 0.0001;
 const CREDIT_CONTRACT_KEY: &str = "global";
+const TRANSFER_FEE_KEY: &str = "global";
+const EMISSION_SNAPSHOT_BUCKET_NS: u64 = 24 * 60 * 60 * 1_000_000_000; // One snapshot per account per day
+const DASHBOARD_SPEND_WINDOW_NS: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+const DASHBOARD_TOP_STAKERS: usize = 10;
+/// Most recipients a single airdrop may credit
+pub const MAX_AIRDROP_RECIPIENTS: usize = 200;
 
 // Account Management
 pub async fn get_account_info(principal_id: String) -> Option<AccountInfo> {
@@ -80,15 +79,19 @@ pub fn update_account_balance(principal_id: String, token_amount: i64, credit_am
     let mut account = get_account(principal_id.clone())
         .ok_or_else(|| "Account not found".to_string())?;
 
-    if token_amount < 0 && account.get_token_balance() < (-token_amount) as u64 {
+    if token_amount < 0 && account.get_token_balance() < token_amount.unsigned_abs() {
         return Err("Insufficient token balance".to_string());
     }
-    if credit_amount < 0 && account.get_credit_balance() < (-credit_amount) as u64 {
+    if credit_amount < 0 && account.get_credit_balance() < credit_amount.unsigned_abs() {
         return Err("Insufficient credit balance".to_string());
     }
 
-    account.token_info.token_balance = (account.get_token_balance() as i64 + token_amount) as u64;
-    account.token_info.credit_balance = (account.get_credit_balance() as i64 + credit_amount) as u64;
+    account.token_info.token_balance = account.get_token_balance()
+        .checked_add_signed(token_amount)
+        .ok_or_else(|| "Token balance overflow".to_string())?;
+    account.token_info.credit_balance = account.get_credit_balance()
+        .checked_add_signed(credit_amount)
+        .ok_or_else(|| "Credit balance overflow".to_string())?;
     account.updated_at = Some(time());
 
     upsert_account(account)
@@ -96,8 +99,9 @@ pub fn update_account_balance(principal_id: String, token_amount: i64, credit_am
 
 // Credit Operations
 pub fn stack_credits(principal_id: String, mcp_name:String ,amount: u64) -> Result<AccountInfo, String> {
-    if amount < MIN_STAKE_AMOUNT {
-        return Err(format!("Minimum stake amount is {}", MIN_STAKE_AMOUNT));
+    let min_stake_amount = get_min_stake_amount();
+    if amount < min_stake_amount {
+        return Err(format!("Minimum stake amount is {}", min_stake_amount));
     }
 
     let mut account = get_account(principal_id.clone())
@@ -134,6 +138,7 @@ pub fn stack_credits(principal_id: String, mcp_name:String ,amount: u64) -> Resu
                 activity_type: CreditActivityType::Stack,
                 status: TransferStatus::Completed,
                 metadata: Some("Credit stacking".to_string()),
+                service: None,
             };
             record_credit_activity(activity)?;
 
@@ -172,26 +177,240 @@ pub fn unstack_credits(principal_id: String, amount: u64) -> Result<AccountInfo,
         activity_type: CreditActivityType::Unstack,
         status: TransferStatus::Completed,
         metadata: Some("Credit unstacking".to_string()),
+        service: None,
     };
     record_credit_activity(activity)?;
 
     Ok(result)
 }
 
+// Credit to Token Conversion
+pub fn get_credit_token_rate() -> u64 {
+    CREDIT_TOKEN_RATE.with(|rate| rate.borrow().get().credits_per_token)
+}
+
+pub fn set_credit_token_rate(updated_by: String, credits_per_token: u64) -> Result<(), String> {
+    if credits_per_token == 0 {
+        return Err("Credits per token must be greater than zero".to_string());
+    }
+    let rate = CreditTokenRate {
+        credits_per_token,
+        updated_at: time(),
+        updated_by,
+    };
+    CREDIT_TOKEN_RATE.with(|cell| {
+        cell.borrow_mut().set(rate)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to store credit token rate: {:?}", e))
+    })
+}
+
+/// Burn credits and mint tokens at the configured rate. Only whole tokens are minted, so
+/// just the credits needed for them are deducted and any remainder stays in the balance.
+pub fn convert_credits_to_tokens(principal_id: String, credit_amount: u64) -> Result<AccountInfo, String> {
+    let credits_per_token = get_credit_token_rate();
+    let token_amount = credit_amount / credits_per_token;
+    if token_amount == 0 {
+        return Err(format!("Converting requires at least {} credits", credits_per_token));
+    }
+    let burned_credits = token_amount * credits_per_token;
+
+    let mut account = get_account(principal_id.clone())
+        .ok_or_else(|| "Account not found".to_string())?;
+
+    if account.get_credit_balance() < burned_credits {
+        return Err("Insufficient credit balance".to_string());
+    }
+
+    account.token_info.credit_balance -= burned_credits;
+    account.token_info.token_balance = account.token_info.token_balance.checked_add(token_amount)
+        .ok_or_else(|| "Token balance overflow".to_string())?;
+    account.updated_at = Some(time());
+
+    let result = upsert_account(account)?;
+
+    let metadata = Some(format!("Credit to token conversion at {} credits per token", credits_per_token));
+    record_credit_activity(CreditActivity {
+        timestamp: time(),
+        principal_id: principal_id.clone(),
+        amount: burned_credits,
+        activity_type: CreditActivityType::Burn,
+        status: TransferStatus::Completed,
+        metadata: metadata.clone(),
+        service: None,
+    })?;
+    record_token_activity(TokenActivity {
+        timestamp: time(),
+        from: principal_id.clone(),
+        to: principal_id,
+        amount: token_amount,
+        activity_type: TokenActivityType::Mint,
+        status: TransferStatus::Completed,
+        metadata,
+    })?;
+    record_minted(token_amount);
+
+    Ok(result)
+}
+
 // Token Operations
+/// Permanently remove tokens from an account and count them as burned
+pub fn burn_tokens(principal_id: String, amount: u64, reason: String) -> Result<AccountInfo, String> {
+    if amount == 0 {
+        return Err("Burn amount must be greater than zero".to_string());
+    }
+    let reason = crate::sanitize::sanitize_and_bound(&reason, crate::sanitize::MAX_REVIEW_COMMENT_LEN)?;
+    let mut account = get_account(principal_id.clone())
+        .ok_or_else(|| "Account not found".to_string())?;
+    if account.get_token_balance() < amount {
+        return Err("Insufficient token balance".to_string());
+    }
+
+    account.token_info.token_balance -= amount;
+    account.updated_at = Some(time());
+    let result = upsert_account(account)?;
+
+    record_token_activity(TokenActivity {
+        timestamp: time(),
+        from: principal_id,
+        to: "burn".to_string(),
+        amount,
+        activity_type: TokenActivityType::Burn,
+        status: TransferStatus::Completed,
+        metadata: Some(reason),
+    })?;
+    TOTAL_BURNED.with(|total| {
+        let mut total = total.borrow_mut();
+        let burned = total.get().saturating_add(amount);
+        total.set(burned).expect("Failed to update total burned");
+    });
+
+    Ok(result)
+}
+
+/// Count newly issued tokens towards the total minted supply
+pub fn record_minted(amount: u64) {
+    TOTAL_MINTED.with(|total| {
+        let mut total = total.borrow_mut();
+        let minted = total.get().saturating_add(amount);
+        total.set(minted).expect("Failed to update total minted");
+    });
+}
+
+/// Seed the minted counter from existing balances on the first upgrade after it was
+/// introduced, so accounts funded earlier are counted in the circulating supply.
+/// Runs at most once; a counter that is already non-zero was seeded or tracked before the flag existed.
+pub fn seed_total_minted() {
+    if TOTAL_MINTED_SEEDED.with(|seeded| *seeded.borrow().get()) {
+        return;
+    }
+    if get_total_minted() == 0 {
+        let balances = crate::stable_mem_storage::ACCOUNTS.with(|accounts| {
+            accounts.borrow().iter().fold(0u64, |sum, (_, account)| sum.saturating_add(account.token_info.token_balance))
+        });
+        if balances > 0 {
+            record_minted(balances.saturating_add(get_total_burned()));
+        }
+    }
+    TOTAL_MINTED_SEEDED.with(|seeded| {
+        seeded.borrow_mut().set(true).expect("Failed to save minted seed flag");
+    });
+}
+
+pub fn get_total_minted() -> u64 {
+    TOTAL_MINTED.with(|total| *total.borrow().get())
+}
+
+pub fn get_total_burned() -> u64 {
+    TOTAL_BURNED.with(|total| *total.borrow().get())
+}
+
+/// Tokens minted so far minus tokens burned
+pub fn get_circulating_supply() -> u64 {
+    get_total_minted().saturating_sub(get_total_burned())
+}
+
+/// Aggregate economy stats in one pass over each store. Entries are streamed from stable
+/// memory, so only the running totals are held on the heap; top stakers come from the
+/// staker rank index and the 30-day spend reads only activities inside the window.
+pub fn get_economy_dashboard() -> EconomyDashboard {
+    let mut dashboard = EconomyDashboard {
+        top_10_stakers: crate::mcp_asset_types::get_top_stakers(DASHBOARD_TOP_STAKERS),
+        ..Default::default()
+    };
+
+    crate::stable_mem_storage::ACCOUNTS.with(|accounts| {
+        for (_, account) in accounts.borrow().iter() {
+            let info = &account.token_info;
+            dashboard.total_accounts += 1;
+            dashboard.total_token_balance = dashboard.total_token_balance.saturating_add(info.token_balance);
+            dashboard.total_credit_balance = dashboard.total_credit_balance.saturating_add(info.credit_balance);
+            dashboard.total_staked_credits = dashboard.total_staked_credits.saturating_add(info.staked_credits);
+        }
+    });
+
+    dashboard.total_mcp_grants_issued = NEWMCP_GRANTS.with(|grants| {
+        grants.borrow().iter().fold(0u64, |total, (_, grant)| total.saturating_add(grant.amount))
+    });
+    dashboard.total_user_grants_issued = NEWUSER_GRANTS.with(|grants| {
+        grants.borrow().iter().fold(0u64, |total, (_, grant)| total.saturating_add(grant.amount))
+    });
+
+    let spend_cutoff = time().saturating_sub(DASHBOARD_SPEND_WINDOW_NS);
+    dashboard.total_credit_spend_30d = CREDIT_ACTIVITIES.with(|activities| {
+        // Activities are appended in time order, so walk back from the newest until the cutoff
+        activities.borrow()
+            .iter()
+            .rev()
+            .take_while(|(_, a)| a.timestamp >= spend_cutoff)
+            .filter(|(_, a)| a.activity_type == CreditActivityType::Spend)
+            .fold(0u64, |total, (_, a)| total.saturating_add(a.amount))
+    });
+
+    dashboard.emission_policy_base_rate = get_emission_policy().map(|policy| policy.base_rate).unwrap_or(0);
+    dashboard.credits_per_icp = get_credits_per_icp();
+    dashboard
+}
+
 pub fn transfer_tokens(from: String, to: String, amount: u64) -> Result<AccountInfo, String> {
+    // Both sides would read and write the same account, letting the second write clobber the first
+    if from == to {
+        return Err("Cannot transfer tokens to the same account".to_string());
+    }
+
     let mut from_account = get_account(from.clone())
         .ok_or_else(|| "From account not found".to_string())?;
     
     let mut to_account = get_account(to.clone())
         .ok_or_else(|| "To account not found".to_string())?;
     
+    let fee_config = get_transfer_fee_config();
+    let fee = fee_config.as_ref().map(|config| config.fee).unwrap_or(0);
+    let total_debit = amount.checked_add(fee)
+        .ok_or_else(|| "Transfer amount overflow".to_string())?;
+    
     if from_account.get_token_balance() < amount {
         return Err("Insufficient token balance".to_string());
     }
+    if from_account.get_token_balance() < total_debit {
+        return Err(format!("Insufficient token balance to cover transfer fee of {}", fee));
+    }
 
-    let from_new_balance = from_account.get_token_balance() - amount;
-    let to_new_balance = to_account.get_token_balance() + amount;
+    let from_new_balance = from_account.get_token_balance() - total_debit;
+    let to_new_balance = to_account.get_token_balance().checked_add(amount)
+        .ok_or_else(|| "Recipient token balance overflow".to_string())?;
+    // Check the treasury credit before any balance is written, so an overflow leaves no partial transfer
+    if let Some(config) = fee_config.as_ref().filter(|config| config.fee > 0) {
+        let treasury_balance = if config.treasury == from {
+            from_new_balance
+        } else if config.treasury == to {
+            to_new_balance
+        } else {
+            get_account(config.treasury.clone()).map_or(0, |account| account.get_token_balance())
+        };
+        treasury_balance.checked_add(config.fee)
+            .ok_or_else(|| "Treasury token balance overflow".to_string())?;
+    }
     
     from_account.token_info.token_balance = (from_new_balance as i64) as u64;
     to_account.token_info.token_balance = (to_new_balance as i64) as u64;
@@ -214,13 +433,183 @@ pub fn transfer_tokens(from: String, to: String, amount: u64) -> Result<AccountI
     };
     record_token_activity(activity)?;
     
-    Ok(from_account)
+    // Route the fee to the treasury
+    if let Some(config) = fee_config.filter(|config| config.fee > 0) {
+        let mut treasury_account = get_account(config.treasury.clone())
+            .unwrap_or_else(|| AccountInfo::new(config.treasury.clone()));
+        treasury_account.token_info.token_balance = treasury_account.get_token_balance().checked_add(config.fee)
+            .ok_or_else(|| "Treasury token balance overflow".to_string())?;
+        treasury_account.updated_at = Some(time());
+        upsert_account(treasury_account)?;
+        
+        let fee_activity = TokenActivity {
+            timestamp: time(),
+            from: from.clone(),
+            to: config.treasury.clone(),
+            amount: config.fee,
+            activity_type: TokenActivityType::Transfer,
+            status: TransferStatus::Completed,
+            metadata: Some("Transfer fee".to_string()),
+        };
+        record_token_activity(fee_activity)?;
+    }
+    
+    // Re-read so the returned balance reflects the fee even when the sender is the treasury
+    get_account(from).ok_or_else(|| "From account not found".to_string())
+}
+
+/// Get the current transfer fee configuration, if any
+pub fn get_transfer_fee_config() -> Option<TransferFeeConfig> {
+    TRANSFER_FEE_CONFIG.with(|store| {
+        store.borrow().get(&TRANSFER_FEE_KEY.to_string())
+    })
+}
+
+/// Set the transfer fee and its treasury account; callers check the Admin role
+pub fn set_transfer_fee(fee: u64, treasury: String) -> Result<(), String> {
+    if treasury.trim().is_empty() {
+        return Err("Treasury principal cannot be empty".to_string());
+    }
+    TRANSFER_FEE_CONFIG.with(|store| {
+        store.borrow_mut().insert(TRANSFER_FEE_KEY.to_string(), TransferFeeConfig { fee, treasury });
+        Ok(())
+    })
+}
+
+/// Credit `amount_each` to every recipient, creating missing accounts. Recipients are
+/// credited independently: failures are reported in the result and do not undo the others.
+pub fn airdrop_credits(initiator: String, recipients: Vec<String>, amount_each: u64, memo: Option<String>) -> Result<AirdropResult, String> {
+    if amount_each == 0 {
+        return Err("Airdrop amount must be greater than zero".to_string());
+    }
+    if recipients.is_empty() || recipients.len() > MAX_AIRDROP_RECIPIENTS {
+        return Err(format!("An airdrop needs between 1 and {} recipients", MAX_AIRDROP_RECIPIENTS));
+    }
+    if amount_each.checked_mul(recipients.len() as u64).is_none() || amount_each > i64::MAX as u64 {
+        return Err("Airdrop total overflows".to_string());
+    }
+
+    let airdrop_id = AIRDROP_HISTORY.with(|history| history.borrow().last_key_value().map(|(k, _)| k + 1).unwrap_or(0));
+    let metadata = format!("Airdrop #{}: {}", airdrop_id, memo.unwrap_or_default());
+    let mut result = AirdropResult::default();
+    let mut seen = std::collections::HashSet::new();
+    for recipient in recipients {
+        let recipient = recipient.trim().to_string();
+        let credited = if recipient.is_empty() {
+            Err("Recipient cannot be empty".to_string())
+        } else if !seen.insert(recipient.clone()) {
+            Err("Duplicate recipient".to_string())
+        } else {
+            credit_airdrop_recipient(&recipient, amount_each, &metadata)
+        };
+        match credited {
+            Ok(()) => result.success_count += 1,
+            Err(e) => {
+                result.failure_count += 1;
+                result.failures.push((recipient, e));
+            }
+        }
+    }
+
+    AIRDROP_HISTORY.with(|history| {
+        history.borrow_mut().insert(airdrop_id, AirdropRecord {
+            airdrop_id,
+            initiator,
+            recipients: result.success_count,
+            amount_each,
+            total: amount_each * result.success_count,
+            timestamp: time(),
+        });
+    });
+    Ok(result)
+}
+
+fn credit_airdrop_recipient(recipient: &str, amount: u64, metadata: &str) -> Result<(), String> {
+    if get_account(recipient.to_string()).is_none() {
+        create_account(recipient.to_string())?;
+    }
+    update_account_balance(recipient.to_string(), 0, amount as i64)?;
+    record_credit_activity(CreditActivity {
+        timestamp: time(),
+        principal_id: recipient.to_string(),
+        amount,
+        activity_type: CreditActivityType::Earn,
+        status: TransferStatus::Completed,
+        metadata: Some(metadata.to_string()),
+        service: None,
+    })
+}
+
+/// Airdrops, most recent first
+pub fn get_airdrop_history(offset: u64, limit: u64) -> Vec<AirdropRecord> {
+    AIRDROP_HISTORY.with(|history| {
+        history.borrow()
+            .iter()
+            .rev()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(|(_, record)| record)
+            .collect()
+    })
 }
 
 // Credit Usage
+/// Add or replace the fee rule for a service pattern
+pub fn set_credit_fee_rule(rule: CreditFeeRule) -> Result<(), String> {
+    let service_pattern = rule.service_pattern.trim().to_string();
+    if service_pattern.is_empty() {
+        return Err("Service pattern cannot be empty".to_string());
+    }
+    if !rule.multiplier.is_finite() || rule.multiplier < 0.0 {
+        return Err("Fee multiplier must be a non-negative number".to_string());
+    }
+    CREDIT_FEE_RULES.with(|rules| {
+        rules.borrow_mut().insert(service_pattern.clone(), CreditFeeRule { service_pattern, ..rule });
+    });
+    Ok(())
+}
+
+pub fn get_credit_fee_rule(service_pattern: String) -> Option<CreditFeeRule> {
+    CREDIT_FEE_RULES.with(|rules| rules.borrow().get(&service_pattern))
+}
+
+pub fn list_credit_fee_rules() -> Vec<CreditFeeRule> {
+    CREDIT_FEE_RULES.with(|rules| rules.borrow().iter().map(|(_, rule)| rule).collect())
+}
+
+pub fn delete_credit_fee_rule(service_pattern: String) -> Result<(), String> {
+    CREDIT_FEE_RULES.with(|rules| {
+        rules.borrow_mut()
+            .remove(&service_pattern)
+            .map(|_| ())
+            .ok_or_else(|| "Credit fee rule not found".to_string())
+    })
+}
+
+/// Enabled rule for a service: an exact match wins, otherwise the longest matching prefix
+fn find_credit_fee_rule(service: &str) -> Option<CreditFeeRule> {
+    CREDIT_FEE_RULES.with(|rules| {
+        let rules = rules.borrow();
+        if let Some(rule) = rules.get(&service.to_string()).filter(|rule| rule.enabled) {
+            return Some(rule);
+        }
+        rules.iter()
+            .map(|(_, rule)| rule)
+            .filter(|rule| rule.enabled && service.starts_with(rule.service_pattern.as_str()))
+            .max_by_key(|rule| rule.service_pattern.len())
+    })
+}
+
+/// Credits actually charged for spending `amount` on `service`; the requested amount when no rule applies
+pub fn compute_credit_fee(service: &str, amount: u64) -> u64 {
+    find_credit_fee_rule(service).map_or(amount, |rule| rule.fee_for(amount))
+}
+
 pub fn use_credits(principal_id: String, amount: u64, service: String, metadata: Option<String>) -> Result<AccountInfo, String> {
     let mut account = get_account(principal_id.clone())
         .ok_or_else(|| "Account not found".to_string())?;
+    let requested = amount;
+    let amount = compute_credit_fee(&service, requested);
     
     if account.get_credit_balance() < amount {
         return Err("Insufficient credit balance".to_string());
@@ -239,13 +628,181 @@ pub fn use_credits(principal_id: String, amount: u64, service: String, metadata:
         amount,
         activity_type: CreditActivityType::Spend,
         status: TransferStatus::Completed,
-        metadata: Some(format!("Credit usage for service: {} - {}", service, metadata.unwrap_or_default())),
+        metadata: Some(format!("Credit usage for service: {} - {} (requested: {}, fee: {})", service, metadata.unwrap_or_default(), requested, amount)),
+        service: Some(service),
     };
     record_credit_activity(activity)?;
     
     Ok(result)
 }
 
+/// Return credits charged for a service that was not delivered and record the refund
+pub fn refund_credits(principal_id: String, amount: u64, service: String, metadata: Option<String>) -> Result<AccountInfo, String> {
+    let mut account = get_account(principal_id.clone())
+        .ok_or_else(|| "Account not found".to_string())?;
+    account.token_info.credit_balance = account.token_info.credit_balance.saturating_add(amount);
+    account.updated_at = Some(time());
+    let result = upsert_account(account)?;
+
+    record_credit_activity(CreditActivity {
+        timestamp: time(),
+        principal_id,
+        amount,
+        activity_type: CreditActivityType::Refund,
+        status: TransferStatus::Completed,
+        metadata: Some(format!("Credit refund for service: {} - {}", service, metadata.unwrap_or_default())),
+        service: Some(service),
+    })?;
+    Ok(result)
+}
+
+/// Authorize `delegate` to spend up to `max_amount` of the delegator's credits until `expires_at`.
+/// Re-delegating to the same principal replaces the allowance and resets what was spent.
+pub fn delegate_credits(delegator: String, delegate: String, max_amount: u64, expires_at: u64) -> Result<(), String> {
+    if delegator == delegate {
+        return Err("Cannot delegate credits to yourself".to_string());
+    }
+    if max_amount == 0 {
+        return Err("Delegation amount must be greater than zero".to_string());
+    }
+    if expires_at <= time() {
+        return Err("Delegation expiry must be in the future".to_string());
+    }
+    get_account(delegator.clone()).ok_or_else(|| "Account not found".to_string())?;
+
+    CREDIT_DELEGATIONS.with(|delegations| {
+        delegations.borrow_mut().insert(
+            CreditDelegationKey { delegator: delegator.clone(), delegate: delegate.clone() },
+            CreditDelegation { delegator, delegate, max_amount, spent_amount: 0, expires_at },
+        );
+    });
+    Ok(())
+}
+
+pub fn revoke_delegation(delegator: String, delegate: String) -> Result<(), String> {
+    CREDIT_DELEGATIONS.with(|delegations| {
+        delegations.borrow_mut()
+            .remove(&CreditDelegationKey { delegator, delegate })
+            .map(|_| ())
+            .ok_or_else(|| "Delegation not found".to_string())
+    })
+}
+
+pub fn get_credit_delegation(delegator: String, delegate: String) -> Option<CreditDelegation> {
+    CREDIT_DELEGATIONS.with(|delegations| {
+        delegations.borrow().get(&CreditDelegationKey { delegator, delegate })
+    })
+}
+
+/// Spend a delegator's credits as `delegate`, within the delegation's remaining allowance
+pub fn use_credits_on_behalf(delegate: String, delegator: String, amount: u64, service: String, metadata: Option<String>) -> Result<AccountInfo, String> {
+    let mut delegation = get_credit_delegation(delegator.clone(), delegate.clone())
+        .ok_or_else(|| "No credit delegation from this account".to_string())?;
+    if time() >= delegation.expires_at {
+        return Err("Credit delegation has expired".to_string());
+    }
+    // The allowance is drawn down by the fee actually charged
+    let fee = compute_credit_fee(&service, amount);
+    let remaining = delegation.max_amount.saturating_sub(delegation.spent_amount);
+    if fee > remaining {
+        return Err(format!("Amount exceeds remaining delegated allowance of {}", remaining));
+    }
+
+    let metadata = Some(format!("delegate {}: {}", delegate, metadata.unwrap_or_default()));
+    let account = use_credits(delegator, amount, service, metadata)?;
+
+    delegation.spent_amount += fee;
+    CREDIT_DELEGATIONS.with(|delegations| {
+        delegations.borrow_mut().insert(
+            CreditDelegationKey { delegator: delegation.delegator.clone(), delegate },
+            delegation,
+        );
+    });
+    Ok(account)
+}
+
+/// Open a dispute against one of the principal's own credit spends. A rejected dispute may
+/// be reopened; an open or approved one may not.
+pub fn dispute_credit_usage(principal_id: String, activity_index: u64, reason: String) -> Result<(), String> {
+    let reason = crate::sanitize::sanitize_and_bound(&reason, crate::sanitize::MAX_REVIEW_COMMENT_LEN)?;
+    let activity = CREDIT_ACTIVITIES.with(|activities| activities.borrow().get(&activity_index))
+        .ok_or_else(|| "Credit activity not found".to_string())?;
+    if activity.principal_id != principal_id {
+        return Err("Credit activity does not belong to this account".to_string());
+    }
+    if activity.activity_type != CreditActivityType::Spend {
+        return Err("Only credit spends can be disputed".to_string());
+    }
+
+    let key = CreditDisputeKey { principal_id: principal_id.clone(), activity_index };
+    CREDIT_DISPUTES.with(|disputes| {
+        let mut disputes = disputes.borrow_mut();
+        if disputes.get(&key).is_some_and(|d| d.status != DisputeStatus::Rejected) {
+            return Err("Credit activity is already disputed".to_string());
+        }
+        disputes.insert(key, CreditDispute {
+            principal_id,
+            activity_index,
+            reason,
+            status: DisputeStatus::Open,
+            created_at: time(),
+        });
+        Ok(())
+    })
+}
+
+/// Close an open dispute. Approving it re-credits the disputed amount and records a refund.
+pub fn resolve_dispute(principal_id: String, activity_index: u64, approve: bool) -> Result<(), String> {
+    let mut dispute = get_credit_dispute(principal_id.clone(), activity_index)
+        .ok_or_else(|| "Dispute not found".to_string())?;
+    if dispute.status != DisputeStatus::Open {
+        return Err("Dispute is already resolved".to_string());
+    }
+
+    if approve {
+        let activity = CREDIT_ACTIVITIES.with(|activities| activities.borrow().get(&dispute.activity_index))
+            .ok_or_else(|| "Disputed credit activity no longer exists".to_string())?;
+        let mut account = get_account(dispute.principal_id.clone())
+            .ok_or_else(|| "Account not found".to_string())?;
+        account.token_info.credit_balance = account.token_info.credit_balance.saturating_add(activity.amount);
+        account.updated_at = Some(time());
+        upsert_account(account)?;
+
+        record_credit_activity(CreditActivity {
+            timestamp: time(),
+            principal_id: dispute.principal_id.clone(),
+            amount: activity.amount,
+            activity_type: CreditActivityType::Refund,
+            status: TransferStatus::Completed,
+            metadata: Some(format!("Refund for disputed credit activity {}", dispute.activity_index)),
+            service: activity.service,
+        })?;
+        dispute.status = DisputeStatus::Approved;
+    } else {
+        dispute.status = DisputeStatus::Rejected;
+    }
+
+    CREDIT_DISPUTES.with(|disputes| {
+        disputes.borrow_mut().insert(CreditDisputeKey { principal_id, activity_index }, dispute);
+    });
+    Ok(())
+}
+
+pub fn get_credit_dispute(principal_id: String, activity_index: u64) -> Option<CreditDispute> {
+    CREDIT_DISPUTES.with(|disputes| disputes.borrow().get(&CreditDisputeKey { principal_id, activity_index }))
+}
+
+pub fn get_credit_disputes_by_principal(principal_id: &str) -> Vec<CreditDispute> {
+    let start = CreditDisputeKey { principal_id: principal_id.to_string(), activity_index: 0 };
+    CREDIT_DISPUTES.with(|disputes| {
+        disputes.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.principal_id == principal_id)
+            .map(|(_, d)| d)
+            .collect()
+    })
+}
+
 // Token Grant Operations
 pub fn create_token_grant(grant: TokenGrant) -> Result<(), String> {
     NEWUSER_GRANTS.with(|grants| {
@@ -257,6 +814,16 @@ pub fn create_token_grant(grant: TokenGrant) -> Result<(), String> {
     })
 }
 
+/// Tell a recipient a grant was issued to them; `source` is "token" or the MCP name of an MCP grant
+pub fn notify_grant_recipient(recipient: &str, source: &str) -> Result<(), String> {
+    crate::society_profile_types::push_notification(
+        format!("grant:{}", source),
+        recipient.to_string(),
+        0,
+        crate::society_profile_types::NotificationCategory::Grant,
+    )
+}
+
 pub fn claim_grant(principal_id: &str) -> Result<u64, String> {
     // First check if the account exists
     let account = get_account(principal_id.to_string())
@@ -272,9 +839,9 @@ pub fn claim_grant(principal_id: &str) -> Result<u64, String> {
         return Err("Grant period has not started".to_string());
     }
 
-    let remaining_amount = grant.amount - grant.claimed_amount;
+    let remaining_amount = grant.vested_amount(current_time).saturating_sub(grant.claimed_amount);
     if remaining_amount == 0 {
-        return Err("No credits available to claim".to_string());
+        return Err("No vested credits available to claim".to_string());
     }
 
     // Update grant
@@ -288,6 +855,7 @@ pub fn claim_grant(principal_id: &str) -> Result<u64, String> {
     let new_credit_balance = account.get_credit_balance() + remaining_amount;
     account.token_info.credit_balance = (new_credit_balance as i64) as u64;
     account.updated_at = Some(current_time);
+    account.record_claim(remaining_amount, current_time);
     ic_cdk::println!("Account updated: {:?}", account);
     upsert_account(account)?;
 
@@ -299,6 +867,7 @@ pub fn claim_grant(principal_id: &str) -> Result<u64, String> {
         activity_type: CreditActivityType::Earn,
         status: TransferStatus::Completed,
         metadata: Some("Grant credit claim".to_string()),
+        service: None,
     };
     ic_cdk::println!("Record CreditActivity: {:?}", activity);
     record_credit_activity(activity)?;
@@ -306,6 +875,81 @@ pub fn claim_grant(principal_id: &str) -> Result<u64, String> {
     Ok(remaining_amount)
 }
 
+/// Last claim recorded on the account as (claim time ns, amount, claim timestamp s)
+pub fn get_last_claim_info(principal_id: &str) -> Option<(u64, u64, u64)> {
+    get_account(principal_id.to_string())?.get_last_claim_info()
+}
+
+/// Amount of the recipient's grant unlocked so far, claimed or not; 0 without a grant
+pub fn get_vested_amount(recipient: &str) -> u64 {
+    get_token_grant(recipient)
+        .map(|grant| grant.vested_amount(time()))
+        .unwrap_or(0)
+}
+
+/// Create (if needed) and claim the new-user grant. Principals without an account get one
+/// created first, the same way `recharge_and_convert_credits` does, so the claim never fails
+/// with "Account not found". Amount, cliff and vesting period of a freshly created grant come
+/// from settings (by default 1000 credits vesting over one year after a 30-day cliff), so the
+/// first call usually records the grant and claims 0.
+pub fn create_and_claim_newuser_grant(principal_id: &str) -> Result<u64, String> {
+    if get_account(principal_id.to_string()).is_none() {
+        upsert_account(AccountInfo::new(principal_id.to_string()))?;
+    }
+
+    match get_token_grant(principal_id) {
+        Some(grant) if grant.status == TokenGrantStatus::Active => claim_grant(principal_id),
+        Some(grant) => Err(format!("Grant exists but is not active. Current status: {:?}", grant.status)),
+        None => {
+            create_token_grant(TokenGrant {
+                recipient: principal_id.to_string(),
+                amount: settings::get_u64(settings::NEWUSER_GRANT_AMOUNT),
+                start_time: time(),
+                claimed_amount: 0,
+                status: TokenGrantStatus::Active,
+                cliff_duration_ns: Some(settings::get_u64(settings::NEWUSER_GRANT_CLIFF_NS)),
+                vesting_duration_ns: Some(settings::get_u64(settings::NEWUSER_GRANT_VESTING_NS)),
+            })?;
+            match get_vested_amount(principal_id) {
+                0 => Ok(0),
+                _ => claim_grant(principal_id),
+            }
+        }
+    }
+}
+
+/// Start times below this are not nanosecond timestamps: legacy new-user grants stored
+/// milliseconds and MCP grants stored units of 10 microseconds
+const LEGACY_GRANT_START_TIME_LIMIT: u64 = 10_000_000_000_000_000;
+
+/// Bring grants written before vesting existed onto nanosecond start times. Legacy new-user
+/// grants are recognised by their missing cliff and a start time below
+/// `LEGACY_GRANT_START_TIME_LIMIT`, and were already claimed in full, so they get no cliff or
+/// vesting period. Migrated and newer grants never match, so running this on every upgrade is safe.
+pub fn migrate_legacy_grants() {
+    let legacy_user_grants: Vec<(TokenGrantKey, TokenGrant)> = NEWUSER_GRANTS.with(|grants| {
+        grants.borrow().iter()
+            .filter(|(_, grant)| grant.cliff_duration_ns.is_none() && grant.start_time < LEGACY_GRANT_START_TIME_LIMIT)
+            .collect()
+    });
+    for (key, mut grant) in legacy_user_grants {
+        grant.start_time = grant.start_time.saturating_mul(1_000_000);
+        grant.cliff_duration_ns = Some(0);
+        grant.vesting_duration_ns = Some(0);
+        NEWUSER_GRANTS.with(|grants| grants.borrow_mut().insert(key, grant));
+    }
+
+    let legacy_mcp_grants: Vec<(NewMcpGrantKey, NewMcpGrant)> = NEWMCP_GRANTS.with(|grants| {
+        grants.borrow().iter()
+            .filter(|(_, grant)| grant.start_time > 0 && grant.start_time < LEGACY_GRANT_START_TIME_LIMIT)
+            .collect()
+    });
+    for (key, mut grant) in legacy_mcp_grants {
+        grant.start_time = grant.start_time.saturating_mul(10_000);
+        NEWMCP_GRANTS.with(|grants| grants.borrow_mut().insert(key, grant));
+    }
+}
+
 pub fn get_token_grant(recipient: &str) -> Option<TokenGrant> {
     NEWUSER_GRANTS.with(|grants| {
         let key = TokenGrantKey {
@@ -315,6 +959,15 @@ pub fn get_token_grant(recipient: &str) -> Option<TokenGrant> {
     })
 }
 
+/// Onboarding signal: a principal is new only if it has no new-user grant, no account
+/// and no profile. Users whose grant was cleaned up but who still have an account or
+/// profile are not new.
+pub fn is_new_user(principal_id: &str) -> bool {
+    get_token_grant(principal_id).is_none()
+        && get_account(principal_id.to_string()).is_none()
+        && crate::society_profile_types::get_user_profile_by_principal(principal_id.to_string()).is_none()
+}
+
 pub fn get_all_token_grants() -> Vec<TokenGrant> {
     NEWUSER_GRANTS.with(|grants| {
         grants.borrow()
@@ -324,6 +977,14 @@ pub fn get_all_token_grants() -> Vec<TokenGrant> {
     })
 }
 
+/// All new-user grants, read only while the instruction budget lasts
+pub fn get_all_token_grants_guarded(guard: &InstructionGuard) -> PagedResult<TokenGrant> {
+    let start_after = guard.start_after().map(|recipient| TokenGrantKey { recipient });
+    NEWUSER_GRANTS.with(|grants| {
+        guard.collect(grants.borrow().range(cursor_key_range(start_after)).map(|(key, grant)| (key.recipient, grant)))
+    })
+}
+
 pub fn get_token_grants_paginated(offset: u64, limit: usize) -> Vec<TokenGrant> {
     NEWUSER_GRANTS.with(|grants| {
         grants.borrow()
@@ -362,10 +1023,11 @@ pub fn get_token_grants_count() -> u64 {
 }
 
 // Activity Recording
+// Keys continue after the last stored record, since archiving can leave gaps below it
 pub fn record_token_activity(activity: TokenActivity) -> Result<(), String> {
     TOKEN_ACTIVITIES.with(|activities| {
         let mut activities = activities.borrow_mut();
-        let index = activities.len();
+        let index = activities.last_key_value().map(|(k, _)| k + 1).unwrap_or(0);
         activities.insert(index, activity);
         Ok(())
     })
@@ -374,12 +1036,80 @@ pub fn record_token_activity(activity: TokenActivity) -> Result<(), String> {
 pub fn record_credit_activity(activity: CreditActivity) -> Result<(), String> {
     CREDIT_ACTIVITIES.with(|activities| {
         let mut activities = activities.borrow_mut();
-        let index = activities.len();
+        let index = activities.last_key_value().map(|(k, _)| k + 1).unwrap_or(0);
         activities.insert(index, activity);
         Ok(())
     })
 }
 
+/// Move token and credit activities older than `cutoff_ns` into per-principal summaries
+/// and delete the detailed records. Returns the number of records archived.
+pub fn archive_activities_before(cutoff_ns: u64) -> Result<u64, String> {
+    let summary_for = |principal_id: &str| -> ActivityArchiveSummary {
+        get_activity_archive_summary(principal_id)
+            .unwrap_or_else(|| ActivityArchiveSummary { principal_id: principal_id.to_string(), ..Default::default() })
+    };
+
+    let old_tokens: Vec<(u64, TokenActivity)> = TOKEN_ACTIVITIES.with(|activities| {
+        activities.borrow().iter().filter(|(_, a)| a.timestamp < cutoff_ns).collect()
+    });
+    let old_credits: Vec<(u64, CreditActivity)> = CREDIT_ACTIVITIES.with(|activities| {
+        activities.borrow().iter().filter(|(_, a)| a.timestamp < cutoff_ns).collect()
+    });
+
+    let mut pending: HashMap<String, ActivityArchiveSummary> = HashMap::new();
+    for (_, activity) in &old_tokens {
+        let mut parties = vec![activity.from.clone()];
+        if activity.to != activity.from {
+            parties.push(activity.to.clone());
+        }
+        for party in parties {
+            let summary = pending.entry(party.clone()).or_insert_with(|| summary_for(&party));
+            summary.token_count += 1;
+            summary.token_amount += activity.amount;
+            if activity.status == TransferStatus::Completed {
+                summary.token_success_count += 1;
+            }
+        }
+    }
+    for (_, activity) in &old_credits {
+        let party = activity.principal_id.clone();
+        let summary = pending.entry(party.clone()).or_insert_with(|| summary_for(&party));
+        summary.credit_count += 1;
+        summary.credit_amount += activity.amount;
+        if activity.status == TransferStatus::Completed {
+            summary.credit_success_count += 1;
+        }
+    }
+
+    ACTIVITY_ARCHIVE.with(|archive| {
+        let mut archive = archive.borrow_mut();
+        for (principal_id, mut summary) in pending {
+            summary.archived_before = summary.archived_before.max(cutoff_ns);
+            archive.insert(principal_id, summary);
+        }
+    });
+    TOKEN_ACTIVITIES.with(|activities| {
+        let mut activities = activities.borrow_mut();
+        for (key, _) in &old_tokens {
+            activities.remove(key);
+        }
+    });
+    CREDIT_ACTIVITIES.with(|activities| {
+        let mut activities = activities.borrow_mut();
+        for (key, _) in &old_credits {
+            activities.remove(key);
+        }
+    });
+
+    Ok((old_tokens.len() + old_credits.len()) as u64)
+}
+
+/// Archived activity totals for a principal, if any of its activities were archived
+pub fn get_activity_archive_summary(principal_id: &str) -> Option<ActivityArchiveSummary> {
+    ACTIVITY_ARCHIVE.with(|archive| archive.borrow().get(&principal_id.to_string()))
+}
+
 // Query Methods
 pub fn get_account_token_info(principal_id: &str) -> Result<TokenInfo, String> {
     let account = get_account(principal_id.to_string())
@@ -443,11 +1173,13 @@ pub fn get_credit_activity_statistics(principal_id: &str) -> (u64, u64, u64) {
 }
 
 // Emission Policy Operations
+/// Store the default emission policy, seeded from the DEFAULT_BASE_RATE, DEFAULT_KAPPA_FACTOR
+/// and DEFAULT_STAKING_BONUS settings as they are at this point
 pub fn init_emission_policy() {
     let mut policy = EmissionPolicy {
-        base_rate: DEFAULT_BASE_RATE,
-        kappa_factor: DEFAULT_KAPPA_FACTOR,
-        staking_bonus: DEFAULT_STAKING_BONUS,
+        base_rate: settings::get_u64(settings::DEFAULT_BASE_RATE),
+        kappa_factor: settings::get_f64(settings::DEFAULT_KAPPA_FACTOR),
+        staking_bonus: settings::get_f64(settings::DEFAULT_STAKING_BONUS),
         subscription_multipliers: HashMap::new(),
         last_update_time: time(),
     };
@@ -527,6 +1259,130 @@ pub fn update_emission_policy(policy: EmissionPolicy) -> Result<(), String> {
     })
 }
 
+pub fn get_subscription_multiplier(plan: SubscriptionPlan) -> Result<f64, String> {
+    let policy = get_emission_policy()?;
+    Ok(policy.subscription_multipliers.get(&plan).copied().unwrap_or(1.0))
+}
+
+/// Set the emission multiplier of a subscription plan; callers check the Admin role
+pub fn set_subscription_multiplier(plan: SubscriptionPlan, value: f64) -> Result<(), String> {
+    if !value.is_finite() || value < 0.0 {
+        return Err("Subscription multiplier must be a non-negative number".to_string());
+    }
+    let mut policy = get_emission_policy()?;
+    policy.subscription_multipliers.insert(plan, value);
+    policy.last_update_time = time();
+    update_emission_policy(policy)
+}
+
+/// Minimum time between two emission claims of the same account
+pub fn get_emission_cooldown_ns() -> u64 {
+    settings::get_u64(settings::EMISSION_COOLDOWN_NS)
+}
+
+pub fn set_emission_cooldown_ns(cooldown_ns: u64) -> Result<(), String> {
+    settings::set_u64(settings::EMISSION_COOLDOWN_NS, cooldown_ns)
+}
+
+pub fn get_min_stake_amount() -> u64 {
+    settings::get_u64(settings::MIN_STAKE_AMOUNT)
+}
+
+pub fn set_min_stake_amount(amount: u64) -> Result<(), String> {
+    settings::set_u64(settings::MIN_STAKE_AMOUNT, amount)
+}
+
+/// Reject an emission claim while the account's last emission claim is still within the cooldown
+pub fn check_emission_cooldown(account: &AccountInfo, now: u64) -> Result<(), String> {
+    let Some(last_emission_time) = account.last_emission_time else {
+        return Ok(());
+    };
+    let next_claim_time = last_emission_time.saturating_add(get_emission_cooldown_ns());
+    if now < next_claim_time {
+        return Err(format!("Emission claim on cooldown for another {} ns", next_claim_time - now));
+    }
+    Ok(())
+}
+
+/// Mint the account's current emission (see `calculate_emission`) into its token balance,
+/// at most once per emission cooldown. Only accounts with staked credits earn emission.
+pub fn claim_emission(principal_id: &str) -> Result<u64, String> {
+    let mut account = get_account(principal_id.to_string())
+        .ok_or_else(|| "Account not found".to_string())?;
+    if account.get_staked_credits() == 0 {
+        return Err("Only accounts with staked credits earn emission".to_string());
+    }
+    let now = time();
+    check_emission_cooldown(&account, now)?;
+
+    let amount = calculate_emission(principal_id)?;
+    account.token_info.token_balance = account.token_info.token_balance.checked_add(amount)
+        .ok_or_else(|| "Token balance overflow".to_string())?;
+    account.last_emission_time = Some(now);
+    account.updated_at = Some(now);
+    upsert_account(account)?;
+
+    record_token_activity(TokenActivity {
+        timestamp: now,
+        from: "emission".to_string(),
+        to: principal_id.to_string(),
+        amount,
+        activity_type: TokenActivityType::Mint,
+        status: TransferStatus::Completed,
+        metadata: Some("Emission claim".to_string()),
+    })?;
+    record_minted(amount);
+    Ok(amount)
+}
+
+/// Persist the current emission of every account holding tokens, credits or stake.
+/// A later run in the same bucket overwrites that bucket's snapshots. Returns the number recorded.
+pub fn record_emission_snapshots(now: u64) -> Result<u64, String> {
+    let policy_version = get_emission_policy()?.last_update_time;
+    let bucket = now / EMISSION_SNAPSHOT_BUCKET_NS;
+    let mut recorded = 0u64;
+
+    for account in crate::account_storage::get_all_accounts() {
+        let info = &account.token_info;
+        if info.token_balance == 0 && info.credit_balance == 0 && info.staked_credits == 0 {
+            continue;
+        }
+        let emission_amount = calculate_emission(&account.principal_id)?;
+        EMISSION_SNAPSHOTS.with(|snapshots| {
+            snapshots.borrow_mut().insert(
+                EmissionSnapshotKey { bucket, principal_id: account.principal_id.clone() },
+                EmissionSnapshot {
+                    principal_id: account.principal_id.clone(),
+                    emission_amount,
+                    snapshot_time: now,
+                    policy_version,
+                },
+            );
+        });
+        recorded += 1;
+    }
+    Ok(recorded)
+}
+
+/// Snapshots of a principal taken between `start` and `end` (nanoseconds, inclusive), oldest first
+pub fn get_emission_snapshots_by_period(principal_id: &str, start: u64, end: u64) -> Vec<EmissionSnapshot> {
+    if start > end {
+        return Vec::new();
+    }
+    let first = EmissionSnapshotKey { bucket: start / EMISSION_SNAPSHOT_BUCKET_NS, principal_id: String::new() };
+    let last_bucket = end / EMISSION_SNAPSHOT_BUCKET_NS;
+    EMISSION_SNAPSHOTS.with(|snapshots| {
+        snapshots.borrow()
+            .range(first..)
+            .take_while(|(key, _)| key.bucket <= last_bucket)
+            .filter(|(key, snapshot)| {
+                key.principal_id == principal_id && snapshot.snapshot_time >= start && snapshot.snapshot_time <= end
+            })
+            .map(|(_, snapshot)| snapshot)
+            .collect()
+    })
+}
+
 // Activity Query Methods
 pub fn get_token_activities_paginated(principal_id: &str, offset: u64, limit: usize) -> Vec<TokenActivity> {
     TOKEN_ACTIVITIES.with(|activities| {
@@ -540,6 +1396,21 @@ pub fn get_token_activities_paginated(principal_id: &str, offset: u64, limit: us
     })
 }
 
+/// Get activities exchanged between two principals, in either direction
+pub fn get_token_activities_between(a: &str, b: &str, offset: u64, limit: usize) -> Vec<TokenActivity> {
+    TOKEN_ACTIVITIES.with(|activities| {
+        activities.borrow()
+            .iter()
+            .filter(|(_, activity)| {
+                (activity.from == a && activity.to == b) || (activity.from == b && activity.to == a)
+            })
+            .skip(offset as usize)
+            .take(limit)
+            .map(|(_, activity)| activity.clone())
+            .collect()
+    })
+}
+
 pub fn get_token_activities_by_type(principal_id: &str, activity_type: TokenActivityType) -> Vec<TokenActivity> {
     TOKEN_ACTIVITIES.with(|activities| {
         activities.borrow()
@@ -615,10 +1486,47 @@ pub fn log_credit_usage(principal_id: String, amount: u64, service: String, meta
         activity_type: CreditActivityType::Spend,
         status: TransferStatus::Completed,
         metadata: Some(format!("Credit usage for service: {} - {}", service, metadata.unwrap_or_default())),
+        service: Some(service),
     };
     record_credit_activity(activity)
 }
 
+/// Get spend activities recorded for a service
+pub fn get_credit_usage_by_service(service: &str, offset: u64, limit: usize) -> Vec<CreditActivity> {
+    CREDIT_ACTIVITIES.with(|activities| {
+        activities.borrow()
+            .iter()
+            .filter(|(_, activity)| {
+                activity.activity_type == CreditActivityType::Spend
+                    && activity.service.as_deref() == Some(service)
+            })
+            .skip(offset as usize)
+            .take(limit)
+            .map(|(_, activity)| activity.clone())
+            .collect()
+    })
+}
+
+/// Get services ranked by total credits spent, as (service, total_spent)
+pub fn get_top_services_by_spend(limit: usize) -> Vec<(String, u64)> {
+    let mut totals: HashMap<String, u64> = HashMap::new();
+    CREDIT_ACTIVITIES.with(|activities| {
+        for (_, activity) in activities.borrow().iter() {
+            if activity.activity_type != CreditActivityType::Spend {
+                continue;
+            }
+            if let Some(service) = activity.service {
+                *totals.entry(service).or_insert(0) += activity.amount;
+            }
+        }
+    });
+
+    let mut ranked: Vec<(String, u64)> = totals.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(limit);
+    ranked
+}
+
 // New MCP Grant Operations
 pub fn create_mcp_grant(grant: NewMcpGrant) -> Result<(), String> {
     NEWMCP_GRANTS.with(|grants| {
@@ -676,6 +1584,7 @@ pub fn claim_mcp_grant(principal_id: &str) -> Result<u64, String> {
     let new_credit_balance = account.get_credit_balance() + total_claimed;
     account.token_info.credit_balance = (new_credit_balance as i64) as u64;
     account.updated_at = Some(current_time);
+    account.record_claim(total_claimed, current_time);
     upsert_account(account)?;
 
     // Record credit activity
@@ -686,6 +1595,7 @@ pub fn claim_mcp_grant(principal_id: &str) -> Result<u64, String> {
         activity_type: CreditActivityType::Earn,
         status: TransferStatus::Completed,
         metadata: Some("MCP grant credit claim for all active grants".to_string()),
+        service: None,
     };
     record_credit_activity(activity)?;
 
@@ -711,6 +1621,18 @@ pub fn get_all_mcp_grants() -> Vec<NewMcpGrant> {
     })
 }
 
+/// All new-MCP grants, read only while the instruction budget lasts. Cursors are
+/// "recipient/mcp_name"; recipients are principal texts, which never contain '/'
+pub fn get_all_mcp_grants_guarded(guard: &InstructionGuard) -> PagedResult<NewMcpGrant> {
+    let start_after = guard.start_after().and_then(|cursor| {
+        cursor.split_once('/').map(|(recipient, mcp_name)| NewMcpGrantKey { recipient: recipient.to_string(), mcp_name: mcp_name.to_string() })
+    });
+    NEWMCP_GRANTS.with(|grants| {
+        guard.collect(grants.borrow().range(cursor_key_range(start_after))
+            .map(|(key, grant)| (format!("{}/{}", key.recipient, key.mcp_name), grant)))
+    })
+}
+
 pub fn get_mcp_grants_paginated(offset: u64, limit: usize) -> Vec<NewMcpGrant> {
     NEWMCP_GRANTS.with(|grants| {
         grants.borrow()
@@ -752,6 +1674,24 @@ pub fn get_mcp_grants_by_status(status: &TokenGrantStatus) -> Vec<NewMcpGrant> {
     })
 }
 
+pub fn get_mcp_grants_filtered(
+    status: Option<TokenGrantStatus>,
+    mcp_name: Option<String>,
+    offset: u64,
+    limit: usize,
+) -> Vec<NewMcpGrant> {
+    NEWMCP_GRANTS.with(|grants| {
+        grants.borrow()
+            .iter()
+            .filter(|(_, grant)| status.as_ref().is_none_or(|s| grant.status == *s))
+            .filter(|(_, grant)| mcp_name.as_ref().is_none_or(|m| grant.mcp_name == *m))
+            .skip(offset as usize)
+            .take(limit)
+            .map(|(_, grant)| grant)
+            .collect()
+    })
+}
+
 pub fn get_mcp_grants_count() -> u64 {
     NEWMCP_GRANTS.with(|grants| {
         grants.borrow().len() as u64
@@ -789,6 +1729,7 @@ pub fn claim_mcp_grant_with_mcpname(principal_id: &str, mcp_name: &str) -> Resul
     let new_credit_balance = account.get_credit_balance() + remaining_amount;
     account.token_info.credit_balance = (new_credit_balance as i64) as u64;
     account.updated_at = Some(current_time);
+    account.record_claim(remaining_amount, current_time);
     upsert_account(account)?;
 
     // Record credit activity
@@ -799,6 +1740,7 @@ pub fn claim_mcp_grant_with_mcpname(principal_id: &str, mcp_name: &str) -> Resul
         activity_type: CreditActivityType::Earn,
         status: TransferStatus::Completed,
         metadata: Some(format!("MCP grant credit claim for MCP: {}", mcp_name)),
+        service: None,
     };
     record_credit_activity(activity)?;
 
@@ -840,15 +1782,20 @@ pub enum ICRC1TransferResult {
     Err(ICRC1TransferError),
 }
 
+// Conversion contract used until an admin stores one
+fn default_credit_contract() -> CreditConvertContract {
+    CreditConvertContract {
+        price_credits: settings::get_f64(settings::DEFAULT_CREDIT_USD_PRICE),
+        price_icp: settings::get_f64(settings::DEFAULT_ICP_USD_PRICE),
+    }
+}
+
 /// Get how many Credits 1 ICP can exchange for currently
 pub fn get_credits_per_icp() -> u64 {
     CREDIT_CONVERT_CONTRACT.with(|store| {
         let store = store.borrow();
         let contract = store.get(&CREDIT_CONTRACT_KEY.to_string())
-            .unwrap_or(CreditConvertContract {
-                price_credits: DEFAULT_CREDIT_USD_PRICE,
-                price_icp: DEFAULT_ICP_USD_PRICE,
-            });
+            .unwrap_or_else(default_credit_contract);
         (contract.price_icp / contract.price_credits) as u64
     })
 }
@@ -861,10 +1808,7 @@ pub fn update_icp_usd_price(caller: Principal, new_price: f64) -> Result<(), Str
     CREDIT_CONVERT_CONTRACT.with(|store| {
         let mut store = store.borrow_mut();
         let mut contract = store.get(&CREDIT_CONTRACT_KEY.to_string())
-            .unwrap_or(CreditConvertContract {
-                price_credits: DEFAULT_CREDIT_USD_PRICE,
-                price_icp: DEFAULT_ICP_USD_PRICE,
-            });
+            .unwrap_or_else(default_credit_contract);
         contract.price_icp = new_price;
         store.insert(CREDIT_CONTRACT_KEY.to_string(), contract);
         Ok(())
@@ -876,18 +1820,40 @@ pub fn simulate_credit_from_icp(icp_amount: f64) -> u64 {
     CREDIT_CONVERT_CONTRACT.with(|store| {
         let store = store.borrow();
         let contract = store.get(&CREDIT_CONTRACT_KEY.to_string())
-            .unwrap_or(CreditConvertContract {
-                price_credits: DEFAULT_CREDIT_USD_PRICE,
-                price_icp: DEFAULT_ICP_USD_PRICE,
-            });
+            .unwrap_or_else(default_credit_contract);
         ((icp_amount * contract.price_icp) / contract.price_credits) as u64
     })
 }
 
-/// Actual recharge, write recharge record and update user balance
-pub fn recharge_and_convert_credits(caller: Principal, icp_amount: f64) -> u64 {
+/// Recharge from an on-chain ICP transfer: the transfer at `block_index` must go from the
+/// caller's default account to the configured recharge account; its amount is converted
+pub async fn recharge_with_icp_block(caller: Principal, block_index: u64) -> Result<u64, String> {
+    if is_recharge_block_used(block_index) {
+        return Err(format!("Block {} has already been used for a recharge", block_index));
+    }
+    let target = get_recharge_principal_account()
+        .ok_or_else(|| "Recharge account is not configured".to_string())?;
+    let to = crate::icp_ledger::recharge_account_identifier(&target)?;
+    let from = crate::icp_ledger::account_identifier(&caller, None);
+
+    let operation = crate::icp_ledger::query_block_operation(block_index).await?;
+    let e8s = crate::icp_ledger::verify_transfer(&operation, &from, &to)?;
+    recharge_and_convert_credits(caller, block_index, e8s as f64 / 100_000_000.0)
+}
+
+fn is_recharge_block_used(block_index: u64) -> bool {
+    RECHARGE_BLOCKS.with(|blocks| blocks.borrow().contains_key(&block_index))
+}
+
+/// Actual recharge, write recharge record and update user balance.
+/// Each ledger block can only be credited once.
+pub fn recharge_and_convert_credits(caller: Principal, block_index: u64, icp_amount: f64) -> Result<u64, String> {
+    // Re-checked here because the ledger lookup awaits and another call may have used the block meanwhile
+    if is_recharge_block_used(block_index) {
+        return Err(format!("Block {} has already been used for a recharge", block_index));
+    }
     let credits = simulate_credit_from_icp(icp_amount);
-    let now = ic_cdk::api::time();
+    let now = time();
     // Write recharge record
     RECHARGE_RECORDS.with(|records| {
         let mut records = records.borrow_mut();
@@ -897,8 +1863,10 @@ pub fn recharge_and_convert_credits(caller: Principal, icp_amount: f64) -> u64 {
             icp_amount,
             credits_obtained: credits,
             timestamp: now,
+            block_index: Some(block_index),
         };
         records.insert(id, record);
+        RECHARGE_BLOCKS.with(|blocks| blocks.borrow_mut().insert(block_index, id));
     });
     // Update user balance
     let principal_id = caller.to_text();
@@ -907,8 +1875,8 @@ pub fn recharge_and_convert_credits(caller: Principal, icp_amount: f64) -> u64 {
     let new_credit_balance = account.get_credit_balance() + credits;
     account.token_info.credit_balance = (new_credit_balance as i64) as u64;
     account.updated_at = Some(now);
-    upsert_account(account).ok();
-    credits
+    upsert_account(account)?;
+    Ok(credits)
 }
 
 /// Query user Credit balance
@@ -932,6 +1900,30 @@ pub fn get_recharge_history(principal: Principal, offset: u64, limit: u64) -> Ve
     })
 }
 
+/// Page through recharge records of all users in insertion order
+pub fn get_all_recharge_records_paginated(offset: u64, limit: u64) -> Vec<RechargeRecord> {
+    RECHARGE_RECORDS.with(|records| {
+        records.borrow()
+            .iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(|(_, rec)| rec)
+            .collect()
+    })
+}
+
+/// Aggregate recharge records of a user: (record_count, total_icp, total_credits)
+pub fn get_recharge_totals(principal: Principal) -> (u64, f64, u64) {
+    RECHARGE_RECORDS.with(|records| {
+        let records = records.borrow();
+        records.iter()
+            .filter(|(_, rec)| rec.user == principal)
+            .fold((0u64, 0f64, 0u64), |(count, icp, credits), (_, rec)| {
+                (count + 1, icp + rec.icp_amount, credits + rec.credits_obtained)
+            })
+    })
+}
+
 // ========== ICP Recharge Principal-Account Mapping Table CRUD ==========
 
 /// Add principal-account mapping (only one item allowed)
@@ -994,4 +1986,689 @@ pub fn list_recharge_principal_accounts() -> Vec<RechargePrincipalAccount> {
             vec![]
         }
     })
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_recharge_totals() {
+        let user = Principal::from_text("2vxsx-fae").unwrap();
+        let other = Principal::management_canister();
+        RECHARGE_RECORDS.with(|records| {
+            let mut records = records.borrow_mut();
+            records.insert(0, RechargeRecord { user, icp_amount: 1.5, credits_obtained: 150, timestamp: 1, block_index: None });
+            records.insert(1, RechargeRecord { user: other, icp_amount: 9.0, credits_obtained: 900, timestamp: 2, block_index: None });
+            records.insert(2, RechargeRecord { user, icp_amount: 2.0, credits_obtained: 200, timestamp: 3, block_index: None });
+        });
+
+        let (count, total_icp, total_credits) = get_recharge_totals(user);
+        assert_eq!(count, 2);
+        assert_eq!(total_icp, 3.5);
+        assert_eq!(total_credits, 350);
+    }
+
+    #[test]
+    fn test_get_all_recharge_records_paginated() {
+        let alice = Principal::from_text("2vxsx-fae").unwrap();
+        let bob = Principal::management_canister();
+        RECHARGE_RECORDS.with(|records| {
+            let mut records = records.borrow_mut();
+            records.insert(0, RechargeRecord { user: alice, icp_amount: 1.0, credits_obtained: 100, timestamp: 1, block_index: None });
+            records.insert(1, RechargeRecord { user: bob, icp_amount: 2.0, credits_obtained: 200, timestamp: 2, block_index: None });
+            records.insert(2, RechargeRecord { user: alice, icp_amount: 3.0, credits_obtained: 300, timestamp: 3, block_index: None });
+        });
+
+        let first = get_all_recharge_records_paginated(0, 2);
+        assert_eq!(first.iter().map(|r| r.timestamp).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(first[1].user, bob);
+        let second = get_all_recharge_records_paginated(2, 2);
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].credits_obtained, 300);
+        assert!(get_all_recharge_records_paginated(3, 2).is_empty());
+    }
+
+    #[test]
+    fn test_credit_delegation_limits() {
+        let mut account = funded_account("delegator", 0);
+        account.token_info.credit_balance = 1000;
+        upsert_account(account).unwrap();
+
+        assert!(delegate_credits("delegator".to_string(), "delegator".to_string(), 100, 10).is_err());
+        assert!(delegate_credits("delegator".to_string(), "helper".to_string(), 100, 0).is_err());
+        assert!(use_credits_on_behalf("helper".to_string(), "delegator".to_string(), 10, "svc".to_string(), None).is_err());
+
+        delegate_credits("delegator".to_string(), "helper".to_string(), 100, 10).unwrap();
+        let account = use_credits_on_behalf("helper".to_string(), "delegator".to_string(), 60, "svc".to_string(), None).unwrap();
+        assert_eq!(account.token_info.credit_balance, 940);
+        assert!(use_credits_on_behalf("helper".to_string(), "delegator".to_string(), 50, "svc".to_string(), None).is_err());
+        use_credits_on_behalf("helper".to_string(), "delegator".to_string(), 40, "svc".to_string(), None).unwrap();
+        assert_eq!(get_credit_delegation("delegator".to_string(), "helper".to_string()).unwrap().spent_amount, 100);
+
+        revoke_delegation("delegator".to_string(), "helper".to_string()).unwrap();
+        assert!(revoke_delegation("delegator".to_string(), "helper".to_string()).is_err());
+        assert!(use_credits_on_behalf("helper".to_string(), "delegator".to_string(), 1, "svc".to_string(), None).is_err());
+    }
+
+    #[test]
+    fn test_seed_total_minted_runs_once() {
+        upsert_account(funded_account("early_holder", 400)).unwrap();
+        seed_total_minted();
+        assert_eq!(get_total_minted(), 400);
+
+        // A counter back at 0 on a later upgrade is not re-seeded
+        TOTAL_MINTED.with(|total| total.borrow_mut().set(0).unwrap());
+        seed_total_minted();
+        assert_eq!(get_total_minted(), 0);
+    }
+
+    fn funded_account(principal_id: &str, token_balance: u64) -> AccountInfo {
+        AccountInfo {
+            principal_id: principal_id.to_string(),
+            token_info: TokenInfo {
+                token_balance,
+                credit_balance: 0,
+                staked_credits: 0,
+                kappa_multiplier: 1.0,
+            },
+            created_at: 0,
+            updated_at: None,
+            metadata: None,
+            last_claim_time: None,
+            last_claim_amount: None,
+            last_claim_timestamp: None,
+            last_emission_time: None,
+        }
+    }
+
+    #[test]
+    fn test_is_new_user_considers_profile_and_account() {
+        use crate::society_profile_types::{UserProfile, LoginMethod, LoginStatus, PrincipalKey};
+        use crate::stable_mem_storage::{USER_PROFILES, PRINCIPAL_INDEX};
+
+        assert!(is_new_user("fresh_user"));
+
+        // A profile without any grant (e.g. the grant was cleaned up) is not a new user
+        let profile = UserProfile {
+            user_id: "user_profiled".to_string(),
+            principal_id: "profiled_user".to_string(),
+            name: None,
+            nickname: "Profiled".to_string(),
+            login_method: LoginMethod::II,
+            login_status: LoginStatus::Authenticated,
+            email: None,
+            picture: None,
+            wallet_address: None,
+            devices: vec![],
+            passwd: None,
+            created_at: 1,
+            updated_at: 1,
+            metadata: None,
+            deleted_at: None,
+        };
+        let index = USER_PROFILES.with(|p| {
+            let p = p.borrow_mut();
+            let index = p.len();
+            p.push(&profile).unwrap();
+            index
+        });
+        PRINCIPAL_INDEX.with(|idx| {
+            idx.borrow_mut().insert(PrincipalKey { principal_id: "profiled_user".to_string() }, index);
+        });
+        assert!(get_token_grant("profiled_user").is_none());
+        assert!(!is_new_user("profiled_user"));
+
+        upsert_account(funded_account("account_only_user", 0)).unwrap();
+        assert!(!is_new_user("account_only_user"));
+    }
+
+    #[test]
+    fn test_convert_credits_to_tokens_uses_configured_rate() {
+        let mut account = funded_account("converter", 5);
+        account.token_info.credit_balance = 250;
+        upsert_account(account).unwrap();
+
+        assert!(set_credit_token_rate("admin".to_string(), 0).is_err());
+        set_credit_token_rate("admin".to_string(), 100).unwrap();
+        assert_eq!(get_credit_token_rate(), 100);
+
+        assert!(convert_credits_to_tokens("converter".to_string(), 99).is_err());
+        // Only whole tokens are minted; the 50 leftover credits are not burned
+        let account = convert_credits_to_tokens("converter".to_string(), 250).unwrap();
+        assert_eq!(account.token_info.credit_balance, 50);
+        assert_eq!(account.token_info.token_balance, 7);
+        assert!(convert_credits_to_tokens("converter".to_string(), 100).is_err());
+
+        let burns = get_credit_activities_by_type("converter", CreditActivityType::Burn);
+        assert_eq!(burns.iter().map(|a| a.amount).collect::<Vec<_>>(), vec![200]);
+        let mints = get_token_activities_by_type("converter", TokenActivityType::Mint);
+        assert_eq!(mints.iter().map(|a| a.amount).collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn test_lowering_min_stake_amount_allows_smaller_stake() {
+        let mut account = funded_account("staker", 0);
+        account.token_info.credit_balance = 500;
+        upsert_account(account).unwrap();
+
+        assert_eq!(get_min_stake_amount(), 100);
+        assert!(stack_credits("staker".to_string(), "stake_mcp".to_string(), 50).is_err());
+        assert!(set_min_stake_amount(0).is_err());
+
+        set_min_stake_amount(10).unwrap();
+        let account = stack_credits("staker".to_string(), "stake_mcp".to_string(), 50).unwrap();
+        assert_eq!(account.token_info.staked_credits, 50);
+        assert_eq!(account.token_info.credit_balance, 450);
+        assert!(mcp_asset_types::stack_mcp("stake_mcp".to_string(), "staker".to_string(), 5).is_err());
+    }
+
+    #[test]
+    fn test_transfer_deducts_fee() {
+        upsert_account(funded_account("sender", 100)).unwrap();
+        upsert_account(funded_account("receiver", 0)).unwrap();
+        upsert_account(funded_account("treasury", 0)).unwrap();
+        set_transfer_fee(5, "treasury".to_string()).unwrap();
+
+        let sender = transfer_tokens("sender".to_string(), "receiver".to_string(), 50).unwrap();
+        assert_eq!(sender.get_token_balance(), 45);
+        assert_eq!(get_account("receiver".to_string()).unwrap().get_token_balance(), 50);
+        assert_eq!(get_account("treasury".to_string()).unwrap().get_token_balance(), 5);
+
+        let activities = get_token_activities("treasury");
+        assert_eq!(activities.len(), 1);
+        assert_eq!(activities[0].amount, 5);
+    }
+
+    #[test]
+    fn test_update_account_balance_rejects_overflow() {
+        upsert_account(funded_account("whale", u64::MAX - 10)).unwrap();
+
+        let result = update_account_balance("whale".to_string(), i64::MAX, 0);
+        assert_eq!(result.unwrap_err(), "Token balance overflow");
+        assert_eq!(get_account("whale".to_string()).unwrap().get_token_balance(), u64::MAX - 10);
+
+        let account = update_account_balance("whale".to_string(), 10, 0).unwrap();
+        assert_eq!(account.get_token_balance(), u64::MAX);
+        assert_eq!(update_account_balance("whale".to_string(), i64::MIN, 0).unwrap().get_token_balance(), u64::MAX - i64::MIN.unsigned_abs());
+        assert_eq!(update_account_balance("whale".to_string(), 0, -1).unwrap_err(), "Insufficient credit balance");
+    }
+
+    #[test]
+    fn test_subscription_multiplier_applies_to_emission() {
+        init_emission_policy();
+        let mut account = funded_account("subscriber", 0);
+        account.metadata = Some(r#"{"subscription_plan":"Premium"}"#.to_string());
+        upsert_account(account).unwrap();
+        assert_eq!(get_subscription_multiplier(SubscriptionPlan::Premium), Ok(2.0));
+        assert_eq!(calculate_emission("subscriber"), Ok(settings::get_u64(settings::DEFAULT_BASE_RATE) * 2));
+
+        assert!(set_subscription_multiplier(SubscriptionPlan::Premium, -1.0).is_err());
+        set_subscription_multiplier(SubscriptionPlan::Premium, 2.5).unwrap();
+        assert_eq!(get_subscription_multiplier(SubscriptionPlan::Premium), Ok(2.5));
+        assert_eq!(calculate_emission("subscriber"), Ok(settings::get_u64(settings::DEFAULT_BASE_RATE) * 5 / 2));
+    }
+
+    #[test]
+    fn test_transfer_rejects_self_transfer() {
+        upsert_account(funded_account("sender", 100)).unwrap();
+
+        let result = transfer_tokens("sender".to_string(), "sender".to_string(), 40);
+        assert_eq!(result.unwrap_err(), "Cannot transfer tokens to the same account");
+        assert_eq!(get_account("sender".to_string()).unwrap().get_token_balance(), 100);
+        assert!(get_token_activities("sender").is_empty());
+    }
+
+    #[test]
+    fn test_transfer_rejects_when_fee_not_covered() {
+        upsert_account(funded_account("sender", 50)).unwrap();
+        upsert_account(funded_account("receiver", 0)).unwrap();
+        set_transfer_fee(5, "treasury".to_string()).unwrap();
+
+        assert!(transfer_tokens("sender".to_string(), "receiver".to_string(), 50).is_err());
+        assert_eq!(get_account("sender".to_string()).unwrap().get_token_balance(), 50);
+        assert_eq!(get_account("receiver".to_string()).unwrap().get_token_balance(), 0);
+
+        assert!(set_transfer_fee(1, " ".to_string()).is_err());
+
+        // A fee that would overflow the treasury is rejected before either balance moves
+        upsert_account(funded_account("treasury", u64::MAX)).unwrap();
+        assert!(transfer_tokens("sender".to_string(), "receiver".to_string(), 10).is_err());
+        assert_eq!(get_account("sender".to_string()).unwrap().get_token_balance(), 50);
+        assert_eq!(get_account("receiver".to_string()).unwrap().get_token_balance(), 0);
+    }
+
+    #[test]
+    fn test_get_token_activities_between() {
+        for (from, to, amount) in [("alice", "bob", 1), ("bob", "carol", 2), ("bob", "alice", 3), ("carol", "alice", 4)] {
+            record_token_activity(TokenActivity {
+                timestamp: 0,
+                from: from.to_string(),
+                to: to.to_string(),
+                amount,
+                activity_type: TokenActivityType::Transfer,
+                status: TransferStatus::Completed,
+                metadata: None,
+            }).unwrap();
+        }
+
+        let between = get_token_activities_between("alice", "bob", 0, 10);
+        assert_eq!(between.iter().map(|a| a.amount).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(get_token_activities_between("bob", "alice", 1, 10).len(), 1);
+    }
+
+    #[test]
+    fn test_credit_usage_by_service() {
+        log_credit_usage("user1".to_string(), 10, "search".to_string(), None).unwrap();
+        log_credit_usage("user2".to_string(), 30, "render".to_string(), None).unwrap();
+        log_credit_usage("user1".to_string(), 5, "search".to_string(), Some("retry".to_string())).unwrap();
+
+        let search = get_credit_usage_by_service("search", 0, 10);
+        assert_eq!(search.len(), 2);
+        assert!(search.iter().all(|a| a.service.as_deref() == Some("search")));
+        assert_eq!(get_credit_usage_by_service("render", 0, 10).len(), 1);
+
+        let ranked = get_top_services_by_spend(10);
+        assert_eq!(ranked, vec![("render".to_string(), 30), ("search".to_string(), 15)]);
+        assert_eq!(get_top_services_by_spend(1).len(), 1);
+    }
+
+    #[test]
+    fn test_get_mcp_grants_filtered() {
+        let grant = |recipient: &str, mcp_name: &str, status: TokenGrantStatus| NewMcpGrant {
+            recipient: recipient.to_string(),
+            amount: 100,
+            start_time: 0,
+            claimed_amount: 0,
+            mcp_name: mcp_name.to_string(),
+            status,
+        };
+        create_mcp_grant(grant("user1", "weather", TokenGrantStatus::Active)).unwrap();
+        create_mcp_grant(grant("user2", "weather", TokenGrantStatus::Completed)).unwrap();
+        create_mcp_grant(grant("user3", "weather", TokenGrantStatus::Active)).unwrap();
+        create_mcp_grant(grant("user1", "search", TokenGrantStatus::Active)).unwrap();
+
+        let active_weather = get_mcp_grants_filtered(Some(TokenGrantStatus::Active), Some("weather".to_string()), 0, 10);
+        assert_eq!(active_weather.len(), 2);
+        assert!(active_weather.iter().all(|g| g.mcp_name == "weather" && g.status == TokenGrantStatus::Active));
+
+        assert_eq!(get_mcp_grants_filtered(Some(TokenGrantStatus::Active), Some("weather".to_string()), 1, 10).len(), 1);
+        assert_eq!(get_mcp_grants_filtered(None, None, 0, 10).len(), 4);
+        assert_eq!(get_mcp_grants_filtered(None, Some("search".to_string()), 0, 10).len(), 1);
+    }
+
+    fn vesting_grant(recipient: &str, amount: u64, cliff_duration_ns: u64, vesting_duration_ns: u64) -> TokenGrant {
+        TokenGrant {
+            recipient: recipient.to_string(),
+            amount,
+            start_time: 0,
+            claimed_amount: 0,
+            status: TokenGrantStatus::Active,
+            cliff_duration_ns: Some(cliff_duration_ns),
+            vesting_duration_ns: Some(vesting_duration_ns),
+        }
+    }
+
+    #[test]
+    fn test_newuser_grant_creates_missing_account() {
+        assert!(get_account("fresh_user".to_string()).is_none());
+        assert_eq!(claim_grant("fresh_user"), Err("Account not found".to_string()));
+
+        // Still inside the 30-day cliff: the grant is recorded but nothing is credited yet
+        assert_eq!(create_and_claim_newuser_grant("fresh_user"), Ok(0));
+        let account = get_account("fresh_user".to_string()).unwrap();
+        assert_eq!(account.token_info.credit_balance, 0);
+        let grant = get_token_grant("fresh_user").unwrap();
+        assert_eq!(grant.amount, settings::get_u64(settings::NEWUSER_GRANT_AMOUNT));
+        assert_eq!(grant.cliff_duration_ns, Some(settings::get_u64(settings::NEWUSER_GRANT_CLIFF_NS)));
+        assert_eq!(grant.vesting_duration_ns, Some(settings::get_u64(settings::NEWUSER_GRANT_VESTING_NS)));
+        assert_eq!(get_vested_amount("fresh_user"), 0);
+    }
+
+    #[test]
+    fn test_updated_settings_drive_newuser_grant() {
+        settings::set_u64(settings::NEWUSER_GRANT_AMOUNT, 250).unwrap();
+        settings::set_u64(settings::NEWUSER_GRANT_CLIFF_NS, 0).unwrap();
+
+        create_and_claim_newuser_grant("tuned_user").unwrap();
+        let grant = get_token_grant("tuned_user").unwrap();
+        assert_eq!(grant.amount, 250);
+        assert_eq!(grant.cliff_duration_ns, Some(0));
+        // The override is stored, not just cached for one call
+        assert_eq!(settings::get_setting(settings::NEWUSER_GRANT_AMOUNT), Some(settings::SettingValue::Nat(250)));
+    }
+
+    #[test]
+    fn test_migrate_legacy_grants_to_nanoseconds() {
+        let legacy = TokenGrant { start_time: 1_700_000_000_000, cliff_duration_ns: None, vesting_duration_ns: None, ..vesting_grant("legacy_user", 1000, 0, 0) };
+        create_token_grant(legacy).unwrap();
+        create_mcp_grant(NewMcpGrant { recipient: "legacy_mcp".to_string(), amount: 10, start_time: 170_000_000_000_000, claimed_amount: 10, mcp_name: "old".to_string(), status: TokenGrantStatus::Active }).unwrap();
+
+        migrate_legacy_grants();
+        migrate_legacy_grants();
+        let grant = get_token_grant("legacy_user").unwrap();
+        assert_eq!(grant.start_time, 1_700_000_000_000_000_000);
+        assert_eq!((grant.cliff_duration_ns, grant.vesting_duration_ns), (Some(0), Some(0)));
+        assert_eq!(grant.vested_amount(grant.start_time), 1000);
+        let mcp_grant = NEWMCP_GRANTS.with(|grants| grants.borrow().get(&NewMcpGrantKey { recipient: "legacy_mcp".to_string(), mcp_name: "old".to_string() })).unwrap();
+        assert_eq!(mcp_grant.start_time, 1_700_000_000_000_000_000);
+
+        // Grants written in nanoseconds without a cliff are left as they are
+        let current = TokenGrant { start_time: 1_800_000_000_000_000_000, cliff_duration_ns: None, vesting_duration_ns: None, ..vesting_grant("current_user", 1000, 0, 0) };
+        create_token_grant(current).unwrap();
+        migrate_legacy_grants();
+        let grant = get_token_grant("current_user").unwrap();
+        assert_eq!((grant.start_time, grant.cliff_duration_ns), (1_800_000_000_000_000_000, None));
+    }
+
+    #[test]
+    fn test_grant_vested_amount_cliff_and_linear() {
+        let grant = TokenGrant { start_time: 100, ..vesting_grant("vester", 1000, 50, 200) };
+        assert_eq!(grant.vested_amount(0), 0);
+        assert_eq!(grant.vested_amount(149), 0);
+        assert_eq!(grant.vested_amount(150), 0);
+        assert_eq!(grant.vested_amount(200), 250);
+        assert_eq!(grant.vested_amount(250), 500);
+        assert_eq!(grant.vested_amount(350), 1000);
+        assert_eq!(grant.vested_amount(u64::MAX), 1000);
+
+        let no_vesting = vesting_grant("vester", 1000, 50, 0);
+        assert_eq!(no_vesting.vested_amount(49), 0);
+        assert_eq!(no_vesting.vested_amount(50), 1000);
+    }
+
+    #[test]
+    fn test_claim_grant_limited_to_vested_portion() {
+        upsert_account(funded_account("locked", 0)).unwrap();
+        create_token_grant(vesting_grant("locked", 1000, 10, 100)).unwrap();
+        assert_eq!(get_vested_amount("locked"), 0);
+        assert_eq!(claim_grant("locked"), Err("No vested credits available to claim".to_string()));
+
+        upsert_account(funded_account("unlocked", 0)).unwrap();
+        create_token_grant(vesting_grant("unlocked", 1000, 0, 0)).unwrap();
+        assert_eq!(get_vested_amount("unlocked"), 1000);
+        assert_eq!(claim_grant("unlocked"), Ok(1000));
+        assert_eq!(claim_grant("unlocked"), Err("No vested credits available to claim".to_string()));
+        assert_eq!(get_vested_amount("nobody"), 0);
+    }
+
+    #[test]
+    fn test_recharge_rejects_reused_block() {
+        let user = Principal::from_slice(&[9]);
+        let credits = recharge_and_convert_credits(user, 42, 1.0).unwrap();
+        assert_eq!(credits, simulate_credit_from_icp(1.0));
+        assert!(recharge_and_convert_credits(user, 42, 1.0).is_err());
+        assert_eq!(get_user_credit_balance(user), credits);
+        assert_eq!(get_recharge_history(user, 0, 10)[0].block_index, Some(42));
+    }
+
+    #[test]
+    fn test_get_last_claim_info_reflects_claim() {
+        upsert_account(funded_account("claimer", 0)).unwrap();
+        assert_eq!(get_last_claim_info("claimer"), None);
+
+        create_token_grant(vesting_grant("claimer", 500, 0, 0)).unwrap();
+        claim_grant("claimer").unwrap();
+        let (claim_time, amount, claim_timestamp) = get_last_claim_info("claimer").unwrap();
+        assert_eq!(amount, 500);
+        assert_eq!(claim_timestamp, claim_time / 1_000_000_000);
+    }
+
+    #[test]
+    fn test_archive_activities_preserves_totals() {
+        let token = |timestamp: u64, amount: u64| TokenActivity {
+            timestamp,
+            from: "alice".to_string(),
+            to: "bob".to_string(),
+            amount,
+            activity_type: TokenActivityType::Transfer,
+            status: TransferStatus::Completed,
+            metadata: None,
+        };
+        let credit = |timestamp: u64, amount: u64| CreditActivity {
+            timestamp,
+            principal_id: "alice".to_string(),
+            amount,
+            activity_type: CreditActivityType::Spend,
+            status: TransferStatus::Completed,
+            metadata: None,
+            service: None,
+        };
+        for (ts, amount) in [(10, 1), (20, 2), (30, 3)] {
+            record_token_activity(token(ts, amount)).unwrap();
+            record_credit_activity(credit(ts, amount * 10)).unwrap();
+        }
+        let (token_count, token_amount, _) = get_token_activity_statistics("alice");
+        let (credit_count, credit_amount, _) = get_credit_activity_statistics("alice");
+
+        assert_eq!(archive_activities_before(25), Ok(4));
+
+        assert_eq!(get_token_activities("alice").len(), 1);
+        assert_eq!(get_credit_activities("alice").len(), 1);
+        let summary = get_activity_archive_summary("alice").unwrap();
+        let (live_token_count, live_token_amount, _) = get_token_activity_statistics("alice");
+        let (live_credit_count, live_credit_amount, _) = get_credit_activity_statistics("alice");
+        assert_eq!(summary.token_count + live_token_count, token_count);
+        assert_eq!(summary.token_amount + live_token_amount, token_amount);
+        assert_eq!(summary.credit_count + live_credit_count, credit_count);
+        assert_eq!(summary.credit_amount + live_credit_amount, credit_amount);
+        assert_eq!(get_activity_archive_summary("bob").unwrap().token_amount, 3);
+
+        // New records must not overwrite the surviving ones after keys were freed
+        record_token_activity(token(40, 4)).unwrap();
+        assert_eq!(get_token_activities("alice").len(), 2);
+    }
+
+    #[test]
+    fn test_shorter_emission_cooldown_allows_earlier_reclaim() {
+        let hour: u64 = 60 * 60 * 1_000_000_000;
+        let mut account = funded_account("emitter", 0);
+        assert!(check_emission_cooldown(&account, hour).is_ok());
+
+        // Grant claims do not start the emission cooldown
+        account.record_claim(10, 0);
+        assert!(check_emission_cooldown(&account, hour).is_ok());
+
+        account.last_emission_time = Some(0);
+        assert_eq!(get_emission_cooldown_ns(), 24 * 60 * 60 * 1_000_000_000);
+        assert!(check_emission_cooldown(&account, hour).is_err());
+        assert!(check_emission_cooldown(&account, 24 * hour).is_ok());
+
+        set_emission_cooldown_ns(hour / 2).unwrap();
+        assert_eq!(get_emission_cooldown_ns(), hour / 2);
+        assert!(check_emission_cooldown(&account, hour).is_ok());
+    }
+
+    #[test]
+    fn test_claim_emission_respects_cooldown() {
+        init_emission_policy();
+        upsert_account(funded_account("claimer", 5)).unwrap();
+        assert!(claim_emission("claimer").unwrap_err().contains("staked"));
+        let mut account = get_account("claimer".to_string()).unwrap();
+        account.token_info.staked_credits = 100;
+        upsert_account(account).unwrap();
+        let emission = calculate_emission("claimer").unwrap();
+
+        assert_eq!(claim_emission("claimer"), Ok(emission));
+        assert_eq!(get_account("claimer".to_string()).unwrap().get_token_balance(), 5 + emission);
+        assert!(claim_emission("claimer").unwrap_err().contains("cooldown"));
+
+        set_emission_cooldown_ns(0).unwrap();
+        assert_eq!(claim_emission("claimer"), Ok(emission));
+        assert!(claim_emission("nobody").is_err());
+    }
+
+    #[test]
+    fn test_emission_snapshots_recorded_per_active_account() {
+        init_emission_policy();
+        let day = EMISSION_SNAPSHOT_BUCKET_NS;
+        upsert_account(funded_account("active", 50)).unwrap();
+        upsert_account(funded_account("idle", 0)).unwrap();
+
+        assert_eq!(record_emission_snapshots(day + 5), Ok(1));
+        assert_eq!(record_emission_snapshots(3 * day), Ok(1));
+        // Same bucket as the previous run: overwritten, not duplicated
+        assert_eq!(record_emission_snapshots(3 * day + 10), Ok(1));
+
+        let all = get_emission_snapshots_by_period("active", 0, u64::MAX);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].snapshot_time, day + 5);
+        assert_eq!(all[1].snapshot_time, 3 * day + 10);
+        assert_eq!(all[0].emission_amount, calculate_emission("active").unwrap());
+        assert_eq!(all[0].policy_version, get_emission_policy().unwrap().last_update_time);
+
+        assert_eq!(get_emission_snapshots_by_period("active", 2 * day, 4 * day).len(), 1);
+        assert_eq!(get_emission_snapshots_by_period("active", day + 6, 3 * day).len(), 0);
+        assert!(get_emission_snapshots_by_period("idle", 0, u64::MAX).is_empty());
+    }
+
+    #[test]
+    fn test_approved_dispute_refunds_credit_spend() {
+        let mut account = funded_account("disputer", 0);
+        account.token_info.credit_balance = 500;
+        upsert_account(account).unwrap();
+        use_credits("disputer".to_string(), 200, "svc".to_string(), None).unwrap();
+        let index = CREDIT_ACTIVITIES.with(|a| a.borrow().last_key_value().map(|(k, _)| k)).unwrap();
+
+        assert!(dispute_credit_usage("someone_else".to_string(), index, "not mine".to_string()).is_err());
+        dispute_credit_usage("disputer".to_string(), index, "Service failed".to_string()).unwrap();
+        assert!(dispute_credit_usage("disputer".to_string(), index, "again".to_string()).is_err());
+
+        let dispute = get_credit_disputes_by_principal("disputer").pop().unwrap();
+        assert_eq!(dispute.status, DisputeStatus::Open);
+        resolve_dispute("disputer".to_string(), index, true).unwrap();
+        assert!(resolve_dispute("disputer".to_string(), index, false).is_err());
+
+        assert_eq!(get_credit_dispute("disputer".to_string(), index).unwrap().status, DisputeStatus::Approved);
+        assert_eq!(get_account("disputer".to_string()).unwrap().token_info.credit_balance, 500);
+        let refunds = get_credit_activities_by_type("disputer", CreditActivityType::Refund);
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0].amount, 200);
+    }
+
+    #[test]
+    fn test_refund_credits_restores_balance() {
+        let mut account = funded_account("refunded", 0);
+        account.token_info.credit_balance = 50;
+        upsert_account(account).unwrap();
+        use_credits("refunded".to_string(), 20, "svc".to_string(), None).unwrap();
+        refund_credits("refunded".to_string(), 20, "svc".to_string(), None).unwrap();
+
+        assert_eq!(get_account("refunded".to_string()).unwrap().token_info.credit_balance, 50);
+        let refunds = get_credit_activities_by_type("refunded", CreditActivityType::Refund);
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0].service.as_deref(), Some("svc"));
+        assert!(refund_credits("nobody".to_string(), 1, "svc".to_string(), None).is_err());
+    }
+
+    #[test]
+    fn test_burn_tokens_reduces_circulating_supply() {
+        upsert_account(funded_account("burner", 300)).unwrap();
+        record_minted(300);
+
+        assert!(burn_tokens("burner".to_string(), 0, "nothing".to_string()).is_err());
+        assert!(burn_tokens("burner".to_string(), 301, "too much".to_string()).is_err());
+        assert!(burn_tokens("missing".to_string(), 1, "no account".to_string()).is_err());
+
+        let account = burn_tokens("burner".to_string(), 120, "Deflation".to_string()).unwrap();
+        assert_eq!(account.token_info.token_balance, 180);
+        assert_eq!(get_total_burned(), 120);
+        assert_eq!(get_circulating_supply(), 180);
+
+        let burns = get_token_activities_by_type("burner", TokenActivityType::Burn);
+        assert_eq!(burns.len(), 1);
+        assert_eq!(burns[0].metadata, Some("Deflation".to_string()));
+    }
+
+    #[test]
+    fn test_economy_dashboard_aggregates_accounts_and_spend() {
+        for (principal_id, staked) in [("staker_a", 300), ("staker_b", 0), ("staker_c", 700)] {
+            let mut account = funded_account(principal_id, 100);
+            account.token_info.credit_balance = 1000;
+            account.token_info.staked_credits = staked;
+            upsert_account(account).unwrap();
+            if staked > 0 {
+                crate::mcp_asset_types::stack_mcp("dashboard_mcp".to_string(), principal_id.to_string(), staked).unwrap();
+            }
+        }
+        use_credits("staker_a".to_string(), 250, "svc".to_string(), None).unwrap();
+        NEWMCP_GRANTS.with(|grants| grants.borrow_mut().insert(
+            NewMcpGrantKey { recipient: "staker_a".to_string(), mcp_name: "granted_mcp".to_string() },
+            NewMcpGrant { recipient: "staker_a".to_string(), amount: 40, start_time: 0, claimed_amount: 0, mcp_name: "granted_mcp".to_string(), status: TokenGrantStatus::Active },
+        ));
+
+        let dashboard = get_economy_dashboard();
+        assert_eq!(dashboard.total_accounts, 3);
+        assert_eq!(dashboard.total_token_balance, 300);
+        assert_eq!(dashboard.total_credit_balance, 2750);
+        assert_eq!(dashboard.total_staked_credits, 1000);
+        assert_eq!(dashboard.total_mcp_grants_issued, 40);
+        assert_eq!(dashboard.total_user_grants_issued, 0);
+        assert_eq!(dashboard.total_credit_spend_30d, 250);
+        assert_eq!(dashboard.top_10_stakers, vec![("staker_c".to_string(), 700), ("staker_a".to_string(), 300)]);
+    }
+
+    #[test]
+    fn test_credit_fee_rules_price_credit_spends() {
+        let mut account = funded_account("fee_payer", 0);
+        account.token_info.credit_balance = 1000;
+        upsert_account(account).unwrap();
+        let rule = |pattern: &str, base_fee: u64, multiplier: f64| CreditFeeRule {
+            service_pattern: pattern.to_string(), base_fee, multiplier, enabled: true,
+        };
+        assert!(set_credit_fee_rule(rule(" ", 1, 1.0)).is_err());
+        assert!(set_credit_fee_rule(rule("llm", 1, -1.0)).is_err());
+        set_credit_fee_rule(rule("llm", 5, 2.0)).unwrap();
+        set_credit_fee_rule(rule("llm/gpt", 10, 1.5)).unwrap();
+        set_credit_fee_rule(rule("llm/gpt-4", 0, 3.0)).unwrap();
+        set_credit_fee_rule(CreditFeeRule { enabled: false, ..rule("image", 100, 1.0) }).unwrap();
+
+        assert_eq!(compute_credit_fee("llm/gpt-4", 10), 30);
+        assert_eq!(compute_credit_fee("llm/gpt-3.5", 10), 25);
+        assert_eq!(compute_credit_fee("llm-small", 10), 25);
+        assert_eq!(compute_credit_fee("image", 10), 10);
+        assert_eq!(compute_credit_fee("search", 10), 10);
+
+        let account = use_credits("fee_payer".to_string(), 100, "llm/gpt-3.5".to_string(), None).unwrap();
+        assert_eq!(account.token_info.credit_balance, 840);
+        let spend = get_credit_activities_by_type("fee_payer", CreditActivityType::Spend).pop().unwrap();
+        assert_eq!(spend.amount, 160);
+        assert!(spend.metadata.unwrap().contains("fee: 160"));
+
+        delete_credit_fee_rule("llm/gpt".to_string()).unwrap();
+        assert!(delete_credit_fee_rule("llm/gpt".to_string()).is_err());
+        assert_eq!(list_credit_fee_rules().len(), 3);
+    }
+
+    #[test]
+    fn test_airdrop_credits_creates_accounts_and_reports_failures() {
+        let mut existing = funded_account("airdrop_existing", 0);
+        existing.token_info.credit_balance = 5;
+        upsert_account(existing).unwrap();
+
+        assert!(airdrop_credits("admin".to_string(), vec!["a".to_string()], 0, None).is_err());
+        assert!(airdrop_credits("admin".to_string(), vec![], 10, None).is_err());
+        let too_many = (0..=MAX_AIRDROP_RECIPIENTS).map(|i| format!("r{}", i)).collect();
+        assert!(airdrop_credits("admin".to_string(), too_many, 10, None).is_err());
+
+        let recipients = vec!["airdrop_existing".to_string(), "airdrop_new".to_string(), " ".to_string(), "airdrop_new".to_string()];
+        let result = airdrop_credits("admin".to_string(), recipients, 50, Some("launch".to_string())).unwrap();
+        assert_eq!(result.success_count, 2);
+        assert_eq!(result.failure_count, 2);
+        assert_eq!(result.failures[1], ("airdrop_new".to_string(), "Duplicate recipient".to_string()));
+
+        assert_eq!(get_account("airdrop_existing".to_string()).unwrap().token_info.credit_balance, 55);
+        assert_eq!(get_account("airdrop_new".to_string()).unwrap().token_info.credit_balance, 50);
+        let earn = get_credit_activities_by_type("airdrop_new", CreditActivityType::Earn).pop().unwrap();
+        assert_eq!(earn.amount, 50);
+        assert_eq!(earn.metadata.as_deref(), Some("Airdrop #0: launch"));
+
+        airdrop_credits("admin".to_string(), vec!["airdrop_new".to_string()], 7, None).unwrap();
+        let history = get_airdrop_history(0, 10);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].airdrop_id, 1);
+        assert_eq!(history[1], AirdropRecord {
+            airdrop_id: 0, initiator: "admin".to_string(), recipients: 2, amount_each: 50, total: 100, timestamp: history[1].timestamp,
+        });
+    }
+}
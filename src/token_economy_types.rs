@@ -9,6 +9,7 @@ use std::collections::HashMap;
 use candid::Principal;
 use num_traits::ToPrimitive;
 use crate::mining_reword::{RewardEntry, UserRewardKey};
+use crate::types::time;
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
@@ -25,6 +26,8 @@ pub enum TokenActivityType {
     Claim,
     Grant,
     Vest,
+    Mint,
+    Burn,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -45,6 +48,8 @@ pub enum CreditActivityType {
     Stack,
     Unstack,
     Reward,
+    Burn,
+    Refund,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -55,6 +60,7 @@ pub struct CreditActivity {
     pub activity_type: CreditActivityType,
     pub status: TransferStatus,
     pub metadata: Option<String>,
+    pub service: Option<String>, // Service the credits were spent on
 }
 
 // Token Economy Types
@@ -101,9 +107,27 @@ pub enum TokenGrantStatus {
 pub struct TokenGrant {
     pub recipient: String,
     pub amount: u64,
-    pub start_time: u64,
+    pub start_time: u64,          // Nanoseconds, as returned by ic_cdk::api::time()
     pub claimed_amount: u64,
     pub status: TokenGrantStatus,
+    pub cliff_duration_ns: Option<u64>,   // Nothing unlocks before start_time + cliff; None on legacy grants
+    pub vesting_duration_ns: Option<u64>, // Linear unlock period after the cliff; 0 or None unlocks everything at the cliff
+}
+
+impl TokenGrant {
+    /// Amount unlocked at `now`: amount * min(1, (now - start_time - cliff) / vesting_duration)
+    pub fn vested_amount(&self, now: u64) -> u64 {
+        let unlock_start = self.start_time.saturating_add(self.cliff_duration_ns.unwrap_or(0));
+        if now < unlock_start {
+            return 0;
+        }
+        let elapsed = now - unlock_start;
+        let vesting_duration_ns = self.vesting_duration_ns.unwrap_or(0);
+        if vesting_duration_ns == 0 || elapsed >= vesting_duration_ns {
+            return self.amount;
+        }
+        (self.amount as u128 * elapsed as u128 / vesting_duration_ns as u128) as u64
+    }
 }
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct NewMcpGrant {
@@ -143,12 +167,27 @@ pub struct AccountInfo {
     pub created_at: u64,
     pub updated_at: Option<u64>,
     pub metadata: Option<String>,
+    pub last_claim_time: Option<u64>,      // Nanoseconds, as returned by ic_cdk::api::time()
+    pub last_claim_amount: Option<u64>,
+    pub last_claim_timestamp: Option<u64>, // Seconds since the Unix epoch
+    pub last_emission_time: Option<u64>,   // Nanoseconds of the last emission claim
 }
 
 impl AccountInfo {
+    pub fn record_claim(&mut self, amount: u64, claim_time: u64) {
+        self.last_claim_time = Some(claim_time);
+        self.last_claim_amount = Some(amount);
+        self.last_claim_timestamp = Some(claim_time / 1_000_000_000);
+    }
+
+    pub fn get_last_claim_info(&self) -> Option<(u64, u64, u64)> {
+        Some((self.last_claim_time?, self.last_claim_amount?, self.last_claim_timestamp?))
+    }
+
     pub fn get_subscription_plan(&self) -> Option<SubscriptionPlan> {
-        // Parse from metadata or dedicated field
-        None // TODO: Implement based on your storage strategy
+        // Parsed from the account metadata JSON, e.g. {"subscription_plan": "Premium"}
+        let metadata: serde_json::Value = serde_json::from_str(self.metadata.as_deref()?).ok()?;
+        serde_json::from_value(metadata.get("subscription_plan")?.clone()).ok()
     }
 
     pub fn get_kappa_multiplier(&self) -> f64 {
@@ -176,9 +215,13 @@ impl AccountInfo {
                 staked_credits: 0u64,
                 kappa_multiplier: 1.0,
             },
-            created_at: ic_cdk::api::time(),
+            created_at: time(),
             updated_at: None,
             metadata: None,
+            last_claim_time: None,
+            last_claim_amount: None,
+            last_claim_timestamp: None,
+            last_emission_time: None,
         }
     }
 }
@@ -394,12 +437,256 @@ impl ic_stable_structures::Storable for CreditConvertContract {
     const BOUND: Bound = Bound::Bounded { max_size: 128, is_fixed_size: false };
 }
 
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TransferFeeConfig {
+    pub fee: u64,          // Tokens charged per transfer
+    pub treasury: String,  // Principal ID receiving the fee
+}
+
+impl ic_stable_structures::Storable for TransferFeeConfig {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode TransferFeeConfig"))
+    }
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode TransferFeeConfig")
+    }
+    const BOUND: Bound = Bound::Bounded { max_size: 256, is_fixed_size: false };
+}
+
+/// Exchange rate applied by convert_credits_to_tokens
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CreditTokenRate {
+    pub credits_per_token: u64,
+    pub updated_at: u64,
+    pub updated_by: String, // Principal ID of the admin who set the rate
+}
+
+impl Default for CreditTokenRate {
+    fn default() -> Self {
+        CreditTokenRate {
+            credits_per_token: 1,
+            updated_at: 0,
+            updated_by: String::new(),
+        }
+    }
+}
+
+impl ic_stable_structures::Storable for CreditTokenRate {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode CreditTokenRate"))
+    }
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode CreditTokenRate")
+    }
+    const BOUND: Bound = Bound::Bounded { max_size: 256, is_fixed_size: false };
+}
+
+/// Per-principal totals of activities removed by archive_activities_before
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ActivityArchiveSummary {
+    pub principal_id: String,
+    pub token_count: u64,
+    pub token_amount: u64,
+    pub token_success_count: u64,
+    pub credit_count: u64,
+    pub credit_amount: u64,
+    pub credit_success_count: u64,
+    pub archived_before: u64,  // Latest cutoff applied to this summary
+}
+
+impl ic_stable_structures::Storable for ActivityArchiveSummary {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode ActivityArchiveSummary"))
+    }
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode ActivityArchiveSummary")
+    }
+    const BOUND: Bound = Bound::Bounded { max_size: 512, is_fixed_size: false };
+}
+
+/// Emission computed for an account when the mining timer fired, kept for auditing
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EmissionSnapshot {
+    pub principal_id: String,
+    pub emission_amount: u64,
+    pub snapshot_time: u64,   // Nanoseconds
+    pub policy_version: u64,  // last_update_time of the emission policy in effect
+}
+
+impl ic_stable_structures::Storable for EmissionSnapshot {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode EmissionSnapshot"))
+    }
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode EmissionSnapshot")
+    }
+    const BOUND: Bound = Bound::Bounded { max_size: 512, is_fixed_size: false };
+}
+
+/// (snapshot_time_bucket, principal_id): one snapshot per account per bucket
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EmissionSnapshotKey {
+    pub bucket: u64,
+    pub principal_id: String,
+}
+
+impl ic_stable_structures::Storable for EmissionSnapshotKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.bucket, &self.principal_id).expect("Failed to encode EmissionSnapshotKey"))
+    }
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let (bucket, principal_id) = Decode!(bytes.as_ref(), u64, String).expect("Failed to decode EmissionSnapshotKey");
+        Self { bucket, principal_id }
+    }
+    const BOUND: Bound = Bound::Bounded { max_size: 256, is_fixed_size: false };
+}
+
+/// Allowance letting `delegate` spend up to `max_amount` of `delegator`'s credits until `expires_at`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CreditDelegation {
+    pub delegator: String,
+    pub delegate: String,
+    pub max_amount: u64,
+    pub spent_amount: u64,
+    pub expires_at: u64,      // Nanoseconds
+}
+
+impl ic_stable_structures::Storable for CreditDelegation {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode CreditDelegation"))
+    }
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode CreditDelegation")
+    }
+    const BOUND: Bound = Bound::Bounded { max_size: 512, is_fixed_size: false };
+}
+
+/// (delegator, delegate)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CreditDelegationKey {
+    pub delegator: String,
+    pub delegate: String,
+}
+
+impl ic_stable_structures::Storable for CreditDelegationKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.delegator, &self.delegate).expect("Failed to encode CreditDelegationKey"))
+    }
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let (delegator, delegate) = Decode!(bytes.as_ref(), String, String).expect("Failed to decode CreditDelegationKey");
+        Self { delegator, delegate }
+    }
+    const BOUND: Bound = Bound::Bounded { max_size: 256, is_fixed_size: false };
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisputeStatus {
+    Open,
+    Approved,
+    Rejected,
+}
+
+/// Disputes are keyed by principal first, so one principal's disputes form a contiguous
+/// range and each credit activity has at most one dispute
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CreditDisputeKey {
+    pub principal_id: String,
+    pub activity_index: u64,
+}
+
+impl ic_stable_structures::Storable for CreditDisputeKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self.principal_id, &self.activity_index).expect("Failed to encode CreditDisputeKey"))
+    }
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let (principal_id, activity_index) = Decode!(bytes.as_ref(), String, u64).expect("Failed to decode CreditDisputeKey");
+        Self { principal_id, activity_index }
+    }
+    const BOUND: Bound = Bound::Bounded { max_size: 256, is_fixed_size: false };
+}
+
+/// A principal's claim that a credit spend (an index into CREDIT_ACTIVITIES) should be refunded
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CreditDispute {
+    pub principal_id: String,
+    pub activity_index: u64,
+    pub reason: String,
+    pub status: DisputeStatus,
+    pub created_at: u64,
+}
+
+impl ic_stable_structures::Storable for CreditDispute {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode CreditDispute"))
+    }
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode CreditDispute")
+    }
+    const BOUND: Bound = Bound::Bounded { max_size: 2048, is_fixed_size: false };
+}
+
+/// Pricing for credit spends on services matching `service_pattern` (exact name, or prefix):
+/// fee = base_fee + amount * multiplier
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CreditFeeRule {
+    pub service_pattern: String,
+    pub base_fee: u64,
+    pub multiplier: f64,
+    pub enabled: bool,
+}
+
+impl CreditFeeRule {
+    pub fn fee_for(&self, amount: u64) -> u64 {
+        self.base_fee.saturating_add((amount as f64 * self.multiplier) as u64)
+    }
+}
+
+impl ic_stable_structures::Storable for CreditFeeRule {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode CreditFeeRule"))
+    }
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode CreditFeeRule")
+    }
+    const BOUND: Bound = Bound::Bounded { max_size: 512, is_fixed_size: false };
+}
+
+/// Outcome of one `airdrop_credits` call; `failures` pairs a recipient with its error
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AirdropResult {
+    pub success_count: u64,
+    pub failure_count: u64,
+    pub failures: Vec<(String, String)>,
+}
+
+/// Audit entry for an airdrop; `recipients` and `total` count only credited recipients
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AirdropRecord {
+    pub airdrop_id: u64,
+    pub initiator: String,
+    pub recipients: u64,
+    pub amount_each: u64,
+    pub total: u64,
+    pub timestamp: u64,
+}
+
+impl ic_stable_structures::Storable for AirdropRecord {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode AirdropRecord"))
+    }
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode AirdropRecord")
+    }
+    const BOUND: Bound = Bound::Bounded { max_size: 512, is_fixed_size: false };
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct RechargeRecord {
     pub user: Principal,
     pub icp_amount: f64,
     pub credits_obtained: u64,
     pub timestamp: u64,
+    pub block_index: Option<u64>, // ICP ledger block of the verified transfer
 }
 
 impl ic_stable_structures::Storable for RechargeRecord {
@@ -428,3 +715,17 @@ impl ic_stable_structures::Storable for RechargePrincipalAccount {
     const BOUND: Bound = Bound::Bounded { max_size: 512, is_fixed_size: false };
 }
 
+/// Economy-wide totals for the admin dashboard; grant totals are in tokens granted
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct EconomyDashboard {
+    pub total_accounts: u64,
+    pub total_token_balance: u64,
+    pub total_credit_balance: u64,
+    pub total_staked_credits: u64,
+    pub total_mcp_grants_issued: u64,
+    pub total_user_grants_issued: u64,
+    pub total_credit_spend_30d: u64,
+    pub top_10_stakers: Vec<(String, u64)>, // (principal_id, net MCP stake), highest first
+    pub emission_policy_base_rate: u64,
+    pub credits_per_icp: u64,
+}
@@ -1,22 +1,26 @@
 use candid::{CandidType, Deserialize};
+use serde::Serialize;
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, Storable};
 use ic_stable_structures::storable::Bound;
-use crate::stable_mem_storage::TRACE_STORAGE;
+use crate::stable_mem_storage::{TRACE_STORAGE, TRACE_COUNTER, TRACE_TIME_INDEX, RATE_LIMIT_MAP, AGENT_TRACE_INDEX, AGENT_EXECUTION_TOTALS, EXPORT_SALT};
+use crate::settings;
+use crate::types::{CursorPage, InstructionGuard, PagedResult, clamp_page_limit, clamp_page_size, cursor_key_range, time};
 use std::cell::RefCell;
 use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::fmt::Debug;
+use sha2::{Digest, Sha256};
 
 const TRACE_BUFFER_SIZE: usize = 100;
 
-#[derive(CandidType, Deserialize, Clone, PartialEq, Debug)]
+#[derive(CandidType, Deserialize, Serialize, Clone, PartialEq, Debug)]
 pub struct IOValue {
     pub data_type: String,
     pub value: IOValueType,
 }
 
-#[derive(CandidType, Deserialize, Clone, PartialEq, Debug)]
+#[derive(CandidType, Deserialize, Serialize, Clone, PartialEq, Debug)]
 pub enum IOValueType {
     Text(String),
     Number(f64),
@@ -26,7 +30,7 @@ pub enum IOValueType {
     Null,
 }
 
-#[derive(CandidType, Deserialize, Clone)]
+#[derive(CandidType, Deserialize, Serialize, Clone)]
 pub struct ProtocolCall {
     pub id: u32,
     pub protocol: String,
@@ -40,7 +44,7 @@ pub struct ProtocolCall {
     pub timestamp: u64,
 }
 
-#[derive(CandidType, Deserialize, Clone)]
+#[derive(CandidType, Deserialize, Serialize, Clone)]
 pub struct TraceLog {
     pub trace_id: String,
     pub context_id: String,
@@ -54,6 +58,40 @@ pub struct TraceStatistics {
     pub error_count: u64,
 }
 
+/// Call statistics of one agent across every call recorded for it
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AgentExecutionStats {
+    pub agent_name: String,
+    pub total_calls: u64,
+    pub success_rate: f64,          // Share of calls with status "ok", 0.0 when never called
+    pub average_latency_ns: u64,    // Mean gap between an agent call and the call before it in the same trace
+    pub last_called_at: Option<u64>,
+}
+
+/// Running call totals of one registered agent, updated as each call is recorded
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AgentExecutionTotals {
+    pub total_calls: u64,
+    pub success_count: u64,
+    pub latency_total_ns: u64,
+    pub latency_samples: u64,
+    pub last_called_at: Option<u64>,
+}
+
+impl AgentExecutionTotals {
+    fn add_call(&mut self, success: bool, timestamp: u64, latency_ns: Option<u64>) {
+        self.total_calls += 1;
+        if success {
+            self.success_count += 1;
+        }
+        self.last_called_at = self.last_called_at.max(Some(timestamp));
+        if let Some(latency_ns) = latency_ns {
+            self.latency_total_ns = self.latency_total_ns.saturating_add(latency_ns);
+            self.latency_samples += 1;
+        }
+    }
+}
+
 #[derive(CandidType, Deserialize, Clone, Hash, Eq, PartialEq)]
 pub struct TraceKey {
     pub trace_id: String,
@@ -71,6 +109,28 @@ impl PartialOrd for TraceKey {
     }
 }
 
+/// Secondary index entry ordering traces by creation time; the trace id keeps
+/// traces created in the same nanosecond distinct
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TraceTimeKey {
+    pub timestamp_ns: u64,
+    pub trace_id: String,
+}
+
+/// Secondary index entry linking a registered agent to a trace that called it
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AgentTraceKey {
+    pub agent_name: String,
+    pub trace_id: String,
+}
+
+/// Calls a principal has made to `record_trace_call` in its current rate-limit window
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RateLimitEntry {
+    pub count: u64,
+    pub window_start_ns: u64,
+}
+
 #[derive(CandidType, Deserialize, Clone)]
 pub struct TraceItem {
     pub trace_id: String,
@@ -86,8 +146,70 @@ pub struct TraceItem {
     pub timestamp: u64,
 }
 
+/// Common view over agent trace logs and work ledger traces
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct UnifiedTrace {
+    pub source: String,        // "trace_log" or "work_ledger"
+    pub trace_id: String,
+    pub context_id: String,
+    pub principal_id: String,
+    pub protocol: String,
+    pub agent: String,
+    pub method: String,
+    pub status: String,
+    pub timestamp: u64,
+}
+
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
+impl Storable for TraceTimeKey {
+    const BOUND: Bound = Bound::Bounded { max_size: 512, is_fixed_size: false };
+
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+impl Storable for AgentTraceKey {
+    const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false };
+
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+impl Storable for AgentExecutionTotals {
+    const BOUND: Bound = Bound::Bounded { max_size: 128, is_fixed_size: false };
+
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+impl Storable for RateLimitEntry {
+    const BOUND: Bound = Bound::Bounded { max_size: 64, is_fixed_size: false };
+
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
 impl Storable for IOValue {
     const BOUND: Bound = Bound::Bounded { max_size: 1024 * 1024, is_fixed_size: false }; // 1MB for IO value
 
@@ -160,6 +282,60 @@ impl Storable for TraceItem {
     }
 }
 
+/// Next value of the persistent trace sequence; never repeats, even across upgrades
+pub fn next_trace_sequence() -> u64 {
+    TRACE_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        let next = counter.get() + 1;
+        counter.set(next).expect("Failed to update trace counter");
+        next
+    })
+}
+
+/// Canister-side trace id: `{context_id}-{time}-{sequence}`
+pub fn generate_trace_id(context_id: &str) -> String {
+    format!("{}-{}-{}", context_id, time(), next_trace_sequence())
+}
+
+/// Count one `record_trace_call` for `principal_id`, starting a new window once the
+/// configured one has elapsed. Fails once the caller exceeds the per-window cap.
+pub fn check_trace_rate_limit(principal_id: &str) -> Result<(), String> {
+    let now = time();
+    let max_calls = settings::get_u64(settings::TRACE_RATE_LIMIT_CALLS);
+    let window_ns = settings::get_u64(settings::TRACE_RATE_LIMIT_WINDOW_NS);
+
+    RATE_LIMIT_MAP.with(|map| {
+        let mut map = map.borrow_mut();
+        let mut entry = map.get(&principal_id.to_string()).unwrap_or_default();
+        if now.saturating_sub(entry.window_start_ns) >= window_ns {
+            entry = RateLimitEntry { count: 0, window_start_ns: now };
+        }
+        if entry.count >= max_calls {
+            return Err("rate limit exceeded".to_string());
+        }
+        entry.count += 1;
+        map.insert(principal_id.to_string(), entry);
+        Ok(())
+    })
+}
+
+/// Rate-limit window state of a principal; zeroed if it has never recorded a trace
+pub fn get_rate_limit_status(principal_id: String) -> RateLimitEntry {
+    RATE_LIMIT_MAP.with(|map| map.borrow().get(&principal_id)).unwrap_or_default()
+}
+
+pub fn set_rate_limit_config(calls_per_window: u64, window_seconds: u64) -> Result<(), String> {
+    if calls_per_window == 0 || window_seconds == 0 {
+        return Err("Rate limit calls and window must be greater than zero".to_string());
+    }
+    let window_ns = window_seconds.checked_mul(1_000_000_000)
+        .ok_or_else(|| "Rate limit window is too large".to_string())?;
+    settings::set_u64(settings::TRACE_RATE_LIMIT_CALLS, calls_per_window)?;
+    settings::set_u64(settings::TRACE_RATE_LIMIT_WINDOW_NS, window_ns)
+}
+
+/// Append a call to a trace log and return the trace id it was stored under.
+/// `trace_id` is deprecated: an empty value makes the canister generate one via `generate_trace_id`.
 pub fn record_trace_call(
     trace_id: String,
     context_id: String,
@@ -171,13 +347,25 @@ pub fn record_trace_call(
     output: IOValue,
     status: String,
     error_message: Option<String>,
-) -> Result<(), String> {
+) -> Result<String, String> {
+    let trace_id = if trace_id.trim().is_empty() {
+        generate_trace_id(&context_id)
+    } else {
+        trace_id
+    };
+
+    let agent_name = agent.clone();
     TRACE_STORAGE.with(|storage| {
         let mut storage = storage.borrow_mut();
-        let mut trace_log = storage.get(&trace_id).unwrap_or_else(|| TraceLog {
-            trace_id: trace_id.clone(),
-            context_id,
-            calls: Vec::new(),
+        let mut trace_log = storage.get(&trace_id).unwrap_or_else(|| {
+            TRACE_TIME_INDEX.with(|index| {
+                index.borrow_mut().insert(TraceTimeKey { timestamp_ns: time(), trace_id: trace_id.clone() }, ());
+            });
+            TraceLog {
+                trace_id: trace_id.clone(),
+                context_id,
+                calls: Vec::new(),
+            }
         });
 
         let call = ProtocolCall {
@@ -190,7 +378,7 @@ pub fn record_trace_call(
             output,
             status,
             error_message,
-            timestamp: ic_cdk::api::time(),
+            timestamp: time(),
         };
 
         // Check if there is a duplicate record
@@ -205,24 +393,98 @@ pub fn record_trace_call(
         });
         ic_cdk::println!("existing_index: {:?}", existing_index);
 
-        if let Some(index) = existing_index {
+        let (success, timestamp) = (call.status == "ok", call.timestamp);
+        let position = if let Some(index) = existing_index {
             // if exists, update the record
             trace_log.calls[index] = call;
+            index
         } else {
             // if not exists, add new record
             trace_log.calls.push(call);
-        }
+            trace_log.calls.len() - 1
+        };
+        // Latency is the gap to the call before it in the same trace
+        let latency_ns = position.checked_sub(1).map(|p| timestamp.saturating_sub(trace_log.calls[p].timestamp));
 
         // Trim buffer if it exceeds maximum size
         if trace_log.calls.len() > TRACE_BUFFER_SIZE {
             trace_log.calls.drain(0..trace_log.calls.len() - TRACE_BUFFER_SIZE);
         }
 
-        storage.insert(trace_id, trace_log);
-        Ok(())
+        storage.insert(trace_id.clone(), trace_log);
+        Ok((trace_id, success, timestamp, latency_ns))
+    }).map(|(trace_id, success, timestamp, latency_ns)| {
+        index_agent_call(&agent_name, &trace_id, success, timestamp, latency_ns);
+        trace_id
     })
 }
 
+/// Link the trace to a registered agent so its history can be looked up by agent name,
+/// and add the call to the agent's running totals
+fn index_agent_call(agent_name: &str, trace_id: &str, success: bool, timestamp: u64, latency_ns: Option<u64>) {
+    if crate::agent_asset_types::get_agent_item_by_name(agent_name.to_string()).is_none() {
+        return;
+    }
+    let key = AgentTraceKey { agent_name: agent_name.to_string(), trace_id: trace_id.to_string() };
+    AGENT_TRACE_INDEX.with(|index| index.borrow_mut().insert(key, ()));
+    AGENT_EXECUTION_TOTALS.with(|totals| {
+        let mut totals = totals.borrow_mut();
+        let mut entry = totals.get(&agent_name.to_string()).unwrap_or_default();
+        entry.add_call(success, timestamp, latency_ns);
+        totals.insert(agent_name.to_string(), entry);
+    });
+}
+
+/// Build the running totals from the indexed traces when upgrading from a release that
+/// computed them on every query. Does nothing once any totals exist.
+pub fn seed_agent_execution_totals() {
+    if !AGENT_EXECUTION_TOTALS.with(|totals| totals.borrow().is_empty()) {
+        return;
+    }
+    let keys: Vec<AgentTraceKey> = AGENT_TRACE_INDEX.with(|index| index.borrow().iter().map(|(key, _)| key).collect());
+    let mut seeded: std::collections::BTreeMap<String, AgentExecutionTotals> = std::collections::BTreeMap::new();
+    for key in keys {
+        let Some(trace) = get_trace_by_id(key.trace_id) else { continue };
+        let entry = seeded.entry(key.agent_name.clone()).or_default();
+        for (i, call) in trace.calls.iter().enumerate().filter(|(_, call)| call.agent == key.agent_name) {
+            let latency_ns = i.checked_sub(1).map(|p| call.timestamp.saturating_sub(trace.calls[p].timestamp));
+            entry.add_call(call.status == "ok", call.timestamp, latency_ns);
+        }
+    }
+    AGENT_EXECUTION_TOTALS.with(|totals| {
+        let mut totals = totals.borrow_mut();
+        for (agent_name, entry) in seeded {
+            totals.insert(agent_name, entry);
+        }
+    });
+}
+
+/// Traces that called the agent, in trace id order; only the requested page is loaded
+pub fn get_traces_by_agent_name(agent_name: String, offset: u64, limit: u64) -> Vec<TraceLog> {
+    let start = AgentTraceKey { agent_name: agent_name.clone(), trace_id: String::new() };
+    let trace_ids: Vec<String> = AGENT_TRACE_INDEX.with(|index| {
+        index.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.agent_name == agent_name)
+            .skip(offset as usize)
+            .take(clamp_page_size(limit) as usize)
+            .map(|(key, _)| key.trace_id)
+            .collect()
+    });
+    trace_ids.into_iter().filter_map(get_trace_by_id).collect()
+}
+
+pub fn get_agent_execution_stats(agent_name: String) -> AgentExecutionStats {
+    let totals = AGENT_EXECUTION_TOTALS.with(|totals| totals.borrow().get(&agent_name)).unwrap_or_default();
+    AgentExecutionStats {
+        agent_name,
+        total_calls: totals.total_calls,
+        success_rate: if totals.total_calls > 0 { totals.success_count as f64 / totals.total_calls as f64 } else { 0.0 },
+        average_latency_ns: totals.latency_total_ns.checked_div(totals.latency_samples).unwrap_or(0),
+        last_called_at: totals.last_called_at,
+    }
+}
+
 pub fn get_trace_by_id(trace_id: String) -> Option<TraceLog> {
     TRACE_STORAGE.with(|storage| storage.borrow().get(&trace_id))
 }
@@ -248,6 +510,11 @@ pub fn get_all_trace_logs() -> Vec<TraceLog> {
 }
 
 
+/// All trace logs in key order, read only while the instruction budget lasts
+pub fn get_all_trace_logs_guarded(guard: &InstructionGuard) -> PagedResult<TraceLog> {
+    TRACE_STORAGE.with(|storage| guard.collect(storage.borrow().range(cursor_key_range(guard.start_after()))))
+}
+
 pub fn get_traces_by_protocol_name(protocol: String) -> Vec<TraceLog> {
     TRACE_STORAGE.with(|storage| {
         storage
@@ -274,6 +541,42 @@ pub fn get_traces_by_method_name(method: String) -> Vec<TraceLog> {
     })
 }
 
+/// Traces created within start_ns..=end_ns, oldest first, read through TRACE_TIME_INDEX
+pub fn get_traces_by_time_range(start_ns: u64, end_ns: u64, offset: u64, limit: u64) -> Vec<TraceLog> {
+    let start = TraceTimeKey { timestamp_ns: start_ns, trace_id: String::new() };
+    let trace_ids: Vec<String> = TRACE_TIME_INDEX.with(|index| {
+        index.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.timestamp_ns <= end_ns)
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(|(key, _)| key.trace_id)
+            .collect()
+    });
+    trace_ids.into_iter().filter_map(get_trace_by_id).collect()
+}
+
+/// Rebuild TRACE_TIME_INDEX from stored traces, using each trace's earliest call time
+/// (e.g. for traces recorded before the index existed)
+pub fn rebuild_trace_time_index() -> u64 {
+    TRACE_TIME_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        let keys: Vec<TraceTimeKey> = index.iter().map(|(k, _)| k).collect();
+        for key in keys {
+            index.remove(&key);
+        }
+    });
+    let traces = get_all_trace_logs();
+    TRACE_TIME_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for trace in &traces {
+            let timestamp_ns = trace.calls.iter().map(|call| call.timestamp).min().unwrap_or(0);
+            index.insert(TraceTimeKey { timestamp_ns, trace_id: trace.trace_id.clone() }, ());
+        }
+    });
+    traces.len() as u64
+}
+
 pub fn get_traces_by_status(status: String, offset: u64, limit: u64) -> Vec<TraceLog> {
     TRACE_STORAGE.with(|storage| {
         storage
@@ -468,6 +771,19 @@ pub fn get_traces_paginated( offset: u64, limit: u64) -> Vec<TraceLog> {
     })
 }
 
+/// Get traces ordered by trace id, starting after the cursor trace id
+pub fn get_traces_paginated_cursor(cursor: Option<String>, limit: usize) -> CursorPage<TraceLog> {
+    let limit = clamp_page_limit(limit);
+    TRACE_STORAGE.with(|storage| {
+        let entries = storage
+            .borrow()
+            .range(cursor_key_range(cursor))
+            .take(limit + 1)
+            .collect();
+        CursorPage::from_entries(entries, limit)
+    })
+}
+
 pub fn get_traces_for_mining_days(offset: u64, limit: u64) -> Vec<TraceItem> {
     let current_time = ic_cdk::api::time();
     let day_seconds = 24 * 60 * 60 * 1_000_000_000; // Nanoseconds in a day
@@ -521,5 +837,551 @@ pub fn update_trace_status(trace_id: String, status: String) -> Result<(), Strin
             Err("Trace not found".to_string())
         }
     })
-} 
+}
+
+/// Merge agent trace logs and work ledger traces involving a principal, sorted by time.
+/// Trace logs are scanned while the instruction budget lasts; work ledger traces are added
+/// on the page that finishes the scan
+pub fn get_all_traces_for_principal(principal_id: String, guard: &InstructionGuard) -> PagedResult<UnifiedTrace> {
+    let scanned = TRACE_STORAGE.with(|storage| {
+        guard.collect_matching(storage.borrow().range(cursor_key_range(guard.start_after())), |trace| {
+            trace.context_id == principal_id || trace.calls.iter().any(|call| call.agent == principal_id)
+        })
+    });
+
+    let mut traces: Vec<UnifiedTrace> = scanned.items.into_iter()
+        .flat_map(|trace| {
+            let context_match = trace.context_id == principal_id;
+            trace.calls.into_iter()
+                .filter(|call| context_match || call.agent == principal_id)
+                .map(|call| UnifiedTrace {
+                    source: "trace_log".to_string(),
+                    trace_id: trace.trace_id.clone(),
+                    context_id: trace.context_id.clone(),
+                    principal_id: principal_id.clone(),
+                    protocol: call.protocol,
+                    agent: call.agent,
+                    method: call.method,
+                    status: call.status,
+                    timestamp: call.timestamp,
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    if !scanned.truncated {
+        for item in crate::aio_workledger_types::get_user_traces(principal_id.clone()) {
+            traces.extend(item.calls.into_iter().map(|call| UnifiedTrace {
+                source: "work_ledger".to_string(),
+                trace_id: item.trace_id.clone(),
+                context_id: item.context_id.clone(),
+                principal_id: principal_id.clone(),
+                protocol: call.protocol,
+                agent: call.agent,
+                method: call.method,
+                status: call.status,
+                timestamp: item.created_at,
+            }));
+        }
+    }
+
+    traces.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.trace_id.cmp(&b.trace_id)));
+    PagedResult {
+        items: traces,
+        truncated: scanned.truncated,
+        items_processed: scanned.items_processed,
+        next_start: scanned.next_start,
+    }
+}
+
+const TRACE_CSV_HEADER: &str = "trace_id,context_id,call_id,protocol,agent,call_type,method,input,output,status,error_message,timestamp";
+const REDACTED: &str = "[redacted]";
+
+/// Seed the export salt from `raw_rand` unless it is already set. The salt never leaves
+/// the canister, so pseudonyms cannot be reversed by hashing candidate principals.
+pub async fn seed_export_salt() {
+    if !EXPORT_SALT.with(|salt| salt.borrow().get().is_empty()) {
+        return;
+    }
+    match ic_cdk::api::management_canister::main::raw_rand().await {
+        Ok((bytes,)) => EXPORT_SALT.with(|salt| {
+            let mut salt = salt.borrow_mut();
+            if salt.get().is_empty() {
+                salt.set(bytes).expect("Failed to save export salt");
+            }
+        }),
+        Err((code, msg)) => ic_cdk::println!("Export salt seeding failed: {:?} {}", code, msg),
+    }
+}
+
+fn export_salt() -> Result<Vec<u8>, String> {
+    let salt = EXPORT_SALT.with(|salt| salt.borrow().get().clone());
+    if salt.is_empty() {
+        return Err("Export salt is not seeded yet; retry shortly".to_string());
+    }
+    Ok(salt)
+}
+
+/// Stable pseudonym for a principal id: the same input always maps to the same value,
+/// so anonymized rows can still be grouped by caller
+pub fn anonymize_principal(salt: &[u8], principal_id: &str) -> String {
+    format!("anon-{}", pseudonym_hash(salt, b"principal:", principal_id))
+}
+
+/// Stable pseudonym for a trace id, which callers may have built from a principal;
+/// the calls of one trace keep sharing it
+pub fn anonymize_trace_id(salt: &[u8], trace_id: &str) -> String {
+    format!("trace-{}", pseudonym_hash(salt, b"trace-id:", trace_id))
+}
+
+fn pseudonym_hash(salt: &[u8], domain: &[u8], value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(domain);
+    hasher.update(value.as_bytes());
+    hex::encode(hasher.finalize())[..16].to_string()
+}
+
+fn io_value_to_string(value: &IOValue) -> String {
+    match &value.value {
+        IOValueType::Text(s) | IOValueType::Object(s) | IOValueType::Array(s) => s.clone(),
+        IOValueType::Number(n) => n.to_string(),
+        IOValueType::Boolean(b) => b.to_string(),
+        IOValueType::Null => String::new(),
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Export one page of trace logs as CSV, one row per protocol call.
+/// With `anonymize`, context ids and agents are replaced by `anonymize_principal`, trace ids
+/// by `anonymize_trace_id`, and inputs, outputs and error messages are redacted.
+/// Anonymized exports fail until the export salt has been seeded.
+pub fn export_traces_csv(offset: u64, limit: u64, anonymize: bool) -> Result<String, String> {
+    let salt = if anonymize { Some(export_salt()?) } else { None };
+    let mut csv = String::from(TRACE_CSV_HEADER);
+    for trace in get_traces_paginated(offset, limit) {
+        let context_id = match &salt { Some(salt) => anonymize_principal(salt, &trace.context_id), None => trace.context_id.clone() };
+        let trace_id = match &salt { Some(salt) => anonymize_trace_id(salt, &trace.trace_id), None => trace.trace_id.clone() };
+        for call in &trace.calls {
+            let (agent, input, output, error_message) = if let Some(salt) = &salt {
+                let error_message = call.error_message.as_ref().map(|_| REDACTED.to_string()).unwrap_or_default();
+                (anonymize_principal(salt, &call.agent), REDACTED.to_string(), REDACTED.to_string(), error_message)
+            } else {
+                (
+                    call.agent.clone(),
+                    io_value_to_string(&call.input),
+                    io_value_to_string(&call.output),
+                    call.error_message.clone().unwrap_or_default(),
+                )
+            };
+            let row = [
+                trace_id.clone(),
+                context_id.clone(),
+                call.id.to_string(),
+                call.protocol.clone(),
+                agent,
+                call.call_type.clone(),
+                call.method.clone(),
+                input,
+                output,
+                call.status.clone(),
+                error_message,
+                call.timestamp.to_string(),
+            ];
+            csv.push('\n');
+            csv.push_str(&row.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(","));
+        }
+    }
+    Ok(csv)
+}
+
+/// Largest NDJSON export returned by one call, including the truncation line
+const NDJSON_MAX_BYTES: usize = 1024 * 1024;
+/// Traces serialized per NDJSON export call, to stay within the query instruction limit
+const NDJSON_MAX_TRACES: usize = 10_000;
+// Room kept free for the truncation line
+const NDJSON_TRAILER_RESERVE: usize = 256;
+
+/// Export traces created within start_ns..=end_ns as newline-delimited JSON, oldest first.
+/// A trace too large to ever fit in one export is replaced by a `{"trace_id":...,"error":...}` line.
+/// When the 1 MB or 10,000 trace cap is hit, the last line is
+/// `{"error":...,"truncated":true,"next_start_ns":...,"next_trace_id":...}`; call again with
+/// that `next_start_ns` and `next_trace_id` to continue without skipping or repeating traces.
+pub fn export_traces_ndjson(start_ns: u64, end_ns: u64, start_trace_id: Option<String>) -> String {
+    export_traces_ndjson_capped(start_ns, end_ns, start_trace_id, NDJSON_MAX_BYTES, NDJSON_MAX_TRACES)
+}
+
+fn export_traces_ndjson_capped(start_ns: u64, end_ns: u64, start_trace_id: Option<String>, max_bytes: usize, max_traces: usize) -> String {
+    let start = TraceTimeKey { timestamp_ns: start_ns, trace_id: start_trace_id.unwrap_or_default() };
+    let keys: Vec<TraceTimeKey> = TRACE_TIME_INDEX.with(|index| {
+        index.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.timestamp_ns <= end_ns)
+            .take(max_traces + 1)
+            .map(|(key, _)| key)
+            .collect()
+    });
 
+    let line_limit = max_bytes.saturating_sub(NDJSON_TRAILER_RESERVE);
+    let mut ndjson = String::new();
+    for (exported, key) in keys.iter().enumerate() {
+        let error = if exported == max_traces {
+            Some(format!("Trace limit of {} reached", max_traces))
+        } else {
+            let Some(trace) = get_trace_by_id(key.trace_id.clone()) else {
+                continue;
+            };
+            let mut line = serde_json::to_string(&trace).unwrap_or_else(|e| {
+                serde_json::json!({ "trace_id": trace.trace_id, "error": e.to_string() }).to_string()
+            });
+            if line.len() + 1 > line_limit {
+                line = serde_json::json!({
+                    "trace_id": trace.trace_id,
+                    "error": format!("Trace of {} bytes exceeds the {} byte export limit", line.len(), max_bytes),
+                }).to_string();
+            }
+            if ndjson.len() + line.len() + 1 > line_limit {
+                Some(format!("Output limit of {} bytes reached", max_bytes))
+            } else {
+                ndjson.push_str(&line);
+                ndjson.push('\n');
+                None
+            }
+        };
+        if let Some(error) = error {
+            ndjson.push_str(&serde_json::json!({
+                "error": error,
+                "truncated": true,
+                "next_start_ns": key.timestamp_ns,
+                "next_trace_id": key.trace_id,
+            }).to_string());
+            ndjson.push('\n');
+            break;
+        }
+    }
+    ndjson
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aio_workledger_types::{add_trace, CallItem, TraceItem as LedgerTraceItem};
+
+    #[test]
+    fn test_get_all_traces_for_principal_merges_sources() {
+        let principal = "user-1".to_string();
+        TRACE_STORAGE.with(|storage| {
+            storage.borrow_mut().insert("finance_trace".to_string(), TraceLog {
+                trace_id: "finance_trace".to_string(),
+                context_id: principal.clone(),
+                calls: vec![ProtocolCall {
+                    id: 1,
+                    protocol: "account".to_string(),
+                    agent: principal.clone(),
+                    call_type: "admin".to_string(),
+                    method: "delete_account".to_string(),
+                    input: IOValue { data_type: "null".to_string(), value: IOValueType::Null },
+                    output: IOValue { data_type: "null".to_string(), value: IOValueType::Null },
+                    status: "ok".to_string(),
+                    error_message: None,
+                    timestamp: 200,
+                }],
+            });
+        });
+        add_trace(LedgerTraceItem {
+            context_id: "ctx".to_string(),
+            trace_id: "agent_trace".to_string(),
+            owner: principal.clone(),
+            created_at: 100,
+            updated_at: 100,
+            calls: vec![CallItem {
+                id: 1,
+                protocol: "aio".to_string(),
+                agent: "weather_agent".to_string(),
+                call_type: "tool".to_string(),
+                method: "forecast".to_string(),
+                inputs: vec![],
+                outputs: vec![],
+                status: "ok".to_string(),
+            }],
+            metadata: None,
+        }).unwrap();
+
+        let traces = get_all_traces_for_principal(principal, &InstructionGuard::new(None)).items;
+        assert_eq!(traces.len(), 2);
+        assert_eq!(traces[0].source, "work_ledger");
+        assert_eq!(traces[0].method, "forecast");
+        assert_eq!(traces[1].source, "trace_log");
+        assert_eq!(traces[1].method, "delete_account");
+    }
+
+    #[test]
+    fn test_traces_cursor_pagination_survives_concurrent_insert() {
+        let insert = |id: &str| {
+            TRACE_STORAGE.with(|storage| {
+                storage.borrow_mut().insert(id.to_string(), TraceLog {
+                    trace_id: id.to_string(),
+                    context_id: "ctx".to_string(),
+                    calls: vec![],
+                });
+            });
+        };
+        for id in ["t1", "t3", "t5"] {
+            insert(id);
+        }
+
+        let first = get_traces_paginated_cursor(None, 2);
+        assert_eq!(first.items.iter().map(|t| t.trace_id.as_str()).collect::<Vec<_>>(), vec!["t1", "t3"]);
+        assert_eq!(first.next_cursor, Some("t3".to_string()));
+
+        // An insert before the cursor must not shift or duplicate the next page
+        insert("t0");
+        insert("t4");
+        let second = get_traces_paginated_cursor(first.next_cursor, 2);
+        assert_eq!(second.items.iter().map(|t| t.trace_id.as_str()).collect::<Vec<_>>(), vec!["t4", "t5"]);
+        assert_eq!(second.next_cursor, None);
+    }
+
+    #[test]
+    fn test_export_traces_csv_anonymized() {
+        let principal = "2vxsx-fae-secret-principal";
+        let call = |id: u32, method: &str| ProtocolCall {
+            id,
+            protocol: "aio".to_string(),
+            agent: principal.to_string(),
+            call_type: "tool".to_string(),
+            method: method.to_string(),
+            input: IOValue { data_type: "text".to_string(), value: IOValueType::Text("card=4111, cvv \"123\"".to_string()) },
+            output: IOValue { data_type: "text".to_string(), value: IOValueType::Text("sensitive output".to_string()) },
+            status: "ok".to_string(),
+            error_message: None,
+            timestamp: 10,
+        };
+        TRACE_STORAGE.with(|storage| {
+            let mut storage = storage.borrow_mut();
+            for (trace_id, method) in [("export_a_2vxsx-fae-secret-principal", "pay"), ("export_b", "refund")] {
+                storage.insert(trace_id.to_string(), TraceLog {
+                    trace_id: trace_id.to_string(),
+                    context_id: principal.to_string(),
+                    calls: vec![call(1, method)],
+                });
+            }
+        });
+
+        let raw = export_traces_csv(0, 100, false).unwrap();
+        assert!(raw.contains(principal));
+        assert!(raw.contains("\"card=4111, cvv \"\"123\"\"\""));
+
+        // Anonymizing without a salt would make pseudonyms reversible, so it is refused
+        assert!(export_traces_csv(0, 100, true).is_err());
+        let salt = vec![7u8; 32];
+        EXPORT_SALT.with(|cell| cell.borrow_mut().set(salt.clone()).unwrap());
+
+        let anonymized = export_traces_csv(0, 100, true).unwrap();
+        assert!(!anonymized.contains(principal));
+        assert!(!anonymized.contains("card=4111"));
+        assert!(!anonymized.contains("sensitive output"));
+
+        // Both rows carry the same pseudonym, so they remain correlatable
+        let pseudonym = anonymize_principal(&salt, principal);
+        assert_ne!(pseudonym, anonymize_principal(&[8u8; 32], principal));
+        let rows: Vec<&str> = anonymized.lines().skip(1).collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|row| row.split(',').nth(1) == Some(pseudonym.as_str())));
+        assert!(rows.iter().all(|row| row.split(',').nth(4) == Some(pseudonym.as_str())));
+        assert!(!anonymized.contains("export_b"));
+        assert_ne!(rows[0].split(',').next(), rows[1].split(',').next());
+    }
+
+    #[test]
+    fn test_trace_rate_limit_caps_calls_per_window() {
+        assert!(set_rate_limit_config(0, 60).is_err());
+        assert!(set_rate_limit_config(3, u64::MAX).is_err());
+        set_rate_limit_config(3, 60).unwrap();
+
+        for _ in 0..3 {
+            check_trace_rate_limit("flooder").unwrap();
+        }
+        assert_eq!(check_trace_rate_limit("flooder"), Err("rate limit exceeded".to_string()));
+        assert_eq!(get_rate_limit_status("flooder".to_string()).count, 3);
+
+        check_trace_rate_limit("other").unwrap();
+        assert_eq!(get_rate_limit_status("idle".to_string()), RateLimitEntry::default());
+    }
+
+    #[test]
+    fn test_record_trace_call_generates_unique_ids() {
+        let null = || IOValue { data_type: "null".to_string(), value: IOValueType::Null };
+        let record = |trace_id: &str| record_trace_call(
+            trace_id.to_string(),
+            "ctx".to_string(),
+            "aio".to_string(),
+            "agent".to_string(),
+            "tool".to_string(),
+            "run".to_string(),
+            null(),
+            null(),
+            "ok".to_string(),
+            None,
+        ).unwrap();
+
+        let first = record("");
+        let second = record("");
+        assert_ne!(first, second);
+        assert!(first.starts_with("ctx-0-"));
+        assert!(get_trace_by_id(first).is_some());
+        assert!(get_trace_by_id(second).is_some());
+
+        // A supplied id is still honoured for backward compatibility
+        assert_eq!(record("legacy_id"), "legacy_id");
+    }
+
+    #[test]
+    fn test_get_traces_by_time_range_uses_time_index() {
+        let null = || IOValue { data_type: "null".to_string(), value: IOValueType::Null };
+        let call = |timestamp: u64| ProtocolCall {
+            id: 1,
+            protocol: "aio".to_string(),
+            agent: "agent".to_string(),
+            call_type: "tool".to_string(),
+            method: "run".to_string(),
+            input: null(),
+            output: null(),
+            status: "ok".to_string(),
+            error_message: None,
+            timestamp,
+        };
+        TRACE_STORAGE.with(|storage| {
+            let mut storage = storage.borrow_mut();
+            for (trace_id, timestamp) in [("t_early", 100), ("t_mid_a", 200), ("t_mid_b", 200), ("t_late", 300)] {
+                storage.insert(trace_id.to_string(), TraceLog {
+                    trace_id: trace_id.to_string(),
+                    context_id: "ctx".to_string(),
+                    calls: vec![call(timestamp)],
+                });
+            }
+        });
+        assert_eq!(rebuild_trace_time_index(), 4);
+
+        let ids = |traces: Vec<TraceLog>| -> Vec<String> { traces.into_iter().map(|t| t.trace_id).collect() };
+        assert_eq!(ids(get_traces_by_time_range(150, 300, 0, 10)), vec!["t_mid_a", "t_mid_b", "t_late"]);
+        assert_eq!(ids(get_traces_by_time_range(200, 200, 1, 10)), vec!["t_mid_b"]);
+        assert!(get_traces_by_time_range(301, u64::MAX, 0, 10).is_empty());
+
+        // New traces are indexed as they are recorded (the test clock is 0)
+        let recorded = record_trace_call(String::new(), "ctx".to_string(), "aio".to_string(), "agent".to_string(),
+            "tool".to_string(), "run".to_string(), null(), null(), "ok".to_string(), None).unwrap();
+        assert_eq!(ids(get_traces_by_time_range(0, 0, 0, 10)), vec![recorded]);
+    }
+
+    #[test]
+    fn test_traces_indexed_by_registered_agent() {
+        crate::agent_asset_types::add_agent_item(crate::agent_asset_types::AgentItem {
+            id: 0,
+            name: "indexed_agent".to_string(),
+            description: "Traced agent".to_string(),
+            author: "tester".to_string(),
+            owner: "owner".to_string(),
+            platform: None,
+            git_repo: "https://github.com/example/agent".to_string(),
+            homepage: None,
+            input_params: None,
+            output_example: None,
+            image_url: None,
+            exec_file_url: None,
+            version: "1.0.0".to_string(),
+            deleted_at: None,
+            mcp_dependencies: Vec::new(),
+        }).unwrap();
+        let null = || IOValue { data_type: "null".to_string(), value: IOValueType::Null };
+        let record = |trace_id: &str, agent: &str, method: &str, status: &str| record_trace_call(
+            trace_id.to_string(),
+            "ctx".to_string(),
+            "aio".to_string(),
+            agent.to_string(),
+            "tool".to_string(),
+            method.to_string(),
+            null(),
+            null(),
+            status.to_string(),
+            None,
+        ).unwrap();
+
+        record("agent_trace_1", "indexed_agent", "plan", "ok");
+        record("agent_trace_1", "indexed_agent", "act", "error");
+        record("agent_trace_2", "indexed_agent", "plan", "ok");
+        record("agent_trace_3", "unknown_agent", "plan", "ok");
+
+        let traces = get_traces_by_agent_name("indexed_agent".to_string(), 0, 10);
+        assert_eq!(traces.iter().map(|t| t.trace_id.as_str()).collect::<Vec<_>>(), vec!["agent_trace_1", "agent_trace_2"]);
+        assert_eq!(get_traces_by_agent_name("indexed_agent".to_string(), 1, 10).len(), 1);
+        assert!(get_traces_by_agent_name("unknown_agent".to_string(), 0, 10).is_empty());
+
+        let stats = get_agent_execution_stats("indexed_agent".to_string());
+        assert_eq!(stats.total_calls, 3);
+        assert!((stats.success_rate - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats.last_called_at, Some(0));
+        assert_eq!(get_agent_execution_stats("unknown_agent".to_string()).total_calls, 0);
+    }
+
+    #[test]
+    fn test_export_traces_ndjson_caps_output() {
+        for (i, id) in ["nd1", "nd2", "nd3"].iter().enumerate() {
+            TRACE_STORAGE.with(|storage| {
+                storage.borrow_mut().insert(id.to_string(), TraceLog {
+                    trace_id: id.to_string(),
+                    context_id: "ctx".to_string(),
+                    calls: vec![],
+                });
+            });
+            TRACE_TIME_INDEX.with(|index| {
+                index.borrow_mut().insert(TraceTimeKey { timestamp_ns: 100 * (i as u64 + 1), trace_id: id.to_string() }, ());
+            });
+        }
+
+        let ndjson = export_traces_ndjson(100, 200, None);
+        let lines: Vec<serde_json::Value> = ndjson.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["trace_id"], "nd1");
+        assert_eq!(lines[1]["context_id"], "ctx");
+        assert!(export_traces_ndjson(400, 500, None).is_empty());
+
+        let capped = export_traces_ndjson_capped(0, u64::MAX, None, 1024, 2);
+        let last: serde_json::Value = serde_json::from_str(capped.lines().last().unwrap()).unwrap();
+        assert_eq!(capped.lines().count(), 3);
+        assert_eq!(last["truncated"], true);
+        assert_eq!(last["next_start_ns"], 300);
+        assert_eq!(last["next_trace_id"], "nd3");
+
+        // Resuming from the cursor continues with the first trace not yet exported
+        let resumed = export_traces_ndjson_capped(300, u64::MAX, Some("nd3".to_string()), 1024, 2);
+        assert_eq!(resumed.lines().count(), 1);
+        assert!(resumed.contains("\"trace_id\":\"nd3\""));
+
+        // A trace that can never fit is replaced by an error line and the export moves on
+        TRACE_STORAGE.with(|storage| {
+            storage.borrow_mut().insert("nd2".to_string(), TraceLog {
+                trace_id: "nd2".to_string(),
+                context_id: "x".repeat(400),
+                calls: vec![],
+            });
+        });
+        let skipped = export_traces_ndjson_capped(0, u64::MAX, None, NDJSON_TRAILER_RESERVE + 300, 10);
+        let lines: Vec<serde_json::Value> = skipped.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1]["trace_id"], "nd2");
+        assert!(lines[1]["error"].as_str().unwrap().contains("exceeds"));
+        assert_eq!(lines[2]["trace_id"], "nd3");
+
+        let tiny = export_traces_ndjson_capped(0, u64::MAX, None, NDJSON_TRAILER_RESERVE + 60, 10);
+        assert_eq!(tiny.lines().count(), 2);
+        assert!(tiny.lines().last().unwrap().contains("Output limit"));
+    }
+}
@@ -1,4 +1,8 @@
-use candid::{CandidType, Deserialize};
+use candid::{CandidType, Decode, Deserialize, Encode};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::Storable;
+use serde::Serialize;
+use std::borrow::Cow;
 
 #[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum OrderStatus { Created, New, Paid, Confirmed, Complete, Expired, Invalid, Delivered }
@@ -32,3 +36,291 @@ pub struct Order {
 
 #[derive(CandidType, Deserialize, Clone, Debug)]
 pub struct InvoiceResp { pub invoice_id: String, pub invoice_url: String }
+
+/// Upper bound on the page size returned by paginated queries, keeping replies under the message size limit
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// Cap a requested page size at MAX_PAGE_SIZE
+pub fn clamp_page_size(limit: u64) -> u64 {
+    limit.min(MAX_PAGE_SIZE as u64)
+}
+
+/// One page of a cursor-paginated listing; pass `next_cursor` back to fetch the next page
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CursorPage<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+impl<T> CursorPage<T> {
+    /// Build a page from up to `limit + 1` keyed entries;
+    /// the extra trailing entry (if any) only signals that more items remain
+    pub fn from_entries(mut entries: Vec<(String, T)>, limit: usize) -> Self {
+        let has_more = entries.len() > limit;
+        entries.truncate(limit);
+        let next_cursor = if has_more {
+            entries.last().map(|(key, _)| key.clone())
+        } else {
+            None
+        };
+        CursorPage {
+            items: entries.into_iter().map(|(_, item)| item).collect(),
+            next_cursor,
+        }
+    }
+}
+
+/// Result of a bulk read cut short once the instruction budget or item cap ran out; `truncated`
+/// is set when items remain that were not read, and `next_start` is the key of the last entry
+/// read, to pass back to resume after it
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct PagedResult<T> {
+    pub items: Vec<T>,
+    pub truncated: bool,
+    pub items_processed: u64,
+    pub next_start: Option<String>,
+}
+
+/// Instruction budget used when no admin has set one, well below the 5B query limit so
+/// encoding the reply still fits
+pub const DEFAULT_INSTRUCTION_LIMIT: u64 = 1_000_000_000;
+
+/// Highest budget an admin may set, leaving room under the 5B query limit to encode the reply
+pub const MAX_INSTRUCTION_LIMIT: u64 = 4_000_000_000;
+
+/// Budget installed by earlier releases, replaced by DEFAULT_INSTRUCTION_LIMIT on upgrade
+const LEGACY_INSTRUCTION_LIMIT: u64 = 5_000_000_000;
+
+/// Current IC time in nanoseconds. ic_cdk::api::time traps outside a canister, so unit
+/// tests run on a fixed clock at 0.
+#[cfg(not(test))]
+pub fn time() -> u64 {
+    ic_cdk::api::time()
+}
+#[cfg(test)]
+pub fn time() -> u64 { 0 }
+
+#[cfg(not(test))]
+fn instruction_counter() -> u64 {
+    ic_cdk::api::performance_counter(0)
+}
+#[cfg(test)]
+fn instruction_counter() -> u64 { 0 }
+
+/// Stops bulk reads once the call has used the configured instruction budget or kept
+/// MAX_PAGE_SIZE items (or the lower `with_max_items` cap)
+pub struct InstructionGuard {
+    limit: u64,
+    start_after: Option<String>,
+    max_items: u64,
+}
+
+impl InstructionGuard {
+    /// Guard a read resuming after the entry keyed `start_after`, as returned in `next_start`
+    pub fn new(start_after: Option<String>) -> Self {
+        InstructionGuard { limit: get_instruction_budget(), start_after, max_items: MAX_PAGE_SIZE as u64 }
+    }
+
+    /// Stop once `max_items` (at most MAX_PAGE_SIZE) items have been kept, reporting the rest as truncated
+    pub fn with_max_items(mut self, max_items: u64) -> Self {
+        self.max_items = max_items.min(MAX_PAGE_SIZE as u64);
+        self
+    }
+
+    /// Key of the last entry the previous page read; callers start their scan right after it
+    pub fn start_after(&self) -> Option<String> {
+        self.start_after.clone()
+    }
+
+    /// Collect keyed items until the budget is spent, checking the instruction counter after each one.
+    /// `items` must already start after `start_after`; each key is the cursor form of the entry's key
+    pub fn collect<T>(&self, items: impl Iterator<Item = (String, T)>) -> PagedResult<T> {
+        self.collect_matching(items, |_| true)
+    }
+
+    /// Like `collect`, but only keeps the items accepted by `keep`; rejected items still count
+    /// against the budget and towards `items_processed`, so sparse filters cannot run unbounded
+    pub fn collect_matching<T>(&self, items: impl Iterator<Item = (String, T)>, keep: impl FnMut(&T) -> bool) -> PagedResult<T> {
+        self.collect_with(items, keep, instruction_counter)
+    }
+
+    fn collect_with<T>(&self, items: impl Iterator<Item = (String, T)>, mut keep: impl FnMut(&T) -> bool, counter: impl Fn() -> u64) -> PagedResult<T> {
+        let mut items = items.peekable();
+        let mut collected = Vec::new();
+        let mut processed = 0u64;
+        let mut last_key = None;
+        let mut truncated = false;
+        while let Some((key, item)) = items.next() {
+            processed += 1;
+            last_key = Some(key);
+            if keep(&item) {
+                collected.push(item);
+            }
+            if collected.len() as u64 >= self.max_items || counter() >= self.limit {
+                truncated = items.peek().is_some();
+                break;
+            }
+        }
+        PagedResult {
+            items: collected,
+            truncated,
+            items_processed: processed,
+            next_start: if truncated { last_key } else { None },
+        }
+    }
+}
+
+pub fn get_instruction_budget() -> u64 {
+    crate::stable_mem_storage::INSTRUCTION_LIMIT.with(|cell| *cell.borrow().get())
+}
+
+pub fn set_instruction_budget(limit: u64) -> Result<(), String> {
+    if limit == 0 || limit > MAX_INSTRUCTION_LIMIT {
+        return Err(format!("Instruction budget must be between 1 and {}", MAX_INSTRUCTION_LIMIT));
+    }
+    crate::stable_mem_storage::INSTRUCTION_LIMIT.with(|cell| {
+        cell.borrow_mut().set(limit)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to store instruction budget: {:?}", e))
+    })
+}
+
+/// Replace the budget stored by earlier releases with the lower default; admin-set values are kept
+pub fn migrate_legacy_instruction_budget() {
+    if get_instruction_budget() == LEGACY_INSTRUCTION_LIMIT {
+        let _ = set_instruction_budget(DEFAULT_INSTRUCTION_LIMIT);
+    }
+}
+
+/// Clamp a requested cursor page size into 1..=MAX_PAGE_SIZE
+pub fn clamp_page_limit(limit: usize) -> usize {
+    limit.clamp(1, MAX_PAGE_SIZE)
+}
+
+/// Range over a map starting strictly after the cursor key
+pub fn cursor_key_range<K>(cursor: Option<K>) -> (std::ops::Bound<K>, std::ops::Bound<K>) {
+    match cursor {
+        Some(key) => (std::ops::Bound::Excluded(key), std::ops::Bound::Unbounded),
+        None => (std::ops::Bound::Unbounded, std::ops::Bound::Unbounded),
+    }
+}
+
+/// First vector index to read for an index-based cursor (the cursor is the last index returned)
+pub fn cursor_start_index(cursor: &Option<String>) -> Result<u64, String> {
+    match cursor {
+        None => Ok(0),
+        Some(c) => c
+            .parse::<u64>()
+            .map(|last| last + 1)
+            .map_err(|_| format!("Invalid cursor: {}", c)),
+    }
+}
+
+/// Heap-only state carried across upgrades; written in pre_upgrade, read back in post_upgrade.
+/// Credit prices are not captured: they live in CREDIT_CONVERT_CONTRACT, which already survives upgrades.
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct UpgradeState {
+    pub mining_timer_running: bool,
+    pub chat_cleanup_timer_running: Option<bool>, // opt so state saved before chat cleanup existed still decodes
+    pub last_upgrade_time: u64,
+    pub webhook_timer_running: Option<bool>,
+    pub device_delivery_timer_running: Option<bool>,
+}
+
+impl Storable for UpgradeState {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode UpgradeState"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode UpgradeState")
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 128, is_fixed_size: false };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_upgrade_state_decodes_snapshot_with_credit_prices() {
+        // Snapshots written before the price fields were dropped still carry them
+        #[derive(CandidType)]
+        struct PricedUpgradeState {
+            mining_timer_running: bool,
+            chat_cleanup_timer_running: Option<bool>,
+            icp_usd_price: f64,
+            credits_per_icp: u64,
+            last_upgrade_time: u64,
+            webhook_timer_running: Option<bool>,
+            device_delivery_timer_running: Option<bool>,
+        }
+        let bytes = Encode!(&PricedUpgradeState {
+            mining_timer_running: true,
+            chat_cleanup_timer_running: None,
+            icp_usd_price: 8.0,
+            credits_per_icp: 40_000,
+            last_upgrade_time: 5,
+            webhook_timer_running: Some(true),
+            device_delivery_timer_running: None,
+        }).unwrap();
+        let state = UpgradeState::from_bytes(Cow::Owned(bytes));
+        assert!(state.mining_timer_running);
+        assert_eq!(state.last_upgrade_time, 5);
+        assert_eq!(state.webhook_timer_running, Some(true));
+    }
+
+    #[test]
+    fn test_instruction_guard_stops_at_budget() {
+        assert!(set_instruction_budget(0).is_err());
+        assert!(set_instruction_budget(MAX_INSTRUCTION_LIMIT + 1).is_err());
+        set_instruction_budget(300).unwrap();
+        let guard = InstructionGuard::new(None);
+        // Keyed like a string map: the scan resumes strictly after the cursor key
+        let map: std::collections::BTreeMap<String, u32> = (1..=9).map(|n| (n.to_string(), n)).collect();
+        let scan = |guard: &InstructionGuard| map.range(cursor_key_range(guard.start_after())).map(|(k, v)| (k.clone(), *v)).collect::<Vec<_>>();
+
+        // Each item costs 100 instructions, so the budget runs out after the third
+        let used = Cell::new(0u64);
+        let counter = || { used.set(used.get() + 100); used.get() };
+        let result = guard.collect_with(scan(&guard).into_iter().take(5), |_| true, counter);
+        assert_eq!(result.items, vec![1, 2, 3]);
+        assert!(result.truncated);
+        assert_eq!(result.items_processed, 3);
+        assert_eq!(result.next_start, Some("3".to_string()));
+
+        // Resuming from next_start picks up after the last key the truncated read saw
+        used.set(0);
+        let resumed_guard = InstructionGuard::new(result.next_start);
+        let resumed = resumed_guard.collect_with(scan(&resumed_guard).into_iter().take(2), |_| true, || { used.set(used.get() + 100); used.get() });
+        assert_eq!(resumed.items, vec![4, 5]);
+        assert!(!resumed.truncated);
+        assert_eq!(resumed.next_start, None);
+
+        // Filtered-out items still use up the budget
+        used.set(0);
+        let result = guard.collect_with(scan(&guard).into_iter(), |n| n % 2 == 0, || { used.set(used.get() + 100); used.get() });
+        assert_eq!(result.items, vec![2]);
+        assert_eq!(result.items_processed, 3);
+        assert_eq!(result.next_start, Some("3".to_string()));
+
+        // Running out on the last item does not count as truncation
+        used.set(0);
+        let result = guard.collect_with(scan(&guard).into_iter().take(3), |_| true, || { used.set(used.get() + 100); used.get() });
+        assert_eq!(result.items.len(), 3);
+        assert!(!result.truncated);
+
+        let result = guard.collect(scan(&guard).into_iter());
+        assert_eq!(result.items_processed, 9);
+        assert!(!result.truncated);
+
+        // The item cap stops the read even with budget to spare, and never exceeds MAX_PAGE_SIZE
+        set_instruction_budget(MAX_INSTRUCTION_LIMIT).unwrap();
+        let result = InstructionGuard::new(None).with_max_items(4).collect(scan(&guard).into_iter());
+        assert_eq!((result.items.len(), result.next_start), (4, Some("4".to_string())));
+        let many = (0..MAX_PAGE_SIZE as u32 + 10).map(|n| (format!("{:04}", n), n));
+        assert_eq!(InstructionGuard::new(None).with_max_items(u64::MAX).collect(many).items.len(), MAX_PAGE_SIZE);
+    }
+}
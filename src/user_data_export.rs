@@ -0,0 +1,214 @@
+use candid::Principal;
+use serde::Serialize;
+use crate::society_profile_types::{UserProfile, Contact, get_user_profile_by_principal, get_contacts_by_owner};
+use crate::device_types::{DeviceInfo, DeviceService};
+use crate::token_economy_types::{AccountInfo, TokenActivity, CreditActivity};
+use crate::token_economy::{get_token_activities, get_credit_activities};
+use crate::account_storage::get_account;
+use crate::pixel_creation_types::{ProjectId, ProjectOwnerKey, VersionId};
+use crate::stable_mem_storage::{PIXEL_PROJECTS, PROJECT_OWNER_INDEX};
+use crate::agent_asset_types::{AgentItem, get_user_agent_items};
+use crate::mcp_asset_types::{McpItem, get_user_mcp_items};
+
+// Maximum number of records exported per section in one page; totals report the full counts
+const EXPORT_SECTION_LIMIT: usize = 100;
+
+#[derive(Serialize, Default)]
+pub struct UserDataExportTotals {
+    pub contacts: usize,
+    pub devices: usize,
+    pub token_activities: usize,
+    pub credit_activities: usize,
+    pub pixel_projects: u64,
+    pub agents: usize,
+    pub mcps: usize,
+}
+
+/// Pixel project metadata; the pixel data of its versions is fetched through the project API
+#[derive(Serialize)]
+pub struct ProjectSummary {
+    pub project_id: ProjectId,
+    pub title: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub current_version_id: VersionId,
+    pub version_count: usize,
+    pub forked_from: Option<ProjectId>,
+}
+
+/// One page of a principal's data: list sections hold their items from `offset` on, at most
+/// `limit` each, and `totals` tells how many pages remain
+#[derive(Serialize)]
+pub struct UserDataExport {
+    pub principal_id: String,
+    pub profile: Option<UserProfile>,
+    pub contacts: Vec<Contact>,
+    pub devices: Vec<DeviceInfo>,
+    pub account: Option<AccountInfo>,
+    pub token_activities: Vec<TokenActivity>,
+    pub credit_activities: Vec<CreditActivity>,
+    pub pixel_projects: Vec<ProjectSummary>,
+    pub agents: Vec<AgentItem>,
+    pub mcps: Vec<McpItem>,
+    pub offset: u64,
+    pub limit: u64,
+    pub totals: UserDataExportTotals,
+}
+
+fn page<T>(items: Vec<T>, offset: u64, limit: u64) -> (Vec<T>, usize) {
+    let total = items.len();
+    (items.into_iter().skip(offset as usize).take(limit as usize).collect(), total)
+}
+
+// Metadata of the owner's projects from `offset` on, walking the owner index so unrelated
+// projects are never loaded
+fn project_summaries(owner: Principal, offset: u64, limit: u64) -> (Vec<ProjectSummary>, u64) {
+    let start = ProjectOwnerKey { owner, project_id: String::new() };
+    let ids: Vec<ProjectId> = PROJECT_OWNER_INDEX.with(|index| {
+        index.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.owner == owner)
+            .map(|(key, _)| key.project_id)
+            .collect()
+    });
+    let total = ids.len() as u64;
+    let summaries = PIXEL_PROJECTS.with(|projects| {
+        let projects = projects.borrow();
+        ids.into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .filter_map(|id| projects.get(&id))
+            .map(|project| ProjectSummary {
+                title: project.current_version.source.metadata.as_ref().and_then(|meta| meta.title.clone()),
+                current_version_id: project.current_version.version_id.clone(),
+                version_count: project.history.len(),
+                project_id: project.project_id,
+                created_at: project.created_at,
+                updated_at: project.updated_at,
+                forked_from: project.forked_from,
+            })
+            .collect()
+    });
+    (summaries, total)
+}
+
+/// Collect one page of everything stored for a principal; `limit` is capped at EXPORT_SECTION_LIMIT
+pub fn collect_user_data(principal_id: &str, offset: u64, limit: u64) -> UserDataExport {
+    let limit = limit.min(EXPORT_SECTION_LIMIT as u64);
+    let mut totals = UserDataExportTotals::default();
+
+    let (contacts, n) = page(get_contacts_by_owner(principal_id.to_string()), offset, limit);
+    totals.contacts = n;
+
+    // Devices and pixel projects are keyed by Principal; skip them for non-principal ids
+    let principal = Principal::from_text(principal_id).ok();
+    let (devices, n) = page(principal.map(|p| DeviceService::get_devices_by_owner(&p)).unwrap_or_default(), offset, limit);
+    totals.devices = n;
+
+    let (pixel_projects, pixel_total) = principal
+        .map(|p| project_summaries(p, offset, limit))
+        .unwrap_or_default();
+    totals.pixel_projects = pixel_total;
+
+    let (token_activities, n) = page(get_token_activities(principal_id), offset, limit);
+    totals.token_activities = n;
+    let (credit_activities, n) = page(get_credit_activities(principal_id), offset, limit);
+    totals.credit_activities = n;
+    let (agents, n) = page(get_user_agent_items(principal_id.to_string()), offset, limit);
+    totals.agents = n;
+    let (mcps, n) = page(get_user_mcp_items(principal_id.to_string()), offset, limit);
+    totals.mcps = n;
+
+    UserDataExport {
+        principal_id: principal_id.to_string(),
+        // The stored password hash is a credential, not user data, and never leaves the canister
+        profile: get_user_profile_by_principal(principal_id.to_string()).map(|profile| UserProfile { passwd: None, ..profile }),
+        contacts,
+        devices,
+        account: get_account(principal_id.to_string()),
+        token_activities,
+        credit_activities,
+        pixel_projects,
+        agents,
+        mcps,
+        offset,
+        limit,
+        totals,
+    }
+}
+
+/// Export one page of the data held for a principal as a JSON document
+pub fn export_user_data(principal_id: &str, offset: u64, limit: u64) -> String {
+    serde_json::to_string(&collect_user_data(principal_id, offset, limit))
+        .unwrap_or_else(|e| format!("{{\"error\":\"Failed to serialize export: {}\"}}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::society_profile_types::{LoginMethod, LoginStatus, PrincipalKey};
+    use crate::stable_mem_storage::{USER_PROFILES, PRINCIPAL_INDEX};
+    use crate::agent_asset_types::add_agent_item;
+
+    #[test]
+    fn test_export_contains_profile_and_owned_agent() {
+        let principal_id = "2vxsx-fae".to_string();
+        let profile = UserProfile {
+            user_id: "user_export".to_string(),
+            principal_id: principal_id.clone(),
+            name: None,
+            nickname: "Exporter".to_string(),
+            login_method: LoginMethod::II,
+            login_status: LoginStatus::Authenticated,
+            email: None,
+            picture: None,
+            wallet_address: None,
+            devices: vec![],
+            passwd: Some("encrypted_secret".to_string()),
+            created_at: 1,
+            updated_at: 1,
+            metadata: None,
+            deleted_at: None,
+        };
+        let index = USER_PROFILES.with(|p| {
+            let p = p.borrow_mut();
+            let index = p.len();
+            p.push(&profile).unwrap();
+            index
+        });
+        PRINCIPAL_INDEX.with(|idx| {
+            idx.borrow_mut().insert(PrincipalKey { principal_id: principal_id.clone() }, index);
+        });
+
+        add_agent_item(AgentItem {
+            id: 0,
+            name: "export_agent".to_string(),
+            description: "Agent owned by exporter".to_string(),
+            author: "Exporter".to_string(),
+            owner: principal_id.clone(),
+            platform: None,
+            git_repo: "https://github.com/example/agent".to_string(),
+            homepage: None,
+            input_params: None,
+            output_example: None,
+            image_url: None,
+            exec_file_url: None,
+            version: "1.0.0".to_string(),
+            deleted_at: None,
+            mcp_dependencies: Vec::new(),
+        }).unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&export_user_data(&principal_id, 0, 10)).unwrap();
+        assert_eq!(json["principal_id"], principal_id);
+        assert_eq!(json["profile"]["nickname"], "Exporter");
+        assert!(json["profile"]["passwd"].is_null());
+        assert!(!export_user_data(&principal_id, 0, 10).contains("encrypted_secret"));
+        assert_eq!(json["agents"][0]["name"], "export_agent");
+        assert_eq!(json["totals"]["agents"], 1);
+
+        // Later pages keep the totals but hold no more items
+        let next: serde_json::Value = serde_json::from_str(&export_user_data(&principal_id, 1, 10)).unwrap();
+        assert_eq!(next["agents"].as_array().unwrap().len(), 0);
+        assert_eq!(next["totals"]["agents"], 1);
+    }
+}
@@ -0,0 +1,283 @@
+use candid::{CandidType, Decode, Encode};
+use ic_cdk::api::management_canister::http_request::{
+    http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod, TransformContext,
+};
+use ic_stable_structures::storable::Bound;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use crate::stable_mem_storage::{WEBHOOK_SUBSCRIPTIONS, WEBHOOK_QUEUE};
+use crate::hmac::sign_webhook_payload;
+use crate::task_queue;
+
+use crate::types::time;
+
+const MAX_SUBSCRIPTIONS_PER_SUBSCRIBER: usize = 20;
+/// Subscriptions kept across all subscribers, since every one of them costs outcall cycles per event
+const MAX_WEBHOOK_SUBSCRIPTIONS: u64 = 1_000;
+const MAX_WEBHOOK_URL_LEN: usize = 512;
+const MAX_WEBHOOK_SECRET_LEN: usize = 256;
+
+/// Tasks POSTed per timer tick; the rest wait for the next tick
+pub const WEBHOOK_BATCH_SIZE: usize = 20;
+/// Pending deliveries kept at most; events raised while the queue is full are dropped
+pub const MAX_WEBHOOK_QUEUE_LEN: u64 = 10_000;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebhookEventType {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// An external endpoint to notify about MCP lifecycle events; `mcp_name` None matches every MCP
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct WebhookSubscription {
+    pub id: String,
+    pub subscriber: String,
+    pub mcp_name: Option<String>,
+    pub events: Vec<WebhookEventType>,
+    pub url: String,
+    pub secret: String,             // HMAC-SHA256 key for the X-Webhook-Signature header
+    pub created_at: u64,
+}
+
+/// One pending POST of `payload` to a subscription's URL
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct WebhookDeliveryTask {
+    pub subscription_id: String,
+    pub event: WebhookEventType,
+    pub payload: String,
+    pub queued_at: u64,
+}
+
+impl ic_stable_structures::Storable for WebhookSubscription {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode WebhookSubscription"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode WebhookSubscription")
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 2048, is_fixed_size: false };
+}
+
+impl ic_stable_structures::Storable for WebhookDeliveryTask {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode WebhookDeliveryTask"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode WebhookDeliveryTask")
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 4096, is_fixed_size: false };
+}
+
+/// Register a webhook for the subscriber. HTTPS is required since deliveries are HTTP outcalls,
+/// and the subscriber must be a non-anonymous principal with an account.
+pub fn subscribe_webhook(
+    subscriber: String,
+    mcp_name: Option<String>,
+    events: Vec<WebhookEventType>,
+    url: String,
+    secret: String,
+) -> Result<String, String> {
+    if subscriber == candid::Principal::anonymous().to_text() {
+        return Err("Anonymous principals cannot subscribe to webhooks".to_string());
+    }
+    if crate::account_storage::get_account(subscriber.clone()).is_none() {
+        return Err("An account is required to subscribe to webhooks".to_string());
+    }
+    let url = url.trim().to_string();
+    if !url.starts_with("https://") || url.len() > MAX_WEBHOOK_URL_LEN {
+        return Err(format!("Webhook URL must be an HTTPS URL of at most {} bytes", MAX_WEBHOOK_URL_LEN));
+    }
+    if secret.is_empty() || secret.len() > MAX_WEBHOOK_SECRET_LEN {
+        return Err(format!("Webhook secret must be between 1 and {} bytes", MAX_WEBHOOK_SECRET_LEN));
+    }
+    let mut unique_events: Vec<WebhookEventType> = Vec::new();
+    for event in events {
+        if !unique_events.contains(&event) {
+            unique_events.push(event);
+        }
+    }
+    let events = unique_events;
+    if events.is_empty() {
+        return Err("Webhook must subscribe to at least one event".to_string());
+    }
+    let mcp_name = mcp_name.map(|name| name.trim().to_string()).filter(|name| !name.is_empty());
+    if get_webhook_subscriptions(subscriber.clone()).len() >= MAX_SUBSCRIPTIONS_PER_SUBSCRIBER {
+        return Err(format!("Cannot have more than {} webhook subscriptions", MAX_SUBSCRIPTIONS_PER_SUBSCRIBER));
+    }
+    if WEBHOOK_SUBSCRIPTIONS.with(|subscriptions| subscriptions.borrow().len()) >= MAX_WEBHOOK_SUBSCRIPTIONS {
+        return Err("The webhook subscription limit has been reached".to_string());
+    }
+
+    let created_at = time();
+    let mut hasher = DefaultHasher::new();
+    format!("{}:{}:{:?}:{}", subscriber, url, mcp_name, created_at).hash(&mut hasher);
+    let id = format!("webhook_{}", hasher.finish());
+
+    WEBHOOK_SUBSCRIPTIONS.with(|subscriptions| {
+        let mut subscriptions = subscriptions.borrow_mut();
+        if subscriptions.contains_key(&id) {
+            return Err("Webhook subscription already exists".to_string());
+        }
+        subscriptions.insert(id.clone(), WebhookSubscription {
+            id: id.clone(),
+            subscriber,
+            mcp_name,
+            events,
+            url,
+            secret,
+            created_at,
+        });
+        Ok(id)
+    })
+}
+
+/// Remove a webhook subscription (subscriber only); tasks already queued for it are dropped on delivery
+pub fn unsubscribe_webhook(id: String, caller_principal: String) -> Result<(), String> {
+    WEBHOOK_SUBSCRIPTIONS.with(|subscriptions| {
+        let mut subscriptions = subscriptions.borrow_mut();
+        let subscription = subscriptions.get(&id).ok_or("Webhook subscription not found")?;
+        if subscription.subscriber != caller_principal {
+            return Err("Only the subscriber can remove this webhook".to_string());
+        }
+        subscriptions.remove(&id);
+        Ok(())
+    })
+}
+
+/// Subscriptions of one subscriber, with secrets blanked out
+pub fn get_webhook_subscriptions(subscriber: String) -> Vec<WebhookSubscription> {
+    WEBHOOK_SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions.borrow()
+            .iter()
+            .filter(|(_, s)| s.subscriber == subscriber)
+            .map(|(_, s)| WebhookSubscription { secret: String::new(), ..s })
+            .collect()
+    })
+}
+
+/// Queue a delivery to every subscription watching this MCP and event; returns the number queued,
+/// which is less than the number of matching subscriptions once the queue is full
+pub fn enqueue_mcp_event(mcp_name: &str, event: WebhookEventType) -> u64 {
+    let queued_at = time();
+    let payload = serde_json::json!({
+        "event": format!("{:?}", event),
+        "mcp_name": mcp_name,
+        "timestamp": queued_at,
+    }).to_string();
+
+    let matching: Vec<String> = WEBHOOK_SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions.borrow()
+            .iter()
+            .filter(|(_, s)| s.events.contains(&event))
+            .filter(|(_, s)| s.mcp_name.as_deref().is_none_or(|name| name == mcp_name))
+            .map(|(id, _)| id)
+            .collect()
+    });
+
+    WEBHOOK_QUEUE.with(|queue| {
+        let mut queue = queue.borrow_mut();
+        let mut queued = 0;
+        for subscription_id in matching {
+            let task = WebhookDeliveryTask {
+                subscription_id: subscription_id.clone(),
+                event,
+                payload: payload.clone(),
+                queued_at,
+            };
+            if task_queue::push_task(&mut queue, task, MAX_WEBHOOK_QUEUE_LEN) {
+                queued += 1;
+            } else {
+                ic_cdk::println!("Webhook queue is full, dropping {:?} event for {}", event, subscription_id);
+            }
+        }
+        queued
+    })
+}
+
+pub fn get_webhook_queue_length() -> u64 {
+    WEBHOOK_QUEUE.with(|queue| queue.borrow().len())
+}
+
+/// Remove up to `max` tasks from the front of the queue, oldest first
+pub fn take_webhook_batch(max: usize) -> Vec<WebhookDeliveryTask> {
+    WEBHOOK_QUEUE.with(|queue| task_queue::take_batch(&mut queue.borrow_mut(), max))
+}
+
+/// POST one batch of queued webhooks. Failed deliveries are logged and not retried.
+pub async fn deliver_pending_webhooks() {
+    for task in take_webhook_batch(WEBHOOK_BATCH_SIZE) {
+        let subscription = WEBHOOK_SUBSCRIPTIONS.with(|subscriptions| subscriptions.borrow().get(&task.subscription_id));
+        let Some(subscription) = subscription else {
+            continue;
+        };
+
+        let body = task.payload.into_bytes();
+        let arg = CanisterHttpRequestArgument {
+            url: subscription.url.clone(),
+            method: HttpMethod::POST,
+            headers: vec![
+                HttpHeader { name: "Content-Type".into(), value: "application/json".into() },
+                HttpHeader { name: "X-Webhook-Event".into(), value: format!("{:?}", task.event) },
+                HttpHeader { name: "X-Webhook-Signature".into(), value: sign_webhook_payload(&body, &subscription.secret) },
+            ],
+            body: Some(body),
+            max_response_bytes: Some(2_048),
+            transform: Some(TransformContext::from_name("transform".to_string(), vec![])),
+        };
+        let cycles = crate::http_outcall::outcall_cycles(&arg);
+        match http_request(arg, cycles).await {
+            Ok((resp,)) => ic_cdk::println!("Webhook {} delivered with status {}", subscription.id, resp.status),
+            Err((code, msg)) => ic_cdk::println!("Webhook {} delivery failed: {:?} {}", subscription.id, code, msg),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mcp_events_are_queued_for_matching_subscriptions() {
+        assert!(subscribe_webhook(candid::Principal::anonymous().to_text(), None, vec![WebhookEventType::Created], "https://hooks.example.com".to_string(), "s".to_string()).is_err());
+        assert!(subscribe_webhook("alice".to_string(), None, vec![WebhookEventType::Created], "https://hooks.example.com".to_string(), "s".to_string()).is_err());
+        crate::token_economy::create_account("alice".to_string()).unwrap();
+        crate::token_economy::create_account("bob".to_string()).unwrap();
+        assert!(subscribe_webhook("alice".to_string(), None, vec![WebhookEventType::Created], "http://insecure".to_string(), "s".to_string()).is_err());
+        assert!(subscribe_webhook("alice".to_string(), None, vec![], "https://hooks.example.com".to_string(), "s".to_string()).is_err());
+
+        let all = subscribe_webhook("alice".to_string(), None, vec![WebhookEventType::Created, WebhookEventType::Deleted], "https://hooks.example.com/all".to_string(), "s1".to_string()).unwrap();
+        let one = subscribe_webhook("bob".to_string(), Some("weather".to_string()), vec![WebhookEventType::Updated], "https://hooks.example.com/one".to_string(), "s2".to_string()).unwrap();
+        assert_eq!(get_webhook_subscriptions("alice".to_string())[0].secret, "");
+
+        assert_eq!(enqueue_mcp_event("weather", WebhookEventType::Created), 1);
+        assert_eq!(enqueue_mcp_event("weather", WebhookEventType::Updated), 1);
+        assert_eq!(enqueue_mcp_event("maps", WebhookEventType::Updated), 0);
+        assert_eq!(get_webhook_queue_length(), 2);
+
+        let batch = take_webhook_batch(1);
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].subscription_id, all);
+        assert!(batch[0].payload.contains("\"mcp_name\":\"weather\""));
+        assert_eq!(take_webhook_batch(10)[0].subscription_id, one);
+        assert_eq!(get_webhook_queue_length(), 0);
+
+        // Tasks are taken in enqueue order
+        assert_eq!(enqueue_mcp_event("weather", WebhookEventType::Deleted), 1);
+        assert_eq!(enqueue_mcp_event("weather", WebhookEventType::Updated), 1);
+        let batch = take_webhook_batch(10);
+        assert_eq!(batch[0].event, WebhookEventType::Deleted);
+        assert_eq!(batch[1].event, WebhookEventType::Updated);
+
+        assert!(unsubscribe_webhook(one.clone(), "alice".to_string()).is_err());
+        unsubscribe_webhook(one, "bob".to_string()).unwrap();
+        assert!(get_webhook_subscriptions("bob".to_string()).is_empty());
+    }
+}